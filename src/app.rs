@@ -1,16 +1,17 @@
 use crate::config::Config;
+use crate::permissions::{Decision, Permissions};
 use crate::process::ProcessTracker;
 use crate::context::ContextManager;
 use crate::ollama::{ChatMessage, ChatMessageRequest, ChatStreamEvent, OllamaClient, ToolCall};
 use reqwest;
-use crate::tools::{CatTool, GrepTool, ListDirectoryTool, ReadUrlTool, ReplaceTextTool, EditFileTool, RunCommandTool, SemanticSearchTool, Tool, WebSearchTool, WriteFileTool, MemoryTool, DeleteFileTool, SymbolSearchTool, RunPythonTool};
+use crate::tools::{CheckpointStore, Checkpointer, Turn, CatTool, GrepTool, ListDirectoryTool, ReadUrlTool, ReplaceTextTool, EditFileTool, ApplyPatchTool, RunCommandTool, SemanticSearchTool, Tool, WebSearchTool, WriteFileTool, MemoryTool, ForgetTool, ListMemoriesTool, UpdateMemoryTool, ManageMemoryTool, DeleteFileTool, SymbolSearchTool, RunPythonTool, ToolContext, Workspace};
 use crate::python::PythonRuntime;
-use crate::persistence::SessionManager;
+use crate::persistence::{SessionFile, SessionManager};
 use crossterm::event::{KeyCode, KeyModifiers};
 use directories::{BaseDirs, ProjectDirs};
 use futures::StreamExt;
 use ratatui::style::Style;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
 use std::env;
 use std::fs;
 use std::path::PathBuf;
@@ -18,9 +19,10 @@ use std::sync::Arc;
 use throbber_widgets_tui::ThrobberState;
 use tokio::sync::mpsc;
 use tokio::task::AbortHandle;
+use tokio_util::sync::CancellationToken;
 use tui_textarea::{Input, TextArea};
 use arboard::Clipboard;
-use tracing::info;
+use tracing::{info, warn};
 
 /// Generates system context information for the LLM to understand the user's environment.
 ///
@@ -94,7 +96,9 @@ pub enum Action {
     /// Adds a tool call request from the AI.
     AddToolCall(ToolCall),
     /// Adds the output of a tool execution.
-    AddToolOutput(String, String), // name, output
+    AddToolOutput(String, String, String), // call id, name, output
+    /// A line of output from the running tool.
+    ToolProgress(String),
     /// Requests the AI to generate a response.
     RequestAiResponse,
     /// Indicates that the AI response generation is complete.
@@ -122,6 +126,10 @@ pub enum Action {
     DeleteSession(String),
     /// Indicates that sessions have been loaded (unused currently).
     SessionsLoaded(Vec<String>),
+    /// Opens the menu of collections used for retrieval in this session.
+    EnterCollectionSelect,
+    /// Turns a collection on or off for this session; `None` enables all.
+    ToggleCollection(Option<String>),
     // Model Management Actions
     /// Enters the model pulling interface.
     EnterModelPull,
@@ -137,7 +145,22 @@ pub enum Action {
     ConfirmToolExecution,
     /// Denies a pending tool execution.
     DenyToolExecution,
-    /// Cancels the current AI generation.
+    /// Settles the pending tool call and remembers the decision for its
+    /// tool for the rest of the session.
+    RememberToolDecision(Decision),
+    /// Opens the editable argument of the pending tool call in an editor.
+    EditToolContent,
+    /// Replaces the editable argument of the pending tool call.
+    UpdateToolContent(String),
+    /// Reverts the last N file edits made by tools.
+    UndoEdits(usize),
+    /// Reverts every file edit made since the message at this index.
+    UndoSinceMessage(usize),
+    /// Reverts the edits of a turn, by id, and every edit after it.
+    UndoTurn(String),
+    /// Opens the list of checkpointed edits.
+    EnterCheckpointList,
+    /// Cancels the current AI generation and any running tool.
     CancelGeneration,
     /// Copies the selected message to clipboard.
     CopyMessage,
@@ -177,10 +200,16 @@ pub enum Mode {
     SessionSelect,
     /// Mode for creating a new session.
     SessionCreate,
+    /// Mode for choosing the collections this session retrieves from.
+    CollectionSelect,
     /// Mode for entering a model name to pull.
     ModelPullInput,
     /// Mode for confirming a tool execution.
     ToolConfirmation,
+    /// Mode for editing the proposed content of a pending tool call.
+    ToolEdit,
+    /// Mode for browsing and undoing the file edits of this session.
+    CheckpointList,
 }
 
 /// The main application state struct.
@@ -233,6 +262,19 @@ pub struct App<'a> {
     pub session_list_state: ratatui::widgets::ListState,
     /// Input area for creating new sessions.
     pub session_input: TextArea<'a>,
+    /// Collections this session retrieves from (`None` for all), shared with
+    /// `semantic_search` and saved with the session.
+    pub session_collections: Arc<std::sync::Mutex<Option<BTreeSet<String>>>>,
+    /// Collections listed in the collection menu.
+    pub available_collections: Vec<String>,
+    /// State for the collection list widget.
+    pub collection_list_state: ratatui::widgets::ListState,
+    /// Files changed by tools in this session, saved before each edit.
+    pub checkpoints: Arc<CheckpointStore>,
+    /// Turns listed in the checkpoint list, newest first.
+    pub checkpoint_turns: Vec<Turn>,
+    /// State for the checkpoint list widget.
+    pub checkpoint_list_state: ratatui::widgets::ListState,
     // Model Management state
     /// Input area for pulling models.
     pub pull_input: TextArea<'a>,
@@ -245,8 +287,25 @@ pub struct App<'a> {
     consecutive_tool_calls: usize,
     /// Pending tool call waiting for user confirmation.
     pub pending_tool_call: Option<ToolCall>,
+    /// Unified diff of the change the pending call would make, or why it
+    /// couldn't be computed. None for tools that don't change files.
+    pub tool_preview: Option<Result<String, String>>,
+    /// Input area for editing the pending call's proposed content.
+    pub tool_edit_input: TextArea<'a>,
+    /// Further calls from the same turn waiting for confirmation.
+    pub queued_tool_calls: VecDeque<ToolCall>,
+    /// Policy deciding which tool calls run, ask first or are refused.
+    pub permissions: Permissions,
+    /// Decisions the user chose to keep for the rest of the session, keyed
+    /// by `Permissions::session_key`: `run_command <words>`, the tool and
+    /// the files it touches, `<tool> (rule N)` or the tool name.
+    pub session_permissions: HashMap<String, Decision>,
     /// Whether a tool is currently executing (for UI feedback).
     pub is_tool_executing: bool,
+    /// Running tool calls by call id, with their name and cancellation token.
+    running_tools: HashMap<String, (String, CancellationToken)>,
+    /// Latest line of output reported by a running tool.
+    pub tool_progress: Option<String>,
     // Persistence
     last_save_time: std::time::Instant,
     // Context Management
@@ -296,9 +355,35 @@ pub struct App<'a> {
     pub enable_session_autonaming: bool,
     pub monologue_parser: Option<crate::monologue::MonologueParser>,
     pub health_status: Vec<crate::health::ServiceStatus>,
+    /// Whether conversation state is written to the sessions directory.
+    /// Disabled for one-shot headless runs so they don't clobber saved sessions.
+    pub persist_session: bool,
+    /// Background re-indexer for the configured knowledge bases, if enabled.
+    pub kb_watcher: Option<crate::rag::watcher::KnowledgeBaseWatcher>,
 }
 
 impl<'a> App<'a> {
+    /// Starts watching the configured knowledge bases, re-indexing them in
+    /// the background as their files change. Progress is shown as status
+    /// messages.
+    pub fn watch_knowledge_bases(&mut self, config: &Config) {
+        let (status_tx, mut status_rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+        let action_tx = self.action_tx.clone();
+        tokio::spawn(async move {
+            while let Some(msg) = status_rx.recv().await {
+                let _ = action_tx.send(Action::ShowStatus(msg));
+            }
+        });
+
+        self.kb_watcher = Some(crate::rag::watcher::KnowledgeBaseWatcher::spawn(
+            Arc::new(self.rag.clone()),
+            &config.knowledge_bases,
+            &config.ignored_patterns,
+            std::time::Duration::from_millis(config.watch_debounce_ms),
+            Some(status_tx),
+        ));
+    }
+
     /// Creates a new instance of the application.
     ///
     /// # Arguments
@@ -337,15 +422,11 @@ impl<'a> App<'a> {
         // Browser client (shared)
         let browser_client = Arc::new(crate::tools::web::BrowserClient::new());
 
-        let vector_index = Arc::new(std::sync::Mutex::new(None));
-
-        // Use async RAG init directly, blocking call is not needed anymore
-        let shared_rag = Arc::new(crate::rag::RagSystem::new(
-             OllamaClient::new(config.ollama_url.clone(), config.api_type.clone(), config.api_key.clone()),
-             config.embedding_model.clone(),
-             vector_index.clone(),
-             storage_path.clone(),
-        ));
+        // The app, its tools and the knowledge base watcher all share this
+        // store and its index.
+        let rag = crate::rag::RagSystem::from_config(&config);
+        let vector_index = rag.index.clone();
+        let shared_rag = Arc::new(rag.clone());
         
         // Attempt to load existing index
         if let Err(_e) = shared_rag.load() {
@@ -356,6 +437,7 @@ impl<'a> App<'a> {
         }
 
         let mut tools: HashMap<String, Arc<dyn Tool>> = HashMap::new();
+        let session_collections = Arc::new(std::sync::Mutex::new(None));
 
         let (status_tx, mut status_rx) = tokio::sync::mpsc::unbounded_channel::<String>();
         let action_tx_clone = action_tx.clone();
@@ -365,53 +447,69 @@ impl<'a> App<'a> {
             }
         });
 
+        let mut quarantined: Vec<String> = shared_rag.quarantined_collections().into_iter().collect();
+        if !quarantined.is_empty() {
+            quarantined.sort();
+            let _ = status_tx.send(format!(
+                "Collections embedded with another model are skipped by vector search until re-embedded: {} (`intus memory reembed <collection>`)",
+                quarantined.join(", ")
+            ));
+        }
+
+        let workspace = Workspace::from_roots(&config.workspace, config.ignored_patterns.clone());
         tools.insert(
             "grep_files".to_string(),
             Arc::new(GrepTool {
-                ignored_patterns: config.ignored_patterns.clone(),
+                workspace: workspace.clone(),
             }),
         );
         tools.insert(
             "read_file".to_string(),
             Arc::new(CatTool {
-                ignored_patterns: config.ignored_patterns.clone(),
+                workspace: workspace.clone(),
                 rag: shared_rag.clone(),
             }),
         );
         tools.insert(
             "list_directory".to_string(),
             Arc::new(ListDirectoryTool {
-                ignored_patterns: config.ignored_patterns.clone(),
+                workspace: workspace.clone(),
             }),
         );
         tools.insert(
             "write_file".to_string(),
             Arc::new(WriteFileTool {
-                ignored_patterns: config.ignored_patterns.clone(),
+                workspace: workspace.clone(),
             }),
         );
         tools.insert(
             "edit_file".to_string(),
             Arc::new(EditFileTool {
-                ignored_patterns: config.ignored_patterns.clone(),
+                workspace: workspace.clone(),
             }),
         );
         tools.insert(
             "replace_text".to_string(),
             Arc::new(ReplaceTextTool {
-                ignored_patterns: config.ignored_patterns.clone(),
+                workspace: workspace.clone(),
+            }),
+        );
+        tools.insert(
+            "apply_patch".to_string(),
+            Arc::new(ApplyPatchTool {
+                workspace: workspace.clone(),
             }),
         );
         tools.insert(
             "delete_file".to_string(),
             Arc::new(DeleteFileTool {
-                ignored_patterns: config.ignored_patterns.clone(),
+                workspace: workspace.clone(),
             }),
         );
         tools.insert(
             "find_symbol".to_string(),
             Arc::new(SymbolSearchTool {
-                ignored_patterns: config.ignored_patterns.clone(),
+                workspace: workspace.clone(),
             }),
         );
 
@@ -422,6 +520,7 @@ impl<'a> App<'a> {
                 ignored_patterns: config.ignored_patterns.clone(),
                 knowledge_bases: config.knowledge_bases.clone(),
                 status_tx: Some(status_tx.clone()),
+                session_collections: session_collections.clone(),
             }),
         );
         tools.insert(
@@ -448,42 +547,35 @@ impl<'a> App<'a> {
                 rag: shared_rag.clone(),
             }),
         );
+        tools.insert(
+            "forget".to_string(),
+            Arc::new(ForgetTool {
+                rag: shared_rag.clone(),
+            }),
+        );
+        tools.insert(
+            "list_memories".to_string(),
+            Arc::new(ListMemoriesTool {
+                rag: shared_rag.clone(),
+            }),
+        );
+        tools.insert(
+            "update_memory".to_string(),
+            Arc::new(UpdateMemoryTool {
+                rag: shared_rag.clone(),
+            }),
+        );
+        tools.insert(
+            "manage_memory".to_string(),
+            Arc::new(ManageMemoryTool {
+                rag: shared_rag.clone(),
+                status_tx: Some(status_tx.clone()),
+            }),
+        );
         tools.insert(
             "run_command".to_string(),
             Arc::new(RunCommandTool {
-                allowed_commands: vec![
-                    "git".to_string(),
-                    "ls".to_string(),
-                    "grep".to_string(),
-                    "rg".to_string(),
-                    "find".to_string(),
-                    "cargo".to_string(),
-                    "mkdir".to_string(),
-                    "rmdir".to_string(),
-                    "touch".to_string(),
-                    "pwd".to_string(),
-                    "date".to_string(),
-                    "echo".to_string(),
-                    "mv".to_string(),
-                    "cp".to_string(),
-                    "stat".to_string(),
-                    "curl".to_string(),
-                    "wget".to_string(),
-                    "jq".to_string(),
-                    "sed".to_string(),
-                    "awk".to_string(),
-                    "python3".to_string(),
-                    "node".to_string(),
-                    "tree".to_string(),
-                    "du".to_string(),
-                    "chmod".to_string(),
-                    "brew".to_string(),
-                    "uv".to_string(),
-                    "which".to_string(),
-                    "cat".to_string(),
-                    "head".to_string(),
-                    "tail".to_string(),
-                ],
+                allowed_commands: config.permissions.commands.clone(),
                 process_tracker: process_tracker.clone(),
             }),
         );
@@ -538,12 +630,25 @@ impl<'a> App<'a> {
             available_sessions: Vec::new(),
             session_list_state: ratatui::widgets::ListState::default(),
             session_input: TextArea::default(),
+            session_collections,
+            available_collections: Vec::new(),
+            collection_list_state: ratatui::widgets::ListState::default(),
+            checkpoints: Arc::new(CheckpointStore::default()),
+            checkpoint_turns: Vec::new(),
+            checkpoint_list_state: ratatui::widgets::ListState::default(),
             pull_input: TextArea::default(),
             pull_progress: None,
             tools,
             consecutive_tool_calls: 0,
             pending_tool_call: None,
+            tool_preview: None,
+            tool_edit_input: TextArea::default(),
+            queued_tool_calls: VecDeque::new(),
+            permissions: config.permissions.clone(),
+            session_permissions: HashMap::new(),
             is_tool_executing: false,
+            running_tools: HashMap::new(),
+            tool_progress: None,
             last_save_time: std::time::Instant::now(),
             context_manager: ContextManager::new(
                 config.auto_context,
@@ -562,12 +667,7 @@ impl<'a> App<'a> {
             notification: None,
             theme: crate::theme::Theme::default(),
             process_tracker,
-            rag: crate::rag::RagSystem::new(
-                OllamaClient::new(config.ollama_url.clone(), config.api_type.clone(), config.api_key.clone()),
-                config.embedding_model.clone(),
-                vector_index.clone(),
-                storage_path,
-            ),
+            rag,
             vector_index,
             session_manager,
             max_consecutive_tool_calls: config.max_consecutive_tool_calls,
@@ -576,6 +676,8 @@ impl<'a> App<'a> {
             enable_session_autonaming: config.enable_session_autonaming,
             monologue_parser: Some(crate::monologue::MonologueParser::new()),
             health_status,
+            persist_session: true,
+            kb_watcher: None,
         };
 
         if load_history {
@@ -904,10 +1006,30 @@ impl<'a> App<'a> {
         }
     }
 
+    /// Fills the collection menu with the indexed collections and any this
+    /// session has enabled.
+    fn list_collections(&mut self) {
+        let mut names: BTreeSet<String> = match self.rag.collection_stats() {
+            Ok(stats) => stats.into_iter().map(|s| s.name).collect(),
+            Err(e) => {
+                info!("Failed to list collections: {}", e);
+                BTreeSet::new()
+            }
+        };
+        names.extend(self.retrieval_scope().unwrap_or_default());
+        self.available_collections = names.into_iter().collect();
+    }
+
     fn save_session(&self) {
+        if !self.persist_session {
+            return;
+        }
         if let Some(path) = self.get_session_path(&self.current_session) {
-            let messages_clone = self.messages.clone();
-            self.session_manager.save_session(path, messages_clone);
+            let session = SessionFile {
+                messages: self.messages.clone(),
+                collections: self.session_collections.lock().unwrap().clone(),
+            };
+            self.session_manager.save_session(path, session);
         }
     }
     
@@ -925,17 +1047,317 @@ impl<'a> App<'a> {
         }
     }
 
+    /// Switches the current session name.
+    fn set_current_session(&mut self, name: String) {
+        self.current_session = name;
+    }
+
+    /// Where the checkpoints of session `name` are saved.
+    fn get_checkpoint_path(&self, name: &str) -> Option<PathBuf> {
+        if !self.persist_session {
+            return None;
+        }
+        self.get_sessions_dir()
+            .map(|dir| dir.join("checkpoints").join(format!("{}.json", name)))
+    }
+
+    /// Switches to the checkpoints of the current session.
+    fn open_checkpoints(&mut self) {
+        self.checkpoints = Arc::new(CheckpointStore::open(self.get_checkpoint_path(&self.current_session)));
+    }
+
+    /// Reports the files an undo restored.
+    fn finish_undo(&mut self, result: anyhow::Result<Vec<PathBuf>>) {
+        match result {
+            Ok(files) if files.is_empty() => {
+                self.notification = Some(("Nothing to undo".to_string(), std::time::Instant::now()));
+            }
+            Ok(files) => {
+                let names: Vec<String> = files.iter().map(|f| f.display().to_string()).collect();
+                info!("Undid edits to {}", names.join(", "));
+                self.notification = Some((format!("Restored {}", names.join(", ")), std::time::Instant::now()));
+            }
+            Err(e) => self.error = Some(format!("Undo failed: {:#}", e)),
+        }
+        self.checkpoint_turns = self.checkpoints.turns().into_iter().rev().collect();
+    }
+
+    /// Handles `/undo [N]` and `/checkpoints`. Returns false for any other
+    /// input, which is sent as a message.
+    fn run_slash_command(&mut self, input: &str) -> bool {
+        let mut words = input.split_whitespace();
+        match (words.next(), words.next(), words.next()) {
+            (Some("/undo"), None, None) => {
+                let _ = self.action_tx.send(Action::UndoEdits(1));
+            }
+            (Some("/undo"), Some(count), None) => match count.parse::<usize>() {
+                Ok(count) if count > 0 => {
+                    let _ = self.action_tx.send(Action::UndoEdits(count));
+                }
+                _ => self.error = Some("Usage: /undo [number of edits]".to_string()),
+            },
+            (Some("/checkpoints"), None, None) => {
+                let _ = self.action_tx.send(Action::EnterCheckpointList);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Position of the latest assistant message with tool calls.
+    fn tool_turn_index(&self) -> Option<usize> {
+        self.messages.iter().rposition(|m| m.role == "assistant" && m.tool_calls.is_some())
+    }
+
+    /// The call with id `id` in the latest assistant message with tool calls.
+    fn find_tool_call(&self, id: &str) -> Option<ToolCall> {
+        let turn = &self.messages[self.tool_turn_index()?];
+        turn.tool_calls.as_ref()?.iter().find(|c| c.id.as_deref() == Some(id)).cloned()
+    }
+
+    /// Whether call `id` of the latest tool turn already has a result.
+    fn is_tool_call_answered(&self, id: &str) -> bool {
+        self.tool_turn_index().is_some_and(|turn| {
+            self.messages[turn + 1..].iter().any(|m| m.role == "tool" && m.tool_call_id.as_deref() == Some(id))
+        })
+    }
+
+    /// Records a tool call requested by the model on the last assistant
+    /// message. Calls run once the response is complete.
+    fn record_tool_call(&mut self, mut tool_call: ToolCall) {
+        // Increment consecutive tool call counter
+        self.consecutive_tool_calls += 1;
+        if let Some(last) = self.messages.last_mut() {
+            if last.role == "assistant" {
+                // Results are matched to calls by id, and not every backend
+                // sends one.
+                if tool_call.id.is_none() {
+                    tool_call.id = Some(format!("call_{:08x}", rand::random::<u32>()));
+                }
+                // Visual feedback
+                last.content
+                    .push_str(&format!("\n> **Tool Call:** `{}`", tool_call.function.name));
+                last.tool_calls.get_or_insert_with(Vec::new).push(tool_call);
+
+                // Recalculate token usage
+                self.current_token_usage = ContextManager::estimate_token_count(&self.messages);
+            }
+        }
+    }
+
+    /// Starts the tool calls of the assistant turn that just completed:
+    /// allowed calls to read-only tools run concurrently, while calls that
+    /// need confirmation or change something are queued and run one after
+    /// another in call order. Each result arrives as `AddToolOutput`.
+    fn dispatch_tool_calls(&mut self) {
+        let calls = match self.messages.last() {
+            Some(last) if last.role == "assistant" => last.tool_calls.clone().unwrap_or_default(),
+            _ => return,
+        };
+        for call in calls {
+            let id = call.id.clone().unwrap_or_default();
+            if self.running_tools.contains_key(&id) || self.is_tool_call_answered(&id) {
+                continue;
+            }
+            let name = call.function.name.clone();
+            match self.tools.get(&name).cloned() {
+                Some(tool) => match self.tool_decision(&call, tool.as_ref()) {
+                    Decision::Allow if !tool.requires_confirmation() => self.spawn_tool(tool, call),
+                    Decision::Allow | Decision::Ask => self.queued_tool_calls.push_back(call),
+                    Decision::Deny => {
+                        let output = format!("Tool call denied: `{}` is not permitted by the [permissions] policy.", name);
+                        let _ = self.action_tx.send(Action::AddToolOutput(id, name, output));
+                    }
+                },
+                None => {
+                    let _ = self.action_tx.send(Action::AddToolOutput(id, name, "Tool not found".to_string()));
+                }
+            }
+        }
+        self.confirm_next_tool_call();
+    }
+
+    /// The permission policy's decision for `call`, with `Ask` settled by a
+    /// decision remembered for the session.
+    fn tool_decision(&self, call: &ToolCall, tool: &dyn Tool) -> Decision {
+        let name = &call.function.name;
+        let paths = tool.target_paths(&call.function.arguments);
+        match self.permissions.decide(name, &call.function.arguments, paths.as_deref(), tool.requires_confirmation()) {
+            Decision::Ask => self.remembered_decision(call).unwrap_or(Decision::Ask),
+            decision => decision,
+        }
+    }
+
+    /// The decision remembered this session for calls like `call`, if any.
+    fn remembered_decision(&self, call: &ToolCall) -> Option<Decision> {
+        self.session_permissions.get(&self.session_key(call)?).copied()
+    }
+
+    /// The key a decision about `call` is remembered under, if it may be.
+    fn session_key(&self, call: &ToolCall) -> Option<String> {
+        let args = &call.function.arguments;
+        let paths = self.tools.get(&call.function.name).and_then(|tool| tool.target_paths(args));
+        self.permissions.session_key(&call.function.name, args, paths.as_deref())
+    }
+
+    /// Starts or shows the next queued call once no call that changes
+    /// something is running, so edits never race each other. Allowed calls
+    /// run, calls denied by a decision remembered meanwhile are answered,
+    /// and the first call that still needs the user goes to the
+    /// confirmation dialog.
+    fn confirm_next_tool_call(&mut self) {
+        self.pending_tool_call = None;
+        while !self.is_mutating_tool_running() {
+            let Some(call) = self.queued_tool_calls.pop_front() else {
+                break;
+            };
+            let tool = self.tools.get(&call.function.name).cloned();
+            match tool.as_ref().map(|tool| self.tool_decision(&call, tool.as_ref())) {
+                Some(Decision::Allow) => self.spawn_tool(tool.unwrap(), call),
+                Some(Decision::Deny) => self.deny_tool_call(call),
+                _ => {
+                    self.pending_tool_call = Some(call);
+                    break;
+                }
+            }
+        }
+        self.refresh_tool_preview();
+        if self.pending_tool_call.is_some() {
+            self.mode = Mode::ToolConfirmation;
+            self.tool_scroll = 0; // Reset scroll
+        } else if matches!(self.mode, Mode::ToolConfirmation | Mode::ToolEdit) {
+            self.mode = Mode::Insert;
+        }
+    }
+
+    /// Whether a running call may change files or run commands.
+    fn is_mutating_tool_running(&self) -> bool {
+        self.running_tools
+            .values()
+            .any(|(name, _)| self.tools.get(name).is_none_or(|tool| tool.requires_confirmation()))
+    }
+
+    /// Recomputes the diff shown for the pending call.
+    fn refresh_tool_preview(&mut self) {
+        self.tool_preview = self.pending_tool_call.as_ref().and_then(|call| {
+            let tool = self.tools.get(&call.function.name)?;
+            let changes = tool.preview(&call.function.arguments)?;
+            Some(changes.map(|changes| changes.iter().map(|c| c.unified_diff()).collect()).map_err(|e| format!("{:#}", e)))
+        });
+    }
+
+    /// Answers a call the user declined.
+    fn deny_tool_call(&self, call: ToolCall) {
+        let _ = self.action_tx.send(Action::AddToolOutput(
+            call.id.unwrap_or_default(),
+            call.function.name,
+            "Tool execution denied by user.".to_string(),
+        ));
+    }
+
+    /// Where call `id` of the latest tool turn saves files before editing
+    /// them. Its turn is named after the turn's first call.
+    fn checkpointer(&self, id: &str) -> Checkpointer {
+        let turn_index = self.tool_turn_index();
+        let turn = turn_index
+            .and_then(|i| self.messages[i].tool_calls.as_ref()?.first()?.id.clone())
+            .unwrap_or_else(|| id.to_string());
+        let prompt = self.messages[..turn_index.unwrap_or(self.messages.len())]
+            .iter()
+            .rev()
+            .find(|m| m.role == "user")
+            .map(|m| m.content.lines().next().unwrap_or_default().chars().take(80).collect())
+            .unwrap_or_default();
+        Checkpointer {
+            store: self.checkpoints.clone(),
+            call_id: id.to_string(),
+            turn,
+            prompt,
+        }
+    }
+
+    /// Runs a tool call in the background. Its output arrives as
+    /// `AddToolOutput` and its progress as `ToolProgress`; `CancelGeneration`
+    /// stops it.
+    fn spawn_tool(&mut self, tool: Arc<dyn Tool>, call: ToolCall) {
+        let id = call.id.unwrap_or_default();
+        let name = call.function.name;
+        let args = call.function.arguments;
+        let (progress_tx, mut progress_rx) = mpsc::unbounded_channel::<String>();
+        let workspace = env::current_dir().unwrap_or_default();
+        let ctx = ToolContext {
+            progress: Some(progress_tx),
+            checkpoints: Some(self.checkpointer(&id)),
+            ..ToolContext::new(self.current_session.clone(), workspace)
+        };
+        self.running_tools.insert(id.clone(), (name.clone(), ctx.cancel.clone()));
+        self.tool_progress = None;
+        self.is_tool_executing = true;
+
+        let tx = self.action_tx.clone();
+        tokio::spawn(async move {
+            while let Some(line) = progress_rx.recv().await {
+                let _ = tx.send(Action::ToolProgress(line));
+            }
+        });
+
+        let tx = self.action_tx.clone();
+        tokio::spawn(async move {
+            let result = tokio::time::timeout(
+                std::time::Duration::from_secs(120),
+                tool.execute(args, &ctx),
+            ).await;
+            if let Some(checkpoints) = &ctx.checkpoints {
+                if let Err(e) = checkpoints.seal() {
+                    warn!("Failed to seal checkpoints of {}: {}", id, e);
+                }
+            }
+            // CancelGeneration has already answered a cancelled call.
+            if ctx.cancel.is_cancelled() {
+                return;
+            }
+            let output = match result {
+                Ok(Ok(s)) => s,
+                Ok(Err(e)) => format!("Tool error: {}", e),
+                Err(_) => "Tool timed out after 120 seconds. Try a more specific search path (e.g., ~/Documents instead of ~).".to_string(),
+            };
+            let _ = tx.send(Action::AddToolOutput(id, name, output));
+        });
+    }
+
+    /// Collections this session retrieves from, `None` meaning all.
+    pub fn retrieval_scope(&self) -> Option<Vec<String>> {
+        self.session_collections
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|set| set.iter().cloned().collect())
+    }
+
+    /// Status bar text for the retrieval scope, e.g. `all`, `work, notes` or `none`.
+    pub fn collections_label(&self) -> String {
+        match self.retrieval_scope() {
+            None => "all".to_string(),
+            Some(names) if names.is_empty() => "none".to_string(),
+            Some(names) => names.join(", "),
+        }
+    }
+
     fn load_session(&mut self, name: &str) {
-        self.current_session = name.to_string();
+        self.set_current_session(name.to_string());
+        self.open_checkpoints();
         self.messages.clear();
+        *self.session_collections.lock().unwrap() = None;
+        self.session_permissions.clear();
         self.vertical_scroll = 0;
         self.current_response_buffer.clear();
 
         if let Some(path) = self.get_session_path(name) {
             if path.exists() {
                 if let Ok(content) = fs::read_to_string(path) {
-                    if let Ok(messages) = serde_json::from_str(&content) {
-                        self.messages = messages;
+                    if let Ok(session) = SessionFile::parse(&content) {
+                        self.messages = session.messages;
+                        *self.session_collections.lock().unwrap() = session.collections;
                         if !self.messages.is_empty() {
                             self.auto_scroll = true;
                         }
@@ -947,18 +1369,22 @@ impl<'a> App<'a> {
     }
 
     pub fn load_session_from_file(&mut self, path: PathBuf) -> Result<(), String> {
-        self.current_session = path.file_stem()
+        self.set_current_session(path.file_stem()
             .and_then(|s| s.to_str())
             .unwrap_or("custom")
-            .to_string();
+            .to_string());
+        self.open_checkpoints();
         self.messages.clear();
+        *self.session_collections.lock().unwrap() = None;
+        self.session_permissions.clear();
         self.vertical_scroll = 0;
         self.current_response_buffer.clear();
         
         if path.exists() {
              let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
-             let messages = serde_json::from_str(&content).map_err(|e| e.to_string())?;
-             self.messages = messages;
+             let session = SessionFile::parse(&content).map_err(|e| e.to_string())?;
+             self.messages = session.messages;
+             *self.session_collections.lock().unwrap() = session.collections;
              if !self.messages.is_empty() {
                  self.auto_scroll = true;
              }
@@ -1021,16 +1447,20 @@ impl<'a> App<'a> {
             // So cloning RagSystem is cheap.
             let query_clone = query.clone();
             let tx = self.action_tx.clone();
+            let chat_model = self.models.get(self.selected_model).cloned().unwrap_or_default();
+            let scope = self.retrieval_scope();
             
             // We spawn the search. The generation will start when RagContextReady is received.
             tokio::spawn(async move {
-                // Limit search to 3 results
-                // Search all collections (None) for general chat context
-                match rag_arc.search(&query_clone, 3, None).await {
+                // Top hits (reranked when configured) from the session's collections
+                match rag_arc.retrieve_context(&query_clone, scope.as_deref(), &chat_model).await {
                     Ok(results) => {
                          info!("RAG search complete. Found {} results", results.len());
                          if !results.is_empty() {
-                             let context = format!("\n\n[Relevant Context from Tools]:\n{}", results.join("\n---\n"));
+                             let citations: Vec<String> = results.iter().map(|hit| hit.citation()).collect();
+                             let _ = tx.send(Action::ShowStatus(format!("Using context from {}", citations.join(", "))));
+                             let blocks: Vec<String> = results.iter().map(|hit| hit.to_string()).collect();
+                             let context = format!("\n\n[Relevant Context from Tools]:\n{}", blocks.join("\n---\n"));
                              let _ = tx.send(Action::RagContextReady(Some(context)));
                          } else {
                              let _ = tx.send(Action::RagContextReady(None));
@@ -1151,12 +1581,15 @@ impl<'a> App<'a> {
                 if content.trim().is_empty() {
                     return true;
                 }
+                let is_command = self.run_slash_command(content.trim());
                 // Clear input
                 self.input = TextArea::default();
                 self.input.set_cursor_line_style(Style::default());
                 self.input.set_placeholder_text("Type a message...");
 
-                let _ = self.action_tx.send(Action::AddUserMessage(content));
+                if !is_command {
+                    let _ = self.action_tx.send(Action::AddUserMessage(content));
+                }
                 true
             }
             Action::AddUserMessage(msg) => {
@@ -1268,7 +1701,7 @@ impl<'a> App<'a> {
 
                 // HEURISTIC: Check for embedded JSON tool calls (common in open-weights models)
                 // Pattern: {"tool":"name","arguments":{...}}
-                let mut found_embedded_tool = false;
+                let mut embedded_tool = None;
                 if let Some(last) = self.messages.last_mut() {
                     if last.role == "assistant" && last.tool_calls.is_none() {
                         // Simple scan for the pattern
@@ -1315,8 +1748,6 @@ impl<'a> App<'a> {
                                          }
                                      };
                                      
-                                     // Add to message
-                                     last.tool_calls = Some(vec![tc.clone()]);
                                      
                                      // Clean up content? 
                                      // Usually better to keep it visible or strip it. 
@@ -1332,16 +1763,21 @@ impl<'a> App<'a> {
                                      // We can't easily call "AddToolCall" action from here without cloning tc and sending action.
                                      // But we are mutable here.
                                      
-                                     // Let's trigger the action to handle execution logic (consecutive count, confirmation, etc)
-                                     let _ = self.action_tx.send(Action::AddToolCall(tc));
-                                     found_embedded_tool = true;
+                                     // Record it like a streamed call so it runs below.
+                                     embedded_tool = Some(tc);
                                  }
                              }
                         }
                     }
                 }
 
+                let found_embedded_tool = embedded_tool.is_some();
+                if let Some(tc) = embedded_tool {
+                    self.record_tool_call(tc);
+                }
+
                 self.loading = false;
+                self.dispatch_tool_calls();
                 self.save_session();
                 
                 // Auto-Rename Session if it's the first exchange in "default"
@@ -1366,6 +1802,35 @@ impl<'a> App<'a> {
                          last.content.push_str("\n[Cancelled]");
                     }
                 }
+                // Stop running tools and answer every open call, so the
+                // conversation stays valid without asking for a reply.
+                let mut open_calls: Vec<(String, String)> = self.running_tools.drain()
+                    .map(|(id, (name, cancel))| {
+                        cancel.cancel();
+                        (id, name)
+                    })
+                    .collect();
+                open_calls.extend(
+                    self.pending_tool_call.take().into_iter()
+                        .chain(self.queued_tool_calls.drain(..))
+                        .map(|c| (c.id.unwrap_or_default(), c.function.name)),
+                );
+                for (id, name) in open_calls {
+                    self.messages.push(ChatMessage {
+                        role: "tool".to_string(),
+                        content: "Tool execution cancelled by user.".to_string(),
+                        images: None,
+                        tool_calls: None,
+                        tool_name: Some(name),
+                        tool_call_id: Some(id),
+                        thought: None,
+                    });
+                }
+                if self.mode == Mode::ToolConfirmation {
+                    self.mode = Mode::Insert;
+                }
+                self.is_tool_executing = false;
+                self.tool_progress = None;
                 self.save_session();
                 true
             }
@@ -1412,151 +1877,176 @@ impl<'a> App<'a> {
     async fn update_tools(&mut self, action: Action) -> bool {
         match action {
             Action::AddToolCall(tool_call) => {
-                // Increment consecutive tool call counter
-                self.consecutive_tool_calls += 1;
-                if let Some(last) = self.messages.last_mut() {
-                    if last.role == "assistant" {
-                        if last.tool_calls.is_none() {
-                            last.tool_calls = Some(Vec::new());
-                        }
-                        if let Some(calls) = &mut last.tool_calls {
-                            calls.push(tool_call.clone());
-                        }
-                        // Visual feedback
-                        last.content
-                            .push_str(&format!("\n> **Tool Call:** `{}`", tool_call.function.name));
-
-                        // Recalculate token usage
-                        self.current_token_usage = ContextManager::estimate_token_count(&self.messages);
-
-                        // EXECUTE TOOL
-                        let tool_name = tool_call.function.name.clone();
-                        let tool_args = tool_call.function.arguments.clone();
-
-                        if let Some(tool) = self.tools.get(&tool_name) {
-                            // Check if confirmation is needed
-                            if tool.requires_confirmation() {
-                                self.pending_tool_call = Some(tool_call.clone());
-                                self.mode = Mode::ToolConfirmation;
-                                self.tool_scroll = 0; // Reset scroll
-                                self.loading = false; // Stop loading spinner while waiting for user
-                                self.is_tool_executing = false;
-                                return true;
-                            }
-                            
-                            self.is_tool_executing = true;
-
-                            let tx = self.action_tx.clone();
-                            let tool_arc = tool.clone();
-
-                            tokio::spawn(async move {
-                                // Use spawn_blocking for the synchronous tool execution
-                                // with a 30-second timeout to prevent hanging
-                                let tool_clone = tool_arc.clone();
-                                let args_clone = tool_args.clone();
-                                
-                                let result = tokio::time::timeout(
-                                    std::time::Duration::from_secs(120),
-                                    tokio::task::spawn_blocking(move || {
-                                        tool_clone.execute(args_clone)
-                                    })
-                                ).await;
-                                
-                                let output = match result {
-                                    Ok(Ok(Ok(s))) => s,
-                                    Ok(Ok(Err(e))) => format!("Tool error: {}", e),
-                                    Ok(Err(e)) => format!("Tool execution failed: {}", e),
-                                    Err(_) => "Tool timed out after 120 seconds. Try a more specific search path (e.g., ~/Documents instead of ~).".to_string(),
-                                };
-                                let _ = tx.send(Action::AddToolOutput(tool_name, output));
-                            });
-                        } else {
-                            let _ = self.action_tx.send(Action::AddToolOutput(
-                                tool_name,
-                                "Tool not found".to_string(),
-                            ));
-                        }
-                    }
-                }
+                self.record_tool_call(tool_call);
                 self.save_session();
                 true
             }
             Action::ConfirmToolExecution => {
                 if let Some(tool_call) = self.pending_tool_call.take() {
-                    let tool_name = tool_call.function.name.clone();
-                    let tool_args = tool_call.function.arguments.clone();
-                    
-                    self.loading = true; // Resume loading
-                    self.is_tool_executing = true;
-                    self.mode = Mode::Insert;
-
-                    if let Some(tool) = self.tools.get(&tool_name) {
-                        let tx = self.action_tx.clone();
-                        let tool_arc = tool.clone();
-                         tokio::spawn(async move {
-                            let tool_clone = tool_arc.clone();
-                            let args_clone = tool_args.clone();
-                            
-                            let result = tokio::time::timeout(
-                                std::time::Duration::from_secs(120),
-                                tokio::task::spawn_blocking(move || {
-                                    tool_clone.execute(args_clone)
-                                })
-                            ).await;
-                            
-                            let output = match result {
-                                Ok(Ok(Ok(s))) => s,
-                                Ok(Ok(Err(e))) => format!("Tool error: {}", e),
-                                Ok(Err(e)) => format!("Tool execution failed: {}", e),
-                                Err(_) => "Tool timed out.".to_string(),
-                            };
-                            let _ = tx.send(Action::AddToolOutput(tool_name, output));
-                        });
+                    if let Some(tool) = self.tools.get(&tool_call.function.name).cloned() {
+                        self.spawn_tool(tool, tool_call);
                     }
+                    self.confirm_next_tool_call();
                 }
                 true
             }
             Action::DenyToolExecution => {
                 if let Some(tool_call) = self.pending_tool_call.take() {
-                     let _ = self.action_tx.send(Action::AddToolOutput(
-                        tool_call.function.name,
-                        "Tool execution denied by user.".to_string(),
-                    ));
+                    self.deny_tool_call(tool_call);
+                    self.confirm_next_tool_call();
+                }
+                true
+            }
+            Action::RememberToolDecision(decision) => {
+                if let Some(tool_call) = self.pending_tool_call.take() {
+                    // Calls that must be confirmed every time are only
+                    // settled once.
+                    if let Some(key) = self.session_key(&tool_call) {
+                        self.session_permissions.insert(key, decision);
+                    }
+                    match self.tools.get(&tool_call.function.name).cloned() {
+                        Some(tool) if decision == Decision::Allow => self.spawn_tool(tool, tool_call),
+                        _ => self.deny_tool_call(tool_call),
+                    }
+                    self.confirm_next_tool_call();
+                }
+                true
+            }
+            Action::EditToolContent => {
+                let Some(call) = &self.pending_tool_call else {
+                    return true;
+                };
+                let Some(key) = self.tools.get(&call.function.name).and_then(|t| t.editable_argument()) else {
+                    return true;
+                };
+                let content = call.function.arguments.get(key).and_then(|v| v.as_str()).unwrap_or_default();
+                self.tool_edit_input = TextArea::new(content.lines().map(|s| s.to_string()).collect());
+                self.tool_edit_input.set_cursor_line_style(Style::default());
+                self.mode = Mode::ToolEdit;
+                true
+            }
+            Action::UpdateToolContent(content) => {
+                if let Some(call) = self.pending_tool_call.as_mut() {
+                    if let Some(key) = self.tools.get(&call.function.name).and_then(|t| t.editable_argument()) {
+                        if let Some(args) = call.function.arguments.as_object_mut() {
+                            // The editor works in lines, so it drops a final
+                            // newline; keep the model's.
+                            let had_newline = args.get(key).and_then(|v| v.as_str()).is_some_and(|v| v.ends_with('\n'));
+                            let content = if had_newline && !content.ends_with('\n') { content + "\n" } else { content };
+                            args.insert(key.to_string(), serde_json::Value::String(content));
+                        }
+                        // Keep the recorded call in step, so the history shows
+                        // what actually ran.
+                        let (id, arguments) = (call.id.clone(), call.function.arguments.clone());
+                        if let Some(turn) = self.tool_turn_index() {
+                            if let Some(recorded) = self.messages[turn]
+                                .tool_calls
+                                .iter_mut()
+                                .flatten()
+                                .find(|c| c.id.is_some() && c.id == id)
+                            {
+                                recorded.function.arguments = arguments;
+                            }
+                        }
+                        self.save_session();
+                    }
+                }
+                self.refresh_tool_preview();
+                self.tool_scroll = 0;
+                if self.pending_tool_call.is_some() {
+                    self.mode = Mode::ToolConfirmation;
+                }
+                true
+            }
+            Action::UndoEdits(count) => {
+                let result = self.checkpoints.undo_last(count);
+                self.finish_undo(result);
+                true
+            }
+            Action::UndoSinceMessage(index) => {
+                // Edits belong to the tool calls from that message on.
+                let calls: HashSet<String> = self.messages[index.min(self.messages.len())..]
+                    .iter()
+                    .flat_map(|m| m.tool_calls.iter().flatten())
+                    .filter_map(|c| c.id.clone())
+                    .collect();
+                let result = self.checkpoints.undo_since(|c| calls.contains(&c.call_id));
+                self.finish_undo(result);
+                true
+            }
+            Action::UndoTurn(turn) => {
+                let result = self.checkpoints.undo_since(|c| c.turn == turn);
+                self.finish_undo(result);
+                if self.checkpoint_turns.is_empty() {
                     self.mode = Mode::Insert;
-                    self.is_tool_executing = false;
+                } else {
+                    self.checkpoint_list_state.select(Some(0));
                 }
                 true
             }
-            Action::AddToolOutput(name, output) => {
-                self.is_tool_executing = false;
+            Action::EnterCheckpointList => {
+                self.checkpoint_turns = self.checkpoints.turns().into_iter().rev().collect();
+                if self.checkpoint_turns.is_empty() {
+                    self.notification = Some(("No edits to undo in this session".to_string(), std::time::Instant::now()));
+                } else {
+                    self.mode = Mode::CheckpointList;
+                    self.checkpoint_list_state.select(Some(0));
+                }
+                true
+            }
+            Action::ToolProgress(line) => {
+                if self.is_tool_executing {
+                    self.tool_progress = Some(line);
+                }
+                true
+            }
+            Action::AddToolOutput(id, name, output) => {
+                // Results of cancelled calls have already been answered.
+                if self.is_tool_call_answered(&id) {
+                    return true;
+                }
+                self.running_tools.remove(&id);
+                self.is_tool_executing = !self.running_tools.is_empty();
+                if !self.is_tool_executing {
+                    self.tool_progress = None;
+                }
+
+                let tool_call = self.find_tool_call(&id);
                 
                 // Spawn async ingestion
                 let output_clone = output.clone();
                 let rag_clone = Arc::new(self.rag.clone());
+                let provenance = crate::tools::Provenance {
+                    tool: name.clone(),
+                    args: tool_call.map(|c| c.function.arguments).unwrap_or_default(),
+                    session: Some(self.current_session.clone()),
+                    url: None,
+                };
                 
                 tokio::spawn(async move {
-                    let _ = rag_clone.add_text(&output_clone, Some("default".to_string())).await;
+                    let _ = rag_clone.add_tool_output(&output_clone, "default", provenance).await;
                 });
 
-                // Find matching tool call id from last assistant message
-                let tool_call_id = self.messages.iter().rev()
-                    .find(|m| m.role == "assistant" && m.tool_calls.is_some())
-                    .and_then(|m| {
-                        m.tool_calls.as_ref().and_then(|calls| {
-                            calls.iter().find(|c| c.function.name == name).and_then(|c| c.id.clone())
-                        })
-                    });
-
                 self.messages.push(ChatMessage {
                     role: "tool".to_string(),
                     content: output,
                     images: None,
                     tool_calls: None,
                     tool_name: Some(name),
-                    tool_call_id,
+                    tool_call_id: Some(id),
                     thought: None,
                 });
-                let _ = self.action_tx.send(Action::RequestAiResponse);
+                // Calls queued behind a finished edit can go now.
+                if self.pending_tool_call.is_none() && !self.queued_tool_calls.is_empty() {
+                    self.confirm_next_tool_call();
+                }
+                // Reply once every call of the turn has its result.
+                let turn_done = !self.is_tool_executing
+                    && self.pending_tool_call.is_none()
+                    && self.queued_tool_calls.is_empty();
+                if turn_done {
+                    let _ = self.action_tx.send(Action::RequestAiResponse);
+                }
                 self.save_session();
                 true
             }
@@ -1637,6 +2127,29 @@ impl<'a> App<'a> {
                 true
             }
             Action::SessionsLoaded(_) => true,
+            Action::EnterCollectionSelect => {
+                self.list_collections();
+                self.mode = Mode::CollectionSelect;
+                self.collection_list_state.select(Some(0));
+                true
+            }
+            Action::ToggleCollection(name) => {
+                {
+                    let mut enabled = self.session_collections.lock().unwrap();
+                    match name {
+                        None => *enabled = None,
+                        Some(name) => {
+                            // Turning one off while all are on keeps the rest enabled.
+                            let set = enabled.get_or_insert_with(|| self.available_collections.iter().cloned().collect());
+                            if !set.remove(&name) {
+                                set.insert(name);
+                            }
+                        }
+                    }
+                }
+                self.save_session();
+                true
+            }
             Action::TriggerAutoNaming => {
                 let messages_snapshot = self.messages.clone();
                 if messages_snapshot.is_empty() { return true; }
@@ -1706,7 +2219,14 @@ impl<'a> App<'a> {
                         
                         if let Ok(_) = fs::rename(&old_path, &new_path) {
                             // 2. Update state
-                            self.current_session = new_path.file_stem().unwrap().to_string_lossy().to_string();
+                            let new_name = new_path.file_stem().unwrap().to_string_lossy().to_string();
+                            if let (Some(old), Some(new)) = (self.get_checkpoint_path(&old_name), self.get_checkpoint_path(&new_name)) {
+                                if old.exists() {
+                                    let _ = fs::rename(old, new);
+                                }
+                            }
+                            self.set_current_session(new_name);
+                            self.open_checkpoints();
                             self.notification = Some((format!("Renamed session to: {}", self.current_session), std::time::Instant::now()));
                             self.list_sessions(); // Refresh list
                         } else {
@@ -1768,6 +2288,11 @@ impl<'a> App<'a> {
                 true
             }
             Action::EnterModelPull => {
+                let backend = self.ollama_client.backend();
+                if !backend.manages_models() {
+                    self.error = Some(crate::backend::Unsupported::new(backend.kind(), "Pulling models").to_string());
+                    return true;
+                }
                 self.mode = Mode::ModelPullInput;
                 self.pull_input = TextArea::default();
                 self.pull_input.set_block(
@@ -1830,6 +2355,11 @@ impl<'a> App<'a> {
                 true
             }
             Action::DeleteModel(name) => {
+                let backend = self.ollama_client.backend();
+                if !backend.manages_models() {
+                    self.error = Some(crate::backend::Unsupported::new(backend.kind(), "Deleting models").to_string());
+                    return true;
+                }
                 self.loading = true;
                 let client = self.ollama_client.clone();
                 let tx = self.action_tx.clone();
@@ -1964,8 +2494,8 @@ impl<'a> App<'a> {
                     return true;
                 }
 
-                // System Prompt Edit
-                if key.code == KeyCode::Char('s') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                // System Prompt Edit (Ctrl+s saves in the tool editor)
+                if key.code == KeyCode::Char('s') && key.modifiers.contains(KeyModifiers::CONTROL) && self.mode != Mode::ToolEdit {
                     let _ = self.action_tx.send(Action::EnterSystemPromptEdit);
                     return true;
                 }
@@ -1976,6 +2506,12 @@ impl<'a> App<'a> {
                     return true;
                 }
 
+                // Collections used for retrieval
+                if key.code == KeyCode::Char('g') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                    let _ = self.action_tx.send(Action::EnterCollectionSelect);
+                    return true;
+                }
+
                 if self.show_help {
                     match key.code {
                         KeyCode::Esc | KeyCode::Char('q') | KeyCode::F(1) => {
@@ -2006,7 +2542,7 @@ impl<'a> App<'a> {
                             } else if key.code == KeyCode::Char('c')
                                 && key.modifiers.contains(KeyModifiers::CONTROL)
                             {
-                                if self.loading {
+                                if self.loading || self.is_tool_executing {
                                    let _ = self.action_tx.send(Action::CancelGeneration);
                                 }
                             } else {
@@ -2026,6 +2562,16 @@ impl<'a> App<'a> {
                             KeyCode::Char('y') => {
                                 let _ = self.action_tx.send(Action::CopyMessage);
                             }
+                            KeyCode::Char('u') => {
+                                let action = match self.selected_message_index {
+                                    Some(index) => Action::UndoSinceMessage(index),
+                                    None => Action::UndoEdits(1),
+                                };
+                                let _ = self.action_tx.send(action);
+                            }
+                            KeyCode::Char('c') if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                                let _ = self.action_tx.send(Action::EnterCheckpointList);
+                            }
                             KeyCode::Char('j') | KeyCode::Down => {
                                 let _ = self.action_tx.send(Action::MoveSelection(1));
                             }
@@ -2037,7 +2583,7 @@ impl<'a> App<'a> {
                             }
                             KeyCode::F(1) => self.show_help = true,
                             KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                                if self.loading {
+                                if self.loading || self.is_tool_executing {
                                     let _ = self.action_tx.send(Action::CancelGeneration);
                                 }
                             }
@@ -2144,6 +2690,52 @@ impl<'a> App<'a> {
                         }
                         _ => {}
                     },
+                    Mode::CollectionSelect => match key.code {
+                        KeyCode::Esc | KeyCode::Enter => {
+                            let _ = self.action_tx.send(Action::SwitchMode(Mode::Insert));
+                        }
+                        KeyCode::Up | KeyCode::Char('k') => {
+                            // Row 0 is "All collections".
+                            let rows = self.available_collections.len() + 1;
+                            let i = self.collection_list_state.selected().map_or(0, |i| (i + rows - 1) % rows);
+                            self.collection_list_state.select(Some(i));
+                        }
+                        KeyCode::Down | KeyCode::Char('j') => {
+                            let rows = self.available_collections.len() + 1;
+                            let i = self.collection_list_state.selected().map_or(0, |i| (i + 1) % rows);
+                            self.collection_list_state.select(Some(i));
+                        }
+                        KeyCode::Char(' ') => {
+                            let name = match self.collection_list_state.selected() {
+                                Some(0) | None => None,
+                                Some(i) => self.available_collections.get(i - 1).cloned(),
+                            };
+                            let _ = self.action_tx.send(Action::ToggleCollection(name));
+                        }
+                        _ => {}
+                    },
+                    Mode::CheckpointList => match key.code {
+                        KeyCode::Esc | KeyCode::Char('q') => {
+                            let _ = self.action_tx.send(Action::SwitchMode(Mode::Insert));
+                        }
+                        KeyCode::Up | KeyCode::Char('k') => {
+                            let rows = self.checkpoint_turns.len().max(1);
+                            let i = self.checkpoint_list_state.selected().map_or(0, |i| (i + rows - 1) % rows);
+                            self.checkpoint_list_state.select(Some(i));
+                        }
+                        KeyCode::Down | KeyCode::Char('j') => {
+                            let rows = self.checkpoint_turns.len().max(1);
+                            let i = self.checkpoint_list_state.selected().map_or(0, |i| (i + 1) % rows);
+                            self.checkpoint_list_state.select(Some(i));
+                        }
+                        KeyCode::Enter | KeyCode::Char('u') => {
+                            let selected = self.checkpoint_list_state.selected().and_then(|i| self.checkpoint_turns.get(i));
+                            if let Some(turn) = selected {
+                                let _ = self.action_tx.send(Action::UndoTurn(turn.id.clone()));
+                            }
+                        }
+                        _ => {}
+                    },
                     Mode::SessionCreate => match key.code {
                         KeyCode::Esc => {
                             let _ = self.action_tx.send(Action::EnterSessionSelect);
@@ -2175,6 +2767,15 @@ impl<'a> App<'a> {
                         KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
                             let _ = self.action_tx.send(Action::DenyToolExecution);
                         }
+                        KeyCode::Char('a') | KeyCode::Char('A') => {
+                            let _ = self.action_tx.send(Action::RememberToolDecision(Decision::Allow));
+                        }
+                        KeyCode::Char('d') | KeyCode::Char('D') => {
+                            let _ = self.action_tx.send(Action::RememberToolDecision(Decision::Deny));
+                        }
+                        KeyCode::Char('e') | KeyCode::Char('E') => {
+                            let _ = self.action_tx.send(Action::EditToolContent);
+                        }
                         KeyCode::Up | KeyCode::Char('k') => {
                             let _ = self.action_tx.send(Action::Scroll(-1));
                         }
@@ -2183,6 +2784,18 @@ impl<'a> App<'a> {
                         }
                         _ => {}
                     },
+                    Mode::ToolEdit => match key.code {
+                        KeyCode::Esc => {
+                            let _ = self.action_tx.send(Action::SwitchMode(Mode::ToolConfirmation));
+                        }
+                        KeyCode::Char('s') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                            let content = self.tool_edit_input.lines().join("\n");
+                            let _ = self.action_tx.send(Action::UpdateToolContent(content));
+                        }
+                        _ => {
+                            self.tool_edit_input.input(Input::from(key));
+                        }
+                    },
                 }
                 true
             }
@@ -2523,6 +3136,78 @@ mod tests {
         assert_eq!(app.messages[0].content, "Generating\n[Cancelled]");
     }
 
+    #[tokio::test]
+    async fn test_cancel_generation_stops_running_tool() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let config = Config::new_test_config();
+        let mut app = App::init(tx, config, false, None).await;
+
+        let tool_call = ToolCall {
+            id: Some("call_1".to_string()),
+            type_: "function".to_string(),
+            function: crate::ollama::ToolCallFunction {
+                name: "run_command".to_string(),
+                arguments: serde_json::json!({"command": "echo", "args": ["started", ";", "sleep", "29.5", ";", "echo", "done"]}),
+            },
+        };
+        app.messages.push(ChatMessage {
+            role: "assistant".to_string(),
+            content: String::new(),
+            images: None,
+            tool_calls: Some(vec![tool_call.clone()]),
+            tool_name: None,
+            tool_call_id: None,
+            thought: None,
+        });
+        let tool: Arc<dyn Tool> = Arc::new(crate::tools::RunCommandTool {
+            allowed_commands: vec!["echo".to_string()],
+            process_tracker: Arc::new(crate::process::ProcessTracker::new()),
+        });
+        app.spawn_tool(tool, tool_call);
+        assert!(app.is_tool_executing);
+
+        // Wait for the command to start streaming.
+        let progress = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            loop {
+                if let Some(Action::ToolProgress(line)) = rx.recv().await {
+                    return line;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(progress, "started");
+        app.update(Action::ToolProgress(progress)).await;
+        assert_eq!(app.tool_progress.as_deref(), Some("started"));
+
+        app.update(Action::CancelGeneration).await;
+        assert!(!app.is_tool_executing);
+        assert!(app.running_tools.is_empty());
+        let last = app.messages.last().unwrap();
+        assert_eq!(last.role, "tool");
+        assert_eq!(last.tool_call_id.as_deref(), Some("call_1"));
+
+        // The cancelled tool reports nothing further.
+        let late = tokio::time::timeout(std::time::Duration::from_millis(500), rx.recv()).await;
+        assert!(!matches!(late, Ok(Some(Action::AddToolOutput(..)))));
+
+        // Nothing the shell started outlives it.
+        if cfg!(target_os = "linux") {
+            let sleeping = || {
+                std::fs::read_dir("/proc").unwrap().flatten().any(|entry| {
+                    std::fs::read(entry.path().join("cmdline")).is_ok_and(|cmdline| cmdline == b"sleep\029.5\0")
+                })
+            };
+            for _ in 0..50 {
+                if !sleeping() {
+                    break;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            }
+            assert!(!sleeping());
+        }
+    }
+
     #[tokio::test]
     async fn test_move_selection() {
         let (tx, _rx) = mpsc::unbounded_channel();
@@ -2629,7 +3314,7 @@ mod tests {
             thought: None,
         });
 
-        // 1. Add Tool Call -> Should set is_tool_executing = true
+        // 1. Tool calls run when the response completes
         let tool_call = ToolCall {
             id: None,
             type_: "function".to_string(),
@@ -2639,10 +3324,467 @@ mod tests {
             },
         };
         app.update(Action::AddToolCall(tool_call)).await;
-        assert!(app.is_tool_executing, "Should be executing tool after AddToolCall");
+        assert!(!app.is_tool_executing, "Should wait for the response to complete");
+        app.update(Action::AiResponseComplete).await;
+        assert!(app.is_tool_executing, "Should be executing tool after the response completes");
 
         // 2. Add Tool Output -> Should set is_tool_executing = false
-        app.update(Action::AddToolOutput("list_directory".to_string(), "file1".to_string())).await;
+        let id = app.messages[0].tool_calls.as_ref().unwrap()[0].id.clone().unwrap();
+        app.update(Action::AddToolOutput(id, "list_directory".to_string(), "file1".to_string())).await;
         assert!(!app.is_tool_executing, "Should NOT be executing tool after output received");
     }
+
+    #[tokio::test]
+    async fn test_permission_policy_and_session_decisions() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut config = Config::new_test_config();
+        config.permissions.tools.insert("delete_file".to_string(), Decision::Deny);
+        let mut app = App::init(tx, config, false, None).await;
+
+        app.messages.push(ChatMessage {
+            role: "assistant".to_string(),
+            content: String::new(),
+            images: None,
+            tool_calls: None,
+            tool_name: None,
+            tool_call_id: None,
+            thought: None,
+        });
+        for (name, path) in [("delete_file", "a.txt"), ("write_file", "b.txt"), ("write_file", "./b.txt"), ("write_file", "c.txt")] {
+            let call = ToolCall {
+                id: None,
+                type_: "function".to_string(),
+                function: crate::ollama::ToolCallFunction {
+                    name: name.to_string(),
+                    arguments: serde_json::json!({"path": path, "content": ""}),
+                },
+            };
+            app.update(Action::AddToolCall(call)).await;
+        }
+        app.update(Action::AiResponseComplete).await;
+
+        // The policy refuses the delete without asking; the first write asks.
+        assert_eq!(app.pending_tool_call.as_ref().unwrap().function.arguments["path"], "b.txt");
+        assert_eq!(app.queued_tool_calls.len(), 2);
+
+        // Denying for the session settles the queued write to the same
+        // file, but not the one to another file.
+        let key = app.session_key(app.pending_tool_call.as_ref().unwrap()).unwrap();
+        assert_eq!(key, format!("write_file {}", env::current_dir().unwrap().canonicalize().unwrap().join("b.txt").display()));
+        app.update(Action::RememberToolDecision(Decision::Deny)).await;
+        assert_eq!(app.pending_tool_call.as_ref().unwrap().function.arguments["path"], "c.txt");
+        assert_eq!(app.session_permissions.get(&key), Some(&Decision::Deny));
+        app.update(Action::DenyToolExecution).await;
+        assert_eq!(app.mode, Mode::Insert);
+        assert!(app.pending_tool_call.is_none() && app.queued_tool_calls.is_empty());
+
+        let mut outputs = Vec::new();
+        while let Ok(action) = rx.try_recv() {
+            if let Action::AddToolOutput(_, name, output) = action {
+                outputs.push((name, output));
+            }
+        }
+        assert_eq!(outputs.len(), 4);
+        assert!(outputs[0].0 == "delete_file" && outputs[0].1.contains("[permissions]"));
+        assert!(outputs[1..].iter().all(|(name, output)| name == "write_file" && output.contains("denied by user")));
+
+        // Switching sessions forgets the decision.
+        app.load_session("other");
+        assert!(app.session_permissions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_path_rules_see_every_file_a_patch_touches() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("secrets")).unwrap();
+        fs::write(root.join("secrets/key.txt"), "old\n").unwrap();
+        fs::write(root.join("notes.txt"), "a\n").unwrap();
+        let mut config = Config::new_test_config();
+        config.permissions.tools.insert("apply_patch".to_string(), Decision::Allow);
+        config.permissions.rules.push(crate::permissions::Rule {
+            tool: "*".to_string(),
+            command: None,
+            path: Some(format!("{}/secrets/**", root.display())),
+            decision: Decision::Deny,
+        });
+        let mut app = App::init(tx, config, false, None).await;
+        let root_str = root.to_str().unwrap();
+        app.tools.insert(
+            "apply_patch".to_string(),
+            Arc::new(crate::tools::ApplyPatchTool { workspace: Workspace::new(&[root_str], &[root_str], vec![]) }),
+        );
+
+        // The secret is reached through a link, alongside an allowed file.
+        #[cfg(unix)]
+        let secret = {
+            std::os::unix::fs::symlink(root.join("secrets"), root.join("docs")).unwrap();
+            root.join("docs/key.txt")
+        };
+        #[cfg(not(unix))]
+        let secret = root.join("secrets/key.txt");
+        let notes = root.join("notes.txt");
+        let patch = format!(
+            "--- {0}\n+++ {0}\n@@ -1 +1 @@\n-a\n+b\n--- {1}\n+++ {1}\n@@ -1 +1 @@\n-old\n+new\n",
+            notes.display(),
+            secret.display()
+        );
+
+        app.messages.push(ChatMessage {
+            role: "assistant".to_string(),
+            content: String::new(),
+            images: None,
+            tool_calls: None,
+            tool_name: None,
+            tool_call_id: None,
+            thought: None,
+        });
+        app.update(Action::AddToolCall(ToolCall {
+            id: None,
+            type_: "function".to_string(),
+            function: crate::ollama::ToolCallFunction {
+                name: "apply_patch".to_string(),
+                arguments: serde_json::json!({ "patch": patch }),
+            },
+        }))
+        .await;
+        app.update(Action::AiResponseComplete).await;
+
+        assert!(app.running_tools.is_empty() && app.pending_tool_call.is_none());
+        let output = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            loop {
+                if let Some(Action::AddToolOutput(_, _, output)) = rx.recv().await {
+                    return output;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert!(output.contains("[permissions]"), "{}", output);
+        assert_eq!(fs::read_to_string(root.join("secrets/key.txt")).unwrap(), "old\n");
+        assert_eq!(fs::read_to_string(&notes).unwrap(), "a\n");
+    }
+
+    #[tokio::test]
+    async fn test_remembered_command_does_not_cover_chained_commands() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let config = Config::new_test_config();
+        let mut app = App::init(tx, config, false, None).await;
+
+        app.messages.push(ChatMessage {
+            role: "assistant".to_string(),
+            content: String::new(),
+            images: None,
+            tool_calls: None,
+            tool_name: None,
+            tool_call_id: None,
+            thought: None,
+        });
+        for args in [vec!["status"], vec!["status", ";", "rm", "x"], vec!["log"]] {
+            app.update(Action::AddToolCall(ToolCall {
+                id: None,
+                type_: "function".to_string(),
+                function: crate::ollama::ToolCallFunction {
+                    name: "run_command".to_string(),
+                    arguments: serde_json::json!({"command": "git", "args": args}),
+                },
+            }))
+            .await;
+        }
+        app.update(Action::AiResponseComplete).await;
+        assert_eq!(app.pending_tool_call.as_ref().unwrap().function.arguments["args"], serde_json::json!(["status"]));
+
+        // Always allowing `git status` runs it but still asks about the
+        // chained call once it is done.
+        app.update(Action::RememberToolDecision(Decision::Allow)).await;
+        assert_eq!(app.session_permissions.get("run_command git status"), Some(&Decision::Allow));
+        assert_eq!(app.running_tools.len(), 1);
+        let output = tokio::time::timeout(std::time::Duration::from_secs(10), async {
+            loop {
+                if let Some(action @ Action::AddToolOutput(..)) = rx.recv().await {
+                    return action;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        app.update(output).await;
+        assert_eq!(app.mode, Mode::ToolConfirmation);
+        let pending = app.pending_tool_call.clone().unwrap();
+        assert_eq!(pending.function.arguments["args"], serde_json::json!(["status", ";", "rm", "x"]));
+
+        // Denying a chained call for the session settles it alone.
+        app.update(Action::RememberToolDecision(Decision::Deny)).await;
+        assert_eq!(app.session_permissions.len(), 1);
+        assert_eq!(app.pending_tool_call.unwrap().function.arguments["args"], serde_json::json!(["log"]));
+    }
+
+    #[tokio::test]
+    async fn test_tool_calls_in_one_turn_are_answered_by_id() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let config = Config::new_test_config();
+        let mut app = App::init(tx, config, false, None).await;
+
+        let dir_a = tempfile::tempdir().unwrap();
+        let dir_b = tempfile::tempdir().unwrap();
+        fs::write(dir_a.path().join("alpha.txt"), "a").unwrap();
+        fs::write(dir_b.path().join("beta.txt"), "b").unwrap();
+        let roots = [dir_a.path().to_str().unwrap(), dir_b.path().to_str().unwrap()];
+        app.tools.insert(
+            "list_directory".to_string(),
+            Arc::new(crate::tools::ListDirectoryTool { workspace: Workspace::new(&roots, &[], vec![]) }),
+        );
+
+        app.messages.push(ChatMessage {
+            role: "assistant".to_string(),
+            content: String::new(),
+            images: None,
+            tool_calls: None,
+            tool_name: None,
+            tool_call_id: None,
+            thought: None,
+        });
+        let call = |name: &str, args: serde_json::Value| ToolCall {
+            id: None,
+            type_: "function".to_string(),
+            function: crate::ollama::ToolCallFunction { name: name.to_string(), arguments: args },
+        };
+        app.update(Action::AddToolCall(call("list_directory", serde_json::json!({"path": dir_a.path()}))))
+            .await;
+        app.update(Action::AddToolCall(call("list_directory", serde_json::json!({"path": dir_b.path()}))))
+            .await;
+        app.update(Action::AddToolCall(call("write_file", serde_json::json!({"path": "x.txt", "content": "x"}))))
+            .await;
+        app.update(Action::AiResponseComplete).await;
+
+        // Both listings run while the write waits for confirmation.
+        assert_eq!(app.running_tools.len(), 2);
+        assert_eq!(app.mode, Mode::ToolConfirmation);
+        assert_eq!(app.pending_tool_call.as_ref().unwrap().function.name, "write_file");
+        app.update(Action::DenyToolExecution).await;
+        assert_eq!(app.mode, Mode::Insert);
+
+        let mut outputs = 0;
+        let mut requests = 0;
+        while outputs < 3 {
+            let action = tokio::time::timeout(std::time::Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+            match action {
+                Action::AddToolOutput(..) => {
+                    outputs += 1;
+                    app.update(action).await;
+                }
+                Action::RequestAiResponse => requests += 1,
+                _ => {}
+            }
+        }
+        while let Ok(action) = rx.try_recv() {
+            if action == Action::RequestAiResponse {
+                requests += 1;
+            }
+        }
+        assert_eq!(requests, 1, "Should ask for one reply after all results");
+
+        let calls = app.messages[0].tool_calls.clone().unwrap();
+        let result_for = |i: usize| {
+            app.messages.iter()
+                .find(|m| m.role == "tool" && m.tool_call_id == calls[i].id)
+                .map(|m| m.content.clone())
+                .unwrap()
+        };
+        assert!(result_for(0).contains("alpha.txt") && !result_for(0).contains("beta.txt"));
+        assert!(result_for(1).contains("beta.txt") && !result_for(1).contains("alpha.txt"));
+        assert!(result_for(2).contains("denied"));
+    }
+
+    #[tokio::test]
+    async fn test_allowed_edits_to_one_file_run_in_order() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let config = Config::new_test_config();
+        let mut app = App::init(tx, config, false, None).await;
+        app.persist_session = false;
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap();
+        app.tools.insert(
+            "replace_text".to_string(),
+            Arc::new(crate::tools::ReplaceTextTool { workspace: Workspace::new(&[root], &[root], vec![]) }),
+        );
+        app.permissions.tools.insert("replace_text".to_string(), Decision::Allow);
+        let file = dir.path().join("notes.txt");
+        fs::write(&file, "alpha\nbeta\n").unwrap();
+
+        app.messages.push(ChatMessage {
+            role: "assistant".to_string(),
+            content: String::new(),
+            images: None,
+            tool_calls: None,
+            tool_name: None,
+            tool_call_id: None,
+            thought: None,
+        });
+        for (old, new) in [("alpha", "ALPHA"), ("beta", "BETA")] {
+            app.update(Action::AddToolCall(ToolCall {
+                id: None,
+                type_: "function".to_string(),
+                function: crate::ollama::ToolCallFunction {
+                    name: "replace_text".to_string(),
+                    arguments: serde_json::json!({ "path": file, "old_text": old, "new_text": new }),
+                },
+            }))
+            .await;
+        }
+        app.update(Action::AiResponseComplete).await;
+
+        // The second edit waits for the first instead of racing it.
+        assert_eq!(app.running_tools.len(), 1);
+        assert_eq!(app.queued_tool_calls.len(), 1);
+        assert!(app.pending_tool_call.is_none());
+
+        let mut outputs = 0;
+        while outputs < 2 {
+            let action = tokio::time::timeout(std::time::Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+            if let Action::AddToolOutput(..) = action {
+                outputs += 1;
+                app.update(action).await;
+                assert!(app.running_tools.len() <= 1);
+            }
+        }
+        assert_eq!(fs::read_to_string(&file).unwrap(), "ALPHA\nBETA\n");
+        assert!(app.messages.iter().filter(|m| m.role == "tool").all(|m| m.content.starts_with("Success")));
+    }
+
+    #[tokio::test]
+    async fn test_tool_confirmation_previews_and_edits_the_change() {
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let config = Config::new_test_config();
+        let mut app = App::init(tx, config, false, None).await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap();
+        app.tools.insert(
+            "write_file".to_string(),
+            Arc::new(WriteFileTool { workspace: Workspace::new(&[root], &[root], vec![]) }),
+        );
+        let file_path = dir.path().join("notes.txt");
+        fs::write(&file_path, "one\ntwo\n").unwrap();
+
+        app.messages.push(ChatMessage {
+            role: "assistant".to_string(),
+            content: String::new(),
+            images: None,
+            tool_calls: None,
+            tool_name: None,
+            tool_call_id: None,
+            thought: None,
+        });
+        app.update(Action::AddToolCall(ToolCall {
+            id: Some("call_1".to_string()),
+            type_: "function".to_string(),
+            function: crate::ollama::ToolCallFunction {
+                name: "write_file".to_string(),
+                arguments: serde_json::json!({ "path": file_path, "content": "one\n2\n" }),
+            },
+        }))
+        .await;
+        app.update(Action::AiResponseComplete).await;
+
+        assert_eq!(app.mode, Mode::ToolConfirmation);
+        let diff = app.tool_preview.clone().unwrap().unwrap();
+        assert!(diff.contains("-two\n+2\n"), "{}", diff);
+
+        app.update(Action::EditToolContent).await;
+        assert_eq!(app.mode, Mode::ToolEdit);
+        assert_eq!(app.tool_edit_input.lines(), ["one", "2"]);
+
+        app.update(Action::UpdateToolContent("one\nthree".to_string())).await;
+        assert_eq!(app.mode, Mode::ToolConfirmation);
+        // The final newline the editor dropped is kept.
+        let edited = serde_json::json!({ "path": file_path, "content": "one\nthree\n" });
+        assert_eq!(app.pending_tool_call.as_ref().unwrap().function.arguments, edited);
+        assert_eq!(app.messages[0].tool_calls.as_ref().unwrap()[0].function.arguments, edited);
+        assert!(app.tool_preview.clone().unwrap().unwrap().contains("-two\n+three\n"));
+        // Nothing is written before the call is approved.
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), "one\ntwo\n");
+    }
+
+    #[tokio::test]
+    async fn test_tool_edits_can_be_undone() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let config = Config::new_test_config();
+        let mut app = App::init(tx, config, false, None).await;
+        app.persist_session = false;
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap();
+        app.tools.insert(
+            "write_file".to_string(),
+            Arc::new(WriteFileTool { workspace: Workspace::new(&[root], &[root], vec![]) }),
+        );
+        app.permissions.tools.insert("write_file".to_string(), Decision::Allow);
+        let existing = dir.path().join("notes.txt");
+        let created = dir.path().join("todo.txt");
+        fs::write(&existing, "old").unwrap();
+
+        let message = |role: &str, content: &str| ChatMessage {
+            role: role.to_string(),
+            content: content.to_string(),
+            images: None,
+            tool_calls: None,
+            tool_name: None,
+            tool_call_id: None,
+            thought: None,
+        };
+        app.messages.push(message("user", "Update my notes"));
+        app.messages.push(message("assistant", ""));
+        for path in [&existing, &created] {
+            app.update(Action::AddToolCall(ToolCall {
+                id: None,
+                type_: "function".to_string(),
+                function: crate::ollama::ToolCallFunction {
+                    name: "write_file".to_string(),
+                    arguments: serde_json::json!({ "path": path, "content": "new" }),
+                },
+            }))
+            .await;
+        }
+        app.update(Action::AiResponseComplete).await;
+        let mut outputs = 0;
+        while outputs < 2 {
+            let action = tokio::time::timeout(std::time::Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+            if let Action::AddToolOutput(..) = action {
+                outputs += 1;
+                app.update(action).await;
+            }
+        }
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "new");
+        assert_eq!(fs::read_to_string(&created).unwrap(), "new");
+
+        let turns = app.checkpoints.turns();
+        assert_eq!(turns.len(), 1);
+        assert_eq!(turns[0].prompt, "Update my notes");
+        assert_eq!(turns[0].edits.len(), 2);
+
+        // `/undo` reverts the latest edit and isn't sent to the model.
+        while rx.try_recv().is_ok() {}
+        app.input = TextArea::new(vec!["/undo".to_string()]);
+        app.update(Action::SendMessage).await;
+        let action = rx.try_recv().unwrap();
+        assert_eq!(action, Action::UndoEdits(1));
+        app.update(action).await;
+        assert!(rx.try_recv().is_err());
+        // Edits run in call order, so the latest created the file.
+        assert!(!created.exists());
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "new");
+
+        // Undoing the turn from the list reverts the rest.
+        app.update(Action::EnterCheckpointList).await;
+        assert_eq!(app.mode, Mode::CheckpointList);
+        let turn = app.checkpoint_turns[0].id.clone();
+        app.update(Action::UndoTurn(turn)).await;
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "old");
+        assert!(!created.exists());
+        assert!(app.checkpoints.list().is_empty());
+        assert_eq!(app.mode, Mode::Insert);
+    }
 }
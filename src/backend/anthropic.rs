@@ -0,0 +1,446 @@
+//! Anthropic Messages-compatible API (`/v1/messages`).
+//!
+//! Spoken by Anthropic itself and by llama.cpp server. The Messages API has
+//! no embeddings endpoint, so embeddings go to the OpenAI-style
+//! `/v1/embeddings` that llama.cpp server also exposes.
+
+use super::openai::{fetch_batch_embeddings, fetch_embeddings, fetch_model_info, fetch_model_list};
+use super::{error_for_status, spawn_line_stream, BackendKind, ChatBackend, ChatStream, LineDecoder};
+use crate::ollama::{ChatMessageRequest, ChatStreamEvent, ModelInfo, ToolCall, ToolCallFunction, ToolDefinition};
+use anyhow::Result;
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderValue};
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// `max_tokens` is mandatory in the Messages API; used when no limit is configured.
+const DEFAULT_MAX_TOKENS: u64 = 4096;
+
+/// Backend for an Anthropic Messages-compatible server.
+#[derive(Debug, Clone)]
+pub struct AnthropicBackend {
+    client: Client,
+    base_url: String,
+}
+
+/// A `tool_use` content block being assembled from streamed JSON fragments.
+struct PartialToolUse {
+    id: String,
+    name: String,
+    input: String,
+}
+
+/// Decodes the Messages API server-sent-event stream.
+///
+/// Text deltas are emitted immediately; tool calls are emitted when their
+/// content block closes.
+#[derive(Default)]
+struct AnthropicDecoder {
+    tool_uses: BTreeMap<u64, PartialToolUse>,
+}
+
+impl AnthropicDecoder {
+    fn close_block(&mut self, index: u64) -> Option<ChatStreamEvent> {
+        let tool = self.tool_uses.remove(&index)?;
+        let arguments = if tool.input.trim().is_empty() {
+            json!({})
+        } else {
+            serde_json::from_str(&tool.input).unwrap_or(json!({}))
+        };
+        Some(ChatStreamEvent::ToolCall(ToolCall {
+            id: Some(tool.id),
+            type_: "function".to_string(),
+            function: ToolCallFunction {
+                name: tool.name,
+                arguments,
+            },
+        }))
+    }
+}
+
+impl LineDecoder for AnthropicDecoder {
+    fn line(&mut self, line: &str) -> Result<Vec<ChatStreamEvent>> {
+        // `event:` lines duplicate the `type` field carried in the data payload.
+        let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+            return Ok(Vec::new());
+        };
+        let Ok(event) = serde_json::from_str::<Value>(data) else {
+            return Ok(Vec::new());
+        };
+        let index = event.get("index").and_then(Value::as_u64).unwrap_or(0);
+
+        match event.get("type").and_then(Value::as_str).unwrap_or_default() {
+            "content_block_start" => {
+                let block = &event["content_block"];
+                if block["type"] == "tool_use" {
+                    self.tool_uses.insert(
+                        index,
+                        PartialToolUse {
+                            id: block["id"].as_str().unwrap_or_default().to_string(),
+                            name: block["name"].as_str().unwrap_or_default().to_string(),
+                            input: String::new(),
+                        },
+                    );
+                } else if let Some(text) = block["text"].as_str().filter(|t| !t.is_empty()) {
+                    return Ok(vec![ChatStreamEvent::Token(text.to_string())]);
+                }
+            }
+            "content_block_delta" => {
+                let delta = &event["delta"];
+                match delta["type"].as_str().unwrap_or_default() {
+                    "text_delta" => {
+                        if let Some(text) = delta["text"].as_str().filter(|t| !t.is_empty()) {
+                            return Ok(vec![ChatStreamEvent::Token(text.to_string())]);
+                        }
+                    }
+                    "input_json_delta" => {
+                        if let (Some(tool), Some(partial)) =
+                            (self.tool_uses.get_mut(&index), delta["partial_json"].as_str())
+                        {
+                            tool.input.push_str(partial);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            "content_block_stop" => {
+                return Ok(self.close_block(index).into_iter().collect());
+            }
+            "error" => {
+                let message = event["error"]["message"].as_str().unwrap_or("unknown error");
+                return Err(anyhow::anyhow!("Server error: {}", message));
+            }
+            _ => {}
+        }
+        Ok(Vec::new())
+    }
+
+    fn finish(&mut self) -> Vec<ChatStreamEvent> {
+        // Flush tool calls whose block never closed (truncated streams).
+        let indices: Vec<u64> = self.tool_uses.keys().copied().collect();
+        indices.into_iter().filter_map(|i| self.close_block(i)).collect()
+    }
+}
+
+/// Converts chat history into a Messages API request body fragment.
+///
+/// Returns the concatenated system prompt and the message list. System
+/// messages move to the top-level `system` field, tool results become
+/// `tool_result` blocks on a user turn, and consecutive turns from the same
+/// role are merged because the API requires strict alternation.
+fn to_anthropic_messages(messages: &[ChatMessageRequest]) -> (String, Vec<Value>) {
+    let mut system = Vec::new();
+    let mut out: Vec<(String, Vec<Value>)> = Vec::new();
+
+    for m in messages {
+        let (role, blocks) = match m.role.as_str() {
+            "system" => {
+                system.push(m.content.clone());
+                continue;
+            }
+            "tool" => {
+                let id = m.tool_call_id.clone().or_else(|| m.tool_name.clone()).unwrap_or_default();
+                ("user", vec![json!({ "type": "tool_result", "tool_use_id": id, "content": m.content })])
+            }
+            "assistant" => {
+                let mut blocks = Vec::new();
+                if !m.content.is_empty() {
+                    blocks.push(json!({ "type": "text", "text": m.content }));
+                }
+                for call in m.tool_calls.iter().flatten() {
+                    blocks.push(json!({
+                        "type": "tool_use",
+                        "id": call.id.clone().unwrap_or_else(|| call.function.name.clone()),
+                        "name": call.function.name,
+                        "input": call.function.arguments,
+                    }));
+                }
+                ("assistant", blocks)
+            }
+            _ => {
+                let mut blocks = Vec::new();
+                for img in m.images.iter().flatten() {
+                    blocks.push(json!({
+                        "type": "image",
+                        "source": { "type": "base64", "media_type": image_media_type(img), "data": img }
+                    }));
+                }
+                blocks.push(json!({ "type": "text", "text": m.content }));
+                ("user", blocks)
+            }
+        };
+
+        if blocks.is_empty() {
+            continue;
+        }
+        match out.last_mut() {
+            Some((last_role, last_blocks)) if last_role == role => last_blocks.extend(blocks),
+            _ => out.push((role.to_string(), blocks)),
+        }
+    }
+
+    let messages = out
+        .into_iter()
+        .map(|(role, content)| json!({ "role": role, "content": content }))
+        .collect();
+    (system.join("\n\n"), messages)
+}
+
+/// The MIME type of a base64-encoded image, from its magic bytes. The API
+/// rejects images whose declared type doesn't match; unknown data is sent
+/// as PNG.
+fn image_media_type(data: &str) -> &'static str {
+    // 16 base64 characters decode to the 12 bytes the signatures need.
+    let mut bytes = Vec::with_capacity(12);
+    let mut bits = 0u32;
+    let mut count = 0;
+    for c in data.bytes().take(16) {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => break,
+        };
+        bits = (bits << 6) | u32::from(value);
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+        }
+    }
+    match bytes.as_slice() {
+        [0xFF, 0xD8, 0xFF, ..] => "image/jpeg",
+        [b'G', b'I', b'F', b'8', ..] => "image/gif",
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => "image/webp",
+        _ => "image/png",
+    }
+}
+
+impl AnthropicBackend {
+    pub fn new(base_url: String, api_key: &str) -> Self {
+        let mut headers = HeaderMap::new();
+        headers.insert("anthropic-version", HeaderValue::from_static(ANTHROPIC_VERSION));
+        if !api_key.is_empty() {
+            if let Ok(val) = HeaderValue::from_str(api_key) {
+                headers.insert("x-api-key", val);
+            }
+        }
+        Self {
+            client: super::http_client(headers),
+            base_url,
+        }
+    }
+}
+
+#[async_trait]
+impl ChatBackend for AnthropicBackend {
+    fn kind(&self) -> BackendKind {
+        BackendKind::Anthropic
+    }
+
+    async fn chat(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessageRequest>,
+        tools: Option<Vec<ToolDefinition>>,
+        options: Option<HashMap<String, Value>>,
+    ) -> Result<ChatStream> {
+        let (system, messages) = to_anthropic_messages(&messages);
+
+        let mut request_body = serde_json::Map::new();
+        request_body.insert("model".to_string(), json!(model));
+        request_body.insert("messages".to_string(), json!(messages));
+        request_body.insert("stream".to_string(), json!(true));
+        request_body.insert("max_tokens".to_string(), json!(DEFAULT_MAX_TOKENS));
+        if !system.is_empty() {
+            request_body.insert("system".to_string(), json!(system));
+        }
+        if let Some(t) = tools {
+            let tools: Vec<Value> = t
+                .into_iter()
+                .map(|t| {
+                    json!({
+                        "name": t.function.name,
+                        "description": t.function.description,
+                        "input_schema": t.function.parameters,
+                    })
+                })
+                .collect();
+            request_body.insert("tools".to_string(), json!(tools));
+        }
+
+        // Only sampling options have a Messages API equivalent.
+        for (k, v) in options.unwrap_or_default() {
+            match k.as_str() {
+                "temperature" | "top_p" | "top_k" => {
+                    request_body.insert(k, v);
+                }
+                "stop" => {
+                    request_body.insert("stop_sequences".to_string(), v);
+                }
+                "num_predict" | "max_tokens" if v.as_i64().is_some_and(|n| n > 0) => {
+                    request_body.insert("max_tokens".to_string(), v);
+                }
+                _ => {}
+            }
+        }
+
+        let response = self
+            .client
+            .post(format!("{}/v1/messages", self.base_url))
+            .json(&request_body)
+            .send()
+            .await?;
+        let response = error_for_status(response, "Chat request").await?;
+
+        Ok(spawn_line_stream(response, AnthropicDecoder::default()))
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>> {
+        fetch_model_list(&self.client, &self.base_url).await
+    }
+
+    async fn embeddings(&self, model: &str, prompt: &str) -> Result<Vec<f64>> {
+        fetch_embeddings(&self.client, &self.base_url, model, prompt).await
+    }
+
+    async fn embed_batch(&self, model: &str, inputs: &[String]) -> Result<Vec<Vec<f64>>> {
+        fetch_batch_embeddings(&self.client, &self.base_url, model, inputs).await
+    }
+
+    async fn model_info(&self, name: &str) -> Result<ModelInfo> {
+        fetch_model_info(&self.client, &self.base_url, name).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use wiremock::matchers::{body_partial_json, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn msg(role: &str, content: &str) -> ChatMessageRequest {
+        ChatMessageRequest {
+            role: role.to_string(),
+            content: content.to_string(),
+            images: None,
+            tool_calls: None,
+            tool_name: None,
+            tool_call_id: None,
+        }
+    }
+
+    #[test]
+    fn test_messages_conversion() {
+        let mut assistant = msg("assistant", "Checking.");
+        assistant.tool_calls = Some(vec![ToolCall {
+            id: Some("toolu_1".to_string()),
+            type_: "function".to_string(),
+            function: ToolCallFunction {
+                name: "read_file".to_string(),
+                arguments: json!({ "path": "a.txt" }),
+            },
+        }]);
+        let mut result = msg("tool", "contents");
+        result.tool_call_id = Some("toolu_1".to_string());
+
+        let (system, messages) = to_anthropic_messages(&[
+            msg("system", "Be brief."),
+            msg("user", "Read a.txt"),
+            assistant,
+            result,
+            msg("user", "Thanks"),
+        ]);
+
+        assert_eq!(system, "Be brief.");
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[1]["content"][1]["type"], "tool_use");
+        assert_eq!(messages[1]["content"][1]["input"]["path"], "a.txt");
+        // Tool result and the following user text share one user turn.
+        assert_eq!(messages[2]["role"], "user");
+        assert_eq!(messages[2]["content"][0]["type"], "tool_result");
+        assert_eq!(messages[2]["content"][0]["tool_use_id"], "toolu_1");
+        assert_eq!(messages[2]["content"][1]["text"], "Thanks");
+    }
+
+    #[test]
+    fn test_image_media_type_from_magic_bytes() {
+        // "/9j/4AAQSkZJRg" is the base64 of a JFIF JPEG header.
+        let mut user = msg("user", "What is this?");
+        user.images = Some(vec!["/9j/4AAQSkZJRgABAQ==".to_string(), "iVBORw0KGgoAAAANSUhEUg==".to_string()]);
+
+        let (_, messages) = to_anthropic_messages(&[user]);
+
+        let content = &messages[0]["content"];
+        assert_eq!(content[0]["source"]["media_type"], "image/jpeg");
+        assert_eq!(content[0]["source"]["data"], "/9j/4AAQSkZJRgABAQ==");
+        assert_eq!(content[1]["source"]["media_type"], "image/png");
+        assert_eq!(image_media_type("R0lGODlhAQABAAAAACw="), "image/gif");
+        assert_eq!(image_media_type("UklGRiQAAABXRUJQVlA4IA=="), "image/webp");
+    }
+
+    #[tokio::test]
+    async fn test_chat_stream_with_tool_use() {
+        let mock_server = MockServer::start().await;
+        let backend = AnthropicBackend::new(mock_server.uri(), "sk-test");
+
+        let body = [
+            r#"event: message_start"#,
+            r#"data: {"type":"message_start","message":{"id":"msg_1","role":"assistant","content":[]}}"#,
+            r#"data: {"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#,
+            r#"data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Let me "}}"#,
+            r#"data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"check."}}"#,
+            r#"data: {"type":"content_block_stop","index":0}"#,
+            r#"data: {"type":"content_block_start","index":1,"content_block":{"type":"tool_use","id":"toolu_9","name":"read_file","input":{}}}"#,
+            r#"data: {"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"{\"path\": "}}"#,
+            r#"data: {"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"\"a.txt\"}"}}"#,
+            r#"data: {"type":"content_block_stop","index":1}"#,
+            r#"data: {"type":"message_stop"}"#,
+        ]
+        .join("\n\n");
+
+        Mock::given(method("POST"))
+            .and(path("/v1/messages"))
+            .and(header("x-api-key", "sk-test"))
+            .and(header("anthropic-version", ANTHROPIC_VERSION))
+            .and(body_partial_json(json!({ "system": "Be brief.", "max_tokens": DEFAULT_MAX_TOKENS })))
+            .respond_with(ResponseTemplate::new(200).set_body_string(body))
+            .mount(&mock_server)
+            .await;
+
+        let mut stream = backend
+            .chat("claude", vec![msg("system", "Be brief."), msg("user", "Hi")], None, None)
+            .await
+            .expect("chat failed");
+
+        let mut text = String::new();
+        let mut calls = Vec::new();
+        while let Some(event) = stream.next().await {
+            match event.expect("stream error") {
+                ChatStreamEvent::Token(t) => text.push_str(&t),
+                ChatStreamEvent::ToolCall(c) => calls.push(c),
+            }
+        }
+
+        assert_eq!(text, "Let me check.");
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].id.as_deref(), Some("toolu_9"));
+        assert_eq!(calls[0].function.name, "read_file");
+        assert_eq!(calls[0].function.arguments, json!({ "path": "a.txt" }));
+    }
+
+    #[test]
+    fn test_decoder_surfaces_stream_errors() {
+        let mut decoder = AnthropicDecoder::default();
+        let err = decoder
+            .line(r#"data: {"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#)
+            .unwrap_err();
+        assert!(err.to_string().contains("Overloaded"));
+    }
+}
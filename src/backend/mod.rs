@@ -0,0 +1,346 @@
+//! Pluggable chat backends.
+//!
+//! Every server wire format (Ollama, OpenAI-compatible, Anthropic Messages)
+//! implements [`ChatBackend`]. [`crate::ollama::OllamaClient`] is a thin
+//! facade over one of these, chosen from the configured `api_type`, so
+//! supporting a new local server means adding a module here rather than
+//! another branch in every client method.
+
+use crate::ollama::{ChatMessageRequest, ChatStreamEvent, ModelInfo, PullModelResponse, RunningModel, ToolDefinition};
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::stream::{BoxStream, StreamExt};
+use reqwest::header::HeaderMap;
+use reqwest::Client;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub mod anthropic;
+pub mod ollama;
+pub mod openai;
+
+pub use anthropic::AnthropicBackend;
+pub use ollama::OllamaBackend;
+pub use openai::OpenAiBackend;
+
+/// Stream of events produced by a chat request.
+pub type ChatStream = BoxStream<'static, Result<ChatStreamEvent>>;
+
+/// Stream of progress updates while a model downloads.
+pub type PullStream = BoxStream<'static, Result<PullModelResponse>>;
+
+/// The wire formats intus can talk to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackendKind {
+    /// Ollama's native `/api/*` endpoints.
+    Ollama,
+    /// OpenAI-compatible `/v1/chat/completions` (LM Studio, vLLM, llama.cpp, ...).
+    OpenAi,
+    /// Anthropic Messages-compatible `/v1/messages` (Anthropic, llama.cpp server).
+    Anthropic,
+}
+
+impl std::fmt::Display for BackendKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            BackendKind::Ollama => "Ollama",
+            BackendKind::OpenAi => "OpenAI-compatible",
+            BackendKind::Anthropic => "Anthropic-compatible",
+        })
+    }
+}
+
+impl BackendKind {
+    /// Parses the `api_type` config value. Unknown values fall back to Ollama.
+    pub fn from_api_type(api_type: &str) -> Self {
+        match api_type.trim().to_lowercase().as_str() {
+            "openai" => BackendKind::OpenAi,
+            "anthropic" | "messages" => BackendKind::Anthropic,
+            "ollama" | "" => BackendKind::Ollama,
+            other => {
+                tracing::warn!("Unknown api_type '{}', falling back to ollama", other);
+                BackendKind::Ollama
+            }
+        }
+    }
+}
+
+/// A server that can hold a streaming, tool-aware conversation.
+#[async_trait]
+pub trait ChatBackend: Send + Sync + std::fmt::Debug {
+    /// Which wire format this backend speaks.
+    fn kind(&self) -> BackendKind;
+
+    /// Starts a chat request and returns a stream of tokens and tool calls.
+    async fn chat(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessageRequest>,
+        tools: Option<Vec<ToolDefinition>>,
+        options: Option<HashMap<String, Value>>,
+    ) -> Result<ChatStream>;
+
+    /// Lists the models the server can serve.
+    async fn list_models(&self) -> Result<Vec<String>>;
+
+    /// Generates an embedding vector for `prompt`.
+    async fn embeddings(&self, model: &str, prompt: &str) -> Result<Vec<f64>>;
+
+    /// Generates one embedding vector per input, in order, with as few
+    /// requests as the server allows.
+    async fn embed_batch(&self, model: &str, inputs: &[String]) -> Result<Vec<Vec<f64>>>;
+
+    /// Returns model metadata, most importantly the context length.
+    async fn model_info(&self, name: &str) -> Result<ModelInfo>;
+
+    /// Scores each of `documents` for relevance to `query` with a reranker
+    /// (cross-encoder) model, returning one score per document in order.
+    async fn rerank(&self, model: &str, query: &str, documents: &[String]) -> Result<Vec<f64>> {
+        let _ = (model, query, documents);
+        anyhow::bail!("{} servers have no rerank endpoint; use the \"llm\" rerank method instead", self.kind())
+    }
+
+    /// Whether the server can download, delete and report loaded models.
+    /// OpenAI- and Anthropic-compatible APIs have no endpoints for this;
+    /// LM Studio, vLLM and llama.cpp manage models with their own tools.
+    fn manages_models(&self) -> bool {
+        false
+    }
+
+    /// Lists the models currently loaded in memory.
+    async fn list_running(&self) -> Result<Vec<RunningModel>> {
+        Err(Unsupported::new(self.kind(), "Listing running models").into())
+    }
+
+    /// Downloads `name`, streaming progress.
+    async fn pull_model(&self, name: &str) -> Result<PullStream> {
+        let _ = name;
+        Err(Unsupported::new(self.kind(), "Pulling models").into())
+    }
+
+    /// Removes the downloaded model `name`.
+    async fn delete_model(&self, name: &str) -> Result<()> {
+        let _ = name;
+        Err(Unsupported::new(self.kind(), "Deleting models").into())
+    }
+}
+
+/// An operation the configured server type has no endpoint for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unsupported {
+    pub kind: BackendKind,
+    pub operation: &'static str,
+}
+
+impl Unsupported {
+    pub fn new(kind: BackendKind, operation: &'static str) -> Self {
+        Self { kind, operation }
+    }
+}
+
+impl std::fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} is not supported by {} servers; use the server's own model management instead",
+            self.operation, self.kind
+        )
+    }
+}
+
+impl std::error::Error for Unsupported {}
+
+/// Creates the backend for `kind`.
+pub fn build_backend(kind: BackendKind, base_url: String, api_key: String) -> Arc<dyn ChatBackend> {
+    match kind {
+        BackendKind::Ollama => Arc::new(OllamaBackend::new(base_url, &api_key)),
+        BackendKind::OpenAi => Arc::new(OpenAiBackend::new(base_url, &api_key)),
+        BackendKind::Anthropic => Arc::new(AnthropicBackend::new(base_url, &api_key)),
+    }
+}
+
+/// Builds the HTTP client shared by all backends.
+pub(crate) fn http_client(headers: HeaderMap) -> Client {
+    Client::builder()
+        .default_headers(headers)
+        .timeout(Duration::from_secs(3600)) // 1 hour timeout
+        .build()
+        .unwrap_or_else(|_| Client::new())
+}
+
+/// Returns `Authorization: Bearer <key>` headers, or none for an empty key.
+pub(crate) fn bearer_headers(api_key: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    if !api_key.is_empty() {
+        if let Ok(val) = reqwest::header::HeaderValue::from_str(&format!("Bearer {}", api_key)) {
+            headers.insert(reqwest::header::AUTHORIZATION, val);
+        }
+    }
+    headers
+}
+
+/// Incremental decoder for a line-oriented streaming response body.
+///
+/// Each backend only describes how to turn one line into events; buffering,
+/// UTF-8 handling and channel plumbing live in [`spawn_line_stream`].
+pub(crate) trait LineDecoder: Send + 'static {
+    /// Decodes one complete line (without the trailing newline). An error
+    /// reported by the server inside the stream ends it.
+    fn line(&mut self, line: &str) -> Result<Vec<ChatStreamEvent>>;
+
+    /// Called once the body ends, for formats that accumulate state.
+    fn finish(&mut self) -> Vec<ChatStreamEvent> {
+        Vec::new()
+    }
+}
+
+/// Streams `response` line by line through `decoder` on a background task.
+pub(crate) fn spawn_line_stream<D: LineDecoder>(response: reqwest::Response, mut decoder: D) -> ChatStream {
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    let mut stream = response.bytes_stream();
+
+    tokio::spawn(async move {
+        let mut buffer = Vec::new();
+
+        while let Some(chunk_result) = stream.next().await {
+            match chunk_result {
+                Ok(bytes) => {
+                    buffer.extend_from_slice(&bytes);
+                    while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
+                        let line_bytes: Vec<u8> = buffer.drain(..=pos).collect();
+                        let line = String::from_utf8_lossy(&line_bytes);
+                        match decoder.line(line.trim()) {
+                            Ok(events) => {
+                                for event in events {
+                                    if tx.send(Ok(event)).is_err() {
+                                        return;
+                                    }
+                                }
+                            }
+                            Err(e) => {
+                                let _ = tx.send(Err(e));
+                                return;
+                            }
+                        }
+                    }
+                }
+                Err(e) => {
+                    let _ = tx.send(Err(anyhow::anyhow!("Chunk error: {}", e)));
+                    return;
+                }
+            }
+        }
+
+        // Process any remaining buffer
+        if !buffer.is_empty() {
+            let line = String::from_utf8_lossy(&buffer);
+            match decoder.line(line.trim()) {
+                Ok(events) => {
+                    for event in events {
+                        let _ = tx.send(Ok(event));
+                    }
+                }
+                Err(e) => {
+                    let _ = tx.send(Err(e));
+                    return;
+                }
+            }
+        }
+        for event in decoder.finish() {
+            let _ = tx.send(Ok(event));
+        }
+    });
+
+    tokio_stream::wrappers::UnboundedReceiverStream::new(rx).boxed()
+}
+
+/// A non-success HTTP response. Kept as a type so callers can tell
+/// transient failures (see [`is_transient`]) from permanent ones.
+#[derive(Debug)]
+pub struct HttpError {
+    pub status: reqwest::StatusCode,
+    message: String,
+}
+
+impl std::fmt::Display for HttpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+/// Returns an error describing a failed response, including its body.
+pub(crate) async fn error_for_status(response: reqwest::Response, what: &str) -> Result<reqwest::Response> {
+    if response.status().is_success() {
+        return Ok(response);
+    }
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    let message = if body.trim().is_empty() {
+        format!("{} failed: {}", what, status)
+    } else {
+        format!("{} failed: {} ({})", what, status, body.trim())
+    };
+    Err(HttpError { status, message }.into())
+}
+
+/// Whether retrying the request that produced `err` may succeed: the server
+/// was unreachable or timed out, rate limited us, or reported a server error.
+pub fn is_transient(err: &anyhow::Error) -> bool {
+    if let Some(e) = err.downcast_ref::<HttpError>() {
+        return e.status == reqwest::StatusCode::TOO_MANY_REQUESTS || e.status.is_server_error();
+    }
+    if let Some(e) = err.downcast_ref::<reqwest::Error>() {
+        return e.is_connect() || e.is_timeout();
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backend_kind_from_api_type() {
+        assert_eq!(BackendKind::from_api_type("ollama"), BackendKind::Ollama);
+        assert_eq!(BackendKind::from_api_type("OpenAI"), BackendKind::OpenAi);
+        assert_eq!(BackendKind::from_api_type("anthropic"), BackendKind::Anthropic);
+        assert_eq!(BackendKind::from_api_type("something-else"), BackendKind::Ollama);
+    }
+
+    #[tokio::test]
+    async fn test_transient_errors() {
+        let mock_server = wiremock::MockServer::start().await;
+        for (route, status) in [("/busy", 503), ("/limited", 429), ("/bad", 400)] {
+            wiremock::Mock::given(wiremock::matchers::path(route))
+                .respond_with(wiremock::ResponseTemplate::new(status).set_body_string("nope"))
+                .mount(&mock_server)
+                .await;
+        }
+        let client = Client::new();
+        let mut transient = Vec::new();
+        for route in ["/busy", "/limited", "/bad"] {
+            let response = client.get(format!("{}{}", mock_server.uri(), route)).send().await.unwrap();
+            let err = error_for_status(response, "Probe").await.unwrap_err();
+            transient.push(is_transient(&err));
+        }
+        assert_eq!(transient, vec![true, true, false]);
+
+        let err = error_for_status(client.get(format!("{}/bad", mock_server.uri())).send().await.unwrap(), "Probe")
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Probe failed: 400 Bad Request (nope)");
+
+        let unreachable = client.get("http://127.0.0.1:9").send().await.unwrap_err();
+        assert!(is_transient(&unreachable.into()));
+    }
+
+    #[test]
+    fn test_build_backend_kind() {
+        let backend = build_backend(BackendKind::Anthropic, "http://localhost".to_string(), String::new());
+        assert_eq!(backend.kind(), BackendKind::Anthropic);
+    }
+}
@@ -0,0 +1,370 @@
+//! Ollama's native API (`/api/chat`, `/api/tags`, `/api/embed`, `/api/embeddings`,
+//! `/api/show`) and its model management (`/api/pull`, `/api/delete`, `/api/ps`).
+
+use super::{error_for_status, spawn_line_stream, BackendKind, ChatBackend, ChatStream, LineDecoder, PullStream};
+use crate::ollama::{ChatMessageRequest, ChatStreamEvent, ModelInfo, PullModelResponse, RunningModel, RunningModelsResponse, ToolCall, ToolDefinition};
+use futures::StreamExt;
+use anyhow::Result;
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Backend for an Ollama server.
+#[derive(Debug, Clone)]
+pub struct OllamaBackend {
+    client: Client,
+    base_url: String,
+}
+
+#[derive(Serialize)]
+struct ChatRequest {
+    model: String,
+    messages: Vec<ChatMessageRequest>,
+    stream: bool,
+    options: Option<HashMap<String, Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<ToolDefinition>>,
+}
+
+#[derive(Deserialize)]
+struct ChatResponse {
+    message: ChatMessageResponse,
+    #[allow(dead_code)]
+    done: bool,
+}
+
+#[derive(Deserialize)]
+struct ChatMessageResponse {
+    #[allow(dead_code)]
+    role: String,
+    content: String,
+    tool_calls: Option<Vec<ToolCall>>,
+}
+
+#[derive(Deserialize)]
+struct ModelsResponse {
+    models: Vec<Model>,
+}
+
+#[derive(Deserialize)]
+struct Model {
+    name: String,
+}
+
+#[derive(Serialize)]
+struct GenerateEmbeddingRequest {
+    model: String,
+    prompt: String,
+}
+
+#[derive(Deserialize)]
+struct GenerateEmbeddingResponse {
+    embedding: Vec<f64>,
+}
+
+#[derive(Serialize)]
+struct ModelNameRequest<'a> {
+    name: &'a str,
+}
+
+#[derive(Serialize)]
+struct PullRequest<'a> {
+    name: &'a str,
+    stream: bool,
+}
+
+/// An `{"error": ...}` line in a pull stream.
+#[derive(Deserialize)]
+struct StreamError {
+    error: String,
+}
+
+#[derive(Serialize)]
+struct EmbedRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Deserialize)]
+struct EmbedResponse {
+    embeddings: Vec<Vec<f64>>,
+}
+
+/// Decodes Ollama's newline-delimited JSON chat stream.
+#[derive(Default)]
+struct OllamaDecoder;
+
+impl LineDecoder for OllamaDecoder {
+    fn line(&mut self, line: &str) -> Result<Vec<ChatStreamEvent>> {
+        let mut events = Vec::new();
+        if let Ok(json) = serde_json::from_str::<ChatResponse>(line) {
+            // Emit Token if content exists
+            if !json.message.content.is_empty() {
+                events.push(ChatStreamEvent::Token(json.message.content));
+            }
+            // Emit ToolCall if exists
+            if let Some(calls) = json.message.tool_calls {
+                events.extend(calls.into_iter().map(ChatStreamEvent::ToolCall));
+            }
+        }
+        Ok(events)
+    }
+}
+
+impl OllamaBackend {
+    pub fn new(base_url: String, api_key: &str) -> Self {
+        Self {
+            client: super::http_client(super::bearer_headers(api_key)),
+            base_url,
+        }
+    }
+}
+
+#[async_trait]
+impl ChatBackend for OllamaBackend {
+    fn kind(&self) -> BackendKind {
+        BackendKind::Ollama
+    }
+
+    async fn chat(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessageRequest>,
+        tools: Option<Vec<ToolDefinition>>,
+        options: Option<HashMap<String, Value>>,
+    ) -> Result<ChatStream> {
+        let mut request_options = HashMap::new();
+        request_options.insert("num_predict".to_string(), serde_json::json!(-1)); // -1 = Infinite generation
+
+        if let Some(opts) = options {
+            request_options.extend(opts);
+        }
+
+        let request = ChatRequest {
+            model: model.to_string(),
+            messages,
+            stream: true,
+            options: Some(request_options),
+            tools,
+        };
+
+        let response = self
+            .client
+            .post(format!("{}/api/chat", self.base_url))
+            .json(&request)
+            .send()
+            .await?;
+        let response = error_for_status(response, "Chat request").await?;
+
+        Ok(spawn_line_stream(response, OllamaDecoder))
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>> {
+        let response = self
+            .client
+            .get(format!("{}/api/tags", self.base_url))
+            .send()
+            .await?
+            .json::<ModelsResponse>()
+            .await?;
+
+        Ok(response.models.into_iter().map(|m| m.name).collect())
+    }
+
+    async fn embeddings(&self, model: &str, prompt: &str) -> Result<Vec<f64>> {
+        let request = GenerateEmbeddingRequest {
+            model: model.to_string(),
+            prompt: prompt.to_string(),
+        };
+
+        let response = self
+            .client
+            .post(format!("{}/api/embeddings", self.base_url))
+            .json(&request)
+            .send()
+            .await?;
+        let response = error_for_status(response, "Embedding generation").await?;
+
+        let embedding_response = response.json::<GenerateEmbeddingResponse>().await?;
+        Ok(embedding_response.embedding)
+    }
+
+    async fn embed_batch(&self, model: &str, inputs: &[String]) -> Result<Vec<Vec<f64>>> {
+        let response = self
+            .client
+            .post(format!("{}/api/embed", self.base_url))
+            .json(&EmbedRequest { model, input: inputs })
+            .send()
+            .await?;
+
+        // Servers older than Ollama 0.3 only have the one-prompt endpoint.
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            let mut embeddings = Vec::with_capacity(inputs.len());
+            for input in inputs {
+                embeddings.push(self.embeddings(model, input).await?);
+            }
+            return Ok(embeddings);
+        }
+        let response = error_for_status(response, "Embedding generation").await?;
+
+        let embed_response = response.json::<EmbedResponse>().await?;
+        if embed_response.embeddings.len() != inputs.len() {
+            anyhow::bail!(
+                "Embedding response had {} vectors for {} inputs",
+                embed_response.embeddings.len(),
+                inputs.len()
+            );
+        }
+        Ok(embed_response.embeddings)
+    }
+
+    async fn model_info(&self, name: &str) -> Result<ModelInfo> {
+        let response = self
+            .client
+            .post(format!("{}/api/show", self.base_url))
+            .json(&ModelNameRequest { name })
+            .send()
+            .await?;
+        let response = error_for_status(response, "Show model").await?;
+
+        Ok(response.json::<ModelInfo>().await?)
+    }
+
+    fn manages_models(&self) -> bool {
+        true
+    }
+
+    async fn list_running(&self) -> Result<Vec<RunningModel>> {
+        let response = self.client.get(format!("{}/api/ps", self.base_url)).send().await?;
+        let response = error_for_status(response, "List running").await?;
+        Ok(response.json::<RunningModelsResponse>().await?.models)
+    }
+
+    async fn pull_model(&self, name: &str) -> Result<PullStream> {
+        let response = self
+            .client
+            .post(format!("{}/api/pull", self.base_url))
+            .json(&PullRequest { name, stream: true })
+            .send()
+            .await?;
+        let response = error_for_status(response, "Pull").await?;
+
+        let mut stream = response.bytes_stream();
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            let mut buffer = Vec::new();
+            let send_line = |line: &[u8]| {
+                if let Ok(progress) = serde_json::from_slice::<PullModelResponse>(line) {
+                    tx.send(Ok(progress)).is_ok()
+                } else if let Ok(e) = serde_json::from_slice::<StreamError>(line) {
+                    let _ = tx.send(Err(anyhow::anyhow!("{}", e.error)));
+                    false
+                } else {
+                    true
+                }
+            };
+
+            while let Some(chunk_result) = stream.next().await {
+                match chunk_result {
+                    Ok(bytes) => {
+                        buffer.extend_from_slice(&bytes);
+                        while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
+                            let line: Vec<u8> = buffer.drain(..=pos).collect();
+                            if !send_line(&line) {
+                                return;
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        let _ = tx.send(Err(anyhow::anyhow!("Chunk error: {}", e)));
+                        return;
+                    }
+                }
+            }
+            if !buffer.is_empty() {
+                send_line(&buffer);
+            }
+        });
+
+        Ok(tokio_stream::wrappers::UnboundedReceiverStream::new(rx).boxed())
+    }
+
+    async fn delete_model(&self, name: &str) -> Result<()> {
+        let response = self
+            .client
+            .delete(format!("{}/api/delete", self.base_url))
+            .json(&ModelNameRequest { name })
+            .send()
+            .await?;
+        error_for_status(response, "Delete").await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decoder_emits_tokens_and_tool_calls() {
+        let mut decoder = OllamaDecoder;
+        let events = decoder
+            .line(r#"{"message":{"role":"assistant","content":"Hi"},"done":false}"#)
+            .unwrap();
+        assert_eq!(events, vec![ChatStreamEvent::Token("Hi".to_string())]);
+
+        let events = decoder
+            .line(r#"{"message":{"role":"assistant","content":"","tool_calls":[{"function":{"name":"read_file","arguments":{"path":"a.txt"}}}]},"done":true}"#)
+            .unwrap();
+        assert_eq!(events.len(), 1);
+        match &events[0] {
+            ChatStreamEvent::ToolCall(call) => {
+                assert_eq!(call.function.name, "read_file");
+                assert_eq!(call.function.arguments["path"], "a.txt");
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        assert!(decoder.line("not json").unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_pull_streams_progress_and_errors() {
+        use wiremock::matchers::{body_partial_json, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let mock_server = MockServer::start().await;
+        let body = concat!(
+            r#"{"status":"pulling manifest"}"#,
+            "\n",
+            r#"{"status":"downloading","digest":"sha256:abc","total":100,"completed":40}"#,
+            "\n",
+            r#"{"error":"max retries exceeded"}"#,
+            "\n",
+        );
+        Mock::given(method("POST"))
+            .and(path("/api/pull"))
+            .and(body_partial_json(serde_json::json!({ "name": "llama3", "stream": true })))
+            .respond_with(ResponseTemplate::new(200).set_body_string(body))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/api/delete"))
+            .respond_with(ResponseTemplate::new(404).set_body_string("model not found"))
+            .mount(&mock_server)
+            .await;
+
+        let backend = OllamaBackend::new(mock_server.uri(), "");
+        assert!(backend.manages_models());
+        let events: Vec<_> = backend.pull_model("llama3").await.unwrap().collect().await;
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[1].as_ref().unwrap().completed, Some(40));
+        assert_eq!(events[2].as_ref().unwrap_err().to_string(), "max retries exceeded");
+
+        let err = backend.delete_model("gone").await.unwrap_err();
+        assert_eq!(err.to_string(), "Delete failed: 404 Not Found (model not found)");
+    }
+}
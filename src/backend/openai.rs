@@ -0,0 +1,534 @@
+//! OpenAI-compatible API (`/v1/chat/completions`, `/v1/models`, `/v1/embeddings`,
+//! `/v1/rerank`).
+//!
+//! Covers LM Studio, vLLM, llama.cpp server, LocalAI and hosted OpenAI.
+
+use super::{error_for_status, spawn_line_stream, BackendKind, ChatBackend, ChatStream, LineDecoder};
+use crate::ollama::{ChatMessageRequest, ChatStreamEvent, ModelInfo, ToolCall, ToolCallFunction, ToolDefinition};
+use anyhow::Result;
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+
+/// Backend for an OpenAI-compatible server.
+#[derive(Debug, Clone)]
+pub struct OpenAiBackend {
+    client: Client,
+    base_url: String,
+}
+
+#[derive(Deserialize)]
+struct OpenAiChatChunk {
+    #[serde(default)]
+    choices: Vec<OpenAiChoice>,
+    error: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiChoice {
+    delta: OpenAiDelta,
+    #[allow(dead_code)]
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiDelta {
+    content: Option<String>,
+    tool_calls: Option<Vec<OpenAiToolCallChunk>>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiToolCallChunk {
+    index: Option<usize>,
+    id: Option<String>,
+    #[serde(rename = "type")]
+    type_: Option<String>,
+    function: Option<OpenAiFunctionChunk>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiFunctionChunk {
+    name: Option<String>,
+    arguments: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiModelsResponse {
+    data: Vec<OpenAiModel>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiModel {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct OpenAiEmbeddingResponse {
+    data: Vec<OpenAiEmbedding>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiEmbedding {
+    embedding: Vec<f64>,
+    /// Position of the input this vector belongs to.
+    #[serde(default)]
+    index: usize,
+}
+
+/// Response of `/v1/rerank` (Jina/Cohere shape).
+#[derive(Deserialize)]
+struct OpenAiRerankResponse {
+    results: Vec<OpenAiRerankResult>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiRerankResult {
+    index: usize,
+    relevance_score: f64,
+}
+
+/// A tool call being assembled from streamed fragments.
+#[derive(Default)]
+struct PartialToolCall {
+    id: String,
+    type_: String,
+    name: String,
+    arguments: String,
+}
+
+/// Decodes the server-sent-event stream of `/v1/chat/completions`.
+///
+/// Tool calls arrive as fragments keyed by index and are emitted once the
+/// stream ends.
+#[derive(Default)]
+struct OpenAiDecoder {
+    tool_calls: BTreeMap<usize, PartialToolCall>,
+}
+
+impl LineDecoder for OpenAiDecoder {
+    fn line(&mut self, line: &str) -> Result<Vec<ChatStreamEvent>> {
+        let mut events = Vec::new();
+        let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+            return Ok(events);
+        };
+        if data == "[DONE]" {
+            return Ok(events);
+        }
+        let Ok(chunk) = serde_json::from_str::<OpenAiChatChunk>(data) else {
+            return Ok(events);
+        };
+        if let Some(error) = chunk.error {
+            let message = error.get("message").and_then(Value::as_str).map(str::to_string);
+            return Err(anyhow::anyhow!("Server error: {}", message.unwrap_or_else(|| error.to_string())));
+        }
+        let Some(choice) = chunk.choices.into_iter().next() else {
+            return Ok(events);
+        };
+
+        if let Some(content) = choice.delta.content {
+            if !content.is_empty() {
+                events.push(ChatStreamEvent::Token(content));
+            }
+        }
+
+        for call_chunk in choice.delta.tool_calls.unwrap_or_default() {
+            let entry = self.tool_calls.entry(call_chunk.index.unwrap_or(0)).or_default();
+            if let Some(id) = call_chunk.id {
+                entry.id = id;
+            }
+            if let Some(t) = call_chunk.type_ {
+                entry.type_ = t;
+            }
+            if let Some(func) = call_chunk.function {
+                if let Some(name) = func.name {
+                    entry.name.push_str(&name);
+                }
+                if let Some(args) = func.arguments {
+                    entry.arguments.push_str(&args);
+                }
+            }
+        }
+        Ok(events)
+    }
+
+    fn finish(&mut self) -> Vec<ChatStreamEvent> {
+        std::mem::take(&mut self.tool_calls)
+            .into_values()
+            .filter(|call| !call.name.is_empty())
+            .map(|call| {
+                ChatStreamEvent::ToolCall(ToolCall {
+                    id: if call.id.is_empty() { None } else { Some(call.id) },
+                    type_: if call.type_.is_empty() { "function".to_string() } else { call.type_ },
+                    function: ToolCallFunction {
+                        name: call.name,
+                        arguments: serde_json::from_str(&call.arguments).unwrap_or(json!({})),
+                    },
+                })
+            })
+            .collect()
+    }
+}
+
+/// Converts chat history to the OpenAI message shape.
+///
+/// OpenAI expects tool call arguments as a JSON-encoded string and has no
+/// `tool_name` field on tool results.
+fn to_openai_messages(messages: &[ChatMessageRequest]) -> Vec<Value> {
+    messages
+        .iter()
+        .map(|m| {
+            let mut msg = json!({ "role": m.role, "content": m.content });
+            if let Some(calls) = &m.tool_calls {
+                msg["tool_calls"] = calls
+                    .iter()
+                    .map(|c| {
+                        json!({
+                            "id": c.id.clone().unwrap_or_else(|| c.function.name.clone()),
+                            "type": c.type_,
+                            "function": {
+                                "name": c.function.name,
+                                "arguments": c.function.arguments.to_string(),
+                            }
+                        })
+                    })
+                    .collect();
+            }
+            if let Some(id) = m.tool_call_id.clone().or_else(|| m.tool_name.clone()) {
+                if m.role == "tool" {
+                    msg["tool_call_id"] = json!(id);
+                }
+            }
+            if let Some(images) = &m.images {
+                let mut parts = vec![json!({ "type": "text", "text": m.content })];
+                parts.extend(images.iter().map(|img| {
+                    json!({ "type": "image_url", "image_url": { "url": format!("data:image/png;base64,{}", img) } })
+                }));
+                msg["content"] = Value::Array(parts);
+            }
+            msg
+        })
+        .collect()
+}
+
+/// Extracts a context length from `/v1/models/{id}` metadata.
+///
+/// There is no standard field; this covers the ones vLLM, LM Studio and
+/// llama.cpp server report.
+pub(crate) fn model_info_from_openai(value: &Value) -> ModelInfo {
+    let context_length = ["context_length", "max_model_len", "context_window", "max_context_length"]
+        .iter()
+        .find_map(|k| value.get(*k).and_then(Value::as_u64))
+        .or_else(|| value.pointer("/meta/n_ctx_train").and_then(Value::as_u64));
+
+    let mut info = ModelInfo::default();
+    if let Some(n) = context_length {
+        info.model_info.insert("context_length".to_string(), json!(n));
+    }
+    info
+}
+
+impl OpenAiBackend {
+    pub fn new(base_url: String, api_key: &str) -> Self {
+        Self {
+            client: super::http_client(super::bearer_headers(api_key)),
+            base_url,
+        }
+    }
+}
+
+#[async_trait]
+impl ChatBackend for OpenAiBackend {
+    fn kind(&self) -> BackendKind {
+        BackendKind::OpenAi
+    }
+
+    async fn chat(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessageRequest>,
+        tools: Option<Vec<ToolDefinition>>,
+        options: Option<HashMap<String, Value>>,
+    ) -> Result<ChatStream> {
+        let mut request_body = serde_json::Map::new();
+        request_body.insert("model".to_string(), json!(model));
+        request_body.insert("messages".to_string(), json!(to_openai_messages(&messages)));
+        request_body.insert("stream".to_string(), json!(true));
+        if let Some(t) = tools {
+            request_body.insert("tools".to_string(), serde_json::to_value(t)?);
+        }
+
+        // Unpack options into top-level; the context window is owned by the server.
+        if let Some(opts) = options {
+            for (k, v) in opts {
+                match k.as_str() {
+                    "num_ctx" => {}
+                    "num_predict" => {
+                        request_body.insert("max_tokens".to_string(), v);
+                    }
+                    _ => {
+                        request_body.insert(k, v);
+                    }
+                }
+            }
+        }
+
+        let response = self
+            .client
+            .post(format!("{}/v1/chat/completions", self.base_url))
+            .json(&request_body)
+            .send()
+            .await?;
+        let response = error_for_status(response, "Chat request").await?;
+
+        Ok(spawn_line_stream(response, OpenAiDecoder::default()))
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>> {
+        fetch_model_list(&self.client, &self.base_url).await
+    }
+
+    async fn embeddings(&self, model: &str, prompt: &str) -> Result<Vec<f64>> {
+        fetch_embeddings(&self.client, &self.base_url, model, prompt).await
+    }
+
+    async fn embed_batch(&self, model: &str, inputs: &[String]) -> Result<Vec<Vec<f64>>> {
+        fetch_batch_embeddings(&self.client, &self.base_url, model, inputs).await
+    }
+
+    async fn model_info(&self, name: &str) -> Result<ModelInfo> {
+        fetch_model_info(&self.client, &self.base_url, name).await
+    }
+
+    async fn rerank(&self, model: &str, query: &str, documents: &[String]) -> Result<Vec<f64>> {
+        let response = self
+            .client
+            .post(format!("{}/v1/rerank", self.base_url))
+            .json(&json!({ "model": model, "query": query, "documents": documents, "top_n": documents.len() }))
+            .send()
+            .await?;
+        let response = error_for_status(response, "Rerank").await?;
+
+        let results = response.json::<OpenAiRerankResponse>().await?.results;
+        let mut scores = vec![None; documents.len()];
+        for result in results {
+            if let Some(slot) = scores.get_mut(result.index) {
+                *slot = Some(result.relevance_score);
+            }
+        }
+        scores
+            .into_iter()
+            .collect::<Option<Vec<f64>>>()
+            .ok_or_else(|| anyhow::anyhow!("Rerank response did not score every document"))
+    }
+}
+
+/// `GET /v1/models`, shared with other backends that expose the same listing.
+pub(crate) async fn fetch_model_list(client: &Client, base_url: &str) -> Result<Vec<String>> {
+    let response = client.get(format!("{}/v1/models", base_url)).send().await?;
+    let response = error_for_status(response, "List models").await?;
+    let models = response.json::<OpenAiModelsResponse>().await?;
+    Ok(models.data.into_iter().map(|m| m.id).collect())
+}
+
+/// `POST /v1/embeddings` for a single input.
+pub(crate) async fn fetch_embeddings(client: &Client, base_url: &str, model: &str, prompt: &str) -> Result<Vec<f64>> {
+    let response = client
+        .post(format!("{}/v1/embeddings", base_url))
+        .json(&json!({ "model": model, "input": prompt }))
+        .send()
+        .await?;
+    let response = error_for_status(response, "Embedding generation").await?;
+
+    let embedding_response = response.json::<OpenAiEmbeddingResponse>().await?;
+    embedding_response
+        .data
+        .into_iter()
+        .next()
+        .map(|e| e.embedding)
+        .ok_or_else(|| anyhow::anyhow!("Embedding response contained no data"))
+}
+
+/// `POST /v1/embeddings` with an array of inputs.
+pub(crate) async fn fetch_batch_embeddings(client: &Client, base_url: &str, model: &str, inputs: &[String]) -> Result<Vec<Vec<f64>>> {
+    let response = client
+        .post(format!("{}/v1/embeddings", base_url))
+        .json(&json!({ "model": model, "input": inputs }))
+        .send()
+        .await?;
+    let response = error_for_status(response, "Embedding generation").await?;
+
+    let mut data = response.json::<OpenAiEmbeddingResponse>().await?.data;
+    if data.len() != inputs.len() {
+        anyhow::bail!("Embedding response had {} vectors for {} inputs", data.len(), inputs.len());
+    }
+    data.sort_by_key(|e| e.index);
+    Ok(data.into_iter().map(|e| e.embedding).collect())
+}
+
+/// `GET /v1/models/{id}`, mapped onto [`ModelInfo`].
+pub(crate) async fn fetch_model_info(client: &Client, base_url: &str, name: &str) -> Result<ModelInfo> {
+    let response = client.get(format!("{}/v1/models/{}", base_url, name)).send().await?;
+    let response = error_for_status(response, "Show model").await?;
+    let value = response.json::<Value>().await?;
+    Ok(model_info_from_openai(&value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use wiremock::matchers::{body_partial_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn test_decoder_accumulates_tool_call_fragments() {
+        let mut decoder = OpenAiDecoder::default();
+        let lines = [
+            r#"data: {"choices":[{"delta":{"content":"Let me look."}}]}"#,
+            r#"data: {"choices":[{"delta":{"tool_calls":[{"index":0,"id":"call_1","type":"function","function":{"name":"read_","arguments":"{\"pa"}}]}}]}"#,
+            r#"data: {"choices":[{"delta":{"tool_calls":[{"index":0,"function":{"name":"file","arguments":"th\":\"a.txt\"}"}}]}}]}"#,
+            "data: [DONE]",
+        ];
+        let events: Vec<_> = lines.iter().flat_map(|l| decoder.line(l).unwrap()).collect();
+        assert_eq!(events, vec![ChatStreamEvent::Token("Let me look.".to_string())]);
+
+        let finished = decoder.finish();
+        assert_eq!(
+            finished,
+            vec![ChatStreamEvent::ToolCall(ToolCall {
+                id: Some("call_1".to_string()),
+                type_: "function".to_string(),
+                function: ToolCallFunction {
+                    name: "read_file".to_string(),
+                    arguments: json!({ "path": "a.txt" }),
+                },
+            })]
+        );
+    }
+
+    #[test]
+    fn test_decoder_surfaces_stream_errors() {
+        let mut decoder = OpenAiDecoder::default();
+        let err = decoder
+            .line(r#"data: {"error":{"message":"context length exceeded"}}"#)
+            .unwrap_err();
+        assert!(err.to_string().contains("context length exceeded"));
+    }
+
+    #[test]
+    fn test_tool_arguments_are_encoded_as_strings() {
+        let messages = vec![ChatMessageRequest {
+            role: "assistant".to_string(),
+            content: String::new(),
+            images: None,
+            tool_calls: Some(vec![ToolCall {
+                id: Some("call_1".to_string()),
+                type_: "function".to_string(),
+                function: ToolCallFunction {
+                    name: "read_file".to_string(),
+                    arguments: json!({ "path": "a.txt" }),
+                },
+            }]),
+            tool_name: None,
+            tool_call_id: None,
+        }];
+        let converted = to_openai_messages(&messages);
+        assert_eq!(converted[0]["tool_calls"][0]["function"]["arguments"], r#"{"path":"a.txt"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_chat_stream_and_embeddings() {
+        let mock_server = MockServer::start().await;
+        let backend = OpenAiBackend::new(mock_server.uri(), "secret");
+
+        let body = "data: {\"choices\":[{\"delta\":{\"content\":\"Hello\"}}]}\n\ndata: {\"choices\":[{\"delta\":{\"content\":\" World\"}}]}\n\ndata: [DONE]\n";
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(body))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1/embeddings"))
+            .and(body_partial_json(json!({ "model": "embed", "input": "hi" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "data": [{ "embedding": [0.5, 0.25] }]
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let mut stream = backend.chat("gpt", vec![], None, None).await.unwrap();
+        let mut response = String::new();
+        while let Some(event) = stream.next().await {
+            if let ChatStreamEvent::Token(t) = event.unwrap() {
+                response.push_str(&t);
+            }
+        }
+        assert_eq!(response, "Hello World");
+
+        assert_eq!(backend.embeddings("embed", "hi").await.unwrap(), vec![0.5, 0.25]);
+    }
+
+    #[tokio::test]
+    async fn test_batch_embeddings_follow_input_order() {
+        let mock_server = MockServer::start().await;
+        let backend = OpenAiBackend::new(mock_server.uri(), "");
+        Mock::given(method("POST"))
+            .and(path("/v1/embeddings"))
+            .and(body_partial_json(json!({ "input": ["a", "b"] })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "data": [{ "index": 1, "embedding": [2.0] }, { "index": 0, "embedding": [1.0] }]
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let inputs = vec!["a".to_string(), "b".to_string()];
+        assert_eq!(backend.embed_batch("embed", &inputs).await.unwrap(), vec![vec![1.0], vec![2.0]]);
+        assert!(backend.embed_batch("embed", &inputs[..1]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rerank_scores_follow_input_order() {
+        let mock_server = MockServer::start().await;
+        let backend = OpenAiBackend::new(mock_server.uri(), "");
+        Mock::given(method("POST"))
+            .and(path("/v1/rerank"))
+            .and(body_partial_json(json!({ "model": "bge-reranker", "query": "q", "documents": ["a", "b"] })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "results": [{ "index": 1, "relevance_score": 0.9 }, { "index": 0, "relevance_score": 0.2 }]
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let documents = vec!["a".to_string(), "b".to_string()];
+        assert_eq!(backend.rerank("bge-reranker", "q", &documents).await.unwrap(), vec![0.2, 0.9]);
+    }
+
+    #[tokio::test]
+    async fn test_rerank_returns_raw_scores() {
+        let mock_server = MockServer::start().await;
+        let backend = OpenAiBackend::new(mock_server.uri(), "");
+        Mock::given(method("POST"))
+            .and(path("/v1/rerank"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "results": [{ "index": 0, "relevance_score": -4.0 }, { "index": 1, "relevance_score": 0.0 }]
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let documents = vec!["a".to_string(), "b".to_string()];
+        // Normalising is left to the caller's `normalize` setting.
+        assert_eq!(backend.rerank("m", "q", &documents).await.unwrap(), vec![-4.0, 0.0]);
+        assert!(backend.rerank("m", "q", &["only".to_string(), "two".to_string(), "three".to_string()]).await.is_err());
+    }
+
+    #[test]
+    fn test_model_info_context_length() {
+        let info = model_info_from_openai(&json!({ "id": "m", "max_model_len": 32768 }));
+        assert_eq!(info.context_length(), Some(32768));
+        let info = model_info_from_openai(&json!({ "id": "m", "meta": { "n_ctx_train": 4096 } }));
+        assert_eq!(info.context_length(), Some(4096));
+        assert_eq!(model_info_from_openai(&json!({ "id": "m" })).context_length(), None);
+    }
+}
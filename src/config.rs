@@ -1,4 +1,9 @@
 use anyhow::Result;
+use crate::permissions::Permissions;
+use crate::rag::chunker::ChunkOptions;
+use crate::rag::embedder::EmbeddingOptions;
+use crate::rag::rerank::RerankOptions;
+use crate::tools::WorkspaceRoots;
 use directories::{BaseDirs, ProjectDirs};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -11,7 +16,7 @@ pub struct Config {
     #[serde(default = "default_ollama_url")]
     pub ollama_url: String,
     
-    /// Type of API to use ("ollama" or "openai").
+    /// Type of API to use ("ollama", "openai" or "anthropic").
     #[serde(default = "default_api_type")]
     pub api_type: String, 
     
@@ -30,6 +35,11 @@ pub struct Config {
     /// List of file/directory patterns to ignore in file operations.
     #[serde(default = "default_ignored_patterns")]
     pub ignored_patterns: Vec<String>,
+
+    /// Directories the file tools may touch: `[workspace]` with `read` and
+    /// `write` roots. Paths are checked after resolving symlinks.
+    #[serde(default)]
+    pub workspace: WorkspaceRoots,
     
     /// Whether to automatically detect optimal context size based on system resources.
     #[serde(default = "default_auto_context")]
@@ -69,10 +79,70 @@ pub struct Config {
     /// Map of named knowledge bases to their directory paths (e.g. "work" -> "~/Documents/Work").
     #[serde(default = "default_knowledge_bases")]
     pub knowledge_bases: HashMap<String, String>,
+
+    /// Whether to watch the `knowledge_bases` directories and re-index them in
+    /// the background as files change.
+    #[serde(default)]
+    pub watch_knowledge_bases: bool,
+
+    /// How long, in milliseconds, a watched knowledge base must be quiet
+    /// before changes are re-indexed.
+    #[serde(default = "default_watch_debounce_ms")]
+    pub watch_debounce_ms: u64,
+
+    /// Chunking settings per knowledge base (or collection), e.g.
+    /// `[chunking.work]` with `strategy = "markdown"` and `max_tokens = 300`.
+    #[serde(default)]
+    pub chunking: HashMap<String, ChunkOptions>,
+
+    /// Embedding throughput: `[embedding]` with `batch_size`, `concurrency`
+    /// and `max_retries`.
+    #[serde(default)]
+    pub embedding: EmbeddingOptions,
+
+    /// Automatic retrieval: `[rerank]` with `method` ("none", "cross_encoder"
+    /// or "llm"), `model`, `candidates`, `top_k`, `min_score` and
+    /// `normalize` ("none" or "sigmoid").
+    #[serde(default)]
+    pub rerank: RerankOptions,
+
+    /// How long tool output ingested into each collection is kept, e.g.
+    /// `[tool_output_ttl]` with `web = "7d"`. Collections not listed keep it
+    /// until forgotten.
+    #[serde(default = "default_tool_output_ttl")]
+    pub tool_output_ttl: HashMap<String, String>,
+
+    /// Which tool calls run, ask first or are refused: `[permissions]` with
+    /// the `commands` run_command may start, per-tool decisions under
+    /// `[permissions.tools]` and ordered `[[permissions.rules]]`.
+    #[serde(default)]
+    pub permissions: Permissions,
     
     /// Whether to enable automatic session renaming based on conversation content.
     #[serde(default = "default_enable_session_autonaming")]
     pub enable_session_autonaming: bool,
+
+    /// Name of the entry in `profiles` to connect with. When set, it overrides
+    /// `ollama_url`, `api_type` and `api_key`.
+    #[serde(default)]
+    pub active_profile: Option<String>,
+
+    /// Named server profiles (e.g. `[profiles.lmstudio]`) for switching backends.
+    #[serde(default)]
+    pub profiles: HashMap<String, BackendProfile>,
+}
+
+/// Connection settings for one chat server.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct BackendProfile {
+    /// Wire format: "ollama", "openai" or "anthropic".
+    #[serde(default = "default_api_type")]
+    pub api_type: String,
+    /// Base URL of the server.
+    pub url: String,
+    /// Optional API key.
+    #[serde(default)]
+    pub api_key: String,
 }
 
 fn default_enable_session_autonaming() -> bool {
@@ -115,6 +185,17 @@ fn default_knowledge_bases() -> HashMap<String, String> {
     HashMap::new()
 }
 
+fn default_tool_output_ttl() -> HashMap<String, String> {
+    HashMap::from([
+        ("default".to_string(), "30d".to_string()),
+        ("web".to_string(), "7d".to_string()),
+    ])
+}
+
+fn default_watch_debounce_ms() -> u64 {
+    2000
+}
+
 fn default_system_prompt() -> String {
     r#"You are `intus`, a highly capable AI assistant that functions as a proactive System Sidecar. You have direct access to the file system, web search, and local knowledge bases.
 
@@ -139,8 +220,13 @@ fn default_system_prompt() -> String {
   * Use this for: "Check weather", "News", "Find docs", "General knowledge".
   * **IMPORTANT**: For "latest news" or time-sensitive queries, INCLUDE the current date (from [System Context]) in your query string (e.g. "SpaceX launch Dec 20 2024").
 - `read_url(url)`: Read the content of a specific URL. Required after `web_search` to get page details.
-- `remember(fact)`: Save important facts to long-term memory.
+- `remember(fact, tags=[])`: Save important facts to long-term memory.
   * Use for: User preferences, project ports, specific file paths they mention often.
+- `list_memories(tag=null)`: List remembered facts with their ids.
+- `update_memory(id, fact=null, tags=null)`: Correct a remembered fact instead of saving a contradicting one.
+- `forget(id)`: Delete a remembered fact that is wrong or no longer wanted.
+- `manage_memory(action, collection=null, path_prefix=null, older_than=null)`: Inspect or prune indexed collections.
+  * `action`: "list", "stats", "forget" (by collection, path prefix and/or age like "30d"), "purge" or "reembed" (migrate a collection to the current embedding model).
 
 - `grep_files(query, path=".")`: Search for string content in files.
 - `read_file(path)`: Read exact file content.
@@ -152,8 +238,10 @@ fn default_system_prompt() -> String {
 - `write_file(path, content)`: Create or overwrite a file.
 - `edit_file(path, start_line, end_line, content)`: Replace lines in a file. **PREFERRED for code edits** as it avoids whitespace issues.
 - `replace_text(path, old_content, new_content)`: Replace a precise string block. Use only for simple, unique text.
-- `semantic_search(query, index_path=null, refresh=false)`: Search local knowledge.
+- `apply_patch(patch)`: Apply a unified diff (`--- a/path`, `+++ b/path`, `@@` hunks with context). **Use for several changes at once**, in one or more files.
+- `semantic_search(query, index_path=null, refresh=false, mode="hybrid")`: Search local knowledge.
   * **USE THIS for conceptual questions**: "What notes do I have on X?", "Recall Y".
+  * **mode**: `"keyword"` for exact identifiers, error codes or file names; `"vector"` for purely conceptual queries; `"hybrid"` (default) combines both.
   * **index_path**: Can be a literal path ("~/Documents") OR a knowledge base name ("work").
   * **Auto-Ingestion**: Remembers what you read and search.
 
@@ -217,7 +305,7 @@ impl Config {
             info!("Loading configuration from local ./config.toml");
             let contents = fs::read_to_string(local_config_path)?;
             let config: Config = toml::from_str(&contents)?;
-            return Ok(config);
+            return Ok(config.with_active_profile());
         }
 
         let config_path = if cfg!(target_os = "macos") || cfg!(target_os = "linux") {
@@ -239,7 +327,7 @@ impl Config {
                 info!("Loading configuration from {:?}", path);
                 let contents = fs::read_to_string(path)?;
                 let config: Config = toml::from_str(&contents)?;
-                return Ok(config);
+                return Ok(config.with_active_profile());
             }
         }
 
@@ -253,6 +341,7 @@ impl Config {
             context_token_limit: default_context_token_limit(),
             system_prompt: default_system_prompt(),
             ignored_patterns: default_ignored_patterns(),
+            workspace: WorkspaceRoots::default(),
             auto_context: default_auto_context(),
             summarization_enabled: default_summarization_enabled(),
             summarization_threshold: default_summarization_threshold(),
@@ -263,7 +352,16 @@ impl Config {
             location: None,
             enable_geolocation: default_enable_geolocation(),
             knowledge_bases: default_knowledge_bases(),
+            watch_knowledge_bases: false,
+            watch_debounce_ms: default_watch_debounce_ms(),
+            chunking: HashMap::new(),
+            embedding: EmbeddingOptions::default(),
+            rerank: RerankOptions::default(),
+            tool_output_ttl: default_tool_output_ttl(),
+            permissions: Permissions::default(),
             enable_session_autonaming: default_enable_session_autonaming(),
+            active_profile: None,
+            profiles: HashMap::new(),
         };
 
         // Try to save the default config
@@ -309,6 +407,7 @@ impl Config {
             context_token_limit: 4096,
             system_prompt: "You are helpful".to_string(),
             ignored_patterns: vec![],
+            workspace: WorkspaceRoots::default(),
             auto_context: true,
             summarization_enabled: true,
             summarization_threshold: 0.8,
@@ -319,7 +418,97 @@ impl Config {
             location: None,
             enable_geolocation: false,
             knowledge_bases: HashMap::new(),
+            watch_knowledge_bases: false,
+            watch_debounce_ms: default_watch_debounce_ms(),
+            chunking: HashMap::new(),
+            embedding: EmbeddingOptions::default(),
+            rerank: RerankOptions::default(),
+            tool_output_ttl: default_tool_output_ttl(),
+            permissions: Permissions::default(),
             enable_session_autonaming: false,
+            active_profile: None,
+            profiles: HashMap::new(),
         }
     }
-}
\ No newline at end of file
+
+    /// Switches the connection settings to the named profile.
+    pub fn use_profile(&mut self, name: &str) -> Result<()> {
+        let profile = self.profiles.get(name).cloned().ok_or_else(|| {
+            let mut known: Vec<&String> = self.profiles.keys().collect();
+            known.sort();
+            anyhow::anyhow!("Unknown profile '{}' (configured: {:?})", name, known)
+        })?;
+        self.ollama_url = profile.url;
+        self.api_type = profile.api_type;
+        self.api_key = profile.api_key;
+        self.active_profile = Some(name.to_string());
+        Ok(())
+    }
+
+    /// Applies `active_profile` after loading, falling back to the top-level
+    /// settings if it names a profile that doesn't exist.
+    fn with_active_profile(mut self) -> Self {
+        if let Some(name) = self.active_profile.clone() {
+            if let Err(e) = self.use_profile(&name) {
+                warn!("{}", e);
+            }
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_active_profile_overrides_connection() {
+        let config: Config = toml::from_str(
+            r#"
+            active_profile = "lmstudio"
+
+            [profiles.lmstudio]
+            api_type = "openai"
+            url = "http://localhost:1234"
+
+            [profiles.llamacpp]
+            api_type = "anthropic"
+            url = "http://localhost:8080"
+            api_key = "secret"
+            "#,
+        )
+        .unwrap();
+        let mut config = config.with_active_profile();
+        assert_eq!(config.ollama_url, "http://localhost:1234");
+        assert_eq!(config.api_type, "openai");
+
+        config.use_profile("llamacpp").unwrap();
+        assert_eq!(config.api_type, "anthropic");
+        assert_eq!(config.api_key, "secret");
+        assert!(config.use_profile("missing").is_err());
+
+        // Profiles must survive a save/load round trip.
+        let saved = toml::to_string_pretty(&config).unwrap();
+        let reloaded: Config = toml::from_str(&saved).unwrap();
+        assert_eq!(reloaded.profiles, config.profiles);
+    }
+
+    #[test]
+    fn test_chunking_per_knowledge_base() {
+        let config: Config = toml::from_str(
+            r#"
+            [knowledge_bases]
+            work = "~/Documents/Work"
+
+            [chunking.work]
+            strategy = "markdown"
+            max_tokens = 300
+            "#,
+        )
+        .unwrap();
+        let work = &config.chunking["work"];
+        assert_eq!(work.strategy, crate::rag::chunker::ChunkStrategy::Markdown);
+        assert_eq!(work.max_tokens, 300);
+        assert_eq!(work.overlap_tokens, ChunkOptions::default().overlap_tokens);
+    }
+}
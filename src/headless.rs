@@ -0,0 +1,256 @@
+//! Headless, one-shot execution of the agent loop.
+//!
+//! `intus ask` drives the same [`App`] state machine as the TUI (tools, RAG,
+//! generation) but never touches the terminal. Actions are pumped from the
+//! channel until the assistant produces an answer that does not request any
+//! further tools, and confirmation-requiring tools are approved or denied
+//! automatically according to [`AskOptions::auto_approve`].
+
+use crate::app::{Action, App, Mode};
+use crate::config::Config;
+use crate::ollama::ChatMessage;
+use anyhow::Result;
+use serde::Serialize;
+use tokio::sync::mpsc;
+
+/// Process exit code when the model produced an answer.
+pub const EXIT_OK: i32 = 0;
+/// Process exit code for connection, model or generation failures.
+pub const EXIT_ERROR: i32 = 1;
+/// Process exit code for invalid invocations (e.g. an empty prompt).
+pub const EXIT_USAGE: i32 = 2;
+/// Process exit code when generation finished without any answer text.
+pub const EXIT_NO_ANSWER: i32 = 3;
+/// Process exit code when the run was interrupted with Ctrl+C.
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+/// Options controlling a headless `ask` run.
+#[derive(Debug, Clone, Default)]
+pub struct AskOptions {
+    /// Model to use. Defaults to the first model reported by the server.
+    pub model: Option<String>,
+    /// Approve tools that require confirmation instead of denying them.
+    pub auto_approve: bool,
+    /// Print tool activity and status messages to stderr.
+    pub verbose: bool,
+}
+
+/// A confirmation decision taken on behalf of the user.
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolDecision {
+    pub tool: String,
+    pub arguments: serde_json::Value,
+    pub approved: bool,
+}
+
+/// The result of a headless run, serialisable as the `--json` transcript.
+#[derive(Debug, Clone, Serialize)]
+pub struct AskOutcome {
+    pub model: String,
+    pub prompt: String,
+    /// Final assistant answer with tool-call annotations stripped.
+    pub answer: String,
+    /// Full conversation, including tool calls and tool outputs.
+    pub messages: Vec<ChatMessage>,
+    /// Confirmations that were automatically approved or denied.
+    pub tool_decisions: Vec<ToolDecision>,
+    /// The last error reported by the agent loop, if any.
+    pub error: Option<String>,
+    #[serde(skip)]
+    pub interrupted: bool,
+}
+
+impl AskOutcome {
+    /// Maps the outcome to a process exit code.
+    pub fn exit_code(&self) -> i32 {
+        if self.interrupted {
+            EXIT_INTERRUPTED
+        } else if self.error.is_some() {
+            EXIT_ERROR
+        } else if self.answer.trim().is_empty() {
+            EXIT_NO_ANSWER
+        } else {
+            EXIT_OK
+        }
+    }
+}
+
+/// Builds the prompt from the positional argument and piped stdin.
+///
+/// Either part may be absent; when both are present the stdin content is
+/// appended below the prompt so `git diff | intus ask "review this"` works.
+pub fn compose_prompt(arg: Option<&str>, stdin: Option<&str>) -> Option<String> {
+    let arg = arg.map(str::trim).filter(|s| !s.is_empty() && *s != "-");
+    let stdin = stdin.map(str::trim_end).filter(|s| !s.trim().is_empty());
+    match (arg, stdin) {
+        (Some(a), Some(s)) => Some(format!("{}\n\n{}", a, s)),
+        (Some(a), None) => Some(a.to_string()),
+        (None, Some(s)) => Some(s.to_string()),
+        (None, None) => None,
+    }
+}
+
+/// Removes the `> **Tool Call:** ...` annotations the app appends to
+/// assistant messages for the TUI.
+fn strip_tool_annotations(content: &str) -> String {
+    content
+        .lines()
+        .filter(|l| !l.starts_with("> **Tool Call:**"))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+/// Runs a single prompt through the agent loop and returns the outcome.
+pub async fn run_ask(mut config: Config, prompt: String, options: AskOptions) -> Result<AskOutcome> {
+    // A one-shot run should never rename or rewrite the user's sessions.
+    config.enable_session_autonaming = false;
+
+    let (action_tx, mut action_rx) = mpsc::unbounded_channel();
+    let mut app = App::init(action_tx.clone(), config, false, None).await;
+    app.persist_session = false;
+
+    let model = match options.model.clone() {
+        Some(m) => m,
+        None => app
+            .ollama_client
+            .list_models()
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("No models available on the server"))?,
+    };
+    app.models = vec![model.clone()];
+    app.selected_model = 0;
+
+    // Fetch the context window for the model, as the TUI does on load.
+    if let Ok(info) = app.ollama_client.show_model(&model).await {
+        if let Some(limit) = info.context_length() {
+            app.update(Action::UpdateModelContextLimit(limit)).await;
+        }
+    }
+
+    let mut outcome = AskOutcome {
+        model,
+        prompt: prompt.clone(),
+        answer: String::new(),
+        messages: Vec::new(),
+        tool_decisions: Vec::new(),
+        error: None,
+        interrupted: false,
+    };
+
+    let _ = action_tx.send(Action::AddUserMessage(prompt));
+
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+
+    loop {
+        let action = tokio::select! {
+            action = action_rx.recv() => match action {
+                Some(a) => a,
+                None => break,
+            },
+            _ = &mut ctrl_c => {
+                outcome.interrupted = true;
+                let _ = app.update(Action::CancelGeneration).await;
+                break;
+            }
+        };
+
+        match &action {
+            Action::Render | Action::Resize(_, _) => continue,
+            Action::Quit => break,
+            Action::Error(e) => outcome.error = Some(e.clone()),
+            Action::ShowStatus(msg) if options.verbose => eprintln!("[status] {}", msg),
+            Action::AddToolCall(call) if options.verbose => {
+                eprintln!("[tool] {} {}", call.function.name, call.function.arguments)
+            }
+            _ => {}
+        }
+
+        app.update(action).await;
+
+        // A turn with several calls asks about each in turn.
+        while app.mode == Mode::ToolConfirmation && app.pending_tool_call.is_some() {
+            if let Some(call) = &app.pending_tool_call {
+                if options.verbose {
+                    let verdict = if options.auto_approve { "approved" } else { "denied" };
+                    eprintln!("[confirm] {} {}", call.function.name, verdict);
+                }
+                outcome.tool_decisions.push(ToolDecision {
+                    tool: call.function.name.clone(),
+                    arguments: call.function.arguments.clone(),
+                    approved: options.auto_approve,
+                });
+            }
+            let decision = if options.auto_approve {
+                Action::ConfirmToolExecution
+            } else {
+                Action::DenyToolExecution
+            };
+            app.update(decision).await;
+        }
+
+        let idle = !app.loading && !app.is_tool_executing && app.pending_tool_call.is_none();
+        if idle && action_rx.is_empty() {
+            break;
+        }
+    }
+
+    app.process_tracker.kill_all();
+
+    outcome.answer = app
+        .messages
+        .iter()
+        .rev()
+        .find(|m| m.role == "assistant")
+        .map(|m| strip_tool_annotations(&m.content))
+        .unwrap_or_default();
+    outcome.messages = app.messages;
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compose_prompt() {
+        assert_eq!(compose_prompt(Some("hi"), None), Some("hi".to_string()));
+        assert_eq!(compose_prompt(None, Some("piped\n")), Some("piped".to_string()));
+        assert_eq!(
+            compose_prompt(Some("review"), Some("diff --git a b\n")),
+            Some("review\n\ndiff --git a b".to_string())
+        );
+        assert_eq!(compose_prompt(Some("-"), Some("from stdin")), Some("from stdin".to_string()));
+        assert_eq!(compose_prompt(Some("  "), Some("\n")), None);
+    }
+
+    #[test]
+    fn test_strip_tool_annotations() {
+        let content = "Let me check.\n> **Tool Call:** `read_file`\nDone.";
+        assert_eq!(strip_tool_annotations(content), "Let me check.\nDone.");
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        let mut outcome = AskOutcome {
+            model: "m".to_string(),
+            prompt: "p".to_string(),
+            answer: "42".to_string(),
+            messages: Vec::new(),
+            tool_decisions: Vec::new(),
+            error: None,
+            interrupted: false,
+        };
+        assert_eq!(outcome.exit_code(), EXIT_OK);
+        outcome.answer.clear();
+        assert_eq!(outcome.exit_code(), EXIT_NO_ANSWER);
+        outcome.error = Some("Chat failed".to_string());
+        assert_eq!(outcome.exit_code(), EXIT_ERROR);
+        outcome.interrupted = true;
+        assert_eq!(outcome.exit_code(), EXIT_INTERRUPTED);
+    }
+}
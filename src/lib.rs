@@ -1,8 +1,11 @@
 pub mod app;
+pub mod backend;
 pub mod config;
 pub mod context;
+pub mod headless;
 pub mod health;
 pub mod ollama;
+pub mod permissions;
 pub mod process;
 pub mod tools;
 pub mod ui;
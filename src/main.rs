@@ -22,31 +22,125 @@ use intus::logging;
 use tracing::{info, warn};
 use std::io::Write;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use intus::headless::{self, AskOptions};
+use intus::rag::collections::{parse_age, ForgetFilter};
+use intus::rag::RagSystem;
+use intus::tools::expand_path;
+use std::io::{IsTerminal, Read};
 
 /// A robust, privacy-first local AI assistant and system sidecar.
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
-struct Cli {}
+struct Cli {
+    /// Connect using a named entry from `[profiles]` in config.toml.
+    #[arg(long, global = true)]
+    profile: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Answer a single prompt without the TUI and print the result to stdout.
+    ///
+    /// Piped stdin is appended to the prompt, e.g. `git diff | intus ask "review this"`.
+    Ask {
+        /// The prompt to send. Use `-` or omit it to read the prompt from stdin.
+        prompt: Option<String>,
+        /// Model to use (defaults to the first available model).
+        #[arg(short, long)]
+        model: Option<String>,
+        /// Approve tools that require confirmation. Without it they are denied.
+        #[arg(short = 'y', long)]
+        yes: bool,
+        /// Print a JSON transcript instead of the plain answer.
+        #[arg(long)]
+        json: bool,
+        /// Print tool calls and status updates to stderr.
+        #[arg(short, long)]
+        verbose: bool,
+    },
+    /// Inspect or prune the vector store behind semantic search and memory.
+    Memory {
+        #[command(subcommand)]
+        command: MemoryCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum MemoryCommand {
+    /// List collections and their chunk counts.
+    List,
+    /// Show chunks, files, embedding model and last-indexed time per collection.
+    Stats {
+        /// Only show this collection.
+        collection: Option<String>,
+    },
+    /// Remove the chunks matching all of the given filters.
+    Forget {
+        /// Only chunks in this collection.
+        #[arg(long)]
+        collection: Option<String>,
+        /// Only chunks whose source path starts with this prefix.
+        #[arg(long)]
+        path: Option<String>,
+        /// Only chunks indexed longer ago than this, e.g. `30d`, `12h`.
+        #[arg(long, value_name = "AGE")]
+        older_than: Option<String>,
+    },
+    /// Re-embed a collection with the configured embedding model, lifting
+    /// its quarantine after a model change.
+    Reembed {
+        /// The collection to migrate; all quarantined collections when omitted.
+        collection: Option<String>,
+    },
+    /// Remove every chunk from every collection.
+    Purge {
+        /// Confirm that everything should be removed.
+        #[arg(short = 'y', long)]
+        yes: bool,
+    },
+}
 
 /// The main entry point for the Ollama TUI application.
 ///
 /// This function:
 /// 1. Initializes logging.
-/// 2. Sets up the terminal in raw mode with mouse support.
-/// 3. Loads the application configuration.
+/// 2. Loads the application configuration (and dispatches `intus ask`).
+/// 3. Sets up the terminal in raw mode with mouse support.
 /// 4. Spawns a background task for handling input events.
 /// 5. Runs the main application loop.
 /// 6. Cleans up the terminal state upon exit.
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let _cli = Cli::parse();
+    let cli = Cli::parse();
 
     // Initialize logging
     let _ = logging::init_logging();
+
+    // Load configuration
+    let config = match load_config(cli.profile.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(headless::EXIT_USAGE);
+        }
+    };
+
+    if let Some(Commands::Ask { prompt, model, yes, json, verbose }) = cli.command {
+        let code = run_ask(config, prompt, AskOptions { model, auto_approve: yes, verbose }, json).await;
+        let _ = std::io::stdout().flush();
+        std::process::exit(code);
+    }
+
+    if let Some(Commands::Memory { command }) = cli.command {
+        std::process::exit(run_memory(&config, command).await);
+    }
+
     info!("Starting Intus");
 
-    // Load config
     enable_raw_mode()?;
     let mut stdout = io::stdout();
     execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
@@ -56,13 +150,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Create app state
     let (action_tx, mut action_rx) = mpsc::unbounded_channel();
 
-    // Load configuration
-    let config = Config::load().unwrap_or_else(|e| {
-        warn!("Failed to load config, using defaults. Error: {}", e);
-        Config::new_test_config()
-    });
-
+    let kb_watch_config = config.watch_knowledge_bases.then(|| config.clone());
     let mut app = App::init(action_tx.clone(), config, true, None).await;
+    if let Some(config) = kb_watch_config {
+        app.watch_knowledge_bases(&config);
+    }
 
     // Input handling task
     let input_handle = {
@@ -129,6 +221,158 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     std::process::exit(0);
 }
 
+/// Loads the configuration, falling back to defaults if it can't be read,
+/// and switches to `profile` if one was requested on the command line.
+fn load_config(profile: Option<&str>) -> anyhow::Result<Config> {
+    let mut config = Config::load().unwrap_or_else(|e| {
+        warn!("Failed to load config, using defaults. Error: {}", e);
+        Config::new_test_config()
+    });
+    if let Some(name) = profile {
+        config.use_profile(name)?;
+    }
+    Ok(config)
+}
+
+/// Runs `intus ask` and returns the process exit code.
+async fn run_ask(config: Config, prompt: Option<String>, options: AskOptions, json: bool) -> i32 {
+    let stdin = if io::stdin().is_terminal() {
+        None
+    } else {
+        let mut buf = String::new();
+        match io::stdin().read_to_string(&mut buf) {
+            Ok(_) => Some(buf),
+            Err(e) => {
+                eprintln!("Error: failed to read stdin: {}", e);
+                return headless::EXIT_USAGE;
+            }
+        }
+    };
+
+    let Some(prompt) = headless::compose_prompt(prompt.as_deref(), stdin.as_deref()) else {
+        eprintln!("Error: no prompt given (pass it as an argument or pipe it on stdin)");
+        return headless::EXIT_USAGE;
+    };
+
+    info!("Running headless ask");
+    let outcome = match headless::run_ask(config, prompt, options).await {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return headless::EXIT_ERROR;
+        }
+    };
+
+    if json {
+        match serde_json::to_string_pretty(&outcome) {
+            Ok(s) => println!("{}", s),
+            Err(e) => {
+                eprintln!("Error: failed to serialize transcript: {}", e);
+                return headless::EXIT_ERROR;
+            }
+        }
+    } else {
+        if !outcome.answer.is_empty() {
+            println!("{}", outcome.answer);
+        }
+        if let Some(err) = &outcome.error {
+            eprintln!("Error: {}", err);
+        }
+    }
+
+    outcome.exit_code()
+}
+
+/// Runs an `intus memory` subcommand and returns the process exit code.
+async fn run_memory(config: &Config, command: MemoryCommand) -> i32 {
+    let rag = RagSystem::from_config(config);
+    let result = match command {
+        MemoryCommand::List => rag.collection_stats().map(|stats| {
+            for s in stats {
+                println!("{}\t{}", s.name, s.chunks);
+            }
+        }),
+        MemoryCommand::Stats { collection } => rag.collection_stats().map(|stats| {
+            let stats: Vec<_> = stats
+                .into_iter()
+                .filter(|s| collection.as_ref().is_none_or(|c| s.name == *c))
+                .collect();
+            if stats.is_empty() {
+                println!("No indexed collections.");
+            }
+            for s in stats {
+                println!("{}", s.summary());
+            }
+        }),
+        MemoryCommand::Forget { collection, path, older_than } => {
+            let older_than = match older_than.as_deref().map(parse_age).transpose() {
+                Ok(age) => age,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    return headless::EXIT_USAGE;
+                }
+            };
+            let filter = ForgetFilter {
+                collection,
+                path_prefix: path.as_deref().map(expand_path),
+                older_than,
+            };
+            if filter.is_empty() {
+                eprintln!("Error: give --collection, --path and/or --older-than (or use `intus memory purge`)");
+                return headless::EXIT_USAGE;
+            }
+            rag.forget(&filter).await.map(|n| println!("Forgot {} chunks.", n))
+        }
+        MemoryCommand::Reembed { collection } => {
+            let collections = match collection {
+                Some(c) => vec![c],
+                None => {
+                    let mut quarantined: Vec<String> = rag.quarantined_collections().into_iter().collect();
+                    quarantined.sort();
+                    quarantined
+                }
+            };
+            if collections.is_empty() {
+                println!("No collections need re-embedding.");
+            }
+            let (tx, mut rx) = mpsc::unbounded_channel();
+            let progress = tokio::spawn(async move {
+                while let Some(msg) = rx.recv().await {
+                    eprintln!("{}", msg);
+                }
+            });
+            let mut result = Ok(());
+            for c in collections {
+                match rag.reembed(&c, Some(tx.clone())).await {
+                    Ok(n) => println!("Re-embedded {} chunks in '{}'.", n, c),
+                    Err(e) => {
+                        result = Err(e);
+                        break;
+                    }
+                }
+            }
+            drop(tx);
+            let _ = progress.await;
+            result
+        }
+        MemoryCommand::Purge { yes } => {
+            if !yes {
+                eprintln!("Error: purge removes every indexed chunk; pass --yes to confirm");
+                return headless::EXIT_USAGE;
+            }
+            rag.purge().await.map(|n| println!("Purged {} chunks.", n))
+        }
+    };
+
+    match result {
+        Ok(()) => headless::EXIT_OK,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            headless::EXIT_ERROR
+        }
+    }
+}
+
 async fn run_app<B: Backend>(
     terminal: &mut Terminal<B>,
     app: &mut App<'_>,
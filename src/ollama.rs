@@ -1,20 +1,19 @@
 use anyhow::Result;
-use futures::stream::StreamExt;
-use futures::Stream;
-use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use crate::backend::{build_backend, BackendKind, ChatBackend, ChatStream, PullStream};
 use std::collections::HashMap;
-use std::time::Duration;
+use std::sync::Arc;
 
 /// Client for interacting with the Ollama API.
+///
+/// Every call is delegated to a [`ChatBackend`] chosen from `api_type`.
+/// Pulling, deleting and listing running models only exist on Ollama;
+/// other backends fail those with [`crate::backend::Unsupported`].
 #[derive(Debug, Clone)]
 pub struct OllamaClient {
-    client: Client,
     base_url: String,
-    api_type: String, // "ollama" or "openai"
-    #[allow(dead_code)]
-    api_key: String,
+    backend: Arc<dyn ChatBackend>,
 }
 
 /// Represents a single message in the chat history.
@@ -41,17 +40,6 @@ pub struct ChatMessage {
     pub thought: Option<String>,
 }
 
-/// Represents a request to the chat API.
-#[derive(Serialize)]
-struct ChatRequest {
-    model: String,
-    messages: Vec<ChatMessageRequest>,
-    stream: bool,
-    options: Option<HashMap<String, Value>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    tools: Option<Vec<ToolDefinition>>,
-}
-
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct ChatMessageRequest {
     pub role: String,
@@ -68,89 +56,6 @@ pub struct ChatMessageRequest {
     pub tool_call_id: Option<String>,
 }
 
-#[derive(Deserialize)]
-struct ChatResponse {
-    message: ChatMessageResponse,
-    #[allow(dead_code)]
-    done: bool,
-}
-
-#[derive(Deserialize)]
-struct ChatMessageResponse {
-    #[allow(dead_code)]
-    role: String,
-    content: String,
-    tool_calls: Option<Vec<ToolCall>>,
-}
-
-#[derive(Deserialize)]
-struct OpenAiChatChunk {
-    choices: Vec<OpenAiChoice>,
-}
-
-#[derive(Deserialize)]
-struct OpenAiChoice {
-    delta: OpenAiDelta,
-    #[allow(dead_code)]
-    finish_reason: Option<String>,
-}
-
-#[derive(Deserialize)]
-struct OpenAiDelta {
-    content: Option<String>,
-    tool_calls: Option<Vec<OpenAiToolCallChunk>>,
-}
-
-#[derive(Deserialize)]
-struct OpenAiToolCallChunk {
-    #[allow(dead_code)]
-    index: Option<usize>,
-    #[allow(dead_code)]
-    id: Option<String>,
-    #[allow(dead_code)]
-    #[serde(rename = "type")]
-    type_: Option<String>,
-    function: Option<OpenAiFunctionChunk>,
-}
-
-#[derive(Deserialize)]
-struct OpenAiFunctionChunk {
-    name: Option<String>,
-    arguments: Option<String>,
-}
-
-
-#[derive(Deserialize)]
-struct ModelsResponse {
-    models: Vec<Model>,
-}
-
-#[derive(Deserialize)]
-struct OpenAiModelsResponse {
-    data: Vec<OpenAiModel>,
-}
-
-#[derive(Deserialize)]
-struct OpenAiModel {
-    id: String,
-}
-
-#[derive(Deserialize)]
-struct Model {
-    name: String,
-}
-
-#[derive(Serialize)]
-struct DeleteModelRequest {
-    name: String,
-}
-
-#[derive(Serialize)]
-struct PullModelRequest {
-    name: String,
-    stream: bool,
-}
-
 #[derive(Deserialize, Debug)]
 pub struct PullModelResponse {
     pub status: String,
@@ -161,7 +66,7 @@ pub struct PullModelResponse {
 }
 
 // Model Information Structures (from /api/show)
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, Default)]
 pub struct ModelInfo {
     #[serde(default)]
     pub modelfile: String,
@@ -206,11 +111,6 @@ impl ModelInfo {
     }
 }
 
-#[derive(Serialize)]
-struct ShowModelRequest {
-    name: String,
-}
-
 // Running Models Structures (from /api/ps)
 #[derive(Deserialize, Debug, Clone)]
 pub struct RunningModelsResponse {
@@ -281,164 +181,50 @@ impl OllamaClient {
     /// * `api_type` - The type of API ("ollama" or "openai").
     /// * `api_key` - Optional API key (mostly for OpenAI-compatible endpoints).
     pub fn new(base_url: String, api_type: String, api_key: String) -> Self {
-        let mut headers = reqwest::header::HeaderMap::new();
-        if !api_key.is_empty() {
-             if let Ok(val) = reqwest::header::HeaderValue::from_str(&format!("Bearer {}", api_key)) {
-                 headers.insert(reqwest::header::AUTHORIZATION, val);
-             }
-        }
+        let backend = build_backend(BackendKind::from_api_type(&api_type), base_url.clone(), api_key.clone());
+        Self::with_backend(base_url, backend)
+    }
 
-        Self {
-            client: Client::builder()
-                .default_headers(headers)
-                .timeout(Duration::from_secs(3600)) // 1 hour timeout
-                .build()
-                .unwrap_or_else(|_| Client::new()),
-            base_url,
-            api_type,
-            api_key,
-        }
+    /// Creates a client over an explicit backend, e.g. a custom implementation.
+    pub fn with_backend(base_url: String, backend: Arc<dyn ChatBackend>) -> Self {
+        Self { base_url, backend }
+    }
+
+    /// Base URL of the server.
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Returns the backend handling chat, models and embeddings.
+    pub fn backend(&self) -> &Arc<dyn ChatBackend> {
+        &self.backend
     }
 
     /// Lists all locally available models.
     pub async fn list_models(&self) -> Result<Vec<String>> {
-        if self.api_type == "openai" {
-            let response = self
-                .client
-                .get(&format!("{}/v1/models", self.base_url))
-                .send()
-                .await?
-                .json::<OpenAiModelsResponse>()
-                .await?;
-            Ok(response.data.into_iter().map(|m| m.id).collect())
-        } else {
-            let response = self
-                .client
-                .get(&format!("{}/api/tags", self.base_url))
-                .send()
-                .await?
-                .json::<ModelsResponse>()
-                .await?;
-
-            Ok(response.models.into_iter().map(|m| m.name).collect())
-        }
+        self.backend.list_models().await
     }
 
     /// Deletes a model from the local storage.
     pub async fn delete_model(&self, name: &str) -> Result<()> {
-        let request = DeleteModelRequest {
-            name: name.to_string(),
-        };
-        let response = self
-            .client
-            .delete(&format!("{}/api/delete", self.base_url))
-            .json(&request)
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-            return Err(anyhow::anyhow!("Delete failed: {}", response.status()));
-        }
-        Ok(())
+        self.backend.delete_model(name).await
     }
 
     /// Get detailed information about a model, including its context length
     pub async fn show_model(&self, name: &str) -> Result<ModelInfo> {
-        let request = ShowModelRequest {
-            name: name.to_string(),
-        };
-        let response = self
-            .client
-            .post(&format!("{}/api/show", self.base_url))
-            .json(&request)
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-            return Err(anyhow::anyhow!("Show model failed: {}", response.status()));
-        }
-
-        let model_info = response.json::<ModelInfo>().await?;
-        Ok(model_info)
+        self.backend.model_info(name).await
     }
 
     /// List currently running models with their VRAM usage
     pub async fn list_running(&self) -> Result<Vec<RunningModel>> {
-        let response = self
-            .client
-            .get(&format!("{}/api/ps", self.base_url))
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-            return Err(anyhow::anyhow!(
-                "List running failed: {}",
-                response.status()
-            ));
-        }
-
-        let running = response.json::<RunningModelsResponse>().await?;
-        Ok(running.models)
+        self.backend.list_running().await
     }
 
     /// Pulls a model from the Ollama library.
     ///
     /// Returns a stream of progress updates.
-    pub async fn pull_model(
-        &self,
-        name: &str,
-    ) -> Result<impl Stream<Item = Result<PullModelResponse, anyhow::Error>>> {
-        let request = PullModelRequest {
-            name: name.to_string(),
-            stream: true,
-        };
-        let response = self
-            .client
-            .post(&format!("{}/api/pull", self.base_url))
-            .json(&request)
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-            return Err(anyhow::anyhow!("Pull failed: {}", response.status()));
-        }
-
-        let stream = response.bytes_stream();
-        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
-
-        tokio::spawn(async move {
-            let mut stream = stream;
-            let mut buffer = Vec::new();
-
-            while let Some(chunk_result) = stream.next().await {
-                match chunk_result {
-                    Ok(bytes) => {
-                        buffer.extend_from_slice(&bytes);
-                        while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
-                            let line_bytes: Vec<u8> = buffer.drain(..=pos).collect();
-                            let s = String::from_utf8_lossy(&line_bytes);
-                            if let Ok(json) = serde_json::from_str::<PullModelResponse>(&s) {
-                                if let Err(_) = tx.send(Ok(json)) {
-                                    return;
-                                }
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        let _ = tx.send(Err(anyhow::anyhow!("Chunk error: {}", e)));
-                        return;
-                    }
-                }
-            }
-            if !buffer.is_empty() {
-                let s = String::from_utf8_lossy(&buffer);
-                if let Ok(json) = serde_json::from_str::<PullModelResponse>(&s) {
-                    let _ = tx.send(Ok(json));
-                }
-            }
-        });
-
-        Ok(tokio_stream::wrappers::UnboundedReceiverStream::new(rx))
+    pub async fn pull_model(&self, name: &str) -> Result<PullStream> {
+        self.backend.pull_model(name).await
     }
 
     /// Sends a chat request to the model.
@@ -457,247 +243,31 @@ impl OllamaClient {
         messages: Vec<ChatMessageRequest>,
         tools: Option<Vec<ToolDefinition>>,
         options: Option<HashMap<String, Value>>,
-    ) -> Result<impl Stream<Item = Result<ChatStreamEvent, anyhow::Error>>> {
-        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
-        let client = self.client.clone();
-        
-        if self.api_type == "openai" {
-            let url = format!("{}/v1/chat/completions", self.base_url);
-            
-            // Build OpenAI Request
-            let mut request_body = serde_json::Map::new();
-            request_body.insert("model".to_string(), serde_json::json!(model));
-            request_body.insert("messages".to_string(), serde_json::to_value(&messages)?);
-            request_body.insert("stream".to_string(), serde_json::json!(true));
-            if let Some(t) = tools {
-                request_body.insert("tools".to_string(), serde_json::to_value(t)?);
-            }
-            
-            // Unpack options into top-level
-            if let Some(opts) = options {
-                for (k, v) in opts {
-                    request_body.insert(k, v);
-                }
-            }
-            
-            let response = client.post(&url).json(&request_body).send().await?;
-             if !response.status().is_success() {
-                return Err(anyhow::anyhow!("Request failed: {}", response.status()));
-            }
-
-            let stream = response.bytes_stream();
-            
-            tokio::spawn(async move {
-                let mut stream = stream;
-                let mut buffer = Vec::new();
-                // Map index -> (id, type, name, args)
-                let mut accumulated_tool_calls: HashMap<usize, (String, String, String, String)> = HashMap::new();
-
-                while let Some(chunk_result) = stream.next().await {
-                     match chunk_result {
-                        Ok(bytes) => {
-                             buffer.extend_from_slice(&bytes);
-                             while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
-                                  let line_bytes: Vec<u8> = buffer.drain(..=pos).collect();
-                                  let line = String::from_utf8_lossy(&line_bytes).trim().to_string();
-                                  
-                                  if line.starts_with("data: ") {
-                                      let data = &line[6..];
-                                      if data == "[DONE]" {
-                                          continue;
-                                      }
-                                      if let Ok(chunk) = serde_json::from_str::<OpenAiChatChunk>(data) {
-                                           if let Some(choice) = chunk.choices.first() {
-                                               if let Some(content) = &choice.delta.content {
-                                                   if !content.is_empty() {
-                                                       let _ = tx.send(Ok(ChatStreamEvent::Token(content.clone())));
-                                                   }
-                                               }
-                                               
-                                               if let Some(tool_calls) = &choice.delta.tool_calls {
-                                                   for call_chunk in tool_calls {
-                                                       let idx = call_chunk.index.unwrap_or(0);
-                                                       let entry = accumulated_tool_calls.entry(idx).or_insert((String::new(), "function".to_string(), String::new(), String::new()));
-                                                       
-                                                       if let Some(id) = &call_chunk.id {
-                                                           entry.0 = id.clone();
-                                                       }
-                                                       if let Some(t) = &call_chunk.type_ {
-                                                           entry.1 = t.clone();
-                                                       }
-                                                       
-                                                       if let Some(func) = &call_chunk.function {
-                                                           if let Some(name) = &func.name {
-                                                               entry.2.push_str(name);
-                                                           }
-                                                           if let Some(args) = &func.arguments {
-                                                               entry.3.push_str(args);
-                                                           }
-                                                       }
-                                                   }
-                                               }
-                                           }
-                                      }
-                                  }
-                             }
-                        }
-                        Err(e) => {
-                            let _ = tx.send(Err(anyhow::anyhow!("Chunk error: {}", e)));
-                            return;
-                        }
-                     }
-                }
-                
-                // After stream ends, emit all accumulated tool calls
-                for (_, (id, type_, name, args)) in accumulated_tool_calls {
-                    if !name.is_empty() {
-                        let parsed_args = serde_json::from_str(&args).unwrap_or(serde_json::json!({}));
-                        let tool_call = ToolCall {
-                            id: if id.is_empty() { None } else { Some(id) },
-                            type_: if type_.is_empty() { "function".to_string() } else { type_ },
-                            function: ToolCallFunction {
-                                name,
-                                arguments: parsed_args,
-                            }
-                        };
-                        let _ = tx.send(Ok(ChatStreamEvent::ToolCall(tool_call)));
-                    }
-                }
-            });
-
-        } else {
-            // Ollama Implementation
-            let mut request_options = HashMap::new();
-            request_options.insert("num_predict".to_string(), serde_json::json!(-1)); // -1 = Infinite generation
-            
-            if let Some(opts) = options {
-                request_options.extend(opts);
-            }
-
-            let request = ChatRequest {
-                model: model.to_string(),
-                messages,
-                stream: true,
-                options: Some(request_options),
-                tools,
-            };
-
-            let response = self
-                .client
-                .post(&format!("{}/api/chat", self.base_url))
-                .json(&request)
-                .send()
-                .await?;
-
-            if !response.status().is_success() {
-                return Err(anyhow::anyhow!(
-                    "Request failed with status: {}",
-                    response.status()
-                ));
-            }
-
-            let stream = response.bytes_stream();
-
-            tokio::spawn(async move {
-                let mut stream = stream;
-                let mut buffer = Vec::new();
-
-                while let Some(chunk_result) = stream.next().await {
-                    match chunk_result {
-                        Ok(bytes) => {
-                            buffer.extend_from_slice(&bytes);
-
-                            // Process buffer for newlines
-                            while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
-                                let line_bytes: Vec<u8> = buffer.drain(..=pos).collect();
-                                let s = String::from_utf8_lossy(&line_bytes);
-                                if let Ok(json) = serde_json::from_str::<ChatResponse>(&s) {
-                                    // Emit Token if content exists
-                                    if !json.message.content.is_empty() {
-                                        if let Err(_) =
-                                            tx.send(Ok(ChatStreamEvent::Token(json.message.content)))
-                                        {
-                                            return;
-                                        }
-                                    }
-                                    // Emit ToolCall if exists
-                                    if let Some(calls) = json.message.tool_calls {
-                                        for call in calls {
-                                            if let Err(_) = tx.send(Ok(ChatStreamEvent::ToolCall(call)))
-                                            {
-                                                return;
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            let _ = tx.send(Err(anyhow::anyhow!("Chunk error: {}", e)));
-                            return;
-                        }
-                    }
-                }
-
-                // Process any remaining buffer
-                if !buffer.is_empty() {
-                    let s = String::from_utf8_lossy(&buffer);
-                    if let Ok(json) = serde_json::from_str::<ChatResponse>(&s) {
-                        if !json.message.content.is_empty() {
-                            let _ = tx.send(Ok(ChatStreamEvent::Token(json.message.content)));
-                        }
-                        if let Some(calls) = json.message.tool_calls {
-                            for call in calls {
-                                let _ = tx.send(Ok(ChatStreamEvent::ToolCall(call)));
-                            }
-                        }
-                    }
-                }
-            });
-        }
-
-        // Convert receiver to stream
-        let stream = tokio_stream::wrappers::UnboundedReceiverStream::new(rx);
-        Ok(stream)
+    ) -> Result<ChatStream> {
+        self.backend.chat(model, messages, tools, options).await
     }
 
     /// Generates vector embeddings for a given prompt.
     pub async fn generate_embeddings(&self, model: &str, prompt: &str) -> Result<Vec<f64>> {
-        let request = GenerateEmbeddingRequest {
-            model: model.to_string(),
-            prompt: prompt.to_string(),
-        };
-
-        let response = self
-            .client
-            .post(&format!("{}/api/embeddings", self.base_url))
-            .json(&request)
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-             return Err(anyhow::anyhow!("Embedding generation failed: {}", response.status()));
-        }
-
-        let embedding_response = response.json::<GenerateEmbeddingResponse>().await?;
-        Ok(embedding_response.embedding)
+        self.backend.embeddings(model, prompt).await
     }
-}
 
-#[derive(Serialize)]
-struct GenerateEmbeddingRequest {
-    model: String,
-    prompt: String,
-}
+    /// Generates one embedding per input in a single batch request where
+    /// the server supports it.
+    pub async fn generate_embeddings_batch(&self, model: &str, inputs: &[String]) -> Result<Vec<Vec<f64>>> {
+        self.backend.embed_batch(model, inputs).await
+    }
 
-#[derive(Deserialize)]
-struct GenerateEmbeddingResponse {
-    embedding: Vec<f64>,
+    /// Scores `documents` against `query` with a reranker model, in order.
+    pub async fn rerank(&self, model: &str, query: &str, documents: &[String]) -> Result<Vec<f64>> {
+        self.backend.rerank(model, query, documents).await
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use futures::StreamExt;
     use serde_json::json;
     use wiremock::matchers::{method, path};
     use wiremock::{Mock, MockServer, ResponseTemplate};
@@ -895,4 +465,50 @@ mod tests {
         assert_eq!(running[0].name, "llama2:latest");
         assert_eq!(running[0].size_vram, 4000000000);
     }
+
+    #[tokio::test]
+    async fn test_openai_compatible_parity() {
+        let mock_server = MockServer::start().await;
+        let client = OllamaClient::new(mock_server.uri(), "openai".to_string(), "".to_string());
+
+        Mock::given(method("GET"))
+            .and(path("/v1/models"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "data": [{ "id": "qwen2.5-7b" }] })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v1/models/qwen2.5-7b"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "id": "qwen2.5-7b", "max_model_len": 32768 })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1/embeddings"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "data": [{ "index": 0, "embedding": [0.5, 0.5] }] })))
+            .mount(&mock_server)
+            .await;
+
+        assert_eq!(client.list_models().await.unwrap(), vec!["qwen2.5-7b"]);
+        assert_eq!(client.show_model("qwen2.5-7b").await.unwrap().context_length(), Some(32768));
+        assert_eq!(client.generate_embeddings("embed", "hi").await.unwrap(), vec![0.5, 0.5]);
+        assert_eq!(
+            client.generate_embeddings_batch("embed", &["hi".to_string()]).await.unwrap(),
+            vec![vec![0.5, 0.5]]
+        );
+
+        // Model management has no OpenAI-compatible endpoint and never hits the server.
+        assert!(!client.backend().manages_models());
+        let err = client.list_running().await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<crate::backend::Unsupported>(),
+            Some(&crate::backend::Unsupported::new(BackendKind::OpenAi, "Listing running models"))
+        );
+        let err = client.pull_model("llama3").await.err().unwrap();
+        assert!(err.to_string().starts_with("Pulling models is not supported by OpenAI-compatible servers"));
+        assert!(client.delete_model("llama3").await.unwrap_err().is::<crate::backend::Unsupported>());
+        let requests = mock_server.received_requests().await.unwrap();
+        assert!(requests.iter().all(|r| r.url.path().starts_with("/v1/")));
+
+        let anthropic = OllamaClient::new(mock_server.uri(), "anthropic".to_string(), "".to_string());
+        assert!(anthropic.list_running().await.unwrap_err().to_string().contains("Anthropic-compatible"));
+    }
 }
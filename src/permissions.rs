@@ -0,0 +1,393 @@
+//! Declarative permissions for tool calls (`[permissions]` in the config).
+//!
+//! Every call is allowed, denied or put to the user. `rules` are checked in
+//! order and the first match decides; a rule names a tool and can narrow it
+//! to `run_command` calls starting with given words or to calls whose `path`
+//! matches a glob. Calls no rule matches get the tool's entry in `tools`,
+//! and tools not listed there ask only when they change something
+//! (`Tool::requires_confirmation`).
+
+use crate::tools::expand_path;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use tracing::warn;
+
+/// Shell operators that chain further commands onto a `run_command` call.
+const SHELL_OPERATORS: [&str; 7] = ["|", "&&", ";", ">", ">>", "<", "&"];
+
+/// What happens to a tool call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+    /// Run without asking.
+    Allow,
+    /// Ask the user first.
+    Ask,
+    /// Refuse the call.
+    Deny,
+}
+
+/// One `[[permissions.rules]]` entry.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rule {
+    /// Tool the rule applies to, or "*" for every tool.
+    pub tool: String,
+    /// Leading words of a `run_command` call, e.g. "git push". A `*` word
+    /// matches any word.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// Glob over the call's `path` argument, e.g. "~/Code/**". `*` stays
+    /// within a directory, `**` crosses directories.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    pub decision: Decision,
+}
+
+/// The `[permissions]` section.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Permissions {
+    /// Programs `run_command` may start at all.
+    pub commands: Vec<String>,
+    /// Decision per tool for calls no rule matches.
+    pub tools: HashMap<String, Decision>,
+    /// Rules checked in order before `tools`.
+    pub rules: Vec<Rule>,
+}
+
+impl Default for Permissions {
+    fn default() -> Self {
+        let commands = [
+            "git", "ls", "grep", "rg", "find", "cargo", "mkdir", "rmdir", "touch", "pwd", "date", "echo", "mv", "cp",
+            "stat", "curl", "wget", "jq", "sed", "awk", "python3", "node", "tree", "du", "chmod", "brew", "uv", "which",
+            "cat", "head", "tail",
+        ];
+        Self {
+            commands: commands.iter().map(|c| c.to_string()).collect(),
+            tools: HashMap::new(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+impl Permissions {
+    /// Decides a call to `tool` with `args`. `paths` are the files the call
+    /// touches (`Tool::target_paths`), or None to use its `path` argument.
+    /// `requires_confirmation` is the tool's own default, used when the
+    /// config says nothing about it.
+    pub fn decide(&self, tool: &str, args: &Value, paths: Option<&[PathBuf]>, requires_confirmation: bool) -> Decision {
+        let paths = call_paths(args, paths);
+        if let Some(rule) = self.rules.iter().find(|rule| rule.matches(tool, args, &paths)) {
+            return rule.decision;
+        }
+        match self.tools.get(tool) {
+            Some(decision) => *decision,
+            None if requires_confirmation => Decision::Ask,
+            None => Decision::Allow,
+        }
+    }
+
+    /// The key a decision remembered for the session is kept under for a
+    /// call, or None if such calls must be confirmed every time. Commands
+    /// are remembered by their whole command line, calls touching files by
+    /// the tool and those files, and other calls by the tool and the rule
+    /// they matched, so allowing `git log` doesn't allow `git push` and one
+    /// patch doesn't allow patching any file. Chained commands and commands
+    /// a rule asks about are never remembered.
+    pub fn session_key(&self, tool: &str, args: &Value, paths: Option<&[PathBuf]>) -> Option<String> {
+        let paths = call_paths(args, paths);
+        let rule = self.rules.iter().position(|rule| rule.matches(tool, args, &paths));
+        if rule.is_some_and(|i| self.rules[i].command.is_some() && self.rules[i].decision == Decision::Ask) {
+            return None;
+        }
+        if tool == "run_command" {
+            let words = command_words(args);
+            return (!is_chained(&words)).then(|| format!("{} {}", tool, words.join(" ")));
+        }
+        if !paths.is_empty() {
+            let paths: Vec<String> = paths.iter().map(|p| p.display().to_string()).collect();
+            return Some(format!("{} {}", tool, paths.join(", ")));
+        }
+        Some(match rule {
+            Some(i) => format!("{} (rule {})", tool, i + 1),
+            None => tool.to_string(),
+        })
+    }
+}
+
+impl Rule {
+    /// Whether the rule covers a call to `tool` with `args` touching
+    /// `paths`.
+    fn matches(&self, tool: &str, args: &Value, paths: &[PathBuf]) -> bool {
+        if self.tool != "*" && self.tool != tool {
+            return false;
+        }
+        if let Some(prefix) = &self.command {
+            let words = command_words(args);
+            // A chained command could do anything after the allowed prefix.
+            if (is_chained(&words) && self.decision == Decision::Allow) || !starts_with_words(&words, prefix) {
+                return false;
+            }
+        }
+        if let Some(pattern) = &self.path {
+            // Allowing needs every path covered; one is enough to ask or deny.
+            let matched = |path: &PathBuf| glob_matches(pattern, path);
+            let covered = match self.decision {
+                Decision::Allow => paths.iter().all(matched),
+                _ => paths.iter().any(matched),
+            };
+            if paths.is_empty() || !covered {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// The paths a call touches: `paths` when the tool reported them, else its
+/// `path` argument, normalised.
+fn call_paths(args: &Value, paths: Option<&[PathBuf]>) -> Vec<PathBuf> {
+    match paths {
+        Some(paths) => paths.iter().map(|p| normalize(&p.to_string_lossy())).collect(),
+        None => args.get("path").and_then(|v| v.as_str()).map(normalize).into_iter().collect(),
+    }
+}
+
+/// The words of a `run_command` call: its command followed by its args.
+fn command_words(args: &Value) -> Vec<String> {
+    let mut words: Vec<String> = args
+        .get("command")
+        .and_then(|v| v.as_str())
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(String::from)
+        .collect();
+    if let Some(extra) = args.get("args").and_then(|v| v.as_array()) {
+        words.extend(extra.iter().filter_map(|v| v.as_str()).flat_map(|s| s.split_whitespace()).map(String::from));
+    }
+    words
+}
+
+/// Whether a command chains further commands with shell operators.
+fn is_chained(words: &[String]) -> bool {
+    words.iter().any(|w| SHELL_OPERATORS.iter().any(|op| w.contains(op)))
+}
+
+fn starts_with_words(words: &[String], prefix: &str) -> bool {
+    let prefix: Vec<&str> = prefix.split_whitespace().collect();
+    words.len() >= prefix.len() && prefix.iter().zip(words).all(|(p, w)| *p == "*" || p == w)
+}
+
+/// `path` with `~` expanded, made absolute and with `.` and `..` resolved,
+/// so `~/Code/../.ssh` can't pass for a path under `~/Code`.
+fn normalize(path: &str) -> PathBuf {
+    let path = PathBuf::from(expand_path(path));
+    let path = if path.is_relative() {
+        std::env::current_dir().unwrap_or_default().join(path)
+    } else {
+        path
+    };
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// Whether `path` matches the glob `pattern`. A trailing `/**` also matches
+/// the directory itself.
+fn glob_matches(pattern: &str, path: &Path) -> bool {
+    let pattern = expand_path(pattern);
+    let mut regex = String::from("^");
+    let mut rest = pattern.as_str();
+    while let Some(c) = rest.chars().next() {
+        if rest == "/**" {
+            regex.push_str("(/.*)?");
+            break;
+        } else if let Some(tail) = rest.strip_prefix("**") {
+            regex.push_str(".*");
+            rest = tail;
+            continue;
+        }
+        match c {
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    regex.push('$');
+    match Regex::new(&regex) {
+        Ok(re) => re.is_match(&path.to_string_lossy()),
+        Err(e) => {
+            warn!("Ignoring permission path pattern '{}': {}", pattern, e);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn permissions(toml_str: &str) -> Permissions {
+        toml::from_str(toml_str).unwrap()
+    }
+
+    #[test]
+    fn test_tool_defaults() {
+        let policy = permissions(
+            r#"
+            [tools]
+            delete_file = "deny"
+            read_file = "ask"
+            "#,
+        );
+        assert_eq!(policy.decide("delete_file", &json!({}), None, true), Decision::Deny);
+        assert_eq!(policy.decide("read_file", &json!({}), None, false), Decision::Ask);
+        // Unlisted tools fall back to their own confirmation setting.
+        assert_eq!(policy.decide("write_file", &json!({}), None, true), Decision::Ask);
+        assert_eq!(policy.decide("grep", &json!({}), None, false), Decision::Allow);
+    }
+
+    #[test]
+    fn test_command_rules() {
+        let policy = permissions(
+            r#"
+            [[rules]]
+            tool = "run_command"
+            command = "git status"
+            decision = "allow"
+
+            [[rules]]
+            tool = "run_command"
+            command = "git push"
+            decision = "ask"
+
+            [[rules]]
+            tool = "run_command"
+            command = "git * --force"
+            decision = "deny"
+            "#,
+        );
+        let call = |command: &str, args: &[&str]| json!({ "command": command, "args": args });
+        assert_eq!(policy.decide("run_command", &call("git", &["status", "-s"]), None, true), Decision::Allow);
+        assert_eq!(policy.decide("run_command", &call("git status", &[]), None, true), Decision::Allow);
+        assert_eq!(policy.decide("run_command", &call("git", &["push", "origin", "main"]), None, true), Decision::Ask);
+        assert_eq!(policy.decide("run_command", &call("git", &["reset", "--force"]), None, true), Decision::Deny);
+        assert_eq!(policy.decide("run_command", &call("git", &["log"]), None, true), Decision::Ask);
+        // Chaining another command onto an allowed one needs confirmation.
+        assert_eq!(policy.decide("run_command", &call("git", &["status", ";", "rm", "-rf", "x"]), None, true), Decision::Ask);
+    }
+
+    #[test]
+    fn test_session_keys() {
+        let policy = permissions(
+            r#"
+            [[rules]]
+            tool = "run_command"
+            command = "git push"
+            decision = "ask"
+
+            [[rules]]
+            tool = "write_file"
+            path = "/home/me/Code/**"
+            decision = "ask"
+            "#,
+        );
+        let call = |command: &str, args: &[&str]| json!({ "command": command, "args": args });
+        let key = |tool: &str, args: Value| policy.session_key(tool, &args, None);
+        assert_eq!(key("run_command", call("git", &["status"])).as_deref(), Some("run_command git status"));
+        assert_eq!(key("run_command", call("git status", &[])).as_deref(), Some("run_command git status"));
+        assert_ne!(key("run_command", call("git", &["log"])), key("run_command", call("git", &["status"])));
+        assert_eq!(key("run_command", call("git", &["status", ";", "rm", "x"])), None);
+        assert_eq!(key("run_command", call("git", &["push", "origin"])), None);
+        // Calls touching files are remembered per file.
+        assert_eq!(key("write_file", json!({ "path": "/home/me/Code/a.rs" })).as_deref(), Some("write_file /home/me/Code/a.rs"));
+        assert_eq!(key("write_file", json!({ "path": "/tmp/../tmp/a.rs" })).as_deref(), Some("write_file /tmp/a.rs"));
+        let patch = [PathBuf::from("/home/me/Code/a.rs"), PathBuf::from("/home/me/Code/b.rs")];
+        assert_eq!(
+            policy.session_key("apply_patch", &json!({}), Some(&patch)).as_deref(),
+            Some("apply_patch /home/me/Code/a.rs, /home/me/Code/b.rs")
+        );
+        assert_eq!(key("write_file", json!({})).as_deref(), Some("write_file"));
+    }
+
+    #[test]
+    fn test_path_rules() {
+        let policy = permissions(
+            r#"
+            [tools]
+            write_file = "deny"
+
+            [[rules]]
+            tool = "write_file"
+            path = "/home/me/Code/**"
+            decision = "allow"
+
+            [[rules]]
+            tool = "*"
+            path = "/home/me/Code/*.lock"
+            decision = "deny"
+            "#,
+        );
+        let write = |path: &str| policy.decide("write_file", &json!({ "path": path, "content": "" }), None, true);
+        assert_eq!(write("/home/me/Code/intus/src/main.rs"), Decision::Allow);
+        assert_eq!(write("/home/me/Code"), Decision::Allow);
+        assert_eq!(write("/home/me/Notes/todo.md"), Decision::Deny);
+        assert_eq!(write("/home/me/Code/../.ssh/config"), Decision::Deny);
+        assert_eq!(write("/home/me/Codex/file"), Decision::Deny);
+        // Rules are checked in order, so the earlier allow wins.
+        assert_eq!(write("/home/me/Code/Cargo.lock"), Decision::Allow);
+        assert_eq!(policy.decide("delete_file", &json!({ "path": "/home/me/Code/Cargo.lock" }), None, true), Decision::Deny);
+        // Allowing needs every path a call touches covered; denying any.
+        let patch = |paths: &[&str]| {
+            let paths: Vec<PathBuf> = paths.iter().map(PathBuf::from).collect();
+            policy.decide("write_file", &json!({}), Some(&paths), true)
+        };
+        assert_eq!(patch(&["/home/me/Code/a.rs", "/home/me/Code/b.rs"]), Decision::Allow);
+        assert_eq!(patch(&["/home/me/Code/a.rs", "/home/me/Notes/todo.md"]), Decision::Deny);
+        assert_eq!(policy.decide("delete_file", &json!({}), Some(&[PathBuf::from("/home/me/Code/Cargo.lock")]), true), Decision::Deny);
+        // A call without a path doesn't match path rules.
+        assert_eq!(policy.decide("delete_file", &json!({}), None, true), Decision::Ask);
+    }
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob_matches("/a/*/c.txt", Path::new("/a/b/c.txt")));
+        assert!(!glob_matches("/a/*/c.txt", Path::new("/a/b/x/c.txt")));
+        assert!(glob_matches("/a/**/c.txt", Path::new("/a/b/x/c.txt")));
+        assert!(glob_matches("/a/file?.md", Path::new("/a/file1.md")));
+        assert!(!glob_matches("/a/file.md", Path::new("/a/fileXmd")));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let policy = permissions(
+            r#"
+            commands = ["git"]
+
+            [tools]
+            delete_file = "deny"
+
+            [[rules]]
+            tool = "run_command"
+            command = "git status"
+            decision = "allow"
+            "#,
+        );
+        let saved = toml::to_string_pretty(&policy).unwrap();
+        assert_eq!(toml::from_str::<Permissions>(&saved).unwrap(), policy);
+        assert_eq!(Permissions::default().commands.len(), 31);
+    }
+}
@@ -1,11 +1,40 @@
 use crate::ollama::ChatMessage;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
 use std::fs;
 use std::path::PathBuf;
 use tokio::sync::mpsc;
 use tokio::sync::oneshot;
 
+/// A saved conversation.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SessionFile {
+    pub messages: Vec<ChatMessage>,
+    /// Collections automatic retrieval and `semantic_search` draw from;
+    /// `None` means all of them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collections: Option<BTreeSet<String>>,
+}
+
+impl SessionFile {
+    /// Parses a saved session, including older ones that were a bare
+    /// message array.
+    pub fn parse(content: &str) -> serde_json::Result<Self> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Stored {
+            Session(SessionFile),
+            Messages(Vec<ChatMessage>),
+        }
+        Ok(match serde_json::from_str(content)? {
+            Stored::Session(session) => session,
+            Stored::Messages(messages) => SessionFile { messages, collections: None },
+        })
+    }
+}
+
 pub enum PersistenceEvent {
-    Save(PathBuf, Vec<ChatMessage>),
+    Save(PathBuf, SessionFile),
     Flush(oneshot::Sender<()>),
 }
 
@@ -21,9 +50,9 @@ impl SessionManager {
         tokio::spawn(async move {
             while let Some(event) = rx.recv().await {
                 match event {
-                    PersistenceEvent::Save(path, messages) => {
+                    PersistenceEvent::Save(path, session) => {
                         let _ = tokio::task::spawn_blocking(move || {
-                            if let Ok(json) = serde_json::to_string(&messages) {
+                            if let Ok(json) = serde_json::to_string(&session) {
                                 if let Some(parent) = path.parent() {
                                     let _ = fs::create_dir_all(parent);
                                 }
@@ -49,8 +78,8 @@ impl SessionManager {
         Self { tx }
     }
 
-    pub fn save_session(&self, path: PathBuf, messages: Vec<ChatMessage>) {
-        let _ = self.tx.send(PersistenceEvent::Save(path, messages));
+    pub fn save_session(&self, path: PathBuf, session: SessionFile) {
+        let _ = self.tx.send(PersistenceEvent::Save(path, session));
     }
 
     pub async fn wait_for_save(&self) {
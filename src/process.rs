@@ -26,6 +26,11 @@ impl ProcessTracker {
         pids.remove(&pid);
     }
 
+    /// Whether no processes are tracked.
+    pub fn is_empty(&self) -> bool {
+        self.pids.lock().unwrap().is_empty()
+    }
+
     /// Kill all tracked processes.
     pub fn kill_all(&self) {
         let pids = self.pids.lock().unwrap();
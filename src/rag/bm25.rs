@@ -0,0 +1,151 @@
+//! BM25 inverted index for lexical search.
+//!
+//! Embeddings are good at concepts but blur exact tokens: identifiers, error
+//! codes and file names. This index scores chunks by the query terms they
+//! actually contain. Like the ANN graph it is built from the chunk list and
+//! extended incrementally as chunks are appended.
+
+use std::collections::HashMap;
+
+/// Term-frequency saturation.
+const K1: f64 = 1.2;
+/// Document-length normalisation.
+const B: f64 = 0.75;
+
+/// Posting lists keyed by term. Document ids are chunk positions.
+#[derive(Debug, Clone, Default)]
+pub struct Bm25Index {
+    postings: HashMap<String, Vec<(u32, u32)>>,
+    doc_lens: Vec<u32>,
+    total_len: u64,
+}
+
+/// Splits text into lowercase terms. Runs of letters, digits and `_` form a
+/// term, so `config.toml` yields `config` and `toml` while `E0502` and
+/// `max_tokens` stay whole; snake_case identifiers also contribute their parts.
+pub fn tokenize(text: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    for word in text
+        .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|w| !w.is_empty())
+    {
+        let word = word.to_lowercase();
+        if word.contains('_') {
+            terms.extend(word.split('_').filter(|p| !p.is_empty()).map(str::to_string));
+        }
+        terms.push(word);
+    }
+    terms
+}
+
+impl Bm25Index {
+    /// Number of documents indexed.
+    pub fn len(&self) -> usize {
+        self.doc_lens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.doc_lens.is_empty()
+    }
+
+    /// Indexes `texts` as the next documents, continuing the id sequence.
+    pub fn extend<'a>(&mut self, texts: impl IntoIterator<Item = &'a str>) {
+        for text in texts {
+            let id = self.doc_lens.len() as u32;
+            let terms = tokenize(text);
+            let mut counts: HashMap<String, u32> = HashMap::new();
+            for term in &terms {
+                *counts.entry(term.clone()).or_default() += 1;
+            }
+            for (term, tf) in counts {
+                self.postings.entry(term).or_default().push((id, tf));
+            }
+            self.doc_lens.push(terms.len() as u32);
+            self.total_len += terms.len() as u64;
+        }
+    }
+
+    /// Returns up to `limit` `(document id, score)` pairs for `query`, best
+    /// first, considering only documents accepted by `matches`.
+    pub fn search(&self, query: &str, limit: usize, matches: impl Fn(usize) -> bool) -> Vec<(usize, f64)> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+        let n = self.len() as f64;
+        let avg_len = (self.total_len as f64 / n).max(1.0);
+
+        let mut terms = tokenize(query);
+        terms.sort();
+        terms.dedup();
+
+        let mut scores: HashMap<usize, f64> = HashMap::new();
+        for term in &terms {
+            let Some(postings) = self.postings.get(term) else {
+                continue;
+            };
+            let df = postings.len() as f64;
+            let idf = (1.0 + (n - df + 0.5) / (df + 0.5)).ln();
+            for &(id, tf) in postings {
+                let id = id as usize;
+                if !matches(id) {
+                    continue;
+                }
+                let tf = tf as f64;
+                let len_norm = 1.0 - B + B * self.doc_lens[id] as f64 / avg_len;
+                *scores.entry(id).or_default() += idf * tf * (K1 + 1.0) / (tf + K1 * len_norm);
+            }
+        }
+
+        let mut scored: Vec<(usize, f64)> = scores.into_iter().collect();
+        scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal).then(a.0.cmp(&b.0)));
+        scored.truncate(limit);
+        scored
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(tokenize("See config.toml"), vec!["see", "config", "toml"]);
+        assert_eq!(tokenize("error[E0502]: borrow"), vec!["error", "e0502", "borrow"]);
+        assert_eq!(tokenize("max_tokens"), vec!["max", "tokens", "max_tokens"]);
+    }
+
+    #[test]
+    fn test_exact_terms_rank_first() {
+        let mut index = Bm25Index::default();
+        index.extend([
+            "The borrow checker rejects this code with a long explanation of lifetimes",
+            "error[E0502]: cannot borrow `x` as mutable because it is also borrowed as immutable",
+            "Unrelated notes about the weekly grocery list",
+        ]);
+        assert_eq!(index.len(), 3);
+
+        let hits = index.search("E0502", 10, |_| true);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].0, 1);
+
+        let hits = index.search("borrow", 10, |_| true);
+        assert_eq!(hits.len(), 2);
+        assert!(hits.iter().all(|(id, _)| *id != 2));
+
+        assert!(index.search("E0502", 10, |id| id != 1).is_empty());
+        assert!(index.search("nothing matches", 10, |_| true).is_empty());
+    }
+
+    #[test]
+    fn test_rare_terms_outweigh_common_ones() {
+        let mut index = Bm25Index::default();
+        index.extend([
+            "fn main config loader",
+            "fn main renderer",
+            "fn main parse_args",
+            "fn helper",
+        ]);
+        let hits = index.search("main parse_args", 10, |_| true);
+        assert_eq!(hits[0].0, 2);
+    }
+}
@@ -0,0 +1,591 @@
+//! Splitting documents into chunks for embedding.
+//!
+//! Every chunker works on whole lines and reports the 1-based line range of
+//! each chunk, so search hits can point back into the file. Sizes are
+//! budgeted in estimated tokens (about four bytes each) rather than lines.
+//!
+//! * [`MarkdownChunker`] splits on headings and prefixes each chunk with its
+//!   heading path (`Install > Linux`), so a section keeps its context.
+//! * [`CodeChunker`] splits on top-level items (functions, types, impls,
+//!   classes), keeping attached doc comments, attributes and decorators.
+//! * [`ProseChunker`] packs paragraphs up to the budget with some overlap.
+
+use crate::tools::TextChunk;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// A piece of a document and its 1-based, inclusive line range.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    pub content: String,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+pub trait Chunker: Send + Sync {
+    fn chunk(&self, text: &str) -> Vec<Chunk>;
+}
+
+/// How to pick a chunker for a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChunkStrategy {
+    /// By file extension: Markdown, code, or prose for everything else.
+    #[default]
+    Auto,
+    Markdown,
+    Code,
+    Prose,
+}
+
+/// Chunking settings, configurable per knowledge base.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChunkOptions {
+    #[serde(default)]
+    pub strategy: ChunkStrategy,
+    /// Upper bound on the estimated tokens in a chunk.
+    #[serde(default = "default_max_tokens")]
+    pub max_tokens: usize,
+    /// Estimated tokens repeated at the start of the next chunk when text
+    /// has to be split mid-section.
+    #[serde(default = "default_overlap_tokens")]
+    pub overlap_tokens: usize,
+}
+
+fn default_max_tokens() -> usize {
+    400
+}
+
+fn default_overlap_tokens() -> usize {
+    50
+}
+
+impl Default for ChunkOptions {
+    fn default() -> Self {
+        Self {
+            strategy: ChunkStrategy::Auto,
+            max_tokens: default_max_tokens(),
+            overlap_tokens: default_overlap_tokens(),
+        }
+    }
+}
+
+/// Rough token count used for budgeting.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.len().div_ceil(4)
+}
+
+/// Picks the chunker for `path` according to `options`.
+pub fn chunker_for(path: &Path, options: &ChunkOptions) -> Box<dyn Chunker> {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase())
+        .unwrap_or_default();
+    let language = Language::from_extension(&ext);
+    let strategy = match options.strategy {
+        // Notebooks, HTML and EPUB are extracted to Markdown-style text.
+        ChunkStrategy::Auto if matches!(ext.as_str(), "md" | "markdown" | "mdx" | "ipynb" | "html" | "htm" | "xhtml" | "epub") => {
+            ChunkStrategy::Markdown
+        }
+        ChunkStrategy::Auto if language != Language::Other => ChunkStrategy::Code,
+        ChunkStrategy::Auto => ChunkStrategy::Prose,
+        explicit => explicit,
+    };
+    match strategy {
+        ChunkStrategy::Markdown => Box::new(MarkdownChunker { options: options.clone() }),
+        ChunkStrategy::Code => Box::new(CodeChunker {
+            language,
+            options: options.clone(),
+        }),
+        _ => Box::new(ProseChunker { options: options.clone() }),
+    }
+}
+
+/// Chunks a file's content into `TextChunk`s (without embeddings).
+pub fn chunk_file(path: &str, content: &str, collection: &str, options: &ChunkOptions) -> Vec<TextChunk> {
+    chunker_for(Path::new(path), options)
+        .chunk(content)
+        .into_iter()
+        .map(|c| TextChunk {
+            file_path: path.to_string(),
+            content: c.content,
+            start_line: c.start_line,
+            end_line: c.end_line,
+            embedding: vec![],
+            collection: collection.to_string(),
+            indexed_at: 0,
+            content_hash: 0,
+            provenance: None,
+        })
+        .collect()
+}
+
+/// A run of text and the lines it came from.
+#[derive(Debug, Clone)]
+struct Unit {
+    start: usize,
+    end: usize,
+    text: String,
+}
+
+impl Unit {
+    /// Tokens including the separator it is joined with, so a packed
+    /// chunk never estimates above its budget.
+    fn tokens(&self) -> usize {
+        (self.text.len() + 1).div_ceil(4)
+    }
+}
+
+fn line_units(lines: &[&str], first: usize) -> Vec<Unit> {
+    lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| Unit {
+            start: first + i,
+            end: first + i,
+            text: line.to_string(),
+        })
+        .collect()
+}
+
+/// Groups blank-line separated runs of `lines` (numbered from `first`).
+fn paragraph_units(lines: &[&str], first: usize) -> Vec<Unit> {
+    let mut units = Vec::new();
+    let mut current: Option<Unit> = None;
+    for (i, line) in lines.iter().enumerate() {
+        if line.trim().is_empty() {
+            units.extend(current.take());
+            continue;
+        }
+        match &mut current {
+            Some(unit) => {
+                unit.text.push('\n');
+                unit.text.push_str(line);
+                unit.end = first + i;
+            }
+            None => {
+                current = Some(Unit {
+                    start: first + i,
+                    end: first + i,
+                    text: line.to_string(),
+                })
+            }
+        }
+    }
+    units.extend(current);
+    units
+}
+
+/// Breaks a unit over the budget into lines, and a line over the budget
+/// into whitespace-separated pieces.
+fn split_oversized(unit: Unit, max_tokens: usize) -> Vec<Unit> {
+    if unit.tokens() <= max_tokens {
+        return vec![unit];
+    }
+    if unit.start != unit.end {
+        let lines: Vec<&str> = unit.text.lines().collect();
+        return line_units(&lines, unit.start)
+            .into_iter()
+            .flat_map(|u| split_oversized(u, max_tokens))
+            .collect();
+    }
+
+    let max_bytes = (max_tokens.max(1) * 4).saturating_sub(1).max(1);
+    let mut pieces = Vec::new();
+    let mut piece = String::new();
+    for word in unit.text.split_whitespace() {
+        if !piece.is_empty() && piece.len() + 1 + word.len() > max_bytes {
+            pieces.push(std::mem::take(&mut piece));
+        }
+        if !piece.is_empty() {
+            piece.push(' ');
+        }
+        piece.push_str(word);
+    }
+    if !piece.is_empty() {
+        pieces.push(piece);
+    }
+    pieces
+        .into_iter()
+        .map(|text| Unit {
+            start: unit.start,
+            end: unit.end,
+            text,
+        })
+        .collect()
+}
+
+/// Greedily packs units into chunks of at most `max_tokens`, starting each
+/// chunk after the first with trailing units worth up to `overlap_tokens`.
+fn pack(units: Vec<Unit>, max_tokens: usize, overlap_tokens: usize) -> Vec<Chunk> {
+    let units: Vec<Unit> = units.into_iter().flat_map(|u| split_oversized(u, max_tokens)).collect();
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < units.len() {
+        let mut end = start;
+        let mut tokens = 0;
+        while end < units.len() && (end == start || tokens + units[end].tokens() <= max_tokens) {
+            tokens += units[end].tokens();
+            end += 1;
+        }
+        chunks.push(join_units(&units[start..end]));
+        if end == units.len() {
+            break;
+        }
+
+        let mut next = end;
+        let mut overlap = 0;
+        while next > start + 1 && overlap + units[next - 1].tokens() <= overlap_tokens {
+            next -= 1;
+            overlap += units[next].tokens();
+        }
+        start = next;
+    }
+    chunks
+}
+
+fn join_units(units: &[Unit]) -> Chunk {
+    let mut content = String::new();
+    for (i, unit) in units.iter().enumerate() {
+        if i > 0 {
+            let prev = &units[i - 1];
+            content.push_str(if unit.start == prev.end {
+                " "
+            } else if unit.start > prev.end + 1 {
+                "\n\n"
+            } else {
+                "\n"
+            });
+        }
+        content.push_str(&unit.text);
+    }
+    Chunk {
+        content,
+        start_line: units[0].start,
+        end_line: units[units.len() - 1].end,
+    }
+}
+
+/// Paragraph packing for plain text.
+pub struct ProseChunker {
+    pub options: ChunkOptions,
+}
+
+impl Chunker for ProseChunker {
+    fn chunk(&self, text: &str) -> Vec<Chunk> {
+        let lines: Vec<&str> = text.lines().collect();
+        pack(paragraph_units(&lines, 1), self.options.max_tokens, self.options.overlap_tokens)
+    }
+}
+
+/// One chunk per heading section; long sections are split like prose.
+pub struct MarkdownChunker {
+    pub options: ChunkOptions,
+}
+
+/// Parses an ATX heading (`## Title`) into its level and title.
+fn heading(line: &str) -> Option<(usize, String)> {
+    if line.starts_with("    ") {
+        return None;
+    }
+    let trimmed = line.trim_start();
+    let level = trimmed.chars().take_while(|c| *c == '#').count();
+    if !(1..=6).contains(&level) {
+        return None;
+    }
+    let rest = &trimmed[level..];
+    if !rest.is_empty() && !rest.starts_with(' ') && !rest.starts_with('\t') {
+        return None;
+    }
+    Some((level, rest.trim().trim_end_matches('#').trim_end().to_string()))
+}
+
+impl Chunker for MarkdownChunker {
+    fn chunk(&self, text: &str) -> Vec<Chunk> {
+        let lines: Vec<&str> = text.lines().collect();
+
+        // (heading path, first line index, end line index exclusive)
+        let mut sections: Vec<(Vec<String>, usize, usize)> = Vec::new();
+        let mut stack: Vec<(usize, String)> = Vec::new();
+        let mut section_start = 0;
+        let mut in_fence = false;
+        for (i, line) in lines.iter().enumerate() {
+            let trimmed = line.trim_start();
+            if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+                in_fence = !in_fence;
+                continue;
+            }
+            if in_fence {
+                continue;
+            }
+            if let Some((level, title)) = heading(line) {
+                if i > section_start {
+                    sections.push((stack.iter().map(|(_, t)| t.clone()).collect(), section_start, i));
+                }
+                while stack.last().is_some_and(|(l, _)| *l >= level) {
+                    stack.pop();
+                }
+                stack.push((level, title));
+                section_start = i;
+            }
+        }
+        if lines.len() > section_start {
+            sections.push((stack.iter().map(|(_, t)| t.clone()).collect(), section_start, lines.len()));
+        }
+
+        let mut chunks = Vec::new();
+        for (path, start, end) in sections {
+            let body = &lines[start..end];
+            let has_heading = !path.is_empty() && heading(body[0]).is_some();
+            // A heading with nothing under it lives on in its children's paths.
+            let content_lines = if has_heading { &body[1..] } else { body };
+            if content_lines.iter().all(|l| l.trim().is_empty()) {
+                continue;
+            }
+
+            let breadcrumb = path.join(" > ");
+            let budget = self
+                .options
+                .max_tokens
+                .saturating_sub(estimate_tokens(&breadcrumb))
+                .max(self.options.max_tokens / 2);
+            for mut chunk in pack(paragraph_units(body, start + 1), budget, self.options.overlap_tokens) {
+                // A top-level section's own heading already names it.
+                let starts_at_heading = has_heading && chunk.start_line == start + 1;
+                let redundant = breadcrumb.is_empty() || (starts_at_heading && path.len() == 1);
+                if !redundant {
+                    chunk.content = format!("{}\n\n{}", breadcrumb, chunk.content);
+                }
+                chunks.push(chunk);
+            }
+        }
+        chunks
+    }
+}
+
+/// Languages the code chunker knows how to find item boundaries in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    Rust,
+    Python,
+    JavaScript,
+    Other,
+}
+
+impl Language {
+    pub fn from_extension(ext: &str) -> Self {
+        match ext {
+            "rs" => Self::Rust,
+            "py" | "pyi" => Self::Python,
+            "js" | "jsx" | "mjs" | "cjs" | "ts" | "tsx" => Self::JavaScript,
+            _ => Self::Other,
+        }
+    }
+
+    /// Lines that belong to the item that follows them: doc comments,
+    /// attributes and decorators.
+    fn is_leading_line(self, line: &str) -> bool {
+        let c_comment = line.starts_with("//") || line.starts_with("/*") || line.starts_with('*');
+        match self {
+            Self::Rust => c_comment || line.starts_with("#[") || line.starts_with("#!["),
+            Self::Python => line.starts_with('#') || line.starts_with('@'),
+            Self::JavaScript => c_comment || line.starts_with('@'),
+            Self::Other => c_comment || line.starts_with('#') || line.starts_with('@'),
+        }
+    }
+}
+
+/// Splits source files on top-level items and packs small neighbouring
+/// items (imports, constants) together.
+pub struct CodeChunker {
+    pub language: Language,
+    pub options: ChunkOptions,
+}
+
+impl CodeChunker {
+    /// Index of the first line of every top-level item.
+    fn item_starts(&self, lines: &[&str]) -> Vec<usize> {
+        let mut starts = vec![0];
+        let mut prev: Option<&str> = None;
+        for (i, line) in lines.iter().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let continuation = line.starts_with(char::is_whitespace)
+                || line.starts_with(['}', ')', ']'])
+                || (self.language == Language::Python && line.starts_with(['"', '\'']));
+            let attached = prev.is_some_and(|p| self.language.is_leading_line(p));
+            if !continuation && !attached && i > 0 {
+                starts.push(i);
+            }
+            prev = Some(line);
+        }
+        starts
+    }
+}
+
+impl Chunker for CodeChunker {
+    fn chunk(&self, text: &str) -> Vec<Chunk> {
+        let lines: Vec<&str> = text.lines().collect();
+        if lines.is_empty() {
+            return Vec::new();
+        }
+
+        let mut starts = self.item_starts(&lines);
+        starts.push(lines.len());
+
+        let mut chunks = Vec::new();
+        let mut run: Vec<Unit> = Vec::new();
+        for bounds in starts.windows(2) {
+            let mut item = &lines[bounds[0]..bounds[1]];
+            while item.last().is_some_and(|l| l.trim().is_empty()) {
+                item = &item[..item.len() - 1];
+            }
+            if item.is_empty() {
+                continue;
+            }
+            let unit = Unit {
+                start: bounds[0] + 1,
+                end: bounds[0] + item.len(),
+                text: item.join("\n"),
+            };
+            if unit.tokens() > self.options.max_tokens {
+                // Large items are split on their own, with overlap.
+                chunks.extend(pack(std::mem::take(&mut run), self.options.max_tokens, 0));
+                chunks.extend(pack(line_units(item, bounds[0] + 1), self.options.max_tokens, self.options.overlap_tokens));
+            } else {
+                run.push(unit);
+            }
+        }
+        chunks.extend(pack(run, self.options.max_tokens, 0));
+        chunks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(max_tokens: usize, overlap_tokens: usize) -> ChunkOptions {
+        ChunkOptions {
+            strategy: ChunkStrategy::Auto,
+            max_tokens,
+            overlap_tokens,
+        }
+    }
+
+    #[test]
+    fn test_markdown_sections_carry_heading_path() {
+        let text = "# Guide\nIntro text.\n\n## Install\n\n### Linux\nRun the installer.\n\n## Usage\nStart it.\n```sh\n# not a heading\n```\n";
+        let chunks = MarkdownChunker { options: options(400, 0) }.chunk(text);
+        let contents: Vec<&str> = chunks.iter().map(|c| c.content.as_str()).collect();
+        assert_eq!(
+            contents,
+            vec![
+                "# Guide\nIntro text.",
+                "Guide > Install > Linux\n\n### Linux\nRun the installer.",
+                "Guide > Usage\n\n## Usage\nStart it.\n```sh\n# not a heading\n```",
+            ]
+        );
+        assert_eq!((chunks[1].start_line, chunks[1].end_line), (6, 7));
+        assert_eq!((chunks[2].start_line, chunks[2].end_line), (9, 13));
+    }
+
+    #[test]
+    fn test_long_markdown_section_is_split_with_breadcrumb() {
+        let para = "word ".repeat(60);
+        let text = format!("# Notes\n\n{}\n\n{}\n\n{}\n", para, para, para);
+        let chunks = MarkdownChunker { options: options(100, 0) }.chunk(&text);
+        assert!(chunks.len() >= 3);
+        assert!(chunks[1..].iter().all(|c| c.content.starts_with("Notes\n\n")));
+    }
+
+    #[test]
+    fn test_rust_items_keep_doc_comments_and_attributes() {
+        let text = "use std::fmt;\nuse std::io;\n\n/// A point.\n#[derive(Debug)]\npub struct Point {\n    x: i32,\n}\n\nimpl Point {\n    fn new() -> Self {\n        Point { x: 0 }\n    }\n}\n";
+        let chunker = CodeChunker {
+            language: Language::Rust,
+            options: options(20, 0),
+        };
+        let chunks = chunker.chunk(text);
+        assert_eq!(chunks.len(), 3, "{:#?}", chunks);
+        assert_eq!(chunks[0].content, "use std::fmt;\nuse std::io;");
+        assert!(chunks[1].content.starts_with("/// A point.\n#[derive(Debug)]\npub struct Point"));
+        assert_eq!((chunks[1].start_line, chunks[1].end_line), (4, 8));
+        assert!(chunks[2].content.starts_with("impl Point"));
+        assert_eq!(chunks[2].end_line, 14);
+    }
+
+    #[test]
+    fn test_small_items_are_packed_together() {
+        let text = "import os\n\n@dataclass\nclass A:\n    x: int\n\ndef f():\n    return 1\n";
+        let chunks = CodeChunker {
+            language: Language::Python,
+            options: options(400, 0),
+        }
+        .chunk(text);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!((chunks[0].start_line, chunks[0].end_line), (1, 8));
+
+        let chunks = CodeChunker {
+            language: Language::Python,
+            options: options(8, 0),
+        }
+        .chunk(text);
+        let starts: Vec<usize> = chunks.iter().map(|c| c.start_line).collect();
+        assert_eq!(starts, vec![1, 3, 7]);
+    }
+
+    #[test]
+    fn test_oversized_function_is_split_by_lines() {
+        let body: String = (0..40).map(|i| format!("    let v{} = {};\n", i, i)).collect();
+        let text = format!("function big() {{\n{}}}\n", body);
+        let chunks = CodeChunker {
+            language: Language::JavaScript,
+            options: options(60, 10),
+        }
+        .chunk(&text);
+        assert!(chunks.len() > 1);
+        assert_eq!(chunks[0].start_line, 1);
+        assert_eq!(chunks.last().unwrap().end_line, 42);
+        // Consecutive pieces overlap.
+        assert!(chunks[1].start_line <= chunks[0].end_line);
+        assert!(chunks.iter().all(|c| estimate_tokens(&c.content) <= 60));
+    }
+
+    #[test]
+    fn test_prose_packs_paragraphs_with_overlap() {
+        let text = "First paragraph here.\n\nSecond paragraph here.\n\nThird paragraph here.\n";
+        let chunks = ProseChunker { options: options(12, 6) }.chunk(text);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].content, "First paragraph here.\n\nSecond paragraph here.");
+        assert_eq!(chunks[1].content, "Second paragraph here.\n\nThird paragraph here.");
+        assert_eq!((chunks[1].start_line, chunks[1].end_line), (3, 5));
+
+        let one_line = "lorem ipsum ".repeat(100);
+        let chunks = ProseChunker { options: options(20, 0) }.chunk(&one_line);
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|c| c.start_line == 1 && estimate_tokens(&c.content) <= 20));
+    }
+
+    #[test]
+    fn test_chunker_selection_and_config() {
+        let opts = ChunkOptions::default();
+        let md = chunk_file("notes/a.md", "# A\nText under A.\n", "kb", &opts);
+        assert_eq!(md[0].content, "# A\nText under A.");
+        assert_eq!(md[0].collection, "kb");
+
+        let forced = ChunkOptions {
+            strategy: ChunkStrategy::Prose,
+            ..ChunkOptions::default()
+        };
+        let rs = chunk_file("src/lib.rs", "fn a() {}\n\nfn b() {}\n", "kb", &forced);
+        assert_eq!(rs.len(), 1);
+
+        let parsed: ChunkOptions = toml::from_str("strategy = \"markdown\"\nmax_tokens = 200").unwrap();
+        assert_eq!(parsed.strategy, ChunkStrategy::Markdown);
+        assert_eq!(parsed.max_tokens, 200);
+        assert_eq!(parsed.overlap_tokens, 50);
+    }
+}
@@ -0,0 +1,612 @@
+//! Inspecting and pruning collections in the vector store.
+//!
+//! Backs the `manage_memory` tool and the `intus memory` subcommands. Each
+//! collection's embedding model and dimension are recorded in
+//! `memory/collections.json` whenever chunks are added. Vectors from
+//! different models can't be compared, so a collection recorded under
+//! another model than the configured one is quarantined: new chunks are
+//! refused and vector search skips it until `reembed` migrates it.
+
+use super::indexer::{Manifest, INDEX_LOCK};
+use super::RagSystem;
+use crate::tools::{unix_now, StatusSender, TextChunk};
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// What produced a collection's embeddings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CollectionInfo {
+    pub embedding_model: String,
+    pub dimension: usize,
+}
+
+/// Per-collection metadata, keyed by collection name.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CollectionRegistry {
+    pub collections: BTreeMap<String, CollectionInfo>,
+}
+
+impl CollectionRegistry {
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+/// Summary of one collection.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CollectionStats {
+    pub name: String,
+    pub chunks: usize,
+    /// Distinct source paths.
+    pub files: usize,
+    pub embedding_model: Option<String>,
+    /// Distinct embedding dimensions; more than one means mixed models.
+    pub dimensions: Vec<usize>,
+    /// Newest `indexed_at` among the chunks, if any was recorded.
+    pub last_indexed: Option<u64>,
+    /// Embedded with another model than the configured one.
+    pub quarantined: bool,
+}
+
+impl CollectionStats {
+    /// One-line summary, e.g. `work: 120 chunks from 14 files, nomic-embed-text (768d), last indexed 2026-10-16 09:30 UTC`.
+    pub fn summary(&self) -> String {
+        let dims = if self.dimensions.is_empty() {
+            "no vectors".to_string()
+        } else {
+            self.dimensions.iter().map(|d| format!("{}d", d)).collect::<Vec<_>>().join("/")
+        };
+        let last = match self.last_indexed.and_then(|t| chrono::DateTime::from_timestamp(t as i64, 0)) {
+            Some(t) => t.format("%Y-%m-%d %H:%M UTC").to_string(),
+            None => "unknown".to_string(),
+        };
+        let mut summary = format!(
+            "{}: {} chunks from {} files, {} ({}), last indexed {}",
+            self.name,
+            self.chunks,
+            self.files,
+            self.embedding_model.as_deref().unwrap_or("unknown model"),
+            dims,
+            last
+        );
+        if self.quarantined {
+            summary.push_str(", quarantined until re-embedded");
+        }
+        summary
+    }
+}
+
+/// Which chunks `RagSystem::forget` removes. Every criterion that is set
+/// must match.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ForgetFilter {
+    pub collection: Option<String>,
+    /// Removes chunks whose source path starts with this.
+    pub path_prefix: Option<String>,
+    /// Removes chunks indexed longer ago than this. Chunks without a
+    /// recorded time count as old.
+    pub older_than: Option<Duration>,
+}
+
+impl ForgetFilter {
+    pub fn is_empty(&self) -> bool {
+        self.collection.is_none() && self.path_prefix.is_none() && self.older_than.is_none()
+    }
+
+    fn matches(&self, chunk: &TextChunk, now: u64) -> bool {
+        self.collection.as_ref().is_none_or(|c| chunk.collection == *c)
+            && self.path_prefix.as_ref().is_none_or(|p| chunk.file_path.starts_with(p.as_str()))
+            && self
+                .older_than
+                .is_none_or(|age| chunk.indexed_at < now.saturating_sub(age.as_secs()))
+    }
+}
+
+/// Parses an age such as `30d`, `12h`, `45m` or `90s`.
+pub fn parse_age(s: &str) -> Result<Duration> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let Ok(n) = number.parse::<u64>() else {
+        bail!("Invalid age '{}': expected a number followed by s, m, h, d or w", s);
+    };
+    let secs = match unit.trim() {
+        "s" => n,
+        "m" => n * 60,
+        "h" => n * 3600,
+        "d" | "" => n * 86_400,
+        "w" => n * 7 * 86_400,
+        other => bail!("Invalid age unit '{}': expected s, m, h, d or w", other),
+    };
+    Ok(Duration::from_secs(secs))
+}
+
+impl RagSystem {
+    /// Location of the collection registry, next to the vector store.
+    pub fn collections_path(&self) -> Option<PathBuf> {
+        self.storage_path
+            .as_ref()
+            .and_then(|p| p.parent())
+            .map(|dir| dir.join("collections.json"))
+    }
+
+    fn registry(&self) -> CollectionRegistry {
+        match self.collections_path() {
+            Some(p) => CollectionRegistry::load(&p).unwrap_or_default(),
+            None => CollectionRegistry::default(),
+        }
+    }
+
+    /// Collections recorded under another embedding model than the
+    /// configured one.
+    pub fn quarantined_collections(&self) -> HashSet<String> {
+        self.registry()
+            .collections
+            .into_iter()
+            .filter(|(_, info)| info.embedding_model != self.embedding_model)
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    /// Fails if vectors of `dimension` from the configured model can't be
+    /// added to `collection`. Collections indexed before models were
+    /// recorded are checked against the dimension of their stored vectors.
+    pub fn check_fingerprint(&self, collection: &str, dimension: Option<usize>) -> Result<()> {
+        let hint = format!("run `intus memory reembed {}` to migrate it", collection);
+        if let Some(info) = self.registry().collections.get(collection) {
+            if info.embedding_model != self.embedding_model {
+                bail!(
+                    "Collection '{}' was embedded with '{}' but the configured embedding model is '{}'; {}",
+                    collection, info.embedding_model, self.embedding_model, hint
+                );
+            }
+            if let Some(d) = dimension.filter(|d| *d != info.dimension) {
+                bail!(
+                    "Collection '{}' holds {}-dimensional vectors but '{}' returned {} dimensions; {}",
+                    collection, info.dimension, self.embedding_model, d, hint
+                );
+            }
+            return Ok(());
+        }
+        let Some(d) = dimension else {
+            return Ok(());
+        };
+        let guard = self.index.lock().unwrap();
+        let existing = guard
+            .iter()
+            .flat_map(|i| i.chunks.iter())
+            .find(|c| c.collection == collection && !c.embedding.is_empty() && c.embedding.len() != d);
+        if let Some(chunk) = existing {
+            bail!(
+                "Collection '{}' holds {}-dimensional vectors but '{}' returned {} dimensions; {}",
+                collection, chunk.embedding.len(), self.embedding_model, d, hint
+            );
+        }
+        Ok(())
+    }
+
+    /// Checks every collection `chunks` would be added to.
+    pub(crate) fn check_fingerprints(&self, chunks: &[TextChunk]) -> Result<()> {
+        let mut seen = HashSet::new();
+        for chunk in chunks {
+            let dimension = (!chunk.embedding.is_empty()).then_some(chunk.embedding.len());
+            if seen.insert((chunk.collection.as_str(), dimension)) {
+                self.check_fingerprint(&chunk.collection, dimension)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Records the configured embedding model for the collections of
+    /// freshly embedded `chunks`.
+    pub(crate) fn record_collections(&self, chunks: &[TextChunk]) -> Result<()> {
+        let Some(path) = self.collections_path() else {
+            return Ok(());
+        };
+        let mut registry = CollectionRegistry::load(&path).unwrap_or_default();
+        let mut changed = false;
+        for chunk in chunks.iter().filter(|c| !c.embedding.is_empty()) {
+            let info = CollectionInfo {
+                embedding_model: self.embedding_model.clone(),
+                dimension: chunk.embedding.len(),
+            };
+            if registry.collections.get(&chunk.collection) != Some(&info) {
+                registry.collections.insert(chunk.collection.clone(), info);
+                changed = true;
+            }
+        }
+        if changed {
+            registry.save(&path)?;
+        }
+        Ok(())
+    }
+
+    /// Statistics for every collection in the index, sorted by name.
+    pub fn collection_stats(&self) -> Result<Vec<CollectionStats>> {
+        self.ensure_loaded()?;
+        let registry = self.registry();
+
+        #[derive(Default)]
+        struct Tally<'a> {
+            chunks: usize,
+            files: BTreeSet<&'a str>,
+            dimensions: BTreeSet<usize>,
+            last_indexed: u64,
+        }
+
+        let mut grouped: BTreeMap<&str, Tally> = BTreeMap::new();
+        let guard = self.index.lock().unwrap();
+        for chunk in guard.iter().flat_map(|index| index.chunks.iter()) {
+            let tally = grouped.entry(chunk.collection.as_str()).or_default();
+            tally.chunks += 1;
+            tally.files.insert(chunk.file_path.as_str());
+            if !chunk.embedding.is_empty() {
+                tally.dimensions.insert(chunk.embedding.len());
+            }
+            tally.last_indexed = tally.last_indexed.max(chunk.indexed_at);
+        }
+
+        Ok(grouped
+            .into_iter()
+            .map(|(name, tally)| CollectionStats {
+                name: name.to_string(),
+                chunks: tally.chunks,
+                files: tally.files.len(),
+                embedding_model: registry.collections.get(name).map(|i| i.embedding_model.clone()),
+                dimensions: tally.dimensions.into_iter().collect(),
+                last_indexed: (tally.last_indexed > 0).then_some(tally.last_indexed),
+                quarantined: registry.collections.get(name).is_some_and(|i| i.embedding_model != self.embedding_model),
+            })
+            .collect())
+    }
+
+    /// Removes the chunks matching `filter` and returns how many were
+    /// removed. Forgotten files are dropped from the indexing manifest so a
+    /// later re-index embeds them again.
+    pub async fn forget(&self, filter: &ForgetFilter) -> Result<usize> {
+        if filter.is_empty() {
+            bail!("Specify a collection, path prefix or age to forget (or purge everything)");
+        }
+        let _guard = INDEX_LOCK.lock().await;
+        self.ensure_loaded()?;
+
+        let now = unix_now();
+        let mut forgotten: HashSet<(String, String)> = HashSet::new();
+        let removed = {
+            let mut guard = self.index.lock().unwrap();
+            let Some(index) = guard.as_mut() else {
+                return Ok(0);
+            };
+            index.retain(|c| {
+                if filter.matches(c, now) {
+                    forgotten.insert((c.collection.clone(), c.file_path.clone()));
+                    false
+                } else {
+                    true
+                }
+            })
+        };
+        if removed == 0 {
+            return Ok(0);
+        }
+        self.save()?;
+
+        if let Some(path) = self.manifest_path().filter(|p| p.exists()) {
+            let mut manifest = Manifest::load(&path)?;
+            for (collection, file) in &forgotten {
+                if let Some(files) = manifest.collections.get_mut(collection) {
+                    files.remove(file);
+                }
+            }
+            manifest.collections.retain(|_, files| !files.is_empty());
+            manifest.save(&path)?;
+        }
+        self.prune_registry()?;
+        Ok(removed)
+    }
+
+    /// Removes every chunk from every collection, along with the indexing
+    /// manifest and collection registry. Returns how many chunks were removed.
+    pub async fn purge(&self) -> Result<usize> {
+        let _guard = INDEX_LOCK.lock().await;
+        self.ensure_loaded()?;
+        let removed = {
+            let mut guard = self.index.lock().unwrap();
+            match guard.as_mut() {
+                Some(index) => index.retain(|_| false),
+                None => 0,
+            }
+        };
+        if removed > 0 {
+            self.save()?;
+        }
+        for path in [self.manifest_path(), self.collections_path()].into_iter().flatten() {
+            if path.exists() {
+                fs::remove_file(&path)?;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Re-embeds every chunk of `collection` with the configured model and
+    /// records the new fingerprint, lifting any quarantine. Progress and
+    /// throughput go to `status_tx`. The collection is only replaced once every chunk has
+    /// been embedded, so a failure leaves it as it was. Returns the number
+    /// of chunks re-embedded.
+    pub async fn reembed(&self, collection: &str, status_tx: Option<StatusSender>) -> Result<usize> {
+        let _guard = INDEX_LOCK.lock().await;
+        self.ensure_loaded()?;
+
+        let mut chunks: Vec<TextChunk> = {
+            let guard = self.index.lock().unwrap();
+            guard
+                .iter()
+                .flat_map(|i| i.chunks.iter())
+                .filter(|c| c.collection == collection)
+                .cloned()
+                .collect()
+        };
+        if chunks.is_empty() {
+            bail!("Collection '{}' has no chunks", collection);
+        }
+
+        let total = chunks.len();
+        let texts: Vec<String> = chunks.iter().map(|c| c.content.clone()).collect();
+        let label = format!("'{}' with {}", collection, self.embedding_model);
+        let vectors = self.embed_texts(&texts, &label, status_tx.as_ref()).await;
+        let mut dimension = None;
+        for (chunk, vector) in chunks.iter_mut().zip(vectors) {
+            let Some(embedding) = vector else {
+                bail!("Re-embedding '{}' failed, collection unchanged", collection);
+            };
+            if *dimension.get_or_insert(embedding.len()) != embedding.len() {
+                bail!("'{}' returned vectors of different dimensions; collection unchanged", self.embedding_model);
+            }
+            chunk.embedding = embedding;
+        }
+
+        {
+            let mut guard = self.index.lock().unwrap();
+            if let Some(index) = guard.as_mut() {
+                index.retain(|c| c.collection != collection);
+                index.extend(chunks);
+            }
+        }
+        self.save()?;
+        if let (Some(path), Some(dimension)) = (self.collections_path(), dimension) {
+            let mut registry = CollectionRegistry::load(&path).unwrap_or_default();
+            registry.collections.insert(
+                collection.to_string(),
+                CollectionInfo { embedding_model: self.embedding_model.clone(), dimension },
+            );
+            registry.save(&path)?;
+        }
+        Ok(total)
+    }
+
+    /// Drops registry entries for collections that no longer have chunks.
+    fn prune_registry(&self) -> Result<()> {
+        let Some(path) = self.collections_path().filter(|p| p.exists()) else {
+            return Ok(());
+        };
+        let live: HashSet<String> = {
+            let guard = self.index.lock().unwrap();
+            guard.iter().flat_map(|i| i.chunks.iter()).map(|c| c.collection.clone()).collect()
+        };
+        let mut registry = CollectionRegistry::load(&path)?;
+        let before = registry.collections.len();
+        registry.collections.retain(|name, _| live.contains(name));
+        if registry.collections.len() != before {
+            registry.save(&path)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ollama::OllamaClient;
+    use crate::tools::VectorIndex;
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn chunk(collection: &str, path: &str, indexed_at: u64) -> TextChunk {
+        TextChunk {
+            file_path: path.to_string(),
+            content: format!("{} in {}", path, collection),
+            embedding: vec![0.5; 4],
+            collection: collection.to_string(),
+            indexed_at,
+            ..Default::default()
+        }
+    }
+
+    fn rag_with(dir: &Path, chunks: Vec<TextChunk>) -> RagSystem {
+        let rag = RagSystem::new(
+            OllamaClient::new("http://127.0.0.1:9".to_string(), "ollama".to_string(), "".to_string()),
+            "nomic-embed-text".to_string(),
+            Arc::new(Mutex::new(None)),
+            Some(dir.join("vectors.json")),
+        );
+        rag.record_collections(&chunks).unwrap();
+        *rag.index.lock().unwrap() = Some(VectorIndex::new(chunks));
+        rag.save().unwrap();
+        rag
+    }
+
+    #[test]
+    fn test_parse_age() {
+        assert_eq!(parse_age("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_age("12h").unwrap(), Duration::from_secs(12 * 3600));
+        assert_eq!(parse_age("7").unwrap(), Duration::from_secs(7 * 86_400));
+        assert_eq!(parse_age("2w").unwrap(), Duration::from_secs(14 * 86_400));
+        assert!(parse_age("soon").is_err());
+        assert!(parse_age("3y").is_err());
+    }
+
+    #[test]
+    fn test_collection_stats() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = unix_now();
+        let rag = rag_with(
+            dir.path(),
+            vec![
+                chunk("work", "/w/a.md", now - 10),
+                chunk("work", "/w/a.md", now - 5),
+                chunk("work", "/w/b.md", now - 20),
+                chunk("web", "session_memory", 0),
+            ],
+        );
+
+        let stats = rag.collection_stats().unwrap();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].name, "web");
+        // Chunks without a time are stamped when added.
+        assert!(stats[0].last_indexed.unwrap() >= now);
+        let work = &stats[1];
+        assert_eq!((work.chunks, work.files), (3, 2));
+        assert_eq!(work.embedding_model.as_deref(), Some("nomic-embed-text"));
+        assert_eq!(work.dimensions, vec![4]);
+        assert_eq!(work.last_indexed, Some(now - 5));
+        assert!(work.summary().starts_with("work: 3 chunks from 2 files, nomic-embed-text (4d), last indexed "));
+    }
+
+    #[tokio::test]
+    async fn test_forget_by_collection_prefix_and_age() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = unix_now();
+        let old = now - 40 * 86_400;
+        let rag = rag_with(
+            dir.path(),
+            vec![
+                chunk("work", "/w/notes/a.md", now),
+                chunk("work", "/w/notes/b.md", old),
+                chunk("work", "/w/src/main.rs", now),
+                chunk("web", "session_memory", old),
+                chunk("personal", "/p/diary.md", now),
+            ],
+        );
+        let mut manifest = Manifest::default();
+        manifest.collections.entry("work".to_string()).or_default().insert(
+            "/w/notes/a.md".to_string(),
+            crate::rag::indexer::FileEntry { mtime: 1, size: 1, hash: 1, chunks: 1 },
+        );
+        manifest.save(&rag.manifest_path().unwrap()).unwrap();
+
+        assert!(rag.forget(&ForgetFilter::default()).await.is_err());
+
+        let removed = rag
+            .forget(&ForgetFilter {
+                collection: Some("work".to_string()),
+                path_prefix: Some("/w/notes/".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(removed, 2);
+        let manifest = Manifest::load(&rag.manifest_path().unwrap()).unwrap();
+        assert!(manifest.collections.is_empty(), "forgotten files must be re-indexed later");
+
+        let removed = rag
+            .forget(&ForgetFilter {
+                older_than: Some(Duration::from_secs(30 * 86_400)),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(removed, 1);
+
+        // Removal is persisted and the registry follows the live collections.
+        let reloaded = RagSystem::new(rag.client.clone(), rag.embedding_model.clone(), Arc::new(Mutex::new(None)), rag.storage_path.clone());
+        let names: Vec<String> = reloaded.collection_stats().unwrap().into_iter().map(|s| s.name).collect();
+        assert_eq!(names, vec!["personal", "work"]);
+        let registry = CollectionRegistry::load(&rag.collections_path().unwrap()).unwrap();
+        assert!(!registry.collections.contains_key("web"));
+
+        assert_eq!(rag.purge().await.unwrap(), 2);
+        assert!(rag.collection_stats().unwrap().is_empty());
+        assert!(!rag.manifest_path().unwrap().exists());
+        assert!(!rag.collections_path().unwrap().exists());
+    }
+
+    #[tokio::test]
+    async fn test_model_change_quarantines_until_reembedded() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = unix_now();
+        let rag = rag_with(
+            dir.path(),
+            vec![
+                chunk("work", "/w/a.md", now),
+                chunk("work", "/w/b.md", now),
+                chunk("work", "/w/c.md", now),
+                chunk("web", "https://example.com", now),
+            ],
+        );
+        let wrong_dimension = TextChunk { embedding: vec![0.5; 3], ..chunk("work", "/w/d.md", now) };
+        assert!(rag.add_chunks(vec![wrong_dimension.clone()]).await.is_err());
+
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/embeddings"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "embedding": [0.1, 0.2, 0.3] })))
+            .mount(&mock_server)
+            .await;
+        let switched = RagSystem::new(
+            OllamaClient::new(mock_server.uri(), "ollama".to_string(), "".to_string()),
+            "mxbai-embed-large".to_string(),
+            Arc::new(Mutex::new(None)),
+            rag.storage_path.clone(),
+        )
+        .with_embedding(crate::rag::embedder::EmbeddingOptions { batch_size: 2, ..Default::default() });
+        switched.ensure_loaded().unwrap();
+        assert_eq!(switched.quarantined_collections(), HashSet::from(["work".to_string(), "web".to_string()]));
+
+        let err = switched.add_chunks(vec![wrong_dimension]).await.unwrap_err();
+        assert!(err.to_string().contains("intus memory reembed work"), "{}", err);
+        assert!(switched.search("a", 5, None).await.unwrap().is_empty(), "quarantined vectors must not be compared");
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        assert_eq!(switched.reembed("work", Some(tx)).await.unwrap(), 3);
+        assert!(rx.try_recv().unwrap().starts_with("Embedded 3 chunks for 'work' with mxbai-embed-large in "));
+
+        assert_eq!(switched.quarantined_collections(), HashSet::from(["web".to_string()]));
+        let hits = switched.search("a", 5, None).await.unwrap();
+        assert_eq!(hits.len(), 3);
+        assert!(hits.iter().all(|h| h.collection == "work"));
+
+        let stats = switched.collection_stats().unwrap();
+        assert_eq!((stats[1].dimensions.clone(), stats[1].quarantined), (vec![3], false));
+        assert!(stats[0].summary().ends_with("quarantined until re-embedded"));
+
+        // A failed re-embed leaves the collection as it was.
+        let offline = RagSystem::new(
+            OllamaClient::new("http://127.0.0.1:9".to_string(), "ollama".to_string(), "".to_string()),
+            "mxbai-embed-large".to_string(),
+            switched.index.clone(),
+            switched.storage_path.clone(),
+        );
+        assert!(offline.reembed("web", None).await.is_err());
+        assert!(offline.quarantined_collections().contains("web"));
+        assert!(offline.reembed("missing", None).await.is_err());
+    }
+}
@@ -0,0 +1,248 @@
+//! Batched, concurrent embedding generation for indexing.
+//!
+//! Texts are sent to the server in batches of `batch_size` (one `/api/embed`
+//! or `/v1/embeddings` request each), with up to `concurrency` batches in
+//! flight. Batches that fail with a transient error are retried with
+//! exponential backoff; a batch that still fails leaves its texts without
+//! vectors so callers can decide what to skip.
+
+use super::RagSystem;
+use crate::backend::is_transient;
+use crate::tools::StatusSender;
+use anyhow::Result;
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Delay before the first retry; doubled for each further attempt.
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(250);
+
+/// Minimum time between progress messages.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Embedding throughput settings (`[embedding]` in the config).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmbeddingOptions {
+    /// Texts per request.
+    pub batch_size: usize,
+    /// Requests in flight at once.
+    pub concurrency: usize,
+    /// Retries for a batch that failed with a transient error.
+    pub max_retries: u32,
+}
+
+impl Default for EmbeddingOptions {
+    fn default() -> Self {
+        Self {
+            batch_size: 32,
+            concurrency: 4,
+            max_retries: 3,
+        }
+    }
+}
+
+impl RagSystem {
+    /// Sets the embedding batch size, concurrency and retries.
+    pub fn with_embedding(mut self, embedding: EmbeddingOptions) -> Self {
+        self.embedding = embedding;
+        self
+    }
+
+    /// Embeds `texts` with the configured model, returning one entry per
+    /// text in order; `None` where its batch failed. When `status_tx` is
+    /// given, progress and throughput are reported under `label`.
+    pub async fn embed_texts(&self, texts: &[String], label: &str, status_tx: Option<&StatusSender>) -> Vec<Option<Vec<f64>>> {
+        let mut results = vec![None; texts.len()];
+        if texts.is_empty() {
+            return results;
+        }
+        let batch_size = self.embedding.batch_size.max(1);
+        let started = Instant::now();
+        let mut last_report = started;
+        let mut done = 0;
+
+        // Collected up front: a closure inside the stream would make the
+        // future not `Send` for `tokio::spawn` callers.
+        let requests: Vec<_> = texts
+            .chunks(batch_size)
+            .enumerate()
+            .map(|(i, batch)| async move { (i, self.embed_batch_with_retry(batch).await) })
+            .collect();
+        let mut batches = stream::iter(requests).buffer_unordered(self.embedding.concurrency.max(1));
+
+        while let Some((i, result)) = batches.next().await {
+            let start = i * batch_size;
+            let len = batch_size.min(texts.len() - start);
+            match result {
+                Ok(vectors) => {
+                    for (slot, vector) in results[start..start + len].iter_mut().zip(vectors) {
+                        *slot = Some(vector);
+                    }
+                }
+                Err(e) => warn!("Embedding {} texts for {} failed: {}", len, label, e),
+            }
+            done += len;
+
+            if let Some(tx) = status_tx {
+                if done < texts.len() && last_report.elapsed() >= PROGRESS_INTERVAL {
+                    last_report = Instant::now();
+                    let _ = tx.send(format!(
+                        "Embedding {}: {}/{} chunks ({:.1} chunks/s)",
+                        label,
+                        done,
+                        texts.len(),
+                        done as f64 / started.elapsed().as_secs_f64()
+                    ));
+                }
+            }
+        }
+
+        if let Some(tx) = status_tx {
+            if texts.len() > batch_size {
+                let secs = started.elapsed().as_secs_f64();
+                let _ = tx.send(format!(
+                    "Embedded {} chunks for {} in {:.1}s ({:.1} chunks/s)",
+                    texts.len(),
+                    label,
+                    secs,
+                    texts.len() as f64 / secs.max(f64::EPSILON)
+                ));
+            }
+        }
+        results
+    }
+
+    async fn embed_batch_with_retry(&self, batch: &[String]) -> Result<Vec<Vec<f64>>> {
+        let mut attempt = 0;
+        loop {
+            let result = self
+                .client
+                .generate_embeddings_batch(&self.embedding_model, batch)
+                .await
+                .and_then(|vectors| {
+                    if vectors.iter().any(|v| v.is_empty()) {
+                        anyhow::bail!("Server returned an empty embedding");
+                    }
+                    Ok(vectors)
+                });
+            match result {
+                Err(e) if attempt < self.embedding.max_retries && is_transient(&e) => {
+                    let delay = RETRY_BASE_DELAY * 2u32.pow(attempt);
+                    warn!("Embedding batch failed ({}), retrying in {:?}", e, delay);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                other => return other,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ollama::OllamaClient;
+    use serde_json::{json, Value};
+    use std::sync::{Arc, Mutex};
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+    /// Embeds each input as `[length, 1.0]`.
+    fn embed_by_length(request: &Request) -> ResponseTemplate {
+        let body: Value = serde_json::from_slice(&request.body).unwrap();
+        let embeddings: Vec<Value> = body["input"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| json!([s.as_str().unwrap().len() as f64, 1.0]))
+            .collect();
+        ResponseTemplate::new(200).set_body_json(json!({ "embeddings": embeddings }))
+    }
+
+    fn rag(uri: String, options: EmbeddingOptions) -> RagSystem {
+        RagSystem::new(
+            OllamaClient::new(uri, "ollama".to_string(), "".to_string()),
+            "m".to_string(),
+            Arc::new(Mutex::new(None)),
+            None,
+        )
+        .with_embedding(options)
+    }
+
+    fn texts(n: usize) -> Vec<String> {
+        (1..=n).map(|i| "x".repeat(i)).collect()
+    }
+
+    #[tokio::test]
+    async fn test_batches_keep_input_order() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/embed"))
+            .respond_with(embed_by_length)
+            .expect(3)
+            .mount(&mock_server)
+            .await;
+        let rag = rag(mock_server.uri(), EmbeddingOptions { batch_size: 2, concurrency: 3, max_retries: 0 });
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let vectors = rag.embed_texts(&texts(5), "'notes'", Some(&tx)).await;
+        let lengths: Vec<f64> = vectors.into_iter().map(|v| v.unwrap()[0]).collect();
+        assert_eq!(lengths, vec![1.0, 2.0, 3.0, 4.0, 5.0]);
+        assert!(rx.try_recv().unwrap().starts_with("Embedded 5 chunks for 'notes' in "));
+    }
+
+    #[tokio::test]
+    async fn test_transient_failures_are_retried() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/embed"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/embed"))
+            .respond_with(embed_by_length)
+            .mount(&mock_server)
+            .await;
+
+        let rag = rag(mock_server.uri(), EmbeddingOptions { batch_size: 8, concurrency: 1, max_retries: 2 });
+        assert!(rag.embed_texts(&texts(3), "test", None).await.iter().all(Option::is_some));
+    }
+
+    #[tokio::test]
+    async fn test_permanent_failures_are_not_retried() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/embed"))
+            .respond_with(ResponseTemplate::new(400))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let rag = rag(mock_server.uri(), EmbeddingOptions::default());
+        assert_eq!(rag.embed_texts(&texts(2), "test", None).await, vec![None, None]);
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_to_single_prompt_endpoint() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/embed"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/embeddings"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "embedding": [0.5] })))
+            .expect(3)
+            .mount(&mock_server)
+            .await;
+
+        let rag = rag(mock_server.uri(), EmbeddingOptions::default());
+        assert_eq!(rag.embed_texts(&texts(3), "test", None).await, vec![Some(vec![0.5]); 3]);
+    }
+}
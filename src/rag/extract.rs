@@ -0,0 +1,396 @@
+//! Plain-text extraction for documents that aren't plain text.
+//!
+//! An [`ExtractorRegistry`] maps file extensions and MIME types to
+//! [`Extractor`]s. The indexer and `read_file` go through it so PDFs, Word
+//! documents, HTML, EPUB books and Jupyter notebooks are read as text
+//! instead of being skipped as binary. Files with no registered extractor
+//! are read as UTF-8.
+
+use anyhow::{anyhow, bail, Context, Result};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Line width used when rendering HTML to text.
+const HTML_WIDTH: usize = 100;
+
+/// Turns the bytes of one document format into text.
+pub trait Extractor: Send + Sync {
+    /// Lowercase file extensions handled, without the dot.
+    fn extensions(&self) -> &[&'static str];
+
+    /// MIME types handled.
+    fn mime_types(&self) -> &[&'static str];
+
+    fn extract(&self, bytes: &[u8]) -> Result<String>;
+}
+
+/// Extractors keyed by extension and MIME type.
+#[derive(Default)]
+pub struct ExtractorRegistry {
+    extractors: Vec<Box<dyn Extractor>>,
+    by_extension: HashMap<&'static str, usize>,
+    by_mime: HashMap<&'static str, usize>,
+}
+
+impl ExtractorRegistry {
+    /// A registry with the built-in PDF, DOCX, HTML, EPUB and notebook
+    /// extractors.
+    pub fn with_defaults() -> Self {
+        let mut registry = Self::default();
+        registry.register(Box::new(PdfExtractor));
+        registry.register(Box::new(DocxExtractor));
+        registry.register(Box::new(HtmlExtractor));
+        registry.register(Box::new(EpubExtractor));
+        registry.register(Box::new(NotebookExtractor));
+        registry
+    }
+
+    /// Adds `extractor`, taking over its extensions and MIME types from any
+    /// extractor registered earlier.
+    pub fn register(&mut self, extractor: Box<dyn Extractor>) {
+        let id = self.extractors.len();
+        for ext in extractor.extensions() {
+            self.by_extension.insert(ext, id);
+        }
+        for mime in extractor.mime_types() {
+            self.by_mime.insert(mime, id);
+        }
+        self.extractors.push(extractor);
+    }
+
+    pub fn for_path(&self, path: &Path) -> Option<&dyn Extractor> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        self.by_extension.get(ext.as_str()).map(|&i| self.extractors[i].as_ref())
+    }
+
+    /// Looks up by MIME type, ignoring parameters such as `; charset=utf-8`.
+    pub fn for_mime(&self, mime: &str) -> Option<&dyn Extractor> {
+        let essence = mime.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+        self.by_mime.get(essence.as_str()).map(|&i| self.extractors[i].as_ref())
+    }
+
+    /// Text of the file at `path` whose contents are `bytes`. `None` means
+    /// a binary file no extractor handles.
+    pub fn extract(&self, path: &Path, bytes: &[u8]) -> Result<Option<String>> {
+        match self.for_path(path) {
+            Some(extractor) => extractor
+                .extract(bytes)
+                .map(Some)
+                .with_context(|| format!("Failed to extract text from {}", path.display())),
+            None => Ok(std::str::from_utf8(bytes).ok().map(str::to_string)),
+        }
+    }
+}
+
+/// The registry with the built-in extractors.
+pub fn extractors() -> &'static ExtractorRegistry {
+    static REGISTRY: OnceLock<ExtractorRegistry> = OnceLock::new();
+    REGISTRY.get_or_init(ExtractorRegistry::with_defaults)
+}
+
+/// Reads the file at `path` as text, extracting it if it is a document.
+pub fn read_text(path: &Path) -> Result<Option<String>> {
+    let bytes = std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    extractors().extract(path, &bytes)
+}
+
+pub struct PdfExtractor;
+
+impl Extractor for PdfExtractor {
+    fn extensions(&self) -> &[&'static str] {
+        &["pdf"]
+    }
+
+    fn mime_types(&self) -> &[&'static str] {
+        &["application/pdf"]
+    }
+
+    fn extract(&self, bytes: &[u8]) -> Result<String> {
+        // pdf-extract panics on some malformed files. This only contains the
+        // damage in builds that unwind; release builds abort on panic.
+        let result = std::panic::catch_unwind(|| pdf_extract::extract_text_from_mem(bytes))
+            .map_err(|_| anyhow!("PDF parser crashed"))?;
+        let text = result.map_err(|e| anyhow!("{}", e))?;
+        Ok(text.trim().to_string())
+    }
+}
+
+pub struct DocxExtractor;
+
+impl Extractor for DocxExtractor {
+    fn extensions(&self) -> &[&'static str] {
+        &["docx"]
+    }
+
+    fn mime_types(&self) -> &[&'static str] {
+        &["application/vnd.openxmlformats-officedocument.wordprocessingml.document"]
+    }
+
+    fn extract(&self, bytes: &[u8]) -> Result<String> {
+        let xml = zip_entry(bytes, "word/document.xml")?;
+        let mut reader = Reader::from_str(&xml);
+        let mut text = String::new();
+        let mut in_text = false;
+        loop {
+            match reader.read_event()? {
+                Event::Start(e) if e.local_name().as_ref() == b"t" => in_text = true,
+                Event::End(e) => match e.local_name().as_ref() {
+                    b"t" => in_text = false,
+                    b"p" => text.push('\n'),
+                    _ => {}
+                },
+                Event::Empty(e) => match e.local_name().as_ref() {
+                    b"tab" => text.push('\t'),
+                    b"br" | b"cr" => text.push('\n'),
+                    _ => {}
+                },
+                Event::Text(t) if in_text => text.push_str(&t.decode()?),
+                Event::GeneralRef(r) if in_text => text.push_str(&resolve_entity(&r.decode()?)),
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+        Ok(text.trim().to_string())
+    }
+}
+
+pub struct HtmlExtractor;
+
+impl Extractor for HtmlExtractor {
+    fn extensions(&self) -> &[&'static str] {
+        &["html", "htm", "xhtml"]
+    }
+
+    fn mime_types(&self) -> &[&'static str] {
+        &["text/html", "application/xhtml+xml"]
+    }
+
+    fn extract(&self, bytes: &[u8]) -> Result<String> {
+        Ok(html2text::from_read(bytes, HTML_WIDTH).trim().to_string())
+    }
+}
+
+/// Reads the chapters of an EPUB in spine (reading) order.
+pub struct EpubExtractor;
+
+impl Extractor for EpubExtractor {
+    fn extensions(&self) -> &[&'static str] {
+        &["epub"]
+    }
+
+    fn mime_types(&self) -> &[&'static str] {
+        &["application/epub+zip"]
+    }
+
+    fn extract(&self, bytes: &[u8]) -> Result<String> {
+        let container = zip_entry(bytes, "META-INF/container.xml")?;
+        let opf_path = first_attribute(&container, b"rootfile", b"full-path")?
+            .ok_or_else(|| anyhow!("EPUB container names no package file"))?;
+        let opf = zip_entry(bytes, &opf_path)?;
+        let base = opf_path.rsplit_once('/').map(|(dir, _)| format!("{}/", dir)).unwrap_or_default();
+
+        let mut manifest: HashMap<String, String> = HashMap::new();
+        let mut spine = Vec::new();
+        let mut reader = Reader::from_str(&opf);
+        loop {
+            match reader.read_event()? {
+                Event::Start(e) | Event::Empty(e) => match e.local_name().as_ref() {
+                    b"item" => {
+                        if let (Some(id), Some(href)) = (attribute(&e, b"id"), attribute(&e, b"href")) {
+                            manifest.insert(id, href);
+                        }
+                    }
+                    b"itemref" => spine.extend(attribute(&e, b"idref")),
+                    _ => {}
+                },
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+
+        let mut chapters = Vec::new();
+        for href in spine.iter().filter_map(|id| manifest.get(id)) {
+            let href = href.split('#').next().unwrap_or_default();
+            let href = urlencoding::decode(href).map(|h| h.into_owned()).unwrap_or_else(|_| href.to_string());
+            let html = zip_entry(bytes, &format!("{}{}", base, href))?;
+            let text = HtmlExtractor.extract(html.as_bytes())?;
+            if !text.is_empty() {
+                chapters.push(text);
+            }
+        }
+        if chapters.is_empty() {
+            bail!("EPUB has no readable chapters");
+        }
+        Ok(chapters.join("\n\n"))
+    }
+}
+
+/// Renders a Jupyter notebook as Markdown: Markdown cells as they are and
+/// code cells as fenced blocks. Outputs are left out.
+pub struct NotebookExtractor;
+
+impl Extractor for NotebookExtractor {
+    fn extensions(&self) -> &[&'static str] {
+        &["ipynb"]
+    }
+
+    fn mime_types(&self) -> &[&'static str] {
+        &["application/x-ipynb+json"]
+    }
+
+    fn extract(&self, bytes: &[u8]) -> Result<String> {
+        let notebook: serde_json::Value = serde_json::from_slice(bytes).context("Invalid notebook JSON")?;
+        let metadata = &notebook["metadata"];
+        let language = metadata["kernelspec"]["language"]
+            .as_str()
+            .or_else(|| metadata["language_info"]["name"].as_str())
+            .unwrap_or("python");
+        let cells = notebook["cells"].as_array().ok_or_else(|| anyhow!("Notebook has no cells"))?;
+
+        let mut blocks = Vec::new();
+        for cell in cells {
+            // `source` is either one string or a list of lines.
+            let source = match &cell["source"] {
+                serde_json::Value::String(s) => s.clone(),
+                serde_json::Value::Array(lines) => lines.iter().filter_map(|l| l.as_str()).collect(),
+                _ => String::new(),
+            };
+            let source = source.trim_end();
+            if source.trim().is_empty() {
+                continue;
+            }
+            match cell["cell_type"].as_str() {
+                Some("code") => blocks.push(format!("```{}\n{}\n```", language, source)),
+                _ => blocks.push(source.to_string()),
+            }
+        }
+        Ok(blocks.join("\n\n"))
+    }
+}
+
+/// Reads a UTF-8 entry from a zip archive.
+fn zip_entry(bytes: &[u8], name: &str) -> Result<String> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).context("Not a valid zip archive")?;
+    let mut entry = archive.by_name(name).with_context(|| format!("Archive has no {}", name))?;
+    let mut content = String::new();
+    entry.read_to_string(&mut content)?;
+    Ok(content)
+}
+
+fn attribute(element: &quick_xml::events::BytesStart, name: &[u8]) -> Option<String> {
+    element
+        .try_get_attribute(name)
+        .ok()
+        .flatten()
+        .map(|a| String::from_utf8_lossy(&a.value).into_owned())
+}
+
+/// The `attr` of the first `element` in `xml`.
+fn first_attribute(xml: &str, element: &[u8], attr: &[u8]) -> Result<Option<String>> {
+    let mut reader = Reader::from_str(xml);
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == element => return Ok(attribute(&e, attr)),
+            Event::Eof => return Ok(None),
+            _ => {}
+        }
+    }
+}
+
+/// Resolves `amp`, `#233` and the like; unknown entities are kept as written.
+fn resolve_entity(name: &str) -> String {
+    if let Some(resolved) = quick_xml::escape::resolve_predefined_entity(name) {
+        return resolved.to_string();
+    }
+    let code = match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => name.strip_prefix('#').and_then(|dec| dec.parse().ok()),
+    };
+    match code.and_then(char::from_u32) {
+        Some(c) => c.to_string(),
+        None => format!("&{};", name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/documents").join(name)
+    }
+
+    fn extract_fixture(name: &str) -> String {
+        read_text(&fixture(name)).unwrap().expect("fixture should have text")
+    }
+
+    #[test]
+    fn test_pdf() {
+        let text = extract_fixture("sample.pdf");
+        assert!(text.contains("Invoice 2041 for consulting services"), "{}", text);
+        assert!(text.contains("Total due: 3400 EUR"), "{}", text);
+    }
+
+    #[test]
+    fn test_docx() {
+        assert_eq!(
+            extract_fixture("sample.docx"),
+            "Quarterly Report\nRevenue grew by 12 percent & costs fell.\nOwner:\tFinance team"
+        );
+    }
+
+    #[test]
+    fn test_html() {
+        let text = extract_fixture("sample.html");
+        assert!(text.starts_with("# Deploy Guide"), "{}", text);
+        assert!(text.contains("make deploy"));
+        assert!(text.contains("Then production"));
+        assert!(!text.contains("console.log"));
+        assert!(!text.contains("font-family"));
+    }
+
+    #[test]
+    fn test_epub_follows_spine_order() {
+        let text = extract_fixture("sample.epub");
+        let lighthouse = text.find("The keeper lit the lamp at dusk.").unwrap();
+        let storm = text.find("Waves broke over the northern rocks.").unwrap();
+        assert!(lighthouse < storm, "{}", text);
+        assert!(!text.contains("navy"));
+    }
+
+    #[test]
+    fn test_notebook() {
+        assert_eq!(
+            extract_fixture("sample.ipynb"),
+            "# Data Cleaning\nDrop rows with missing prices.\n\n\
+             ```python\nimport pandas as pd\ndf = pd.read_csv(\"prices.csv\")\ndf = df.dropna()\n```\n\n\
+             raw cell text"
+        );
+    }
+
+    #[test]
+    fn test_registry_lookup_and_fallback() {
+        let registry = extractors();
+        assert!(registry.for_path(Path::new("Report.PDF")).is_some());
+        assert!(registry.for_mime("text/html; charset=utf-8").is_some());
+        assert!(registry.for_path(Path::new("notes.md")).is_none());
+
+        assert_eq!(registry.extract(Path::new("notes.md"), b"# Notes").unwrap().as_deref(), Some("# Notes"));
+        assert_eq!(registry.extract(Path::new("image.png"), &[0x89, 0x50, 0xff, 0xfe]).unwrap(), None);
+        let err = registry.extract(Path::new("broken.docx"), b"not a zip").unwrap_err();
+        assert!(format!("{:#}", err).contains("broken.docx"));
+    }
+
+    #[test]
+    fn test_resolve_entity() {
+        assert_eq!(resolve_entity("amp"), "&");
+        assert_eq!(resolve_entity("#233"), "é");
+        assert_eq!(resolve_entity("#x41"), "A");
+        assert_eq!(resolve_entity("nbsp"), "&nbsp;");
+    }
+}
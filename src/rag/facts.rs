@@ -0,0 +1,368 @@
+//! Long-term memory facts.
+//!
+//! Facts saved with `remember` are records in `memory/facts.json` with an
+//! id, timestamps, the session they came from and optional tags. Each fact
+//! is also indexed as a single chunk in the `memory` collection (with
+//! `file_path` set to `memory:<id>`) so it shows up in search; updating or
+//! forgetting a fact replaces or removes that chunk.
+
+use super::indexer::INDEX_LOCK;
+use super::RagSystem;
+use crate::tools::{unix_now, TextChunk};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Collection that holds fact chunks.
+pub const MEMORY_COLLECTION: &str = "memory";
+
+/// `file_path` used by `remember` before facts were records.
+const LEGACY_MEMORY_PATH: &str = "session_memory";
+
+/// One remembered fact.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Fact {
+    pub id: u64,
+    pub text: String,
+    /// Seconds since the Unix epoch.
+    pub created_at: u64,
+    pub updated_at: u64,
+    /// Session the fact was saved from, if known.
+    #[serde(default)]
+    pub session: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl Fact {
+    /// The `file_path` of the fact's chunk.
+    pub fn chunk_path(id: u64) -> String {
+        format!("memory:{}", id)
+    }
+
+    fn chunk(&self) -> TextChunk {
+        TextChunk {
+            file_path: Self::chunk_path(self.id),
+            content: self.text.clone(),
+            collection: MEMORY_COLLECTION.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// `#12 [work, ports] The project uses port 8081 (2026-10-16, session: default)`
+    pub fn summary(&self) -> String {
+        let mut line = format!("#{} ", self.id);
+        if !self.tags.is_empty() {
+            line.push_str(&format!("[{}] ", self.tags.join(", ")));
+        }
+        line.push_str(&self.text);
+        let date = chrono::DateTime::from_timestamp(self.updated_at as i64, 0)
+            .map(|t| t.format("%Y-%m-%d").to_string())
+            .unwrap_or_else(|| "unknown date".to_string());
+        match &self.session {
+            Some(session) => line.push_str(&format!(" ({}, session: {})", date, session)),
+            None => line.push_str(&format!(" ({})", date)),
+        }
+        line
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct FactStore {
+    next_id: u64,
+    facts: Vec<Fact>,
+}
+
+impl FactStore {
+    fn load(path: &Path) -> Result<Self> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    fn push(&mut self, text: String, session: Option<String>, tags: Vec<String>, created_at: u64) -> Fact {
+        self.next_id += 1;
+        let fact = Fact {
+            id: self.next_id,
+            text,
+            created_at,
+            updated_at: created_at,
+            session,
+            tags,
+        };
+        self.facts.push(fact.clone());
+        fact
+    }
+}
+
+/// Normalises tags: trimmed, lowercase, no empties or duplicates.
+fn clean_tags(tags: Vec<String>) -> Vec<String> {
+    let mut cleaned: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.trim().to_lowercase();
+        if !tag.is_empty() && !cleaned.contains(&tag) {
+            cleaned.push(tag);
+        }
+    }
+    cleaned
+}
+
+impl RagSystem {
+    /// Location of the fact records, next to the vector store.
+    pub fn facts_path(&self) -> Option<PathBuf> {
+        self.storage_path
+            .as_ref()
+            .and_then(|p| p.parent())
+            .map(|dir| dir.join("facts.json"))
+    }
+
+    /// Reads the fact records. The first time, memories saved before facts
+    /// were records are converted: each one becomes a fact and its chunk is
+    /// re-pointed at it.
+    fn load_facts(&self) -> Result<FactStore> {
+        let Some(path) = self.facts_path() else {
+            return Ok(FactStore::default());
+        };
+        if path.exists() {
+            return FactStore::load(&path);
+        }
+
+        self.ensure_loaded()?;
+        let mut store = FactStore::default();
+        {
+            let mut guard = self.index.lock().unwrap();
+            if let Some(index) = guard.as_mut() {
+                for chunk in index
+                    .chunks
+                    .iter_mut()
+                    .filter(|c| c.collection == MEMORY_COLLECTION && c.file_path == LEGACY_MEMORY_PATH)
+                {
+                    let fact = store.push(chunk.content.clone(), None, Vec::new(), chunk.indexed_at);
+                    chunk.file_path = Fact::chunk_path(fact.id);
+                }
+                if !store.facts.is_empty() {
+                    index.rewrite_needed = true;
+                }
+            }
+        }
+        if !store.facts.is_empty() {
+            info!("Converted {} saved memories into fact records", store.facts.len());
+            self.save()?;
+        }
+        store.save(&path)?;
+        Ok(store)
+    }
+
+    fn save_facts(&self, store: &FactStore) -> Result<()> {
+        match self.facts_path() {
+            Some(path) => store.save(&path),
+            None => Ok(()),
+        }
+    }
+
+    /// Embeds a fact's chunk and puts it in the index, replacing any
+    /// previous chunk for the same fact.
+    async fn index_fact(&self, fact: &Fact) -> Result<()> {
+        self.ensure_loaded()?;
+        let mut chunk = fact.chunk();
+        match self.client.generate_embeddings(&self.embedding_model, &chunk.content).await {
+            Ok(embedding) => chunk.embedding = embedding,
+            // Still findable by keyword search; re-saving the fact retries.
+            Err(e) => warn!("Failed to embed fact #{}: {}", fact.id, e),
+        }
+        self.remove_fact_chunks(fact.id);
+        self.add_chunks(vec![chunk]).await
+    }
+
+    fn remove_fact_chunks(&self, id: u64) -> usize {
+        let path = Fact::chunk_path(id);
+        let mut guard = self.index.lock().unwrap();
+        match guard.as_mut() {
+            Some(index) => index.retain(|c| !(c.collection == MEMORY_COLLECTION && c.file_path == path)),
+            None => 0,
+        }
+    }
+
+    /// Saves a new fact and indexes it.
+    pub async fn remember(&self, text: &str, session: Option<String>, tags: Vec<String>) -> Result<Fact> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(anyhow!("Cannot remember an empty fact"));
+        }
+        let _guard = INDEX_LOCK.lock().await;
+        self.check_fingerprint(MEMORY_COLLECTION, None)?;
+        let mut store = self.load_facts()?;
+        let fact = store.push(text.to_string(), session, clean_tags(tags), unix_now());
+        self.save_facts(&store)?;
+        self.index_fact(&fact).await?;
+        Ok(fact)
+    }
+
+    /// Replaces a fact's text and/or tags and re-indexes it.
+    pub async fn update_fact(&self, id: u64, text: Option<&str>, tags: Option<Vec<String>>) -> Result<Fact> {
+        let _guard = INDEX_LOCK.lock().await;
+        self.check_fingerprint(MEMORY_COLLECTION, None)?;
+        let mut store = self.load_facts()?;
+        let fact = store
+            .facts
+            .iter_mut()
+            .find(|f| f.id == id)
+            .ok_or_else(|| anyhow!("No memory with id #{}", id))?;
+        if let Some(text) = text.map(str::trim) {
+            if text.is_empty() {
+                return Err(anyhow!("A memory can't be updated to empty text; forget it instead"));
+            }
+            fact.text = text.to_string();
+        }
+        if let Some(tags) = tags {
+            fact.tags = clean_tags(tags);
+        }
+        fact.updated_at = unix_now();
+        let fact = fact.clone();
+        self.save_facts(&store)?;
+        self.index_fact(&fact).await?;
+        Ok(fact)
+    }
+
+    /// Deletes a fact and its chunk.
+    pub async fn forget_fact(&self, id: u64) -> Result<Fact> {
+        let _guard = INDEX_LOCK.lock().await;
+        let mut store = self.load_facts()?;
+        let pos = store
+            .facts
+            .iter()
+            .position(|f| f.id == id)
+            .ok_or_else(|| anyhow!("No memory with id #{}", id))?;
+        let fact = store.facts.remove(pos);
+        self.save_facts(&store)?;
+        self.ensure_loaded()?;
+        if self.remove_fact_chunks(id) > 0 {
+            self.save()?;
+        }
+        Ok(fact)
+    }
+
+    /// Facts, oldest first, optionally only those tagged `tag`.
+    pub fn list_facts(&self, tag: Option<&str>) -> Result<Vec<Fact>> {
+        let tag = tag.map(|t| t.trim().to_lowercase());
+        Ok(self
+            .load_facts()?
+            .facts
+            .into_iter()
+            .filter(|f| tag.as_ref().is_none_or(|t| f.tags.contains(t)))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ollama::OllamaClient;
+    use crate::rag::SearchMode;
+    use crate::tools::VectorIndex;
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    async fn rag_at(dir: &Path) -> (RagSystem, MockServer) {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/embeddings"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "embedding": [0.1, 0.2, 0.3] })))
+            .mount(&mock_server)
+            .await;
+        let rag = RagSystem::new(
+            OllamaClient::new(mock_server.uri(), "ollama".to_string(), "".to_string()),
+            "m".to_string(),
+            Arc::new(Mutex::new(None)),
+            Some(dir.join("vectors.json")),
+        );
+        (rag, mock_server)
+    }
+
+    fn memory_chunks(rag: &RagSystem) -> Vec<(String, String)> {
+        let guard = rag.index.lock().unwrap();
+        guard
+            .iter()
+            .flat_map(|i| i.chunks.iter())
+            .filter(|c| c.collection == MEMORY_COLLECTION)
+            .map(|c| (c.file_path.clone(), c.content.clone()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_remember_update_and_forget() {
+        let dir = tempfile::tempdir().unwrap();
+        let (rag, _server) = rag_at(dir.path()).await;
+
+        let port = rag
+            .remember("The project uses port 8081\nbehind nginx", Some("work".to_string()), vec!["Ports".to_string(), " ".to_string()])
+            .await
+            .unwrap();
+        let theme = rag.remember("User prefers dark mode", None, vec![]).await.unwrap();
+        assert_eq!((port.id, theme.id), (1, 2));
+        assert_eq!(port.tags, vec!["ports"]);
+        // Multi-line facts stay one record and one chunk.
+        assert_eq!(memory_chunks(&rag).len(), 2);
+
+        let updated = rag.update_fact(port.id, Some("The project uses port 9090"), None).await.unwrap();
+        assert_eq!(updated.tags, vec!["ports"]);
+        assert_eq!(updated.session.as_deref(), Some("work"));
+        let chunks = memory_chunks(&rag);
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks.contains(&("memory:1".to_string(), "The project uses port 9090".to_string())));
+
+        let hits = rag.search_with_mode("port 8081", 5, Some(MEMORY_COLLECTION), SearchMode::Keyword).await.unwrap();
+        assert!(hits.iter().all(|h| !h.content.contains("8081")), "stale fact must not be found");
+
+        assert_eq!(rag.list_facts(Some("PORTS")).unwrap().len(), 1);
+        rag.forget_fact(theme.id).await.unwrap();
+        assert!(rag.forget_fact(theme.id).await.is_err());
+        assert!(rag.update_fact(42, Some("x"), None).await.is_err());
+
+        // Records and chunks survive a restart.
+        let (reloaded, _server) = rag_at(dir.path()).await;
+        reloaded.ensure_loaded().unwrap();
+        let facts = reloaded.list_facts(None).unwrap();
+        assert_eq!(facts.len(), 1);
+        assert_eq!(facts[0].text, "The project uses port 9090");
+        assert_eq!(memory_chunks(&reloaded), vec![("memory:1".to_string(), "The project uses port 9090".to_string())]);
+        assert_eq!(reloaded.remember("next", None, vec![]).await.unwrap().id, 3);
+    }
+
+    #[tokio::test]
+    async fn test_legacy_memories_become_facts() {
+        let dir = tempfile::tempdir().unwrap();
+        let (rag, _server) = rag_at(dir.path()).await;
+        let legacy = |content: &str| TextChunk {
+            file_path: LEGACY_MEMORY_PATH.to_string(),
+            content: content.to_string(),
+            embedding: vec![0.1, 0.2, 0.3],
+            collection: MEMORY_COLLECTION.to_string(),
+            indexed_at: 1_700_000_000,
+            ..Default::default()
+        };
+        *rag.index.lock().unwrap() = Some(VectorIndex::new(vec![legacy("Uses port 8081"), legacy("Likes tea")]));
+        rag.save().unwrap();
+
+        let facts = rag.list_facts(None).unwrap();
+        assert_eq!(facts.iter().map(|f| f.text.as_str()).collect::<Vec<_>>(), vec!["Uses port 8081", "Likes tea"]);
+        assert_eq!(facts[0].created_at, 1_700_000_000);
+        let paths: Vec<String> = memory_chunks(&rag).into_iter().map(|(p, _)| p).collect();
+        assert_eq!(paths, vec!["memory:1", "memory:2"]);
+
+        rag.forget_fact(1).await.unwrap();
+        assert_eq!(memory_chunks(&rag).len(), 1);
+    }
+}
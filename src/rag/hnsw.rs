@@ -0,0 +1,453 @@
+//! Hierarchical Navigable Small World graph for approximate cosine search.
+//!
+//! The graph stores only node links and cached norms; vectors are read from
+//! the owning [`VectorIndex`](crate::tools::VectorIndex) through
+//! [`VectorSource`], so node ids are chunk positions. Level assignment is a
+//! pure function of the node id, which keeps builds reproducible and lets
+//! the graph be persisted and extended incrementally.
+
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashSet};
+
+/// Read access to the vectors a graph is built over.
+pub trait VectorSource {
+    /// Number of vectors.
+    fn len(&self) -> usize;
+    /// The vector for node `id`.
+    fn vector(&self, id: usize) -> &[f64];
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl VectorSource for [Vec<f64>] {
+    fn len(&self) -> usize {
+        <[Vec<f64>]>::len(self)
+    }
+
+    fn vector(&self, id: usize) -> &[f64] {
+        &self[id]
+    }
+}
+
+/// Tuning parameters.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct HnswParams {
+    /// Links per node on upper layers (layer 0 keeps twice as many).
+    pub m: usize,
+    /// Candidate list size while inserting.
+    pub ef_construction: usize,
+    /// Minimum candidate list size while searching.
+    pub ef_search: usize,
+    /// Seed for level assignment.
+    pub seed: u64,
+}
+
+impl Default for HnswParams {
+    fn default() -> Self {
+        Self {
+            m: 16,
+            ef_construction: 100,
+            ef_search: 100,
+            seed: 0x5eed_1dea,
+        }
+    }
+}
+
+/// Cosine distance paired with a node id, ordered by distance.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Scored {
+    dist: f64,
+    id: u32,
+}
+
+impl Eq for Scored {}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.dist.total_cmp(&other.dist).then(self.id.cmp(&other.id))
+    }
+}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// An HNSW graph over cosine distance.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HnswIndex {
+    params: HnswParams,
+    /// Dimension of the indexed vectors; set by the first insert.
+    dim: usize,
+    /// `links[node][layer]` are the neighbours of `node` on `layer`.
+    links: Vec<Vec<Vec<u32>>>,
+    /// Cached `1 / |v|`; zero for nodes that were not linked (empty vector
+    /// or a dimension that doesn't match the graph).
+    inv_norms: Vec<f64>,
+    entry_point: Option<u32>,
+    max_level: usize,
+}
+
+impl Default for HnswIndex {
+    fn default() -> Self {
+        Self::new(HnswParams::default())
+    }
+}
+
+/// SplitMix64, used to derive a node's level from its id.
+fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+fn inv_norm(v: &[f64]) -> f64 {
+    let norm = v.iter().map(|x| x * x).sum::<f64>().sqrt();
+    if norm == 0.0 {
+        0.0
+    } else {
+        1.0 / norm
+    }
+}
+
+impl HnswIndex {
+    pub fn new(params: HnswParams) -> Self {
+        Self {
+            params,
+            dim: 0,
+            links: Vec::new(),
+            inv_norms: Vec::new(),
+            entry_point: None,
+            max_level: 0,
+        }
+    }
+
+    /// Builds a graph over every vector in `source`.
+    pub fn build<S: VectorSource + ?Sized>(source: &S, params: HnswParams) -> Self {
+        let mut index = Self::new(params);
+        index.extend(source);
+        index
+    }
+
+    /// Number of nodes (linked or not) the graph covers.
+    pub fn len(&self) -> usize {
+        self.links.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.links.is_empty()
+    }
+
+    /// Dimension of the linked vectors, or 0 if nothing is linked yet.
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// Inserts the vectors of `source` that the graph doesn't cover yet.
+    ///
+    /// Node ids are positions in `source`, so this only works for sources
+    /// that have been appended to since the last call. Callers must rebuild
+    /// after removing vectors.
+    pub fn extend<S: VectorSource + ?Sized>(&mut self, source: &S) {
+        for id in self.len()..source.len() {
+            self.insert(source, id);
+        }
+    }
+
+    fn level_for(&self, id: usize) -> usize {
+        let ml = 1.0 / (self.params.m.max(2) as f64).ln();
+        // Uniform in (0, 1]
+        let u = ((splitmix64(self.params.seed ^ id as u64) >> 11) as f64 + 1.0) / (1u64 << 53) as f64;
+        ((-u.ln() * ml).floor() as usize).min(16)
+    }
+
+    fn max_links(&self, layer: usize) -> usize {
+        if layer == 0 {
+            self.params.m * 2
+        } else {
+            self.params.m
+        }
+    }
+
+    fn distance<S: VectorSource + ?Sized>(&self, source: &S, query: &[f64], query_inv: f64, id: u32) -> f64 {
+        let v = source.vector(id as usize);
+        let dot: f64 = query.iter().zip(v).map(|(a, b)| a * b).sum();
+        1.0 - dot * query_inv * self.inv_norms[id as usize]
+    }
+
+    fn insert<S: VectorSource + ?Sized>(&mut self, source: &S, id: usize) {
+        debug_assert_eq!(id, self.links.len());
+        let vector = source.vector(id);
+        let inv = inv_norm(vector);
+
+        if self.dim == 0 && inv != 0.0 {
+            self.dim = vector.len();
+        }
+        if inv == 0.0 || vector.len() != self.dim {
+            // Keep the id slot so ids stay aligned with the source.
+            self.links.push(Vec::new());
+            self.inv_norms.push(0.0);
+            return;
+        }
+
+        let level = self.level_for(id);
+        self.links.push(vec![Vec::new(); level + 1]);
+        self.inv_norms.push(inv);
+        let node = id as u32;
+
+        let Some(mut entry) = self.entry_point else {
+            self.entry_point = Some(node);
+            self.max_level = level;
+            return;
+        };
+
+        // Greedy descent through the layers above the new node's level.
+        for layer in (level + 1..=self.max_level).rev() {
+            entry = self.search_layer(source, vector, inv, &[entry], 1, layer)[0].id;
+        }
+
+        let mut entries = vec![entry];
+        for layer in (0..=level.min(self.max_level)).rev() {
+            let candidates = self.search_layer(source, vector, inv, &entries, self.params.ef_construction, layer);
+            let neighbours = self.select_neighbours(source, &candidates, self.params.m);
+            self.links[id][layer] = neighbours.clone();
+
+            for &n in &neighbours {
+                let max = self.max_links(layer);
+                let list = &mut self.links[n as usize][layer];
+                list.push(node);
+                if list.len() > max {
+                    self.prune(source, n, layer);
+                }
+            }
+            entries = candidates.iter().map(|s| s.id).collect();
+        }
+
+        if level > self.max_level {
+            self.max_level = level;
+            self.entry_point = Some(node);
+        }
+    }
+
+    /// Shrinks the links of `node` on `layer` back to capacity.
+    fn prune<S: VectorSource + ?Sized>(&mut self, source: &S, node: u32, layer: usize) {
+        let vector = source.vector(node as usize);
+        let inv = self.inv_norms[node as usize];
+        let mut scored: Vec<Scored> = self.links[node as usize][layer]
+            .iter()
+            .map(|&n| Scored {
+                dist: self.distance(source, vector, inv, n),
+                id: n,
+            })
+            .collect();
+        scored.sort();
+        let keep = self.select_neighbours(source, &scored, self.max_links(layer));
+        self.links[node as usize][layer] = keep;
+    }
+
+    /// Neighbour selection heuristic: prefer candidates that are closer to
+    /// the base node than to any already selected neighbour, which keeps
+    /// links spread across clusters. Remaining slots are filled with the
+    /// closest pruned candidates. `candidates` must be sorted by distance.
+    fn select_neighbours<S: VectorSource + ?Sized>(&self, source: &S, candidates: &[Scored], m: usize) -> Vec<u32> {
+        let mut selected: Vec<u32> = Vec::with_capacity(m);
+        let mut pruned = Vec::new();
+        for c in candidates {
+            if selected.len() >= m {
+                break;
+            }
+            let cv = source.vector(c.id as usize);
+            let cinv = self.inv_norms[c.id as usize];
+            let dominated = selected
+                .iter()
+                .any(|&s| self.distance(source, cv, cinv, s) < c.dist);
+            if dominated {
+                pruned.push(c.id);
+            } else {
+                selected.push(c.id);
+            }
+        }
+        for id in pruned {
+            if selected.len() >= m {
+                break;
+            }
+            selected.push(id);
+        }
+        selected
+    }
+
+    /// Best-first search on one layer; returns up to `ef` nodes sorted by distance.
+    fn search_layer<S: VectorSource + ?Sized>(
+        &self,
+        source: &S,
+        query: &[f64],
+        query_inv: f64,
+        entries: &[u32],
+        ef: usize,
+        layer: usize,
+    ) -> Vec<Scored> {
+        let mut visited: HashSet<u32> = HashSet::new();
+        let mut candidates: BinaryHeap<std::cmp::Reverse<Scored>> = BinaryHeap::new();
+        let mut results: BinaryHeap<Scored> = BinaryHeap::new();
+
+        for &e in entries {
+            if visited.insert(e) {
+                let s = Scored {
+                    dist: self.distance(source, query, query_inv, e),
+                    id: e,
+                };
+                candidates.push(std::cmp::Reverse(s));
+                results.push(s);
+            }
+        }
+        while results.len() > ef {
+            results.pop();
+        }
+
+        while let Some(std::cmp::Reverse(current)) = candidates.pop() {
+            if let Some(worst) = results.peek() {
+                if results.len() >= ef && current.dist > worst.dist {
+                    break;
+                }
+            }
+            let Some(neighbours) = self.links[current.id as usize].get(layer) else {
+                continue;
+            };
+            for &n in neighbours {
+                if !visited.insert(n) {
+                    continue;
+                }
+                let s = Scored {
+                    dist: self.distance(source, query, query_inv, n),
+                    id: n,
+                };
+                let worse_than_all = results.len() >= ef && results.peek().is_some_and(|w| s.dist >= w.dist);
+                if !worse_than_all {
+                    candidates.push(std::cmp::Reverse(s));
+                    results.push(s);
+                    if results.len() > ef {
+                        results.pop();
+                    }
+                }
+            }
+        }
+
+        results.into_sorted_vec()
+    }
+
+    /// Returns up to `ef` approximate nearest neighbours of `query` as
+    /// `(node id, cosine similarity)`, best first.
+    ///
+    /// `ef` is raised to the configured `ef_search` if smaller. Returns an
+    /// empty list if the query dimension doesn't match the graph.
+    pub fn search<S: VectorSource + ?Sized>(&self, source: &S, query: &[f64], ef: usize) -> Vec<(usize, f64)> {
+        let Some(mut entry) = self.entry_point else {
+            return Vec::new();
+        };
+        let query_inv = inv_norm(query);
+        if query.len() != self.dim || query_inv == 0.0 {
+            return Vec::new();
+        }
+
+        for layer in (1..=self.max_level).rev() {
+            entry = self.search_layer(source, query, query_inv, &[entry], 1, layer)[0].id;
+        }
+        let ef = ef.max(self.params.ef_search);
+        self.search_layer(source, query, query_inv, &[entry], ef, 0)
+            .into_iter()
+            .map(|s| (s.id as usize, 1.0 - s.dist))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic pseudo-random vectors with some cluster structure.
+    fn sample_vectors(n: usize, dim: usize, seed: u64) -> Vec<Vec<f64>> {
+        let mut state = seed;
+        let mut next = || {
+            state = splitmix64(state);
+            (state >> 11) as f64 / (1u64 << 53) as f64 - 0.5
+        };
+        let centers: Vec<Vec<f64>> = (0..8).map(|_| (0..dim).map(|_| next()).collect()).collect();
+        (0..n)
+            .map(|i| centers[i % centers.len()].iter().map(|c| c + next() * 0.3).collect())
+            .collect()
+    }
+
+    fn brute_force(vectors: &[Vec<f64>], query: &[f64], k: usize) -> Vec<usize> {
+        let qinv = inv_norm(query);
+        let mut scored: Vec<(usize, f64)> = vectors
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (i, query.iter().zip(v).map(|(a, b)| a * b).sum::<f64>() * qinv * inv_norm(v)))
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored.into_iter().take(k).map(|(i, _)| i).collect()
+    }
+
+    #[test]
+    fn test_recall_against_brute_force() {
+        // Queries come from the same distribution as the indexed vectors.
+        let mut vectors = sample_vectors(2050, 48, 7);
+        let queries = vectors.split_off(2000);
+        let index = HnswIndex::build(vectors.as_slice(), HnswParams::default());
+
+        let k = 10;
+        let mut hits = 0;
+        for q in &queries {
+            let exact: HashSet<usize> = brute_force(&vectors, q, k).into_iter().collect();
+            let approx = index.search(vectors.as_slice(), q, k);
+            hits += approx.iter().take(k).filter(|(id, _)| exact.contains(id)).count();
+        }
+        let recall = hits as f64 / (queries.len() * k) as f64;
+        assert!(recall >= 0.95, "recall@{} too low: {:.3}", k, recall);
+    }
+
+    #[test]
+    fn test_incremental_extend_matches_full_build() {
+        let vectors = sample_vectors(500, 16, 3);
+        let full = HnswIndex::build(vectors.as_slice(), HnswParams::default());
+
+        let mut incremental = HnswIndex::build(&vectors[..200], HnswParams::default());
+        incremental.extend(vectors.as_slice());
+
+        assert_eq!(incremental.len(), full.len());
+        // Insertion is deterministic, so both graphs are identical.
+        assert_eq!(incremental.links, full.links);
+    }
+
+    #[test]
+    fn test_mismatched_dimensions_are_skipped() {
+        let vectors = vec![vec![1.0, 0.0], vec![0.0, 1.0, 0.0], vec![], vec![0.9, 0.1]];
+        let index = HnswIndex::build(vectors.as_slice(), HnswParams::default());
+        assert_eq!(index.len(), 4);
+        assert_eq!(index.dim(), 2);
+
+        let results = index.search(vectors.as_slice(), &[1.0, 0.0], 10);
+        let ids: Vec<usize> = results.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, vec![0, 3]);
+        assert!(index.search(vectors.as_slice(), &[1.0, 0.0, 0.0], 10).is_empty());
+    }
+
+    #[test]
+    fn test_serde_round_trip() {
+        let vectors = sample_vectors(100, 8, 1);
+        let index = HnswIndex::build(vectors.as_slice(), HnswParams::default());
+        let json = serde_json::to_string(&index).unwrap();
+        let restored: HnswIndex = serde_json::from_str(&json).unwrap();
+        let q = &vectors[42];
+        let ids = |results: Vec<(usize, f64)>| results.into_iter().map(|(id, _)| id).collect::<Vec<_>>();
+        assert_eq!(
+            ids(index.search(vectors.as_slice(), q, 5)),
+            ids(restored.search(vectors.as_slice(), q, 5))
+        );
+    }
+}
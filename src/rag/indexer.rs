@@ -0,0 +1,455 @@
+//! Change-aware indexing of directories into the RAG store.
+//!
+//! A manifest (`memory/manifest.json`) records, per collection, every file
+//! that has been indexed together with its mtime, size and content hash.
+//! Re-indexing a directory only reads files whose mtime or size changed,
+//! only re-embeds files whose content hash changed, and drops the chunks of
+//! files that disappeared. Changed files are embedded together in batches
+//! (see `embedder`).
+
+use super::chunker::chunk_file;
+use super::extract::extractors;
+use super::RagSystem;
+use crate::tools::StatusSender;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Chunks shorter than this carry too little signal to embed.
+const MIN_CHUNK_CHARS: usize = 50;
+
+/// Serialises indexing runs and other edits of the manifest.
+pub(crate) static INDEX_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// What was known about a file when it was last indexed.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FileEntry {
+    /// Modification time in nanoseconds since the Unix epoch.
+    pub mtime: u128,
+    pub size: u64,
+    /// FNV-1a hash of the file contents.
+    pub hash: u64,
+    /// Number of chunks stored for the file.
+    pub chunks: usize,
+}
+
+/// Indexed files, keyed by collection and then by absolute path.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    pub collections: BTreeMap<String, BTreeMap<String, FileEntry>>,
+}
+
+impl Manifest {
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+/// Summary of one indexing run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IndexReport {
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+    pub unchanged: usize,
+    /// Files skipped because embedding failed; they are retried next run.
+    pub failed: usize,
+    /// Chunks embedded in this run.
+    pub chunks: usize,
+}
+
+impl IndexReport {
+    /// Whether the run changed the stored index.
+    pub fn has_changes(&self) -> bool {
+        self.added + self.updated + self.removed > 0
+    }
+}
+
+impl fmt::Display for IndexReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} added, {} updated, {} removed, {} unchanged ({} chunks embedded)",
+            self.added, self.updated, self.removed, self.unchanged, self.chunks
+        )?;
+        if self.failed > 0 {
+            write!(f, ", {} failed", self.failed)?;
+        }
+        Ok(())
+    }
+}
+
+/// 64-bit FNV-1a.
+pub fn content_hash(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in bytes {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+struct WalkedFile {
+    path: PathBuf,
+    key: String,
+    mtime: u128,
+    size: u64,
+}
+
+fn walk(root: &Path) -> Vec<WalkedFile> {
+    let mut files = Vec::new();
+    let walker = ignore::WalkBuilder::new(root).standard_filters(true).build();
+    for entry in walker.flatten() {
+        if !entry.file_type().is_some_and(|ft| ft.is_file()) {
+            continue;
+        }
+        let Ok(meta) = entry.metadata() else {
+            continue;
+        };
+        let mtime = meta
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        let path = entry.path().to_path_buf();
+        files.push(WalkedFile {
+            key: path.to_string_lossy().to_string(),
+            path,
+            mtime,
+            size: meta.len(),
+        });
+    }
+    files
+}
+
+impl RagSystem {
+    /// Location of the file manifest, next to the vector store.
+    pub fn manifest_path(&self) -> Option<PathBuf> {
+        self.storage_path
+            .as_ref()
+            .and_then(|p| p.parent())
+            .map(|dir| dir.join("manifest.json"))
+    }
+
+    /// Brings `collection` up to date with the files under `dir`.
+    pub async fn index_directory(&self, dir: &str, collection: &str, status_tx: Option<StatusSender>) -> Result<IndexReport> {
+        let _guard = INDEX_LOCK.lock().await;
+
+        let root = fs::canonicalize(dir).map_err(|e| anyhow::anyhow!("Cannot index '{}': {}", dir, e))?;
+        let manifest_path = self.manifest_path();
+        let mut manifest = match &manifest_path {
+            Some(p) => Manifest::load(p).unwrap_or_else(|e| {
+                tracing::warn!("Ignoring unreadable manifest {:?}: {}", p, e);
+                Manifest::default()
+            }),
+            None => Manifest::default(),
+        };
+
+        // Make sure previously indexed chunks are in memory before we edit them.
+        self.ensure_loaded()?;
+        self.check_fingerprint(collection, None)?;
+
+        let walk_root = root.clone();
+        let files = tokio::task::spawn_blocking(move || walk(&walk_root)).await?;
+
+        let options = self.chunk_options(collection);
+        let known = manifest.collections.entry(collection.to_string()).or_default();
+        let mut report = IndexReport::default();
+        let mut seen = HashSet::new();
+        // Files whose stored chunks must be dropped, and their replacements.
+        let mut stale: HashSet<String> = HashSet::new();
+        let mut new_chunks = Vec::new();
+        let mut manifest_changed = false;
+
+        let mut pending = Vec::new();
+        for file in files {
+            seen.insert(file.key.clone());
+            match known.get_mut(&file.key) {
+                Some(entry) if entry.mtime == file.mtime && entry.size == file.size => {
+                    report.unchanged += 1;
+                }
+                _ => pending.push(file),
+            }
+        }
+
+        if !pending.is_empty() {
+            if let Some(tx) = &status_tx {
+                let _ = tx.send(format!("Checking {} new or modified files...", pending.len()));
+            }
+        }
+
+        // Chunk every changed file first so embeddings go out in full batches.
+        let mut prepared = Vec::new();
+        for file in pending {
+            let Ok(bytes) = fs::read(&file.path) else {
+                continue;
+            };
+            let hash = content_hash(&bytes);
+            let previous = known.get(&file.key).cloned();
+
+            if let Some(prev) = &previous {
+                if prev.hash == hash {
+                    // Touched but not modified.
+                    known.insert(file.key.clone(), FileEntry { mtime: file.mtime, size: file.size, ..prev.clone() });
+                    report.unchanged += 1;
+                    manifest_changed = true;
+                    continue;
+                }
+            }
+
+            // Documents go through their extractor. Binary files, and
+            // documents that can't be read, are recorded so they aren't
+            // re-read every run.
+            let text = extractors().extract(&file.path, &bytes).unwrap_or_else(|e| {
+                tracing::warn!("{:#}", e);
+                None
+            });
+            let chunks: Vec<_> = match text {
+                Some(content) => chunk_file(&file.key, &content, collection, &options)
+                    .into_iter()
+                    .filter(|c| c.content.trim().len() > MIN_CHUNK_CHARS)
+                    .collect(),
+                None => Vec::new(),
+            };
+            prepared.push((file, hash, previous, chunks));
+        }
+
+        let texts: Vec<String> = prepared
+            .iter()
+            .flat_map(|(_, _, _, chunks)| chunks.iter().map(|c| c.content.clone()))
+            .collect();
+        let mut vectors = self
+            .embed_texts(&texts, &format!("'{}'", collection), status_tx.as_ref())
+            .await
+            .into_iter();
+
+        for (file, hash, previous, mut chunks) in prepared {
+            let mut complete = true;
+            for chunk in &mut chunks {
+                match vectors.next().flatten() {
+                    Some(embedding) => chunk.embedding = embedding,
+                    None => complete = false,
+                }
+            }
+            if !complete {
+                report.failed += 1;
+                continue;
+            }
+
+            if previous.is_some() {
+                report.updated += 1;
+            } else {
+                report.added += 1;
+            }
+            report.chunks += chunks.len();
+            stale.insert(file.key.clone());
+            manifest_changed = true;
+            known.insert(
+                file.key.clone(),
+                FileEntry {
+                    mtime: file.mtime,
+                    size: file.size,
+                    hash,
+                    chunks: chunks.len(),
+                },
+            );
+            new_chunks.extend(chunks);
+        }
+
+        // Files under this root that are gone.
+        let root_prefix = root.to_string_lossy().to_string();
+        let deleted: Vec<String> = known
+            .keys()
+            .filter(|k| Path::new(k).starts_with(&root_prefix) && !seen.contains(*k))
+            .cloned()
+            .collect();
+        for key in deleted {
+            known.remove(&key);
+            stale.insert(key);
+            report.removed += 1;
+            manifest_changed = true;
+        }
+
+        if !stale.is_empty() || !new_chunks.is_empty() {
+            self.check_fingerprints(&new_chunks)?;
+            self.record_collections(&new_chunks)?;
+            {
+                let mut guard = self.index.lock().unwrap();
+                let index = guard.get_or_insert_with(|| crate::tools::VectorIndex::new(Vec::new()));
+                index.retain(|c| !(c.collection == collection && stale.contains(&c.file_path)));
+                index.extend(new_chunks);
+            }
+            self.save()?;
+        }
+
+        if manifest_changed {
+            if let Some(p) = &manifest_path {
+                manifest.save(p)?;
+            }
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ollama::OllamaClient;
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn long_text(word: &str) -> String {
+        (0..10).map(|i| format!("{} line number {} with enough text", word, i)).collect::<Vec<_>>().join("\n")
+    }
+
+    fn chunk_count(rag: &RagSystem, collection: &str) -> usize {
+        let guard = rag.index.lock().unwrap();
+        guard
+            .as_ref()
+            .map(|i| i.chunks.iter().filter(|c| c.collection == collection).count())
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn test_content_hash_is_stable() {
+        assert_eq!(content_hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(content_hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_ne!(content_hash(b"abc"), content_hash(b"abd"));
+    }
+
+    #[tokio::test]
+    async fn test_reindex_only_touches_changed_files() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/embeddings"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "embedding": [0.1, 0.2, 0.3] })))
+            .mount(&mock_server)
+            .await;
+
+        let memory = tempfile::tempdir().unwrap();
+        let docs = tempfile::tempdir().unwrap();
+        fs::write(docs.path().join("a.md"), long_text("alpha")).unwrap();
+        fs::write(docs.path().join("b.md"), long_text("beta")).unwrap();
+        fs::write(docs.path().join("c.md"), long_text("gamma")).unwrap();
+
+        let client = OllamaClient::new(mock_server.uri(), "ollama".to_string(), "".to_string());
+        let rag = RagSystem::new(
+            client,
+            "m".to_string(),
+            Arc::new(Mutex::new(None)),
+            Some(memory.path().join("vectors.json")),
+        );
+        let dir = docs.path().to_str().unwrap();
+
+        let report = rag.index_directory(dir, "notes", None).await.unwrap();
+        assert_eq!((report.added, report.updated, report.removed, report.unchanged), (3, 0, 0, 0));
+        assert_eq!(chunk_count(&rag, "notes"), 3);
+        assert!(memory.path().join("manifest.json").exists());
+
+        // Nothing changed: no embeddings requested.
+        let requests_before = mock_server.received_requests().await.unwrap().len();
+        let report = rag.index_directory(dir, "notes", None).await.unwrap();
+        assert_eq!((report.added, report.updated, report.removed, report.unchanged), (0, 0, 0, 3));
+        assert_eq!(mock_server.received_requests().await.unwrap().len(), requests_before);
+        assert_eq!(chunk_count(&rag, "notes"), 3);
+
+        // Modify one, delete one, add one.
+        fs::write(docs.path().join("a.md"), long_text("alpha v2 is longer")).unwrap();
+        fs::remove_file(docs.path().join("b.md")).unwrap();
+        fs::write(docs.path().join("d.md"), long_text("delta")).unwrap();
+
+        let report = rag.index_directory(dir, "notes", None).await.unwrap();
+        assert_eq!((report.added, report.updated, report.removed, report.unchanged), (1, 1, 1, 1));
+        assert_eq!(chunk_count(&rag, "notes"), 3);
+
+        let guard = rag.index.lock().unwrap();
+        let index = guard.as_ref().unwrap();
+        assert!(!index.chunks.iter().any(|c| c.file_path.ends_with("b.md")));
+        assert!(index.chunks.iter().any(|c| c.content.contains("alpha v2")));
+        assert!(!index.chunks.iter().any(|c| c.content.starts_with("alpha line")));
+    }
+
+    #[tokio::test]
+    async fn test_failed_embeddings_are_retried() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/embeddings"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&mock_server)
+            .await;
+
+        let memory = tempfile::tempdir().unwrap();
+        let docs = tempfile::tempdir().unwrap();
+        fs::write(docs.path().join("a.md"), long_text("alpha")).unwrap();
+
+        let client = OllamaClient::new(mock_server.uri(), "ollama".to_string(), "".to_string());
+        let rag = RagSystem::new(
+            client,
+            "m".to_string(),
+            Arc::new(Mutex::new(None)),
+            Some(memory.path().join("vectors.json")),
+        );
+        let dir = docs.path().to_str().unwrap();
+
+        let report = rag.index_directory(dir, "notes", None).await.unwrap();
+        assert_eq!(report.failed, 1);
+        let report = rag.index_directory(dir, "notes", None).await.unwrap();
+        assert_eq!(report.failed, 1, "failed files must not be recorded as indexed");
+    }
+
+    #[tokio::test]
+    async fn test_documents_are_extracted() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/embeddings"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "embedding": [0.1, 0.2, 0.3] })))
+            .mount(&mock_server)
+            .await;
+
+        let memory = tempfile::tempdir().unwrap();
+        let docs = tempfile::tempdir().unwrap();
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/documents");
+        for name in ["sample.pdf", "sample.docx", "sample.epub", "sample.ipynb"] {
+            fs::copy(fixtures.join(name), docs.path().join(name)).unwrap();
+        }
+        fs::write(docs.path().join("broken.pdf"), b"%PDF-1.4 truncated").unwrap();
+
+        let rag = RagSystem::new(
+            OllamaClient::new(mock_server.uri(), "ollama".to_string(), "".to_string()),
+            "m".to_string(),
+            Arc::new(Mutex::new(None)),
+            Some(memory.path().join("vectors.json")),
+        );
+        let report = rag.index_directory(docs.path().to_str().unwrap(), "work", None).await.unwrap();
+        assert_eq!((report.added, report.failed), (5, 0));
+
+        let guard = rag.index.lock().unwrap();
+        let chunks = &guard.as_ref().unwrap().chunks;
+        let has = |file: &str, text: &str| chunks.iter().any(|c| c.file_path.ends_with(file) && c.content.contains(text));
+        assert!(has("sample.pdf", "Invoice 2041"));
+        assert!(has("sample.docx", "Revenue grew by 12 percent"));
+        assert!(has("sample.epub", "The keeper lit the lamp"));
+        assert!(has("sample.ipynb", "pd.read_csv"));
+        assert!(!chunks.iter().any(|c| c.file_path.ends_with("broken.pdf")));
+    }
+}
@@ -0,0 +1,211 @@
+//! Tool output ingested into the index as the agent works.
+//!
+//! Each chunk records the call that produced it (`Provenance`), chunks
+//! already in their collection are skipped, and collections listed in
+//! `tool_output_ttl` stop returning tool output once it is older than the
+//! limit. Expired chunks are removed when the index is loaded or on an
+//! explicit `purge_expired`, not on every add, since removal rebuilds the
+//! search indexes and rewrites the store. Chunks from indexed files carry no
+//! provenance and never expire.
+
+use super::collections::parse_age;
+use super::RagSystem;
+use crate::tools::{stamp_content_hash, unix_now, Provenance, TextChunk};
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+impl RagSystem {
+    /// Sets how long tool output is kept per collection, as ages like "7d".
+    /// Unreadable ages are ignored with a warning.
+    pub fn with_tool_output_ttl(mut self, ttl: HashMap<String, String>) -> Self {
+        self.tool_output_ttl = ttl
+            .into_iter()
+            .filter_map(|(collection, age)| match parse_age(&age) {
+                Ok(duration) => Some((collection, duration)),
+                Err(e) => {
+                    warn!("Ignoring tool_output_ttl for '{}': {}", collection, e);
+                    None
+                }
+            })
+            .collect();
+        self
+    }
+
+    /// Adds the output of a tool call to `collection`, recording where it
+    /// came from.
+    pub async fn add_tool_output(&self, text: &str, collection: &str, provenance: Provenance) -> Result<()> {
+        self.ingest_text(text, Some(collection.to_string()), Some(provenance)).await
+    }
+
+    /// Whether `chunk` is tool output older than its collection's TTL.
+    pub fn is_expired(&self, chunk: &TextChunk, now: u64) -> bool {
+        chunk.provenance.is_some()
+            && self
+                .tool_output_ttl
+                .get(&chunk.collection)
+                .is_some_and(|ttl: &Duration| chunk.indexed_at.saturating_add(ttl.as_secs()) <= now)
+    }
+
+    /// Removes expired tool output and returns how many chunks were removed.
+    pub fn purge_expired(&self) -> Result<usize> {
+        if self.tool_output_ttl.is_empty() {
+            return Ok(0);
+        }
+        let now = unix_now();
+        let removed = match self.index.lock().unwrap().as_mut() {
+            Some(index) => index.retain(|c| !self.is_expired(c, now)),
+            None => 0,
+        };
+        if removed > 0 {
+            debug!("Expired {} chunks of tool output", removed);
+            self.save()?;
+        }
+        Ok(removed)
+    }
+
+    /// Drops chunks whose content is already in the index under the same
+    /// collection and path, or repeats an earlier chunk in `chunks`.
+    pub(crate) fn drop_duplicates(&self, chunks: &mut Vec<TextChunk>) {
+        stamp_content_hash(chunks);
+        let hashes: HashSet<u64> = chunks.iter().map(|c| c.content_hash).collect();
+        let now = unix_now();
+        let mut seen: HashSet<(String, String, u64)> = HashSet::new();
+        if let Some(index) = self.index.lock().unwrap().as_ref() {
+            // Expired copies are about to be purged, so they don't count.
+            for chunk in index.chunks.iter().filter(|c| hashes.contains(&c.content_hash) && !self.is_expired(c, now)) {
+                seen.insert((chunk.collection.clone(), chunk.file_path.clone(), chunk.content_hash));
+            }
+        }
+        let before = chunks.len();
+        chunks.retain(|c| seen.insert((c.collection.clone(), c.file_path.clone(), c.content_hash)));
+        if chunks.len() < before {
+            debug!("Skipped {} chunks already in the index", before - chunks.len());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ollama::OllamaClient;
+    use crate::rag::SearchMode;
+    use crate::tools::VectorIndex;
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    async fn rag_at(dir: &std::path::Path) -> (RagSystem, MockServer) {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/embeddings"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "embedding": [0.1, 0.2, 0.3] })))
+            .mount(&server)
+            .await;
+        let client = OllamaClient::new(server.uri(), "ollama".to_string(), "".to_string());
+        let rag = RagSystem::new(client, "m".to_string(), Arc::new(Mutex::new(None)), Some(dir.join("vectors.json")));
+        (rag, server)
+    }
+
+    fn provenance(url: &str) -> Provenance {
+        Provenance {
+            tool: "read_url".to_string(),
+            args: json!({ "url": url }),
+            session: Some("research".to_string()),
+            url: Some(url.to_string()),
+        }
+    }
+
+    fn chunk_count(rag: &RagSystem) -> usize {
+        rag.index.lock().unwrap().as_ref().map_or(0, |index| index.chunks.len())
+    }
+
+    #[tokio::test]
+    async fn test_repeated_tool_output_is_added_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let (rag, server) = rag_at(dir.path()).await;
+        let page = "Release notes for 2.0.\n\nThe CLI gained a --json flag.";
+
+        rag.add_tool_output(page, "web", provenance("https://example.com/notes")).await.unwrap();
+        let added = chunk_count(&rag);
+        let embeddings = server.received_requests().await.unwrap().len();
+        rag.add_tool_output(page, "web", provenance("https://example.com/notes")).await.unwrap();
+        assert_eq!(chunk_count(&rag), added);
+        // Duplicates are dropped before embedding.
+        assert_eq!(server.received_requests().await.unwrap().len(), embeddings);
+
+        // The same text from elsewhere, or in another collection, is kept.
+        rag.add_tool_output(page, "web", provenance("https://mirror.example.com/notes")).await.unwrap();
+        rag.add_tool_output(page, "default", provenance("https://example.com/notes")).await.unwrap();
+        assert_eq!(chunk_count(&rag), added * 3);
+    }
+
+    #[tokio::test]
+    async fn test_provenance_survives_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let (rag, _server) = rag_at(dir.path()).await;
+        rag.add_tool_output("Tokyo is the capital of Japan.", "web", provenance("https://example.com/japan")).await.unwrap();
+
+        let (reloaded, _server) = rag_at(dir.path()).await;
+        reloaded.load().unwrap();
+        let guard = reloaded.index.lock().unwrap();
+        let chunk = &guard.as_ref().unwrap().chunks[0];
+        assert_eq!(chunk.file_path, "https://example.com/japan");
+        assert_eq!(chunk.provenance, Some(provenance("https://example.com/japan")));
+        assert!(chunk.indexed_at > 0);
+        assert_ne!(chunk.content_hash, 0);
+    }
+
+    #[tokio::test]
+    async fn test_expired_tool_output_is_purged() {
+        let dir = tempfile::tempdir().unwrap();
+        let (rag, _server) = rag_at(dir.path()).await;
+        let rag = rag.with_tool_output_ttl(HashMap::from([("web".to_string(), "1d".to_string())]));
+        let old = unix_now() - 2 * 86_400;
+        let chunk = |collection: &str, content: &str, provenance: Option<Provenance>| TextChunk {
+            file_path: provenance.as_ref().and_then(|p| p.url.clone()).unwrap_or_else(|| "notes.md".to_string()),
+            content: content.to_string(),
+            embedding: vec![0.1, 0.2, 0.3],
+            collection: collection.to_string(),
+            indexed_at: old,
+            provenance,
+            ..Default::default()
+        };
+        *rag.index.lock().unwrap() = Some(VectorIndex::new(vec![
+            chunk("web", "stale search results", Some(provenance("https://example.com/a"))),
+            chunk("web", "a page indexed from disk", None),
+            chunk("default", "older tool output", Some(provenance("https://example.com/b"))),
+        ]));
+        rag.save().unwrap();
+        assert!(rag.is_expired(&rag.index.lock().unwrap().as_ref().unwrap().chunks[0], unix_now()));
+
+        // The expired copy doesn't count as a duplicate, so the content is
+        // added afresh. Adding leaves the expired copy in place, but search
+        // skips it.
+        rag.add_tool_output("stale search results", "web", provenance("https://example.com/a")).await.unwrap();
+        assert_eq!(chunk_count(&rag), 4);
+        let hits = rag.search_scoped("stale search results", 10, None, SearchMode::Keyword).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(rag.purge_expired().unwrap(), 1);
+
+        let (reloaded, _server) = rag_at(dir.path()).await;
+        let reloaded = reloaded.with_tool_output_ttl(HashMap::from([("web".to_string(), "1d".to_string())]));
+        reloaded.load().unwrap();
+        let guard = reloaded.index.lock().unwrap();
+        let chunks = &guard.as_ref().unwrap().chunks;
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks.iter().all(|c| !reloaded.is_expired(c, unix_now())));
+    }
+
+    #[test]
+    fn test_invalid_ttl_is_ignored() {
+        let client = OllamaClient::new("http://localhost".to_string(), "ollama".to_string(), "".to_string());
+        let rag = RagSystem::new(client, "m".to_string(), Arc::new(Mutex::new(None)), None).with_tool_output_ttl(HashMap::from([
+            ("web".to_string(), "7d".to_string()),
+            ("default".to_string(), "soon".to_string()),
+        ]));
+        assert_eq!(rag.tool_output_ttl, HashMap::from([("web".to_string(), Duration::from_secs(7 * 86_400))]));
+    }
+}
@@ -0,0 +1,779 @@
+use anyhow::Result;
+use crate::ollama::OllamaClient;
+use crate::tools::{VectorIndex, TextChunk, Provenance};
+use std::sync::{Arc, Mutex};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::fs;
+use tracing::{info, warn};
+
+pub mod bm25;
+pub mod chunker;
+pub mod collections;
+pub mod embedder;
+pub mod extract;
+pub mod facts;
+pub mod hnsw;
+pub mod indexer;
+pub mod ingest;
+pub mod rerank;
+pub mod watcher;
+pub mod store;
+
+use chunker::{ChunkOptions, Chunker, ProseChunker};
+use embedder::EmbeddingOptions;
+use hnsw::{HnswIndex, VectorSource};
+use rerank::RerankOptions;
+use store::{SegmentStore, MAX_SEGMENTS};
+
+/// Below this many candidate chunks, search is exact (brute-force cosine).
+const EXACT_SEARCH_THRESHOLD: usize = 2000;
+
+/// Rank damping constant for reciprocal-rank fusion (the usual 60).
+const RRF_K: f64 = 60.0;
+
+/// Minimum number of candidates taken from each list before fusion.
+const FUSION_DEPTH: usize = 20;
+
+/// How `RagSystem::search_with_mode` retrieves chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SearchMode {
+    /// Embedding similarity only.
+    Vector,
+    /// BM25 over chunk text only; needs no embedding call.
+    Keyword,
+    /// Both, merged with reciprocal-rank fusion.
+    #[default]
+    Hybrid,
+}
+
+impl std::str::FromStr for SearchMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "vector" => Ok(Self::Vector),
+            "keyword" => Ok(Self::Keyword),
+            "hybrid" => Ok(Self::Hybrid),
+            other => anyhow::bail!("Unknown search mode '{}' (expected vector, keyword or hybrid)", other),
+        }
+    }
+}
+
+/// A chunk returned by search, with where it came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    pub path: String,
+    /// 1-based, inclusive line range; both 0 for text that didn't come from
+    /// a file (memories, web pages).
+    pub start_line: usize,
+    pub end_line: usize,
+    pub collection: String,
+    /// Cosine similarity, BM25 score or fused rank score, depending on the
+    /// search mode. Only comparable within one result list.
+    pub score: f64,
+    pub content: String,
+}
+
+impl SearchHit {
+    fn new(chunk: &TextChunk, score: f64) -> Self {
+        Self {
+            path: chunk.file_path.clone(),
+            start_line: chunk.start_line,
+            end_line: chunk.end_line,
+            collection: chunk.collection.clone(),
+            score,
+            content: chunk.content.clone(),
+        }
+    }
+
+    /// `path:start-end`, or just the path when there is no line range.
+    pub fn citation(&self) -> String {
+        if self.start_line == 0 {
+            self.path.clone()
+        } else {
+            format!("{}:{}-{}", self.path, self.start_line, self.end_line)
+        }
+    }
+
+    /// `path:start-end (score)`.
+    pub fn header(&self) -> String {
+        format!("{} ({:.3})", self.citation(), self.score)
+    }
+}
+
+impl std::fmt::Display for SearchHit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}\n{}", self.header(), self.content.trim())
+    }
+}
+
+/// The ANN graph is rewritten after this many new nodes; anything newer is
+/// re-linked from the store on load.
+const ANN_SAVE_INTERVAL: usize = 1000;
+
+impl VectorSource for [TextChunk] {
+    fn len(&self) -> usize {
+        <[TextChunk]>::len(self)
+    }
+
+    fn vector(&self, id: usize) -> &[f64] {
+        &self[id].embedding
+    }
+}
+
+#[derive(Clone)]
+pub struct RagSystem {
+    pub client: OllamaClient,
+    pub embedding_model: String,
+    pub index: Arc<Mutex<Option<VectorIndex>>>,
+    pub storage_path: Option<PathBuf>,
+    /// Chunking settings per collection; collections not listed use the
+    /// defaults.
+    pub chunking: HashMap<String, ChunkOptions>,
+    /// Batch size, concurrency and retries for embedding calls.
+    pub embedding: EmbeddingOptions,
+    /// How automatic retrieval reranks its hits.
+    pub rerank: RerankOptions,
+    /// How long tool output is kept, per collection; collections not listed
+    /// keep it until forgotten.
+    pub tool_output_ttl: HashMap<String, std::time::Duration>,
+}
+
+impl RagSystem {
+    pub fn new(client: OllamaClient, embedding_model: String, index: Arc<Mutex<Option<VectorIndex>>>, storage_path: Option<PathBuf>) -> Self {
+        Self {
+            client,
+            embedding_model,
+            index,
+            storage_path,
+            chunking: HashMap::new(),
+            embedding: EmbeddingOptions::default(),
+            rerank: RerankOptions::default(),
+            tool_output_ttl: HashMap::new(),
+        }
+    }
+
+    /// The store the app uses for `config`: `memory/vectors.json` in the
+    /// config directory, with the configured server and embedding model.
+    pub fn from_config(config: &crate::config::Config) -> Self {
+        Self::new(
+            OllamaClient::new(config.ollama_url.clone(), config.api_type.clone(), config.api_key.clone()),
+            config.embedding_model.clone(),
+            Arc::new(Mutex::new(None)),
+            config.get_config_dir().map(|d| d.join("memory").join("vectors.json")),
+        )
+        .with_chunking(config.chunking.clone())
+        .with_embedding(config.embedding.clone())
+        .with_rerank(config.rerank.clone())
+        .with_tool_output_ttl(config.tool_output_ttl.clone())
+    }
+
+    /// Sets per-collection chunking options.
+    pub fn with_chunking(mut self, chunking: HashMap<String, ChunkOptions>) -> Self {
+        self.chunking = chunking;
+        self
+    }
+
+    /// Chunking options for `collection`.
+    pub fn chunk_options(&self, collection: &str) -> ChunkOptions {
+        self.chunking.get(collection).cloned().unwrap_or_default()
+    }
+
+    /// Add text to the RAG index
+    pub async fn add_text(&self, text: &str, collection: Option<String>) -> Result<()> {
+        self.ingest_text(text, collection, None).await
+    }
+
+    /// Chunks, embeds and adds `text`, tagged with `provenance` when it is
+    /// tool output.
+    async fn ingest_text(&self, text: &str, collection: Option<String>, provenance: Option<Provenance>) -> Result<()> {
+        let collection_name = collection.unwrap_or_else(|| "default".to_string());
+        let chunker = ProseChunker { options: self.chunk_options(&collection_name) };
+        let chunks = chunker.chunk(text);
+        if chunks.is_empty() {
+             return Ok(());
+        }
+
+        // For ad-hoc RAG text, we don't have file paths or line numbers easily.
+        // We use the source URL when there is one, or a placeholder.
+        let file_path = provenance
+            .as_ref()
+            .and_then(|p| p.url.clone())
+            .unwrap_or_else(|| "session_memory".to_string());
+        let mut doc_chunks = Vec::new();
+
+        // 1. Create text chunks (embeddings generated next)
+        for chunk in chunks {
+            doc_chunks.push(TextChunk {
+                file_path: file_path.clone(),
+                content: chunk.content,
+                start_line: 0,
+                end_line: 0,
+                embedding: Vec::new(),
+                collection: collection_name.clone(),
+                indexed_at: 0,
+                content_hash: 0,
+                provenance: provenance.clone(),
+            });
+        }
+
+        // Skip text already in the collection before paying for embeddings.
+        self.drop_duplicates(&mut doc_chunks);
+        if doc_chunks.is_empty() {
+            return Ok(());
+        }
+        
+        // 2. Generate embeddings
+        let texts: Vec<String> = doc_chunks.iter().map(|c| c.content.clone()).collect();
+        let vectors = self.embed_texts(&texts, &format!("'{}'", collection_name), None).await;
+        for (chunk, vector) in doc_chunks.iter_mut().zip(vectors) {
+            chunk.embedding = vector.unwrap_or_default();
+        }
+        
+        // Remove failed embeddings
+        doc_chunks.retain(|c| !c.embedding.is_empty());
+
+        if doc_chunks.is_empty() {
+            return Ok(());
+        }
+
+        // 3. Add to shared index
+        self.add_chunks(doc_chunks).await
+    }
+
+    /// Explicitly add chunks to the index. Chunks whose content is already
+    /// in their collection under the same path are skipped.
+    pub async fn add_chunks(&self, mut doc_chunks: Vec<TextChunk>) -> Result<()> {
+        self.drop_duplicates(&mut doc_chunks);
+        if doc_chunks.is_empty() {
+            return Ok(());
+        }
+
+        self.check_fingerprints(&doc_chunks)?;
+        self.record_collections(&doc_chunks)?;
+        {
+            let mut guard = self.index.lock().unwrap();
+            if let Some(index) = &mut *guard {
+                index.extend(doc_chunks);
+            } else {
+                *guard = Some(VectorIndex::new(doc_chunks));
+            }
+        }
+
+        self.save()?;
+        Ok(())
+    }
+
+    /// Loads the index from disk unless it is already in memory.
+    pub fn ensure_loaded(&self) -> Result<()> {
+        if self.index.lock().unwrap().is_none() {
+            self.load()?;
+        }
+        Ok(())
+    }
+
+    /// Path of the persisted ANN graph, next to the vectors file.
+    fn ann_path(&self) -> Option<PathBuf> {
+        self.storage_path.as_ref().map(|p| p.with_extension("hnsw"))
+    }
+
+    /// The segment store backing `storage_path` (`vectors.json` -> `vectors.store/`).
+    pub fn store(&self) -> Option<SegmentStore> {
+        self.storage_path.as_ref().map(|p| SegmentStore::new(p.with_extension("store")))
+    }
+
+    /// Persists chunks added since the last save as a new segment, or
+    /// compacts the store when chunks were removed or segments piled up.
+    pub fn save(&self) -> Result<()> {
+        let Some(store) = self.store() else {
+            return Ok(());
+        };
+        let mut guard = self.index.lock().unwrap();
+        let Some(index) = guard.as_mut() else {
+            return Ok(());
+        };
+
+        let compact = index.rewrite_needed || store.segment_count()? >= MAX_SEGMENTS;
+        let ann_path = self.ann_path();
+        if compact {
+            // Drop the saved graph first so a crash can't pair it with the wrong chunks.
+            if let Some(p) = &ann_path {
+                let _ = fs::remove_file(p);
+            }
+            store.rewrite(&index.chunks)?;
+        } else if index.persisted < index.chunks.len() {
+            store.append(&index.chunks[index.persisted..])?;
+        }
+        index.persisted = index.chunks.len();
+        index.rewrite_needed = false;
+
+        if let Some(p) = ann_path {
+            if compact || !p.exists() || index.ann.len() >= index.ann_persisted + ANN_SAVE_INTERVAL {
+                fs::write(&p, serde_json::to_string(&index.ann)?)?;
+                index.ann_persisted = index.ann.len();
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads the index from the segment store, migrating a legacy
+    /// `vectors.json` into the store the first time.
+    pub fn load(&self) -> Result<()> {
+        let (Some(path), Some(store)) = (&self.storage_path, self.store()) else {
+            return Ok(());
+        };
+
+        let chunks = if store.exists() {
+            store.load()?
+        } else if path.exists() {
+            let content = fs::read_to_string(path)?;
+            let legacy: VectorIndex = serde_json::from_str(&content)?;
+            store.rewrite(&legacy.chunks)?;
+            let migrated = path.with_extension("json.migrated");
+            if let Err(e) = fs::rename(path, &migrated) {
+                warn!("Failed to move {:?} aside after migration: {}", path, e);
+            }
+            info!("Migrated {} chunks from {:?} to {:?}", legacy.chunks.len(), path, store.dir());
+            // Re-read so embeddings carry the store's f32 precision.
+            store.load()?
+        } else {
+            return Ok(());
+        };
+
+        let mut index = VectorIndex::from_store(chunks);
+
+        // Reuse the saved graph when it still describes a prefix of
+        // the chunks; `sync_ann` links anything newer or rebuilds.
+        if let Some(ann_path) = self.ann_path().filter(|p| p.exists()) {
+            match fs::read_to_string(&ann_path)
+                .map_err(anyhow::Error::from)
+                .and_then(|s| Ok(serde_json::from_str::<HnswIndex>(&s)?))
+            {
+                Ok(ann) => {
+                    index.ann_persisted = ann.len();
+                    index.ann = ann;
+                }
+                Err(e) => warn!("Ignoring unreadable ANN index {:?}: {}", ann_path, e),
+            }
+        }
+        index.sync_ann();
+        index.sync_bm25();
+
+        *self.index.lock().unwrap() = Some(index);
+        self.purge_expired()?;
+        Ok(())
+    }
+
+    /// Search the RAG index using Cosine Similarity
+    /// collection_filter: If Some, only search chunks belonging to this collection.
+    pub async fn search(&self, query: &str, limit: usize, collection_filter: Option<&str>) -> Result<Vec<SearchHit>> {
+        self.search_with_mode(query, limit, collection_filter, SearchMode::Vector).await
+    }
+
+    /// Search the RAG index with the given retrieval `mode`.
+    /// collection_filter: If Some, only search chunks belonging to this collection.
+    pub async fn search_with_mode(&self, query: &str, limit: usize, collection_filter: Option<&str>, mode: SearchMode) -> Result<Vec<SearchHit>> {
+        let scope = collection_filter.map(|c| vec![c.to_string()]);
+        self.search_scoped(query, limit, scope.as_deref(), mode).await
+    }
+
+    /// Search the RAG index with the given retrieval `mode`.
+    /// collections: If Some, only search chunks belonging to one of these collections.
+    pub async fn search_scoped(&self, query: &str, limit: usize, collections: Option<&[String]>, mode: SearchMode) -> Result<Vec<SearchHit>> {
+        // Check if index exists and has chunks (fast check)
+        {
+            let guard = self.index.lock().unwrap();
+            if let Some(index) = &*guard {
+                if index.chunks.is_empty() {
+                    return Ok(Vec::new());
+                }
+            } else {
+                return Ok(Vec::new());
+            }
+        }
+
+        let query_embedding = match mode {
+            SearchMode::Keyword => None,
+            SearchMode::Vector => Some(self.client.generate_embeddings(&self.embedding_model, query).await?),
+            // Lexical results are still useful when the embedding server is down.
+            SearchMode::Hybrid => match self.client.generate_embeddings(&self.embedding_model, query).await {
+                Ok(embedding) => Some(embedding),
+                Err(e) => {
+                    warn!("Embedding failed, using keyword search only: {}", e);
+                    None
+                }
+            },
+        };
+
+        // Vectors from another model aren't comparable with the query's.
+        let quarantined = self.quarantined_collections();
+        if query_embedding.is_some() && !quarantined.is_empty() {
+            let mut names: Vec<&str> = quarantined.iter().map(String::as_str).collect();
+            names.sort();
+            warn!("Vector search skips collections embedded with another model: {}", names.join(", "));
+        }
+
+        // Expired tool output stays in the index until the next load purges
+        // it, but is never returned.
+        let now = crate::tools::unix_now();
+        let live = |chunk: &TextChunk| !self.is_expired(chunk, now);
+
+        // Re-acquire lock to search
+        let guard = self.index.lock().unwrap();
+        let Some(index) = &*guard else {
+            return Ok(Vec::new());
+        };
+        let hits = match (mode, query_embedding) {
+            (SearchMode::Vector, Some(embedding)) => search_index(index, &embedding, limit, collections, &quarantined, &live),
+            (SearchMode::Hybrid, Some(embedding)) => {
+                let depth = (limit * 4).max(FUSION_DEPTH);
+                let vector = search_index(index, &embedding, depth, collections, &quarantined, &live);
+                let keyword = keyword_search(index, query, depth, collections, &live);
+                reciprocal_rank_fusion(&[vector, keyword], limit)
+            }
+            _ => keyword_search(index, query, limit, collections, &live),
+        };
+        Ok(hits
+            .into_iter()
+            .map(|(i, score)| SearchHit::new(&index.chunks[i], score))
+            .collect())
+    }
+}
+
+/// BM25 search over the `live` chunks in `collections` (all when None).
+fn keyword_search(
+    index: &VectorIndex,
+    query: &str,
+    limit: usize,
+    collections: Option<&[String]>,
+    live: &dyn Fn(&TextChunk) -> bool,
+) -> Vec<(usize, f64)> {
+    index.bm25.search(query, limit, |i| {
+        index.chunks.get(i).is_some_and(|c| live(c) && collections.is_none_or(|f| f.contains(&c.collection)))
+    })
+}
+
+/// Merges ranked lists with reciprocal-rank fusion: each list contributes
+/// `1 / (RRF_K + rank)` per item, so agreement between lists matters more
+/// than the scales of their raw scores.
+fn reciprocal_rank_fusion(lists: &[Vec<(usize, f64)>], limit: usize) -> Vec<(usize, f64)> {
+    let mut fused: Vec<(usize, f64)> = Vec::new();
+    for list in lists {
+        for (rank, (id, _)) in list.iter().enumerate() {
+            let score = 1.0 / (RRF_K + rank as f64 + 1.0);
+            match fused.iter_mut().find(|(i, _)| i == id) {
+                Some(entry) => entry.1 += score,
+                None => fused.push((*id, score)),
+            }
+        }
+    }
+    // Stable sort keeps the first list's order on ties.
+    fused.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    fused.truncate(limit);
+    fused
+}
+
+/// Returns the `limit` best `(chunk index, similarity)` pairs for `query`.
+///
+/// Uses the ANN graph when there are enough candidates to make it worthwhile,
+/// and falls back to exact search when the candidate set is small or the
+/// graph can't produce `limit` matches for `collections`. Chunks in
+/// `quarantined` collections, with vectors of another dimension than
+/// `query` or not `live` are never returned.
+fn search_index(
+    index: &VectorIndex,
+    query: &[f64],
+    limit: usize,
+    collections: Option<&[String]>,
+    quarantined: &HashSet<String>,
+    live: &dyn Fn(&TextChunk) -> bool,
+) -> Vec<(usize, f64)> {
+    // If no filter is provided, search everything.
+    let matches = |chunk: &TextChunk| {
+        live(chunk)
+            && collections.is_none_or(|f| f.contains(&chunk.collection))
+            && chunk.embedding.len() == query.len()
+            && !quarantined.contains(&chunk.collection)
+    };
+
+    let candidates = index.chunks.iter().filter(|c| matches(c)).count();
+    if candidates > EXACT_SEARCH_THRESHOLD && index.ann.len() == index.chunks.len() && index.ann.dim() == query.len() {
+        // Widen the beam in proportion to how much of the graph the filter excludes.
+        let ef = (limit * 2 * index.chunks.len() / candidates.max(1)).min(index.chunks.len());
+        let hits: Vec<(usize, f64)> = index
+            .ann
+            .search(index.chunks.as_slice(), query, ef)
+            .into_iter()
+            .filter(|(i, _)| matches(&index.chunks[*i]))
+            .take(limit)
+            .collect();
+        if hits.len() >= limit.min(candidates) {
+            return hits;
+        }
+    }
+
+    exact_search(index, query, limit, matches)
+}
+
+/// Brute-force cosine similarity over every chunk accepted by `matches`.
+fn exact_search(index: &VectorIndex, query: &[f64], limit: usize, matches: impl Fn(&TextChunk) -> bool) -> Vec<(usize, f64)> {
+    let mut scored: Vec<(usize, f64)> = index
+        .chunks
+        .iter()
+        .enumerate()
+        .filter(|(_, chunk)| matches(chunk))
+        .map(|(i, chunk)| (i, cosine_similarity(query, &chunk.embedding)))
+        .collect();
+
+    // Sort by score descending
+    scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    scored.truncate(limit);
+    scored
+}
+
+fn cosine_similarity(a: &[f64], b: &[f64]) -> f64 {
+    // Vectors from different models can't be compared.
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot_product: f64 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a: f64 = a.iter().map(|x| x * x).sum::<f64>().sqrt();
+    let norm_b: f64 = b.iter().map(|x| x * x).sum::<f64>().sqrt();
+    
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    
+    dot_product / (norm_a * norm_b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cosine_similarity() {
+        let a = vec![1.0, 0.0, 0.0];
+        let b = vec![1.0, 0.0, 0.0];
+        assert!((cosine_similarity(&a, &b) - 1.0).abs() < 1e-6);
+
+        let c = vec![0.0, 1.0, 0.0];
+        assert!((cosine_similarity(&a, &c)).abs() < 1e-6);
+    }
+
+    fn chunk(collection: &str, embedding: Vec<f64>) -> TextChunk {
+        TextChunk {
+            file_path: "test".to_string(),
+            content: format!("{:?}", embedding),
+            start_line: 0,
+            end_line: 0,
+            embedding,
+            collection: collection.to_string(),
+            indexed_at: 0,
+            content_hash: 0,
+            provenance: None,
+        }
+    }
+
+    fn pseudo_random_chunks(n: usize, dim: usize, seed: u64) -> Vec<TextChunk> {
+        let mut state = seed;
+        let mut next = || {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 11) as f64 / (1u64 << 53) as f64 - 0.5
+        };
+        (0..n)
+            .map(|i| {
+                let collection = if i % 100 == 0 { "small" } else { "default" };
+                chunk(collection, (0..dim).map(|_| next()).collect())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_search_index_ann_and_filtered_fallback() {
+        let index = VectorIndex::new(pseudo_random_chunks(EXACT_SEARCH_THRESHOLD + 500, 16, 42));
+        assert_eq!(index.ann.len(), index.chunks.len());
+
+        // Querying with a stored vector must find that chunk first.
+        for probe in [3, 777, 2400] {
+            let query = index.chunks[probe].embedding.clone();
+            let hits = search_index(&index, &query, 5, None, &HashSet::new(), &|_| true);
+            assert_eq!(hits.len(), 5);
+            assert_eq!(hits[0].0, probe);
+        }
+
+        // A filter matching only a few chunks is answered exactly.
+        let query = index.chunks[1].embedding.clone();
+        let hits = search_index(&index, &query, 10, Some(&["small".to_string()]), &HashSet::new(), &|_| true);
+        let expected = exact_search(&index, &query, 10, |c| c.collection == "small");
+        assert_eq!(hits, expected);
+        assert!(hits.iter().all(|(i, _)| index.chunks[*i].collection == "small"));
+    }
+
+    #[test]
+    fn test_reciprocal_rank_fusion_rewards_agreement() {
+        let vector = vec![(1, 0.9), (2, 0.8), (3, 0.7)];
+        let keyword = vec![(3, 12.0), (4, 8.0)];
+        let fused = reciprocal_rank_fusion(&[vector, keyword], 3);
+        let ids: Vec<usize> = fused.iter().map(|(i, _)| *i).collect();
+        // 2 and 4 tie at rank two; the first list wins.
+        assert_eq!(ids, vec![3, 1, 2]);
+    }
+
+    #[test]
+    fn test_search_hit_formatting() {
+        let mut hit = SearchHit {
+            path: "/src/main.rs".to_string(),
+            start_line: 26,
+            end_line: 55,
+            collection: "default".to_string(),
+            score: 0.87654,
+            content: "  fn main() {}\n".to_string(),
+        };
+        assert_eq!(hit.header(), "/src/main.rs:26-55 (0.877)");
+        assert_eq!(hit.to_string(), "/src/main.rs:26-55 (0.877)\nfn main() {}");
+
+        hit.path = "session_memory".to_string();
+        hit.start_line = 0;
+        hit.end_line = 0;
+        assert_eq!(hit.citation(), "session_memory");
+    }
+
+    #[test]
+    fn test_search_mode_parse() {
+        assert_eq!("Keyword".parse::<SearchMode>().unwrap(), SearchMode::Keyword);
+        assert_eq!(SearchMode::default(), SearchMode::Hybrid);
+        assert!("fuzzy".parse::<SearchMode>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_search_modes_find_exact_identifiers() {
+        use serde_json::json;
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/embeddings"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "embedding": [1.0, 0.0] })))
+            .mount(&mock_server)
+            .await;
+
+        let text = |content: &str, embedding: Vec<f64>| TextChunk {
+            content: content.to_string(),
+            ..chunk("default", embedding)
+        };
+        let chunks = vec![
+            text("general notes on compiler diagnostics", vec![1.0, 0.0]),
+            text("more prose about borrowing rules", vec![0.9, 0.1]),
+            text("error[E0502] seen when mutating a borrowed vec", vec![0.0, 1.0]),
+        ];
+        let client = OllamaClient::new(mock_server.uri(), "ollama".to_string(), "".to_string());
+        let rag = RagSystem::new(client, "m".to_string(), Arc::new(Mutex::new(Some(VectorIndex::new(chunks)))), None);
+
+        let vector = rag.search_with_mode("E0502", 2, None, SearchMode::Vector).await.unwrap();
+        assert!(!vector.iter().any(|hit| hit.content.contains("E0502")));
+
+        let keyword = rag.search_with_mode("E0502", 2, None, SearchMode::Keyword).await.unwrap();
+        assert_eq!(keyword.len(), 1);
+        assert!(keyword[0].content.contains("E0502"));
+        assert_eq!(keyword[0].collection, "default");
+        assert!(keyword[0].score > 0.0);
+
+        let hybrid = rag.search_with_mode("E0502", 2, None, SearchMode::Hybrid).await.unwrap();
+        assert_eq!(hybrid.len(), 2);
+        assert!(hybrid.iter().any(|hit| hit.content.contains("E0502")));
+
+        // Keyword search works without the embedding server; hybrid degrades to it.
+        let offline = RagSystem::new(
+            OllamaClient::new("http://127.0.0.1:9".to_string(), "ollama".to_string(), "".to_string()),
+            "m".to_string(),
+            rag.index.clone(),
+            None,
+        );
+        assert_eq!(offline.search_with_mode("E0502", 2, Some("default"), SearchMode::Keyword).await.unwrap().len(), 1);
+        assert_eq!(offline.search_with_mode("E0502", 2, None, SearchMode::Hybrid).await.unwrap().len(), 1);
+        assert!(offline.search_with_mode("E0502", 2, Some("other"), SearchMode::Keyword).await.unwrap().is_empty());
+
+        // A scope of several collections matches any of them; an empty one matches nothing.
+        let scope = ["other".to_string(), "default".to_string()];
+        assert_eq!(offline.search_scoped("E0502", 2, Some(&scope), SearchMode::Keyword).await.unwrap().len(), 1);
+        assert!(offline.search_scoped("E0502", 2, Some(&[]), SearchMode::Keyword).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_ann_graph_persisted_next_to_vectors() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = dir.path().join("vectors.json");
+        let client = OllamaClient::new("http://localhost".to_string(), "ollama".to_string(), "".to_string());
+
+        let rag = RagSystem::new(client.clone(), "m".to_string(), Arc::new(Mutex::new(None)), Some(storage.clone()));
+        rag.add_chunks(pseudo_random_chunks(50, 8, 1)).await.unwrap();
+        rag.add_chunks(pseudo_random_chunks(20, 8, 2)).await.unwrap();
+        assert!(storage.with_extension("hnsw").exists());
+
+        let reloaded = RagSystem::new(client, "m".to_string(), Arc::new(Mutex::new(None)), Some(storage));
+        reloaded.load().unwrap();
+        let guard = reloaded.index.lock().unwrap();
+        let index = guard.as_ref().unwrap();
+        assert_eq!(index.ann.len(), 70);
+        assert_eq!(index.ann.len(), index.chunks.len());
+    }
+
+    #[tokio::test]
+    async fn test_saves_append_segments_and_compact_on_removal() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = dir.path().join("vectors.json");
+        let client = OllamaClient::new("http://localhost".to_string(), "ollama".to_string(), "".to_string());
+        let rag = RagSystem::new(client, "m".to_string(), Arc::new(Mutex::new(None)), Some(storage));
+        let store = rag.store().unwrap();
+
+        rag.add_chunks(pseudo_random_chunks(10, 4, 1)).await.unwrap();
+        rag.add_chunks(pseudo_random_chunks(5, 4, 2)).await.unwrap();
+        rag.add_chunks(pseudo_random_chunks(5, 4, 3)).await.unwrap();
+        assert_eq!(store.segment_count().unwrap(), 3);
+        assert_eq!(store.load().unwrap().len(), 20);
+
+        let removed = rag
+            .index
+            .lock()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .retain(|c| c.collection != "small");
+        assert_eq!(removed, 3);
+        rag.save().unwrap();
+        assert_eq!(store.segment_count().unwrap(), 1);
+        assert_eq!(store.load().unwrap().len(), 17);
+    }
+
+    #[test]
+    fn test_legacy_json_is_migrated() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = dir.path().join("vectors.json");
+        let legacy = VectorIndex::new(pseudo_random_chunks(12, 4, 42));
+        fs::write(&storage, serde_json::to_string(&legacy).unwrap()).unwrap();
+
+        let client = OllamaClient::new("http://localhost".to_string(), "ollama".to_string(), "".to_string());
+        let rag = RagSystem::new(client, "m".to_string(), Arc::new(Mutex::new(None)), Some(storage.clone()));
+        rag.load().unwrap();
+
+        assert!(!storage.exists());
+        assert!(storage.with_extension("json.migrated").exists());
+        assert!(rag.store().unwrap().exists());
+
+        let guard = rag.index.lock().unwrap();
+        let index = guard.as_ref().unwrap();
+        assert_eq!(index.chunks.len(), 12);
+        assert_eq!(index.persisted, 12);
+        assert_eq!(index.chunks[5].content, legacy.chunks[5].content);
+        let drift = index.chunks[5]
+            .embedding
+            .iter()
+            .zip(&legacy.chunks[5].embedding)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f64::max);
+        assert!(drift < 1e-6);
+    }
+}
@@ -0,0 +1,332 @@
+//! Optional reranking of retrieved chunks before automatic context injection.
+//!
+//! Retrieval fetches `candidates` hits; the reranker scores each against the
+//! query from 0 to 1, hits below `min_score` are dropped and the best `top_k`
+//! kept. `cross_encoder` sends the hits to a reranker model behind
+//! `/v1/rerank` (llama.cpp server with `--reranking`, vLLM, LocalAI); `llm`
+//! asks a chat model to rate each hit, so it works with any backend,
+//! Ollama included.
+
+use super::{RagSystem, SearchHit, SearchMode};
+use crate::ollama::{ChatMessageRequest, ChatStreamEvent};
+use anyhow::{anyhow, bail, Result};
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use tracing::warn;
+
+/// Relevance judgements in flight at once for the `llm` method.
+const LLM_CONCURRENCY: usize = 4;
+
+const JUDGE_PROMPT: &str = "You rate search results. Given a query and a passage, reply with a single number from 0 (unrelated) to 10 (directly answers the query). Reply with the number only.";
+
+/// How retrieved hits are re-scored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RerankMethod {
+    /// Keep retrieval order.
+    #[default]
+    None,
+    /// A reranker model scores each query/passage pair.
+    CrossEncoder,
+    /// A chat model rates each passage.
+    Llm,
+}
+
+/// How cross-encoder scores are mapped to 0-1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RerankNormalize {
+    /// Use the scores as reported, for servers that already normalise them
+    /// (vLLM, LocalAI).
+    #[default]
+    None,
+    /// Squash raw logits through a sigmoid, for servers that report them
+    /// (llama.cpp).
+    Sigmoid,
+}
+
+impl RerankNormalize {
+    fn apply(self, scores: Vec<f64>) -> Vec<f64> {
+        match self {
+            RerankNormalize::None => scores,
+            RerankNormalize::Sigmoid => scores.into_iter().map(|s| 1.0 / (1.0 + (-s).exp())).collect(),
+        }
+    }
+}
+
+/// Settings for automatic retrieval (`[rerank]` in the config).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RerankOptions {
+    pub method: RerankMethod,
+    /// Reranker or judge model. When empty, `llm` uses the chat model.
+    pub model: String,
+    /// Hits retrieved for the reranker to choose from.
+    pub candidates: usize,
+    /// Most hits injected into a prompt.
+    pub top_k: usize,
+    /// Reranked hits scoring below this (0 to 1) are dropped. Retrieval
+    /// scores aren't on a fixed scale, so it is ignored without reranking.
+    pub min_score: f64,
+    /// How `cross_encoder` scores are brought to 0-1.
+    pub normalize: RerankNormalize,
+}
+
+impl Default for RerankOptions {
+    fn default() -> Self {
+        Self {
+            method: RerankMethod::None,
+            model: String::new(),
+            candidates: 20,
+            top_k: 3,
+            min_score: 0.5,
+            normalize: RerankNormalize::None,
+        }
+    }
+}
+
+impl RagSystem {
+    /// Sets how automatic retrieval reranks its hits.
+    pub fn with_rerank(mut self, rerank: RerankOptions) -> Self {
+        self.rerank = rerank;
+        self
+    }
+
+    /// Hits to inject as context for `query`: hybrid search over
+    /// `collections` (all when None), reranked when configured. `chat_model`
+    /// judges relevance for the `llm` method when no model is set. If
+    /// reranking fails, the top hits are used as retrieved.
+    pub async fn retrieve_context(&self, query: &str, collections: Option<&[String]>, chat_model: &str) -> Result<Vec<SearchHit>> {
+        let top_k = self.rerank.top_k;
+        if self.rerank.method == RerankMethod::None {
+            return self.search_scoped(query, top_k, collections, SearchMode::Hybrid).await;
+        }
+
+        let depth = self.rerank.candidates.max(top_k);
+        let hits = self.search_scoped(query, depth, collections, SearchMode::Hybrid).await?;
+        if hits.is_empty() {
+            return Ok(hits);
+        }
+        match self.rerank_scores(query, &hits, chat_model).await {
+            Ok(scores) => Ok(select(hits, scores, top_k, self.rerank.min_score)),
+            Err(e) => {
+                warn!("Reranking failed, using retrieval order: {:#}", e);
+                Ok(hits.into_iter().take(top_k).collect())
+            }
+        }
+    }
+
+    /// Relevance of each hit to `query` from 0 to 1, in order.
+    pub async fn rerank_scores(&self, query: &str, hits: &[SearchHit], chat_model: &str) -> Result<Vec<f64>> {
+        let model = if self.rerank.model.is_empty() { chat_model } else { self.rerank.model.as_str() };
+        match self.rerank.method {
+            RerankMethod::None => Ok(hits.iter().map(|hit| hit.score).collect()),
+            RerankMethod::CrossEncoder => {
+                if self.rerank.model.is_empty() {
+                    bail!("The cross_encoder rerank method needs `model` set to a reranker model");
+                }
+                let documents: Vec<String> = hits.iter().map(|hit| hit.content.clone()).collect();
+                Ok(self.rerank.normalize.apply(self.client.rerank(model, query, &documents).await?))
+            }
+            RerankMethod::Llm => {
+                // Collected up front, as in `embed_texts`, to keep the future `Send`.
+                let requests: Vec<_> = hits.iter().map(|hit| self.judge(model, query, &hit.content)).collect();
+                stream::iter(requests).buffered(LLM_CONCURRENCY).collect::<Vec<_>>().await.into_iter().collect()
+            }
+        }
+    }
+
+    /// Asks `model` to rate `passage` for `query`.
+    async fn judge(&self, model: &str, query: &str, passage: &str) -> Result<f64> {
+        let message = |role: &str, content: String| ChatMessageRequest {
+            role: role.to_string(),
+            content,
+            images: None,
+            tool_calls: None,
+            tool_name: None,
+            tool_call_id: None,
+        };
+        let messages = vec![
+            message("system", JUDGE_PROMPT.to_string()),
+            message("user", format!("Query: {}\n\nPassage:\n{}", query, passage.trim())),
+        ];
+        let options = HashMap::from([
+            ("temperature".to_string(), json!(0)),
+            ("num_predict".to_string(), json!(8)),
+        ]);
+
+        let mut stream = self.client.chat(model, messages, None, Some(options)).await?;
+        let mut reply = String::new();
+        while let Some(event) = stream.next().await {
+            if let ChatStreamEvent::Token(token) = event? {
+                reply.push_str(&token);
+            }
+        }
+        parse_rating(&reply).ok_or_else(|| anyhow!("Unreadable relevance rating from {}: {:?}", model, reply))
+    }
+}
+
+/// The first number in `reply`, read as a 0-10 rating and scaled to 0-1.
+fn parse_rating(reply: &str) -> Option<f64> {
+    let start = reply.find(|c: char| c.is_ascii_digit())?;
+    let number: String = reply[start..].chars().take_while(|c| c.is_ascii_digit() || *c == '.').collect();
+    let rating: f64 = number.trim_end_matches('.').parse().ok()?;
+    Some((rating / 10.0).clamp(0.0, 1.0))
+}
+
+/// Gives `hits` their reranked `scores`, drops those under `min_score` and
+/// keeps the best `top_k`.
+fn select(hits: Vec<SearchHit>, scores: Vec<f64>, top_k: usize, min_score: f64) -> Vec<SearchHit> {
+    let mut kept: Vec<SearchHit> = hits
+        .into_iter()
+        .zip(scores)
+        .filter(|(_, score)| *score >= min_score)
+        .map(|(hit, score)| SearchHit { score, ..hit })
+        .collect();
+    kept.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+    kept.truncate(top_k);
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ollama::OllamaClient;
+    use crate::tools::{TextChunk, VectorIndex};
+    use std::sync::{Arc, Mutex};
+    use wiremock::matchers::{body_string_contains, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn hit(content: &str, score: f64) -> SearchHit {
+        SearchHit {
+            path: "notes.md".to_string(),
+            start_line: 1,
+            end_line: 2,
+            collection: "default".to_string(),
+            score,
+            content: content.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_rating() {
+        assert_eq!(parse_rating("8"), Some(0.8));
+        assert_eq!(parse_rating("Rating: 7.5/10"), Some(0.75));
+        assert_eq!(parse_rating("10."), Some(1.0));
+        assert_eq!(parse_rating("42"), Some(1.0));
+        assert_eq!(parse_rating("not relevant"), None);
+    }
+
+    #[test]
+    fn test_select_applies_threshold_and_top_k() {
+        let hits = vec![hit("a", 0.03), hit("b", 0.02), hit("c", 0.01), hit("d", 0.0)];
+        let kept = select(hits, vec![0.4, 0.9, 0.6, 0.7], 2, 0.5);
+        let contents: Vec<&str> = kept.iter().map(|h| h.content.as_str()).collect();
+        assert_eq!(contents, vec!["b", "d"]);
+        assert_eq!(kept[0].score, 0.9);
+    }
+
+    async fn rag_with_chunks(server: &MockServer, rerank: RerankOptions) -> RagSystem {
+        Mock::given(method("POST"))
+            .and(path("/api/embeddings"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "embedding": [1.0, 0.0] })))
+            .mount(server)
+            .await;
+        let chunks = ["deploy steps: run make deploy", "deploy party photos from 2019", "lunch menu"]
+            .iter()
+            .map(|content| TextChunk {
+                file_path: "notes.md".to_string(),
+                content: content.to_string(),
+                start_line: 1,
+                end_line: 1,
+                embedding: vec![1.0, 0.0],
+                collection: "default".to_string(),
+                indexed_at: 0,
+                content_hash: 0,
+                provenance: None,
+            })
+            .collect();
+        let client = OllamaClient::new(server.uri(), "ollama".to_string(), "".to_string());
+        RagSystem::new(client, "m".to_string(), Arc::new(Mutex::new(Some(VectorIndex::new(chunks)))), None).with_rerank(rerank)
+    }
+
+    fn chat_reply(text: &str) -> ResponseTemplate {
+        let body = json!({ "message": { "role": "assistant", "content": text }, "done": true });
+        ResponseTemplate::new(200).set_body_string(format!("{}\n", body))
+    }
+
+    #[tokio::test]
+    async fn test_llm_rerank_drops_irrelevant_hits() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/chat"))
+            .and(body_string_contains("make deploy"))
+            .respond_with(chat_reply("9"))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/chat"))
+            .respond_with(chat_reply("2"))
+            .mount(&server)
+            .await;
+        let options = RerankOptions { method: RerankMethod::Llm, ..RerankOptions::default() };
+        let rag = rag_with_chunks(&server, options).await;
+
+        let hits = rag.retrieve_context("how do I deploy", None, "llama3").await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert!(hits[0].content.contains("make deploy"));
+        assert_eq!(hits[0].score, 0.9);
+    }
+
+    #[tokio::test]
+    async fn test_cross_encoder_normalize_setting() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/rerank"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "results": [{ "index": 0, "relevance_score": -4.0 }, { "index": 1, "relevance_score": 0.0 }]
+            })))
+            .mount(&server)
+            .await;
+        let client = OllamaClient::new(server.uri(), "openai".to_string(), "".to_string());
+        let hits = vec![hit("a", 0.0), hit("b", 0.0)];
+        let options = RerankOptions {
+            method: RerankMethod::CrossEncoder,
+            model: "bge-reranker".to_string(),
+            ..RerankOptions::default()
+        };
+
+        let rag = RagSystem::new(client, "m".to_string(), Arc::new(Mutex::new(None)), None).with_rerank(options.clone());
+        assert_eq!(rag.rerank_scores("q", &hits, "llama3").await.unwrap(), vec![-4.0, 0.0]);
+
+        let rag = rag.with_rerank(RerankOptions { normalize: RerankNormalize::Sigmoid, ..options });
+        let scores = rag.rerank_scores("q", &hits, "llama3").await.unwrap();
+        assert!(scores[0] < 0.05);
+        assert!((scores[1] - 0.5).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_failed_rerank_falls_back_to_retrieval_order() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/chat"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&server)
+            .await;
+        let options = RerankOptions { method: RerankMethod::Llm, top_k: 2, ..RerankOptions::default() };
+        let rag = rag_with_chunks(&server, options).await;
+        assert_eq!(rag.retrieve_context("deploy", None, "llama3").await.unwrap().len(), 2);
+
+        // Ollama has no rerank endpoint, so a cross-encoder falls back too.
+        let options = RerankOptions {
+            method: RerankMethod::CrossEncoder,
+            model: "bge-reranker".to_string(),
+            top_k: 1,
+            ..RerankOptions::default()
+        };
+        let rag = rag.with_rerank(options);
+        assert_eq!(rag.retrieve_context("deploy", None, "llama3").await.unwrap().len(), 1);
+    }
+}
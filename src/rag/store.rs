@@ -0,0 +1,317 @@
+//! Segment-based binary storage for the vector index.
+//!
+//! The store is a directory holding a `manifest.json` and a list of
+//! append-only segments. Each segment is a pair of files written together:
+//!
+//! * `seg-NNNNNN.vec` — `IVEC` magic, format version and record count (all
+//!   little-endian `u32`), then per record its dimension followed by that
+//!   many `f32` components.
+//! * `seg-NNNNNN.jsonl` — one JSON line of chunk metadata per record, in the
+//!   same order, with the embedding left out.
+//!
+//! New chunks are written as a new segment and the manifest is replaced
+//! atomically afterwards, so a save costs O(new chunks) and a crash mid-save
+//! leaves the previous state intact. [`SegmentStore::rewrite`] compacts
+//! everything into a single segment and removes files no longer referenced.
+
+use crate::tools::TextChunk;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8; 4] = b"IVEC";
+const FORMAT_VERSION: u32 = 1;
+const MANIFEST: &str = "manifest.json";
+
+/// Appending beyond this many segments triggers a compaction.
+pub const MAX_SEGMENTS: usize = 32;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct SegmentInfo {
+    id: u64,
+    count: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+struct Manifest {
+    version: u32,
+    next_id: u64,
+    segments: Vec<SegmentInfo>,
+}
+
+/// Handle to a store directory.
+#[derive(Debug, Clone)]
+pub struct SegmentStore {
+    dir: PathBuf,
+}
+
+impl SegmentStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Whether a store has been written to this directory.
+    pub fn exists(&self) -> bool {
+        self.dir.join(MANIFEST).exists()
+    }
+
+    /// Number of live segments.
+    pub fn segment_count(&self) -> Result<usize> {
+        Ok(self.read_manifest()?.segments.len())
+    }
+
+    fn read_manifest(&self) -> Result<Manifest> {
+        let path = self.dir.join(MANIFEST);
+        if !path.exists() {
+            return Ok(Manifest {
+                version: FORMAT_VERSION,
+                ..Default::default()
+            });
+        }
+        let manifest: Manifest = serde_json::from_str(&fs::read_to_string(&path)?)
+            .with_context(|| format!("Invalid store manifest {:?}", path))?;
+        if manifest.version != FORMAT_VERSION {
+            bail!("Unsupported store version {} in {:?}", manifest.version, path);
+        }
+        Ok(manifest)
+    }
+
+    fn write_manifest(&self, manifest: &Manifest) -> Result<()> {
+        let tmp = self.dir.join(format!("{}.tmp", MANIFEST));
+        fs::write(&tmp, serde_json::to_string_pretty(manifest)?)?;
+        fs::rename(&tmp, self.dir.join(MANIFEST))?;
+        Ok(())
+    }
+
+    fn vec_path(&self, id: u64) -> PathBuf {
+        self.dir.join(format!("seg-{:06}.vec", id))
+    }
+
+    fn meta_path(&self, id: u64) -> PathBuf {
+        self.dir.join(format!("seg-{:06}.jsonl", id))
+    }
+
+    /// Loads every chunk in segment order. Returns an empty list for a
+    /// store that hasn't been written yet.
+    pub fn load(&self) -> Result<Vec<TextChunk>> {
+        let manifest = self.read_manifest()?;
+        let mut chunks = Vec::with_capacity(manifest.segments.iter().map(|s| s.count).sum());
+        for segment in &manifest.segments {
+            chunks.extend(self.read_segment(segment)?);
+        }
+        Ok(chunks)
+    }
+
+    fn read_segment(&self, segment: &SegmentInfo) -> Result<Vec<TextChunk>> {
+        let vec_path = self.vec_path(segment.id);
+        let mut reader = BufReader::new(
+            fs::File::open(&vec_path).with_context(|| format!("Missing segment {:?}", vec_path))?,
+        );
+
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            bail!("{:?} is not a vector segment", vec_path);
+        }
+        let version = read_u32(&mut reader)?;
+        if version != FORMAT_VERSION {
+            bail!("Unsupported segment version {} in {:?}", version, vec_path);
+        }
+        let count = read_u32(&mut reader)? as usize;
+        if count != segment.count {
+            bail!("Segment {:?} holds {} records, manifest expects {}", vec_path, count, segment.count);
+        }
+
+        let meta_path = self.meta_path(segment.id);
+        let meta = BufReader::new(
+            fs::File::open(&meta_path).with_context(|| format!("Missing segment metadata {:?}", meta_path))?,
+        );
+
+        let mut chunks = Vec::with_capacity(count);
+        let mut lines = meta.lines();
+        let mut buf = Vec::new();
+        for i in 0..count {
+            let line = lines
+                .next()
+                .with_context(|| format!("{:?} ends after {} of {} records", meta_path, i, count))??;
+            let mut chunk: TextChunk = serde_json::from_str(&line)
+                .with_context(|| format!("Invalid metadata on line {} of {:?}", i + 1, meta_path))?;
+
+            let dim = read_u32(&mut reader)? as usize;
+            buf.resize(dim * 4, 0);
+            reader.read_exact(&mut buf)?;
+            chunk.embedding = buf
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64)
+                .collect();
+            chunks.push(chunk);
+        }
+        Ok(chunks)
+    }
+
+    fn write_segment(&self, id: u64, chunks: &[TextChunk]) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+
+        let mut vec_out = BufWriter::new(fs::File::create(self.vec_path(id))?);
+        vec_out.write_all(MAGIC)?;
+        vec_out.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        vec_out.write_all(&(chunks.len() as u32).to_le_bytes())?;
+
+        let mut meta_out = BufWriter::new(fs::File::create(self.meta_path(id))?);
+        for chunk in chunks {
+            vec_out.write_all(&(chunk.embedding.len() as u32).to_le_bytes())?;
+            for x in &chunk.embedding {
+                vec_out.write_all(&(*x as f32).to_le_bytes())?;
+            }
+
+            let mut meta = serde_json::to_value(chunk)?;
+            if let Some(obj) = meta.as_object_mut() {
+                obj.remove("embedding");
+            }
+            serde_json::to_writer(&mut meta_out, &meta)?;
+            meta_out.write_all(b"\n")?;
+        }
+
+        vec_out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        meta_out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        Ok(())
+    }
+
+    /// Writes `chunks` as a new segment after the existing ones.
+    pub fn append(&self, chunks: &[TextChunk]) -> Result<()> {
+        if chunks.is_empty() {
+            return Ok(());
+        }
+        let mut manifest = self.read_manifest()?;
+        let id = manifest.next_id;
+        self.write_segment(id, chunks)?;
+
+        manifest.next_id += 1;
+        manifest.segments.push(SegmentInfo {
+            id,
+            count: chunks.len(),
+        });
+        self.write_manifest(&manifest)
+    }
+
+    /// Replaces the whole store with `chunks` in a single segment and
+    /// deletes every file the new manifest doesn't reference.
+    pub fn rewrite(&self, chunks: &[TextChunk]) -> Result<()> {
+        let mut manifest = self.read_manifest()?;
+        let id = manifest.next_id;
+        self.write_segment(id, chunks)?;
+
+        manifest.next_id += 1;
+        manifest.segments = vec![SegmentInfo {
+            id,
+            count: chunks.len(),
+        }];
+        self.write_manifest(&manifest)?;
+
+        let keep = [self.vec_path(id), self.meta_path(id)];
+        for entry in fs::read_dir(&self.dir)?.flatten() {
+            let path = entry.path();
+            let is_segment = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with("seg-"));
+            if is_segment && !keep.contains(&path) {
+                let _ = fs::remove_file(&path);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(i: usize, dim: usize) -> TextChunk {
+        TextChunk {
+            file_path: format!("file{}.md", i),
+            content: format!("chunk {}\nwith \"quotes\"", i),
+            start_line: i,
+            end_line: i + 1,
+            embedding: (0..dim).map(|d| (i * dim + d) as f64 * 0.25).collect(),
+            collection: "work".to_string(),
+            indexed_at: 1_700_000_000 + i as u64,
+            content_hash: 0,
+            provenance: None,
+        }
+    }
+
+    #[test]
+    fn test_append_and_load_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SegmentStore::new(dir.path().join("vectors.store"));
+        assert!(!store.exists());
+        assert!(store.load().unwrap().is_empty());
+
+        store.append(&[chunk(0, 4), chunk(1, 4)]).unwrap();
+        // Mixed dimensions and empty embeddings are preserved.
+        store.append(&[chunk(2, 3), chunk(3, 0)]).unwrap();
+        assert_eq!(store.segment_count().unwrap(), 2);
+
+        let loaded = store.load().unwrap();
+        assert_eq!(loaded.len(), 4);
+        for (i, c) in loaded.iter().enumerate() {
+            let expected = chunk(i, if i < 2 { 4 } else if i == 2 { 3 } else { 0 });
+            assert_eq!(c.file_path, expected.file_path);
+            assert_eq!(c.content, expected.content);
+            assert_eq!(c.start_line, expected.start_line);
+            assert_eq!(c.collection, "work");
+            assert_eq!(c.indexed_at, expected.indexed_at);
+            assert_eq!(c.embedding, expected.embedding);
+        }
+    }
+
+    #[test]
+    fn test_rewrite_compacts_segments() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SegmentStore::new(dir.path().to_path_buf());
+        for i in 0..5 {
+            store.append(&[chunk(i, 2)]).unwrap();
+        }
+        // An orphan left behind by an interrupted save.
+        fs::write(dir.path().join("seg-999999.vec"), b"junk").unwrap();
+
+        let all = store.load().unwrap();
+        store.rewrite(&all[1..]).unwrap();
+
+        assert_eq!(store.segment_count().unwrap(), 1);
+        let segment_files = fs::read_dir(dir.path())
+            .unwrap()
+            .flatten()
+            .filter(|e| e.file_name().to_string_lossy().starts_with("seg-"))
+            .count();
+        assert_eq!(segment_files, 2);
+        let loaded = store.load().unwrap();
+        assert_eq!(loaded.len(), 4);
+        assert_eq!(loaded[0].file_path, "file1.md");
+    }
+
+    #[test]
+    fn test_truncated_segment_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SegmentStore::new(dir.path().to_path_buf());
+        store.append(&[chunk(0, 8), chunk(1, 8)]).unwrap();
+
+        let vec_path = store.vec_path(0);
+        let bytes = fs::read(&vec_path).unwrap();
+        fs::write(&vec_path, &bytes[..bytes.len() - 4]).unwrap();
+        assert!(store.load().is_err());
+    }
+}
@@ -0,0 +1,226 @@
+//! Background re-indexing of named knowledge bases.
+//!
+//! Each configured knowledge base gets a recursive filesystem watcher. Change
+//! events are debounced and then fed to [`RagSystem::index_directory`], which
+//! only re-embeds what actually changed. Updates are reported through the
+//! usual [`StatusSender`].
+
+use super::RagSystem;
+use crate::tools::{expand_path, StatusSender};
+use anyhow::{Context, Result};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::warn;
+
+/// Keeps the watchers alive; dropping it stops watching and ends the
+/// re-indexing tasks.
+pub struct KnowledgeBaseWatcher {
+    watchers: Vec<RecommendedWatcher>,
+}
+
+impl KnowledgeBaseWatcher {
+    /// Watches every entry of `bases` (name -> directory), indexing each into
+    /// the collection of the same name. Bases that can't be watched are
+    /// reported on `status_tx` and skipped.
+    pub fn spawn(
+        rag: Arc<RagSystem>,
+        bases: &HashMap<String, String>,
+        ignored_patterns: &[String],
+        debounce: Duration,
+        status_tx: Option<StatusSender>,
+    ) -> Self {
+        let mut watchers = Vec::new();
+        for (name, path) in bases {
+            let dir = PathBuf::from(expand_path(path));
+            match watch_base(rag.clone(), name, &dir, ignored_patterns, debounce, status_tx.clone()) {
+                Ok(watcher) => watchers.push(watcher),
+                Err(e) => {
+                    warn!("Not watching knowledge base '{}': {:#}", name, e);
+                    if let Some(tx) = &status_tx {
+                        let _ = tx.send(format!("Not watching knowledge base '{}': {:#}", name, e));
+                    }
+                }
+            }
+        }
+        Self { watchers }
+    }
+
+    /// Number of knowledge bases being watched.
+    pub fn len(&self) -> usize {
+        self.watchers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.watchers.is_empty()
+    }
+}
+
+fn watch_base(
+    rag: Arc<RagSystem>,
+    name: &str,
+    dir: &Path,
+    ignored_patterns: &[String],
+    debounce: Duration,
+    status_tx: Option<StatusSender>,
+) -> Result<RecommendedWatcher> {
+    let root = dir
+        .canonicalize()
+        .with_context(|| format!("cannot open {:?}", dir))?;
+
+    // The manifest and vector store live in the memory directory; writes
+    // there must not trigger another round of indexing.
+    let memory_dir = rag.manifest_path().and_then(|p| p.parent().map(Path::to_path_buf));
+    let ignored = ignored_patterns.to_vec();
+    let watch_root = root.clone();
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        let Ok(event) = res else {
+            return;
+        };
+        if matches!(event.kind, EventKind::Access(_)) {
+            return;
+        }
+        let relevant = event.paths.iter().any(|p| {
+            let relative = p.strip_prefix(&watch_root).unwrap_or(p);
+            !memory_dir.as_ref().is_some_and(|m| p.starts_with(m)) && !is_ignored(relative, &ignored)
+        });
+        if relevant {
+            let _ = tx.send(());
+        }
+    })?;
+    watcher.watch(&root, RecursiveMode::Recursive)?;
+
+    tokio::spawn(reindex_loop(
+        rag,
+        name.to_string(),
+        root.to_string_lossy().to_string(),
+        rx,
+        debounce,
+        status_tx,
+    ));
+    Ok(watcher)
+}
+
+/// Skips hidden files (`.git`, editor swap files) and configured patterns.
+/// `path` is relative to the knowledge base root.
+fn is_ignored(path: &Path, ignored_patterns: &[String]) -> bool {
+    path.components().any(|c| {
+        let part = c.as_os_str().to_string_lossy();
+        part.starts_with('.') || ignored_patterns.iter().any(|p| *p == part)
+    })
+}
+
+async fn reindex_loop(
+    rag: Arc<RagSystem>,
+    name: String,
+    dir: String,
+    mut rx: mpsc::UnboundedReceiver<()>,
+    debounce: Duration,
+    status_tx: Option<StatusSender>,
+) {
+    // Catch up with anything that changed while we weren't running.
+    reindex(&rag, &name, &dir, &status_tx).await;
+
+    while rx.recv().await.is_some() {
+        // Wait until the directory has been quiet for `debounce`.
+        loop {
+            match tokio::time::timeout(debounce, rx.recv()).await {
+                Ok(Some(())) => continue,
+                Ok(None) => return,
+                Err(_) => break,
+            }
+        }
+        reindex(&rag, &name, &dir, &status_tx).await;
+    }
+}
+
+async fn reindex(rag: &RagSystem, name: &str, dir: &str, status_tx: &Option<StatusSender>) {
+    let message = match rag.index_directory(dir, name, None).await {
+        Ok(report) if report.has_changes() => format!("Knowledge base '{}' updated: {}.", name, report),
+        Ok(_) => return,
+        Err(e) => format!("Re-indexing knowledge base '{}' failed: {}", name, e),
+    };
+    if let Some(tx) = status_tx {
+        let _ = tx.send(message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ollama::OllamaClient;
+    use serde_json::json;
+    use std::fs;
+    use std::sync::Mutex;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn test_is_ignored() {
+        let ignored = vec!["target".to_string()];
+        assert!(is_ignored(Path::new(".git/index"), &ignored));
+        assert!(is_ignored(Path::new("target/debug/x"), &ignored));
+        assert!(!is_ignored(Path::new("notes/todo.md"), &ignored));
+    }
+
+    #[tokio::test]
+    async fn test_watcher_indexes_new_files() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/embeddings"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "embedding": [0.1, 0.2, 0.3] })))
+            .mount(&mock_server)
+            .await;
+
+        let memory = tempfile::tempdir().unwrap();
+        let docs = tempfile::tempdir().unwrap();
+        let rag = Arc::new(RagSystem::new(
+            OllamaClient::new(mock_server.uri(), "ollama".to_string(), "".to_string()),
+            "m".to_string(),
+            Arc::new(Mutex::new(None)),
+            Some(memory.path().join("vectors.json")),
+        ));
+
+        let (status_tx, mut status_rx) = mpsc::unbounded_channel();
+        let bases = HashMap::from([("notes".to_string(), docs.path().to_string_lossy().to_string())]);
+        let watcher = KnowledgeBaseWatcher::spawn(rag.clone(), &bases, &[], Duration::from_millis(100), Some(status_tx));
+        assert_eq!(watcher.len(), 1);
+
+        // Give the initial catch-up pass time to finish on the empty directory.
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let text = (0..10).map(|i| format!("watched line {} with some padding text", i)).collect::<Vec<_>>().join("\n");
+        fs::write(docs.path().join("new.md"), text).unwrap();
+
+        let status = tokio::time::timeout(Duration::from_secs(10), status_rx.recv())
+            .await
+            .expect("no status from watcher")
+            .unwrap();
+        assert!(status.contains("Knowledge base 'notes' updated: 1 added"), "{}", status);
+
+        let guard = rag.index.lock().unwrap();
+        let index = guard.as_ref().unwrap();
+        assert!(index.chunks.iter().any(|c| c.collection == "notes" && c.file_path.ends_with("new.md")));
+    }
+
+    #[tokio::test]
+    async fn test_missing_directory_is_reported() {
+        let memory = tempfile::tempdir().unwrap();
+        let rag = Arc::new(RagSystem::new(
+            OllamaClient::new("http://127.0.0.1:9".to_string(), "ollama".to_string(), "".to_string()),
+            "m".to_string(),
+            Arc::new(Mutex::new(None)),
+            Some(memory.path().join("vectors.json")),
+        ));
+        let (status_tx, mut status_rx) = mpsc::unbounded_channel();
+        let bases = HashMap::from([("gone".to_string(), "/nonexistent/intus-kb".to_string())]);
+        let watcher = KnowledgeBaseWatcher::spawn(rag, &bases, &[], Duration::from_millis(100), Some(status_tx));
+
+        assert!(watcher.is_empty());
+        assert!(status_rx.try_recv().unwrap().contains("Not watching knowledge base 'gone'"));
+    }
+}
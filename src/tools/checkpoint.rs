@@ -0,0 +1,398 @@
+//! Checkpoints of the files agent tools change, so their edits can be undone.
+//!
+//! Before a mutating tool writes or deletes a file it saves the current
+//! content (or the fact that the file didn't exist) under the call that is
+//! about to change it. Undoing restores checkpoints newest first, so a file
+//! edited several times ends up as it was before the earliest undone edit.
+//! Each session has its own store, kept next to the session file. Content
+//! is kept as bytes, so binary files can be restored too.
+//!
+//! Once the call finishes, each file's state is sealed into its snapshot.
+//! Undo refuses to restore a file that has changed since, so edits made by
+//! hand after the agent's are never overwritten.
+
+use super::unix_now;
+use crate::rag::indexer::content_hash;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+
+/// A file as it was before an edit.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub path: PathBuf,
+    /// Content before the edit, or None if the edit created the file.
+    #[serde(with = "base64_content")]
+    pub content: Option<Vec<u8>>,
+    /// The file right after the call, or None while the call is running.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after: Option<FileState>,
+}
+
+/// A file's content hash, or the fact that it doesn't exist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FileState {
+    Missing,
+    Hash(u64),
+}
+
+impl FileState {
+    fn of(path: &Path) -> Result<Self> {
+        match path.exists() {
+            true => Ok(Self::Hash(content_hash(&fs::read(path)?))),
+            false => Ok(Self::Missing),
+        }
+    }
+}
+
+/// Snapshot content as base64, so binary files survive the JSON store.
+mod base64_content {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(content: &Option<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error> {
+        match content {
+            Some(bytes) => serializer.serialize_some(&STANDARD.encode(bytes)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<u8>>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|text| STANDARD.decode(text).map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
+
+/// The files one tool call changed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub call_id: String,
+    /// Id of the first call in the assistant turn, grouping its edits.
+    pub turn: String,
+    /// The user message the turn answered.
+    pub prompt: String,
+    pub tool: String,
+    pub created_at: u64,
+    pub files: Vec<Snapshot>,
+}
+
+/// The edits of one assistant turn.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Turn {
+    pub id: String,
+    pub prompt: String,
+    pub edits: Vec<Checkpoint>,
+}
+
+impl Turn {
+    /// Files the turn touched, in the order it first touched them.
+    pub fn files(&self) -> Vec<&Path> {
+        let mut files: Vec<&Path> = Vec::new();
+        for snapshot in self.edits.iter().flat_map(|e| &e.files) {
+            if !files.contains(&snapshot.path.as_path()) {
+                files.push(&snapshot.path);
+            }
+        }
+        files
+    }
+}
+
+/// A session's checkpoints, saved to `path` when set.
+#[derive(Debug, Default)]
+pub struct CheckpointStore {
+    path: Option<PathBuf>,
+    checkpoints: Mutex<Vec<Checkpoint>>,
+}
+
+impl CheckpointStore {
+    /// Opens the store saved at `path`, or an in-memory one. An unreadable
+    /// file is logged and replaced.
+    pub fn open(path: Option<PathBuf>) -> Self {
+        let checkpoints = path
+            .as_ref()
+            .filter(|p| p.exists())
+            .and_then(|p| match fs::read_to_string(p).map_err(anyhow::Error::from).and_then(|s| Ok(serde_json::from_str(&s)?)) {
+                Ok(checkpoints) => Some(checkpoints),
+                Err(e) => {
+                    warn!("Ignoring unreadable checkpoints at {}: {}", p.display(), e);
+                    None
+                }
+            })
+            .unwrap_or_default();
+        Self { path, checkpoints: Mutex::new(checkpoints) }
+    }
+
+    /// Saves `path` as it is now under call `call_id`. Later saves of the
+    /// same file by the same call keep the first snapshot.
+    pub fn record(&self, call_id: &str, turn: &str, prompt: &str, tool: &str, path: &Path) -> Result<()> {
+        let content = match path.exists() {
+            true => Some(fs::read(path).with_context(|| format!("Can't checkpoint {}", path.display()))?),
+            false => None,
+        };
+        let mut checkpoints = self.checkpoints.lock().unwrap();
+        let index = match checkpoints.iter().position(|c| c.call_id == call_id) {
+            Some(index) => index,
+            None => {
+                checkpoints.push(Checkpoint {
+                    call_id: call_id.to_string(),
+                    turn: turn.to_string(),
+                    prompt: prompt.to_string(),
+                    tool: tool.to_string(),
+                    created_at: unix_now(),
+                    files: Vec::new(),
+                });
+                checkpoints.len() - 1
+            }
+        };
+        let files = &mut checkpoints[index].files;
+        if !files.iter().any(|s| s.path == path) {
+            files.push(Snapshot { path: path.to_path_buf(), content, after: None });
+        }
+        self.save(&checkpoints)
+    }
+
+    /// Records the state call `call_id` left its files in, so undo can tell
+    /// whether they were changed afterwards.
+    pub fn seal(&self, call_id: &str) -> Result<()> {
+        let mut checkpoints = self.checkpoints.lock().unwrap();
+        let Some(checkpoint) = checkpoints.iter_mut().find(|c| c.call_id == call_id) else {
+            return Ok(());
+        };
+        for snapshot in &mut checkpoint.files {
+            snapshot.after = Some(FileState::of(&snapshot.path)?);
+        }
+        self.save(&checkpoints)
+    }
+
+    /// Every checkpoint, oldest first.
+    pub fn list(&self) -> Vec<Checkpoint> {
+        self.checkpoints.lock().unwrap().clone()
+    }
+
+    /// Checkpoints grouped by assistant turn, oldest first.
+    pub fn turns(&self) -> Vec<Turn> {
+        let mut turns: Vec<Turn> = Vec::new();
+        for checkpoint in self.list() {
+            match turns.last_mut() {
+                Some(turn) if turn.id == checkpoint.turn => turn.edits.push(checkpoint),
+                _ => turns.push(Turn {
+                    id: checkpoint.turn.clone(),
+                    prompt: checkpoint.prompt.clone(),
+                    edits: vec![checkpoint],
+                }),
+            }
+        }
+        turns
+    }
+
+    /// Reverts the last `count` edits and returns the files restored.
+    pub fn undo_last(&self, count: usize) -> Result<Vec<PathBuf>> {
+        let len = self.checkpoints.lock().unwrap().len();
+        self.undo_from(len.saturating_sub(count))
+    }
+
+    /// Reverts the first edit matching `pred` and every edit after it.
+    pub fn undo_since(&self, pred: impl Fn(&Checkpoint) -> bool) -> Result<Vec<PathBuf>> {
+        let start = self.checkpoints.lock().unwrap().iter().position(pred);
+        match start {
+            Some(start) => self.undo_from(start),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Reverts the edits from position `start` on, newest first. If a file
+    /// changed since the edit or can't be restored, that edit and the ones
+    /// before it are kept.
+    fn undo_from(&self, start: usize) -> Result<Vec<PathBuf>> {
+        let mut checkpoints = self.checkpoints.lock().unwrap();
+        let mut restored: Vec<PathBuf> = Vec::new();
+        let mut result = Ok(());
+        while checkpoints.len() > start {
+            let checkpoint = checkpoints.pop().unwrap();
+            let undone = checkpoint
+                .files
+                .iter()
+                .try_for_each(|s| unchanged(s, &checkpoint.tool))
+                .and_then(|_| checkpoint.files.iter().rev().try_for_each(restore));
+            if let Err(e) = undone {
+                checkpoints.push(checkpoint);
+                result = Err(e);
+                break;
+            }
+            for snapshot in checkpoint.files {
+                if !restored.contains(&snapshot.path) {
+                    restored.push(snapshot.path);
+                }
+            }
+        }
+        self.save(&checkpoints)?;
+        result.map(|_| restored)
+    }
+
+    fn save(&self, checkpoints: &[Checkpoint]) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let temp_path = path.with_extension("json.tmp");
+        fs::write(&temp_path, serde_json::to_string(checkpoints)?)?;
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    }
+}
+
+/// Fails if the file no longer is as `tool` left it.
+fn unchanged(snapshot: &Snapshot, tool: &str) -> Result<()> {
+    let path = &snapshot.path;
+    match snapshot.after {
+        Some(after) if FileState::of(path)? != after => {
+            bail!("{} changed after {} edited it; not restoring it", path.display(), tool)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Puts a file back as `snapshot` recorded it.
+fn restore(snapshot: &Snapshot) -> Result<()> {
+    let path = &snapshot.path;
+    match &snapshot.content {
+        Some(content) => {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, content).with_context(|| format!("Failed to restore {}", path.display()))
+        }
+        None if path.exists() => fs::remove_file(path).with_context(|| format!("Failed to remove {}", path.display())),
+        None => Ok(()),
+    }
+}
+
+/// Where a tool call saves files before changing them.
+#[derive(Debug, Clone)]
+pub struct Checkpointer {
+    pub store: Arc<CheckpointStore>,
+    pub call_id: String,
+    pub turn: String,
+    pub prompt: String,
+}
+
+impl Checkpointer {
+    /// Saves `path` before `tool` changes it.
+    pub fn save(&self, tool: &str, path: &Path) -> Result<()> {
+        self.store.record(&self.call_id, &self.turn, &self.prompt, tool, path)
+    }
+
+    /// Records the state the call left its files in.
+    pub fn seal(&self) -> Result<()> {
+        self.store.seal(&self.call_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_undo_restores_files_newest_first() -> Result<()> {
+        let dir = tempdir()?;
+        let notes = dir.path().join("notes.md");
+        let created = dir.path().join("src/new.rs");
+        fs::write(&notes, "v1")?;
+        let store = CheckpointStore::open(None);
+
+        store.record("call_1", "call_1", "tidy notes", "write_file", &notes)?;
+        fs::write(&notes, "v2")?;
+        store.record("call_2", "call_2", "more", "edit_file", &notes)?;
+        fs::write(&notes, "v3")?;
+        store.record("call_3", "call_2", "more", "write_file", &created)?;
+        fs::create_dir_all(created.parent().unwrap())?;
+        fs::write(&created, "fn x() {}")?;
+
+        assert_eq!(store.undo_last(1)?, vec![created.clone()]);
+        assert!(!created.exists());
+        assert_eq!(fs::read_to_string(&notes)?, "v3");
+
+        assert_eq!(store.undo_since(|c| c.turn == "call_1")?, vec![notes.clone()]);
+        assert_eq!(fs::read_to_string(&notes)?, "v1");
+        assert!(store.list().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_files_changed_after_the_edit_are_not_restored() -> Result<()> {
+        let dir = tempdir()?;
+        let (a, b) = (dir.path().join("a.txt"), dir.path().join("b.txt"));
+        fs::write(&a, "a1")?;
+        let store = CheckpointStore::open(None);
+
+        store.record("call_1", "call_1", "edit", "write_file", &a)?;
+        fs::write(&a, "a2")?;
+        store.seal("call_1")?;
+        store.record("call_2", "call_2", "edit", "write_file", &b)?;
+        fs::write(&b, "b1")?;
+        store.seal("call_2")?;
+        // The user edits a.txt by hand after the agent did.
+        fs::write(&a, "a2 and mine")?;
+
+        let err = store.undo_last(2).unwrap_err();
+        assert!(err.to_string().contains("changed after write_file edited it"), "{err}");
+        assert_eq!(fs::read_to_string(&a)?, "a2 and mine");
+        assert!(!b.exists());
+        assert_eq!(store.list().len(), 1);
+
+        // Putting the agent's version back makes the edit undoable again.
+        fs::write(&a, "a2")?;
+        assert_eq!(store.undo_last(1)?, vec![a.clone()]);
+        assert_eq!(fs::read_to_string(&a)?, "a1");
+        Ok(())
+    }
+
+    #[test]
+    fn test_binary_files_are_restored() -> Result<()> {
+        let dir = tempdir()?;
+        let image = dir.path().join("logo.png");
+        let bytes = vec![0x89, b'P', b'N', b'G', 0xFF, 0x00, 0xFE];
+        fs::write(&image, &bytes)?;
+        let path = dir.path().join("checkpoints/default.json");
+
+        CheckpointStore::open(Some(path.clone())).record("call_1", "call_1", "tidy", "delete_file", &image)?;
+        fs::remove_file(&image)?;
+
+        assert_eq!(CheckpointStore::open(Some(path)).undo_last(1)?, vec![image.clone()]);
+        assert_eq!(fs::read(&image)?, bytes);
+        Ok(())
+    }
+
+    #[test]
+    fn test_turns_group_edits_and_survive_reload() -> Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("checkpoints/default.json");
+        let (a, b) = (dir.path().join("a.txt"), dir.path().join("b.txt"));
+        fs::write(&a, "a")?;
+
+        let store = CheckpointStore::open(Some(path.clone()));
+        store.record("call_1", "call_1", "first", "write_file", &a)?;
+        // The same call saving a file twice keeps the first snapshot.
+        fs::write(&a, "changed")?;
+        store.record("call_1", "call_1", "first", "write_file", &a)?;
+        store.record("call_2", "call_1", "first", "write_file", &b)?;
+        store.record("call_3", "call_3", "second", "delete_file", &a)?;
+
+        let reloaded = CheckpointStore::open(Some(path));
+        let turns = reloaded.turns();
+        assert_eq!(turns.len(), 2);
+        assert_eq!(turns[0].prompt, "first");
+        assert_eq!(turns[0].files(), vec![a.as_path(), b.as_path()]);
+        assert_eq!(turns[0].edits[0].files[0].content.as_deref(), Some(b"a".as_slice()));
+        assert_eq!(turns[1].edits[0].tool, "delete_file");
+        Ok(())
+    }
+}
@@ -1,14 +1,18 @@
-use super::{expand_path, Tool};
-use anyhow::Result;
+use super::sandbox::{Access, Workspace};
+use super::{path_argument, Tool, ToolContext};
+use anyhow::{bail, Result};
+use async_trait::async_trait;
 use ignore::WalkBuilder;
 use regex::Regex;
 use serde_json::Value;
 use std::fs;
+use std::path::PathBuf;
 
 pub struct SymbolSearchTool {
-    pub ignored_patterns: Vec<String>,
+    pub workspace: Workspace,
 }
 
+#[async_trait]
 impl Tool for SymbolSearchTool {
     fn name(&self) -> &str {
         "find_symbol"
@@ -41,13 +45,17 @@ impl Tool for SymbolSearchTool {
         })
     }
 
-    fn execute(&self, args: Value) -> Result<String> {
+    fn target_paths(&self, args: &Value) -> Option<Vec<PathBuf>> {
+        path_argument(&self.workspace, args, Some("."))
+    }
+
+    async fn execute(&self, args: Value, ctx: &ToolContext) -> Result<String> {
         let query = args
             .get("query")
             .and_then(|v| v.as_str())
             .ok_or_else(|| anyhow::anyhow!("Missing 'query' argument"))?;
 
-        let path_str = expand_path(args.get("path").and_then(|v| v.as_str()).unwrap_or("."));
+        let root = self.workspace.resolve(args.get("path").and_then(|v| v.as_str()).unwrap_or("."), Access::Read)?;
         let extension = args.get("file_extension").and_then(|v| v.as_str());
 
         // Regex patterns for different languages
@@ -64,7 +72,7 @@ impl Tool for SymbolSearchTool {
         let re_py = Regex::new(&py_pattern).unwrap();
         let re_md = Regex::new(&md_pattern).unwrap();
 
-        let walker = WalkBuilder::new(&path_str)
+        let walker = WalkBuilder::new(&root)
             .hidden(false) 
             .ignore(false) // We manually check ignored patterns for flexibility or just use standard .gitignore
             .git_ignore(true)
@@ -73,6 +81,9 @@ impl Tool for SymbolSearchTool {
         let mut results = Vec::new();
 
         for result in walker {
+            if ctx.cancel.is_cancelled() {
+                bail!("Cancelled by user");
+            }
             match result {
                 Ok(entry) => {
                     let path = entry.path();
@@ -86,8 +97,7 @@ impl Tool for SymbolSearchTool {
                     }
 
                     // Manually check ignored patterns from config
-                    let path_lossy = path.to_string_lossy();
-                    if self.ignored_patterns.iter().any(|ignore| path_lossy.contains(ignore)) {
+                    if self.workspace.is_ignored(path) {
                         continue;
                     }
 
@@ -130,22 +140,27 @@ mod tests {
     use std::io::Write;
     use tempfile::tempdir;
 
-    #[test]
-    fn test_find_symbol_rust() -> Result<()> {
+    fn workspace(dir: &std::path::Path) -> Workspace {
+        let root = dir.to_str().unwrap();
+        Workspace::new(&[root], &[root], vec!["target".to_string()])
+    }
+
+    #[tokio::test]
+    async fn test_find_symbol_rust() -> Result<()> {
         let dir = tempdir()?;
         let file_path = dir.path().join("test_code.rs");
         let mut file = File::create(&file_path)?;
         writeln!(file, "fn my_func() {{}}")?;
         writeln!(file, "struct MyStruct {{}}")?;
 
-        let tool = SymbolSearchTool { ignored_patterns: vec![] };
+        let tool = SymbolSearchTool { workspace: workspace(dir.path()) };
         
         // Find function
         let args = serde_json::json!({
             "query": "my_func",
             "path": dir.path().to_str().unwrap()
         });
-        let output = tool.execute(args)?;
+        let output = tool.execute(args, &ToolContext::default()).await?;
         assert!(output.contains("fn my_func"));
 
         // Find struct
@@ -153,28 +168,48 @@ mod tests {
             "query": "MyStruct",
             "path": dir.path().to_str().unwrap()
         });
-        let output = tool.execute(args)?;
+        let output = tool.execute(args, &ToolContext::default()).await?;
         assert!(output.contains("struct MyStruct"));
         
         Ok(())
     }
 
-    #[test]
-    fn test_find_symbol_markdown() -> Result<()> {
+    #[tokio::test]
+    async fn test_find_symbol_markdown() -> Result<()> {
         let dir = tempdir()?;
         let file_path = dir.path().join("README.md");
         let mut file = File::create(&file_path)?;
         writeln!(file, "# Installation Guide")?;
 
-        let tool = SymbolSearchTool { ignored_patterns: vec![] };
+        let tool = SymbolSearchTool { workspace: workspace(dir.path()) };
         
         let args = serde_json::json!({
             "query": "Installation",
             "path": dir.path().to_str().unwrap()
         });
-        let output = tool.execute(args)?;
+        let output = tool.execute(args, &ToolContext::default()).await?;
         assert!(output.contains("# Installation Guide"));
         
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_find_symbol_skips_ignored_directories_only() -> Result<()> {
+        let dir = tempdir()?;
+        for sub in ["target", "target_docs"] {
+            fs::create_dir_all(dir.path().join(sub))?;
+            fs::write(dir.path().join(sub).join("lib.rs"), "fn built() {}\n")?;
+        }
+        let tool = SymbolSearchTool { workspace: workspace(dir.path()) };
+
+        let args = serde_json::json!({ "query": "built", "path": dir.path().to_str().unwrap() });
+        let output = tool.execute(args, &ToolContext::default()).await?;
+        assert!(output.contains("target_docs"), "{}", output);
+        assert!(!output.contains("target/"), "{}", output);
+
+        let outside = tempdir()?;
+        let args = serde_json::json!({ "query": "built", "path": outside.path().to_str().unwrap() });
+        assert!(tool.execute(args, &ToolContext::default()).await.is_err());
+        Ok(())
+    }
 }
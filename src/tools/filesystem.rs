@@ -1,15 +1,22 @@
-use super::{expand_path, Tool, TextChunk};
+use super::patch;
+use super::sandbox::{Access, Workspace};
+use super::{path_argument, FileChange, Provenance, Tool, ToolContext};
 use anyhow::Result;
+use async_trait::async_trait;
 use serde_json::Value;
-use std::process::Command;
-use std::fs::OpenOptions;
+use tokio::process::Command;
+use std::io::Write;
+use std::path::PathBuf;
 use std::sync::Arc;
 use crate::rag::RagSystem;
+use crate::rag::chunker::chunk_file;
+use crate::rag::extract::{extractors, read_text};
 
 pub struct ListDirectoryTool {
-    pub ignored_patterns: Vec<String>,
+    pub workspace: Workspace,
 }
 
+#[async_trait]
 impl Tool for ListDirectoryTool {
     fn name(&self) -> &str {
         "list_directory"
@@ -31,15 +38,19 @@ impl Tool for ListDirectoryTool {
         })
     }
 
-    fn execute(&self, args: Value) -> Result<String> {
-        let path = expand_path(args.get("path").and_then(|v| v.as_str()).unwrap_or("."));
+    fn target_paths(&self, args: &Value) -> Option<Vec<PathBuf>> {
+        path_argument(&self.workspace, args, Some("."))
+    }
+
+    async fn execute(&self, args: Value, ctx: &ToolContext) -> Result<String> {
+        let path = self.workspace.resolve(args.get("path").and_then(|v| v.as_str()).unwrap_or("."), Access::Read)?;
 
         let mut cmd = Command::new("find");
         cmd.arg(path);
         cmd.arg("-maxdepth").arg("1");
 
         // Ignore patterns
-        for ignore in &self.ignored_patterns {
+        for ignore in self.workspace.ignored_patterns() {
             cmd.arg("-not");
             cmd.arg("-path");
             cmd.arg(format!("*/{}/*", ignore));
@@ -50,7 +61,7 @@ impl Tool for ListDirectoryTool {
 
         cmd.arg("-ls");
 
-        let output = cmd.output()?;
+        let output = ctx.output(&mut cmd).await?;
 
         if !output.status.success() {
             return Err(anyhow::anyhow!(
@@ -75,9 +86,10 @@ impl Tool for ListDirectoryTool {
 }
 
 pub struct GrepTool {
-    pub ignored_patterns: Vec<String>,
+    pub workspace: Workspace,
 }
 
+#[async_trait]
 impl Tool for GrepTool {
     fn name(&self) -> &str {
         "grep_files"
@@ -112,13 +124,17 @@ impl Tool for GrepTool {
         })
     }
 
-    fn execute(&self, args: Value) -> Result<String> {
+    fn target_paths(&self, args: &Value) -> Option<Vec<PathBuf>> {
+        path_argument(&self.workspace, args, Some("."))
+    }
+
+    async fn execute(&self, args: Value, ctx: &ToolContext) -> Result<String> {
         let pattern = args
             .get("pattern")
             .and_then(|v| v.as_str())
             .ok_or_else(|| anyhow::anyhow!("Missing 'pattern' argument"))?;
 
-        let path = expand_path(args.get("path").and_then(|v| v.as_str()).unwrap_or("."));
+        let path = self.workspace.resolve(args.get("path").and_then(|v| v.as_str()).unwrap_or("."), Access::Read)?;
         let recursive = args
             .get("recursive")
             .and_then(|v| v.as_bool())
@@ -144,7 +160,7 @@ impl Tool for GrepTool {
             cmd.arg("--max-depth=1");
         }
 
-        for ignore in &self.ignored_patterns {
+        for ignore in self.workspace.ignored_patterns() {
             cmd.arg("-g");
             cmd.arg(format!("!{}", ignore));
             cmd.arg("-g");
@@ -157,7 +173,7 @@ impl Tool for GrepTool {
         cmd.arg(pattern);
         cmd.arg(&path);
 
-        match cmd.output() {
+        match ctx.output(&mut cmd).await {
             Ok(output) => {
                  let exit_code = output.status.code().unwrap_or(-1);
                  
@@ -193,7 +209,7 @@ impl Tool for GrepTool {
             cmd.arg("-i");
         }
 
-        for ignore in &self.ignored_patterns {
+        for ignore in self.workspace.ignored_patterns() {
             cmd.arg(format!("--exclude-dir={}", ignore));
             cmd.arg(format!("--exclude={}", ignore));
             cmd.arg(format!("--exclude={}/", ignore)); 
@@ -202,7 +218,7 @@ impl Tool for GrepTool {
         cmd.arg(pattern);
         cmd.arg(path);
 
-        let output = cmd.output()?;
+        let output = ctx.output(&mut cmd).await?;
         
         let exit_code = output.status.code().unwrap_or(-1);
         if exit_code != 0 && exit_code != 1 && exit_code != 2 {
@@ -231,17 +247,18 @@ impl Tool for GrepTool {
 }
 
 pub struct CatTool {
-    pub ignored_patterns: Vec<String>,
+    pub workspace: Workspace,
     pub rag: Arc<RagSystem>,
 }
 
+#[async_trait]
 impl Tool for CatTool {
     fn name(&self) -> &str {
         "read_file"
     }
 
     fn description(&self) -> &str {
-        "USE THIS to read the full contents of a file. Use this AFTER you have located the file. Returns the complete file text content. If the file is extremely large, it will be truncated."
+        "USE THIS to read the full contents of a file. Use this AFTER you have located the file. Returns the complete file text content; PDF, Word (.docx), HTML, EPUB and Jupyter notebook files are converted to text. If the file is extremely large, it will be truncated."
     }
 
     fn parameters(&self) -> Value {
@@ -261,7 +278,11 @@ impl Tool for CatTool {
         })
     }
 
-    fn execute(&self, args: Value) -> Result<String> {
+    fn target_paths(&self, args: &Value) -> Option<Vec<PathBuf>> {
+        path_argument(&self.workspace, args, None)
+    }
+
+    async fn execute(&self, args: Value, ctx: &ToolContext) -> Result<String> {
         let raw_path = args
             .get("path")
             .and_then(|v| v.as_str())
@@ -272,35 +293,34 @@ impl Tool for CatTool {
             .and_then(|v| v.as_bool())
             .unwrap_or(true);
 
-        let path = expand_path(raw_path);
-
-        if path.contains("..") {
-            return Err(anyhow::anyhow!("Security: '..' not allowed"));
-        }
+        let path = self.workspace.resolve(raw_path, Access::Read)?.to_string_lossy().to_string();
 
-        for ignore in &self.ignored_patterns {
-            if path.contains(ignore) {
+        // PDFs, Office documents, notebooks and the like are read as text.
+        let content_str = if extractors().for_path(std::path::Path::new(&path)).is_some() {
+            let file = std::path::PathBuf::from(&path);
+            ctx.blocking(move || read_text(&file)).await?.unwrap_or_default()
+        } else {
+            let output = ctx.output(Command::new("cat").arg(&path)).await?;
+            if !output.status.success() {
                 return Err(anyhow::anyhow!(
-                    "Access denied: Path contains ignored pattern '{}'",
-                    ignore
+                    "Cat failed: {}",
+                    String::from_utf8_lossy(&output.stderr)
                 ));
             }
-        }
-
-        let output = Command::new("cat").arg(&path).output()?;
-        if !output.status.success() {
-            return Err(anyhow::anyhow!(
-                "Cat failed: {}",
-                String::from_utf8_lossy(&output.stderr)
-            ));
-        }
-
-        let content = String::from_utf8_lossy(&output.stdout);
-        let content_str = content.to_string();
+            String::from_utf8_lossy(&output.stdout).to_string()
+        };
         
-        let handle = tokio::runtime::Handle::current();
-        let chunks = self.chunk_file_for_rag(&path, &content_str);
-        let _ = handle.block_on(self.rag.add_chunks(chunks));
+        let mut chunks = chunk_file(&path, &content_str, "default", &self.rag.chunk_options("default"));
+        let provenance = Provenance {
+            tool: self.name().to_string(),
+            args: args.clone(),
+            session: Some(ctx.session.clone()),
+            url: None,
+        };
+        for chunk in &mut chunks {
+            chunk.provenance = Some(provenance.clone());
+        }
+        let _ = self.rag.add_chunks(chunks).await;
 
         let display_content = if numbered {
             content_str.lines()
@@ -320,38 +340,11 @@ impl Tool for CatTool {
     }
 }
 
-impl CatTool {
-    fn chunk_file_for_rag(&self, file_path: &str, content: &str) -> Vec<TextChunk> {
-        let lines: Vec<&str> = content.lines().collect();
-        let chunk_size = 50;
-        let overlap = 10;
-        let mut chunks = Vec::new();
-
-        let mut start = 0;
-        while start < lines.len() {
-            let end = std::cmp::min(start + chunk_size, lines.len());
-            let chunk_content = lines[start..end].join("\n");
-            if chunk_content.len() > 50 { 
-                chunks.push(TextChunk {
-                    file_path: file_path.to_string(),
-                    content: chunk_content,
-                    start_line: start + 1,
-                    end_line: end,
-                    embedding: vec![],
-                    collection: "default".to_string(),
-                });
-            }
-            if end == lines.len() { break; }
-            start += chunk_size - overlap;
-        }
-        chunks
-    }
-}
-
 pub struct WriteFileTool {
-    pub ignored_patterns: Vec<String>,
+    pub workspace: Workspace,
 }
 
+#[async_trait]
 impl Tool for WriteFileTool {
     fn name(&self) -> &str {
         "write_file"
@@ -382,7 +375,46 @@ impl Tool for WriteFileTool {
         })
     }
 
-    fn execute(&self, args: Value) -> Result<String> {
+    fn target_paths(&self, args: &Value) -> Option<Vec<PathBuf>> {
+        path_argument(&self.workspace, args, None)
+    }
+
+    async fn execute(&self, args: Value, ctx: &ToolContext) -> Result<String> {
+        let (path, content, append) = self.arguments(&args)?;
+        ctx.checkpoint(self.name(), &path)?;
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        if append {
+            let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&path)?;
+            file.write_all(content.as_bytes())?;
+            Ok(format!("Successfully appended to {}", path.display()))
+        } else {
+            std::fs::write(&path, content)?;
+            Ok(format!("Successfully wrote to {}", path.display()))
+        }
+    }
+
+    fn requires_confirmation(&self) -> bool {
+        true
+    }
+
+    fn preview(&self, args: &Value) -> Option<Result<Vec<FileChange>>> {
+        Some(self.change(args).map(|change| vec![change]))
+    }
+
+    fn editable_argument(&self) -> Option<&str> {
+        Some("content")
+    }
+}
+
+/// Files larger than this are described rather than diffed.
+const MAX_DIFF_BYTES: u64 = 1024 * 1024;
+
+impl WriteFileTool {
+    /// The file to write, the content and whether to append it.
+    fn arguments<'a>(&self, args: &'a Value) -> Result<(PathBuf, &'a str, bool)> {
         let raw_path = args
             .get("path")
             .and_then(|v| v.as_str())
@@ -391,56 +423,48 @@ impl Tool for WriteFileTool {
             .get("content")
             .and_then(|v| v.as_str())
             .ok_or_else(|| anyhow::anyhow!("Missing 'content' argument"))?;
-        
         let append = args
             .get("append")
             .and_then(|v| v.as_bool())
             .unwrap_or(false);
 
-        let path = expand_path(raw_path);
-
-        if path.contains("..") {
-            return Err(anyhow::anyhow!("Security: '..' not allowed"));
-        }
-
-        for ignore in &self.ignored_patterns {
-            if path.contains(ignore) {
-                return Err(anyhow::anyhow!(
-                    "Access denied: Path contains ignored pattern '{}'",
-                    ignore
-                ));
-            }
-        }
-
-        let path_obj = std::path::Path::new(&path);
-        if let Some(parent) = path_obj.parent() {
-            std::fs::create_dir_all(parent)?;
-        }
-
-        if append {
-            let mut file = OpenOptions::new()
-                .write(true)
-                .append(true)
-                .create(true)
-                .open(&path)?;
-            use std::io::Write; 
-            file.write_all(content.as_bytes())?;
-            Ok(format!("Successfully appended to {}", path))
-        } else {
-            std::fs::write(&path, content)?;
-            Ok(format!("Successfully wrote to {}", path))
-        }
+        Ok((self.workspace.resolve(raw_path, Access::Write)?, content, append))
     }
 
-    fn requires_confirmation(&self) -> bool {
-        true
+    /// The file the call writes and its content afterwards. Only the
+    /// preview reads the current content.
+    fn change(&self, args: &Value) -> Result<FileChange> {
+        let (path, content, append) = self.arguments(args)?;
+        let Ok(metadata) = std::fs::metadata(&path) else {
+            return Ok(FileChange { path, before: None, after: content.to_string(), summary: None });
+        };
+        let existing = match metadata.len() <= MAX_DIFF_BYTES {
+            true => std::fs::read(&path).ok().and_then(|bytes| String::from_utf8(bytes).ok()),
+            false => None,
+        };
+        let Some(existing) = existing else {
+            let action = if append { "appends" } else { "replaces it with" };
+            let summary = format!(
+                "{} bytes, binary or too large to diff; the call {} {} bytes",
+                metadata.len(),
+                action,
+                content.len()
+            );
+            return Ok(FileChange { path, before: None, after: content.to_string(), summary: Some(summary) });
+        };
+        let after = match append {
+            true => format!("{}{}", existing, content),
+            false => content.to_string(),
+        };
+        Ok(FileChange { path, before: Some(existing), after, summary: None })
     }
 }
 
 pub struct EditFileTool {
-    pub ignored_patterns: Vec<String>,
+    pub workspace: Workspace,
 }
 
+#[async_trait]
 impl Tool for EditFileTool {
     fn name(&self) -> &str {
         "edit_file"
@@ -480,7 +504,36 @@ impl Tool for EditFileTool {
         })
     }
 
-    fn execute(&self, args: Value) -> Result<String> {
+    fn target_paths(&self, args: &Value) -> Option<Vec<PathBuf>> {
+        path_argument(&self.workspace, args, None)
+    }
+
+    async fn execute(&self, args: Value, ctx: &ToolContext) -> Result<String> {
+        let change = self.change(&args)?;
+        ctx.checkpoint(self.name(), &change.path)?;
+        std::fs::write(&change.path, &change.after)?;
+
+        let start_line = args.get("start_line").and_then(|v| v.as_u64()).unwrap_or_default();
+        let end_line = args.get("end_line").and_then(|v| v.as_u64()).unwrap_or_default();
+        Ok(format!("Successfully updated lines {}-{} in {}", start_line, end_line, change.path.display()))
+    }
+
+    fn requires_confirmation(&self) -> bool {
+        true
+    }
+
+    fn preview(&self, args: &Value) -> Option<Result<Vec<FileChange>>> {
+        Some(self.change(args).map(|change| vec![change]))
+    }
+
+    fn editable_argument(&self) -> Option<&str> {
+        Some("content")
+    }
+}
+
+impl EditFileTool {
+    /// The file with the call's line range replaced.
+    fn change(&self, args: &Value) -> Result<FileChange> {
         let raw_path = args
             .get("path")
             .and_then(|v| v.as_str())
@@ -502,20 +555,7 @@ impl Tool for EditFileTool {
             return Err(anyhow::anyhow!("Invalid line range: {}-{}", start_line, end_line));
         }
 
-        let path = expand_path(raw_path);
-
-        if path.contains("..") {
-            return Err(anyhow::anyhow!("Security: '..' not allowed"));
-        }
-
-        for ignore in &self.ignored_patterns {
-            if path.contains(ignore) {
-                return Err(anyhow::anyhow!(
-                    "Access denied: Path contains ignored pattern '{}'",
-                    ignore
-                ));
-            }
-        }
+        let path = self.workspace.resolve(raw_path, Access::Write)?;
 
         let current_content = std::fs::read_to_string(&path)?;
         let lines: Vec<&str> = current_content.lines().collect();
@@ -549,20 +589,15 @@ impl Tool for EditFileTool {
         // We might want a trailing newline.
         let final_content = if final_content.ends_with('\n') { final_content } else { final_content + "\n" };
 
-        std::fs::write(&path, final_content)?;
-
-        Ok(format!("Successfully updated lines {}-{} in {}", start_line, end_line, path))
-    }
-
-    fn requires_confirmation(&self) -> bool {
-        true
+        Ok(FileChange { path, before: Some(current_content), after: final_content, summary: None })
     }
 }
 
 pub struct ReplaceTextTool {
-    pub ignored_patterns: Vec<String>,
+    pub workspace: Workspace,
 }
 
+#[async_trait]
 impl Tool for ReplaceTextTool {
     fn name(&self) -> &str {
         "replace_text"
@@ -593,7 +628,34 @@ impl Tool for ReplaceTextTool {
         })
     }
 
-    fn execute(&self, args: Value) -> Result<String> {
+    fn target_paths(&self, args: &Value) -> Option<Vec<PathBuf>> {
+        path_argument(&self.workspace, args, None)
+    }
+
+    async fn execute(&self, args: Value, ctx: &ToolContext) -> Result<String> {
+        let change = self.change(&args)?;
+        ctx.checkpoint(self.name(), &change.path)?;
+        std::fs::write(&change.path, &change.after)?;
+
+        Ok(format!("Successfully modified {}", change.path.display()))
+    }
+
+    fn requires_confirmation(&self) -> bool {
+        true
+    }
+
+    fn preview(&self, args: &Value) -> Option<Result<Vec<FileChange>>> {
+        Some(self.change(args).map(|change| vec![change]))
+    }
+
+    fn editable_argument(&self) -> Option<&str> {
+        Some("new_text")
+    }
+}
+
+impl ReplaceTextTool {
+    /// The file with the call's `old_text` replaced.
+    fn change(&self, args: &Value) -> Result<FileChange> {
         let raw_path = args
             .get("path")
             .and_then(|v| v.as_str())
@@ -607,20 +669,7 @@ impl Tool for ReplaceTextTool {
             .and_then(|v| v.as_str())
             .ok_or_else(|| anyhow::anyhow!("Missing 'new_text' argument"))?;
 
-        let path = expand_path(raw_path);
-
-        if path.contains("..") {
-            return Err(anyhow::anyhow!("Security: '..' not allowed"));
-        }
-
-        for ignore in &self.ignored_patterns {
-            if path.contains(ignore) {
-                return Err(anyhow::anyhow!(
-                    "Access denied: Path contains ignored pattern '{}'",
-                    ignore
-                ));
-            }
-        }
+        let path = self.workspace.resolve(raw_path, Access::Write)?;
 
         let current_content = std::fs::read_to_string(&path)?;
 
@@ -634,27 +683,22 @@ impl Tool for ReplaceTextTool {
         }
 
         let new_content = current_content.replace(old_text, new_text);
-        std::fs::write(&path, new_content)?;
-
-        Ok(format!("Successfully modified {}", path))
-    }
-
-    fn requires_confirmation(&self) -> bool {
-        true
+        Ok(FileChange { path, before: Some(current_content), after: new_content, summary: None })
     }
 }
 
 pub struct DeleteFileTool {
-    pub ignored_patterns: Vec<String>,
+    pub workspace: Workspace,
 }
 
+#[async_trait]
 impl Tool for DeleteFileTool {
     fn name(&self) -> &str {
         "delete_file"
     }
 
     fn description(&self) -> &str {
-        "USE THIS to DELETE a file. WARNING: This is permanent. Input: path. Checks for ignored patterns."
+        "USE THIS to DELETE a file. WARNING: This is permanent. Input: path. Only files inside the workspace can be deleted."
     }
 
     fn parameters(&self) -> Value {
@@ -670,31 +714,23 @@ impl Tool for DeleteFileTool {
         })
     }
 
-    fn execute(&self, args: Value) -> Result<String> {
+    fn target_paths(&self, args: &Value) -> Option<Vec<PathBuf>> {
+        path_argument(&self.workspace, args, None)
+    }
+
+    async fn execute(&self, args: Value, ctx: &ToolContext) -> Result<String> {
         let raw_path = args
             .get("path")
             .and_then(|v| v.as_str())
             .ok_or_else(|| anyhow::anyhow!("Missing 'path' argument"))?;
 
-        let path = expand_path(raw_path);
-
-        if path.contains("..") {
-            return Err(anyhow::anyhow!("Security: '..' not allowed"));
-        }
-
-        for ignore in &self.ignored_patterns {
-            if path.contains(ignore) {
-                return Err(anyhow::anyhow!(
-                    "Access denied: Path contains ignored pattern '{}'",
-                    ignore
-                ));
-            }
-        }
+        let path = self.workspace.resolve(raw_path, Access::Write)?.to_string_lossy().to_string();
 
         if !std::path::Path::new(&path).exists() {
              return Err(anyhow::anyhow!("File does not exist: {}", path));
         }
 
+        ctx.checkpoint(self.name(), std::path::Path::new(&path))?;
         std::fs::remove_file(&path)?;
 
         Ok(format!("Successfully deleted {}", path))
@@ -705,6 +741,136 @@ impl Tool for DeleteFileTool {
     }
 }
 
+pub struct ApplyPatchTool {
+    pub workspace: Workspace,
+}
+
+#[async_trait]
+impl Tool for ApplyPatchTool {
+    fn name(&self) -> &str {
+        "apply_patch"
+    }
+
+    fn description(&self) -> &str {
+        "USE THIS for edits with several changes, in one or more files. Input: a unified diff ('--- a/path', '+++ b/path', '@@' hunks with 3 lines of context). Context is matched fuzzily, so line numbers may be approximate. Use '--- /dev/null' to create a file. If any hunk doesn't apply, nothing is changed and each hunk's result is reported."
+    }
+
+    fn parameters(&self) -> Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "patch": {
+                    "type": "string",
+                    "description": "The unified diff to apply."
+                }
+            },
+            "required": ["patch"]
+        })
+    }
+
+    fn target_paths(&self, args: &Value) -> Option<Vec<PathBuf>> {
+        let files = patch::parse(args.get("patch")?.as_str()?).ok()?;
+        let mut paths: Vec<PathBuf> = Vec::new();
+        for raw in files.iter().flat_map(|f| f.old_path.iter().chain(&f.new_path)) {
+            let path = self.workspace.target(raw);
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+        Some(paths)
+    }
+
+    async fn execute(&self, args: Value, ctx: &ToolContext) -> Result<String> {
+        let (changes, report) = self.changes(&args)?;
+        for change in &changes {
+            ctx.checkpoint(self.name(), &change.path)?;
+        }
+        for change in &changes {
+            if let Some(parent) = change.path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&change.path, &change.after)?;
+        }
+
+        Ok(format!("Successfully patched {} file(s):\n{}", changes.len(), report))
+    }
+
+    fn requires_confirmation(&self) -> bool {
+        true
+    }
+
+    fn preview(&self, args: &Value) -> Option<Result<Vec<FileChange>>> {
+        Some(self.changes(args).map(|(changes, _)| changes))
+    }
+
+    fn editable_argument(&self) -> Option<&str> {
+        Some("patch")
+    }
+}
+
+impl ApplyPatchTool {
+    /// The files the patch changes, with a line per hunk saying where it
+    /// applied. Fails with that report if any hunk doesn't apply.
+    fn changes(&self, args: &Value) -> Result<(Vec<FileChange>, String)> {
+        let patch = args
+            .get("patch")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow::anyhow!("Missing 'patch' argument"))?;
+
+        let mut changes: Vec<FileChange> = Vec::new();
+        let mut report = Vec::new();
+        let mut failed = false;
+        for file in patch::parse(patch)? {
+            let name = file.display_path().to_string();
+            let Some(raw_path) = &file.new_path else {
+                anyhow::bail!("The patch deletes {}; use delete_file to delete files", name);
+            };
+            if file.old_path.as_ref().is_some_and(|old| old != raw_path) {
+                anyhow::bail!("The patch renames {} to {}; renaming isn't supported", file.old_path.unwrap(), raw_path);
+            }
+            let path = self.workspace.resolve(raw_path, Access::Write)?;
+
+            // Several patches to one file apply in turn.
+            let index = match changes.iter().position(|c| c.path == path) {
+                Some(index) => index,
+                None => {
+                    let before = if path.exists() {
+                        if file.old_path.is_none() {
+                            anyhow::bail!("The patch creates {}, but it already exists", name);
+                        }
+                        Some(std::fs::read_to_string(&path)?)
+                    } else if file.old_path.is_some() {
+                        anyhow::bail!("File does not exist: {}", name);
+                    } else {
+                        None
+                    };
+                    let after = before.clone().unwrap_or_default();
+                    changes.push(FileChange { path, before, after, summary: None });
+                    changes.len() - 1
+                }
+            };
+
+            let (patched, results) = patch::apply(&changes[index].after, &file.hunks);
+            for (i, result) in results.iter().enumerate() {
+                match result {
+                    Ok(placement) => report.push(format!("{}: hunk {} {}", name, i + 1, placement)),
+                    Err(e) => {
+                        failed = true;
+                        report.push(format!("{}: hunk {} FAILED: {}", name, i + 1, e));
+                    }
+                }
+            }
+            changes[index].after = patched;
+        }
+
+        let report = report.join("\n");
+        if failed {
+            anyhow::bail!("Patch not applied; no files were changed.\n{}", report);
+        }
+        Ok((changes, report))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -714,19 +880,29 @@ mod tests {
     use crate::ollama::OllamaClient;
     use std::sync::Mutex;
 
-    #[test]
-    fn test_list_directory_tool() -> Result<()> {
+    fn workspace(dir: &std::path::Path) -> Workspace {
+        let root = dir.to_str().unwrap();
+        Workspace::new(&[root], &[root], vec![])
+    }
+
+    #[tokio::test]
+    async fn test_list_directory_tool() -> Result<()> {
         let dir = tempdir()?;
         let file_path = dir.path().join("test_ls.txt");
         File::create(&file_path)?;
 
         let tool = ListDirectoryTool {
-            ignored_patterns: vec![],
+            workspace: workspace(dir.path()),
         };
         let args = serde_json::json!({ "path": dir.path().to_str().unwrap() });
 
-        let output = tool.execute(args)?;
+        let output = tool.execute(args, &ToolContext::default()).await?;
         assert!(output.contains("test_ls.txt"));
+
+        // Directories outside the workspace can't be listed.
+        let outside = tempdir()?;
+        let args = serde_json::json!({ "path": outside.path().to_str().unwrap() });
+        assert!(tool.execute(args, &ToolContext::default()).await.unwrap_err().to_string().contains("Access denied"));
         Ok(())
     }
 
@@ -738,7 +914,7 @@ mod tests {
         writeln!(file, "Hello Tool World")?;
 
         let tool = Arc::new(CatTool {
-            ignored_patterns: vec![],
+            workspace: workspace(dir.path()),
             rag: Arc::new(crate::rag::RagSystem::new(
                 OllamaClient::new("http://localhost".to_string(), "ollama".to_string(), "".to_string()),
                 "dummy".to_string(),
@@ -751,36 +927,34 @@ mod tests {
             "numbered": false 
         });
 
-        let output = tokio::task::spawn_blocking(move || {
-            tool.execute(args)
-        }).await??;
+        let output = tool.execute(args, &ToolContext::default()).await?;
 
         assert_eq!(output.trim(), "Hello Tool World");
         Ok(())
     }
 
-    #[test]
-    fn test_grep_tool() -> Result<()> {
+    #[tokio::test]
+    async fn test_grep_tool() -> Result<()> {
         let dir = tempdir()?;
         let file_path = dir.path().join("grep_me.txt");
         let mut file = File::create(&file_path)?;
         writeln!(file, "Line 1\nMatchThis\nLine 3")?;
 
         let tool = GrepTool {
-            ignored_patterns: vec![],
+            workspace: workspace(dir.path()),
         };
         let args = serde_json::json!({
             "pattern": "MatchThis",
             "path": dir.path().to_str().unwrap()
         });
 
-        let output = tool.execute(args)?;
+        let output = tool.execute(args, &ToolContext::default()).await?;
         assert!(output.contains("MatchThis"));
         Ok(())
     }
 
-    #[test]
-    fn test_grep_tool_multi_file() -> Result<()> {
+    #[tokio::test]
+    async fn test_grep_tool_multi_file() -> Result<()> {
         let dir = tempdir()?;
         let file1 = dir.path().join("file1.txt");
         {
@@ -795,32 +969,32 @@ mod tests {
         }
 
         let tool = GrepTool {
-            ignored_patterns: vec![],
+            workspace: workspace(dir.path()),
         };
         let args = serde_json::json!({
             "pattern": "MatchThis",
             "path": dir.path().to_str().unwrap()
         });
 
-        let output = tool.execute(args)?;
+        let output = tool.execute(args, &ToolContext::default()).await?;
         assert!(output.contains("MatchThis"));
         Ok(())
     }
 
-    #[test]
-    fn test_write_file_tool() -> Result<()> {
+    #[tokio::test]
+    async fn test_write_file_tool() -> Result<()> {
         let dir = tempdir()?;
         let file_path = dir.path().join("new_file.txt");
         
         let tool = WriteFileTool {
-            ignored_patterns: vec![],
+            workspace: workspace(dir.path()),
         };
         let args = serde_json::json!({
             "path": file_path.to_str().unwrap(),
             "content": "Hello Writer"
         });
 
-        let output = tool.execute(args)?;
+        let output = tool.execute(args, &ToolContext::default()).await?;
         assert!(output.contains("Successfully wrote"));
         
         let content = std::fs::read_to_string(&file_path)?;
@@ -828,14 +1002,14 @@ mod tests {
         Ok(())
     }
 
-    #[test]
-    fn test_replace_text_tool() -> Result<()> {
+    #[tokio::test]
+    async fn test_replace_text_tool() -> Result<()> {
         let dir = tempdir()?;
         let file_path = dir.path().join("code.rs");
         std::fs::write(&file_path, "fn main() { println!(\"Old\"); }")?;
 
         let tool = ReplaceTextTool {
-            ignored_patterns: vec![],
+            workspace: workspace(dir.path()),
         };
         
         let args = serde_json::json!({
@@ -844,7 +1018,7 @@ mod tests {
             "new_text": "println!(\"New\")"
         });
 
-        let output = tool.execute(args)?;
+        let output = tool.execute(args, &ToolContext::default()).await?;
         assert!(output.contains("Successfully modified"));
 
         let content = std::fs::read_to_string(&file_path)?;
@@ -852,14 +1026,14 @@ mod tests {
         Ok(())
     }
 
-    #[test]
-    fn test_replace_text_not_found() -> Result<()> {
+    #[tokio::test]
+    async fn test_replace_text_not_found() -> Result<()> {
         let dir = tempdir()?;
         let file_path = dir.path().join("fail.txt");
         std::fs::write(&file_path, "content")?;
 
         let tool = ReplaceTextTool {
-            ignored_patterns: vec![],
+            workspace: workspace(dir.path()),
         };
 
         let args = serde_json::json!({
@@ -868,19 +1042,19 @@ mod tests {
             "new_text": "replaced"
         });
 
-        let result = tool.execute(args);
+        let result = tool.execute(args, &ToolContext::default()).await;
         assert!(result.is_err());
         Ok(())
     }
 
-    #[test]
-    fn test_write_file_append() -> Result<()> {
+    #[tokio::test]
+    async fn test_write_file_append() -> Result<()> {
         let dir = tempdir()?;
         let file_path = dir.path().join("append_test.txt");
         std::fs::write(&file_path, "Initial")?;
 
         let tool = WriteFileTool {
-            ignored_patterns: vec![],
+            workspace: workspace(dir.path()),
         };
         let args = serde_json::json!({
             "path": file_path.to_str().unwrap(),
@@ -888,7 +1062,7 @@ mod tests {
             "append": true
         });
 
-        let output = tool.execute(args)?;
+        let output = tool.execute(args, &ToolContext::default()).await?;
         assert!(output.contains("Successfully appended"));
 
         let content = std::fs::read_to_string(&file_path)?;
@@ -896,22 +1070,126 @@ mod tests {
         Ok(())
     }
 
-    #[test]
-    fn test_delete_file_tool() -> Result<()> {
+    #[tokio::test]
+    async fn test_delete_file_tool() -> Result<()> {
         let dir = tempdir()?;
         let file_path = dir.path().join("delete_me.txt");
         File::create(&file_path)?;
 
         let tool = DeleteFileTool {
-            ignored_patterns: vec![],
+            workspace: workspace(dir.path()),
         };
         let args = serde_json::json!({
             "path": file_path.to_str().unwrap()
         });
 
-        let output = tool.execute(args)?;
+        let output = tool.execute(args, &ToolContext::default()).await?;
         assert!(output.contains("Successfully deleted"));
         assert!(!file_path.exists());
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_file_tools_stay_inside_the_workspace() -> Result<()> {
+        let dir = tempdir()?;
+        let outside = tempdir()?;
+        let target = outside.path().join("escaped.txt");
+
+        let tool = WriteFileTool {
+            workspace: workspace(dir.path()),
+        };
+        let escape = format!("{}/../{}/escaped.txt", dir.path().display(), outside.path().file_name().unwrap().to_string_lossy());
+        for path in [target.to_str().unwrap(), escape.as_str()] {
+            let args = serde_json::json!({ "path": path, "content": "x" });
+            let err = tool.execute(args, &ToolContext::default()).await.unwrap_err();
+            assert!(err.to_string().contains("outside the workspace write roots"), "{}", err);
+        }
+        assert!(!target.exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_write_file_appends_to_and_replaces_binary_files() -> Result<()> {
+        let dir = tempdir()?;
+        let file_path = dir.path().join("data.bin");
+        std::fs::write(&file_path, [0xFF, 0x00, 0xFE])?;
+        let path = file_path.to_str().unwrap();
+        let write = WriteFileTool { workspace: workspace(dir.path()) };
+
+        let args = serde_json::json!({ "path": path, "content": "tail", "append": true });
+        let change = write.preview(&args).unwrap()?.remove(0);
+        assert_eq!(change.unified_diff(), format!("{}: 3 bytes, binary or too large to diff; the call appends 4 bytes\n", path));
+        write.execute(args, &ToolContext::default()).await?;
+        assert_eq!(std::fs::read(&file_path)?, b"\xFF\x00\xFEtail");
+
+        let args = serde_json::json!({ "path": path, "content": "text" });
+        assert!(write.preview(&args).unwrap()?[0].summary.is_some());
+        write.execute(args, &ToolContext::default()).await?;
+        assert_eq!(std::fs::read_to_string(&file_path)?, "text");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_previews_match_what_the_tools_write() -> Result<()> {
+        let dir = tempdir()?;
+        let file_path = dir.path().join("notes.txt");
+        std::fs::write(&file_path, "one\ntwo\nthree\n")?;
+        let path = file_path.to_str().unwrap();
+
+        let edit = EditFileTool { workspace: workspace(dir.path()) };
+        let args = serde_json::json!({ "path": path, "start_line": 2, "end_line": 2, "content": "2" });
+        let change = edit.preview(&args).unwrap()?.remove(0);
+        assert!(change.unified_diff().contains("-two\n+2\n"));
+        // Previewing doesn't touch the file.
+        assert_eq!(std::fs::read_to_string(&file_path)?, "one\ntwo\nthree\n");
+        edit.execute(args, &ToolContext::default()).await?;
+        assert_eq!(std::fs::read_to_string(&file_path)?, change.after);
+
+        let write = WriteFileTool { workspace: workspace(dir.path()) };
+        let args = serde_json::json!({ "path": path, "content": "four\n", "append": true });
+        assert_eq!(write.preview(&args).unwrap()?[0].after, "one\n2\nthree\nfour\n");
+        let new_file = dir.path().join("new.md");
+        let args = serde_json::json!({ "path": new_file.to_str().unwrap(), "content": "# New\n" });
+        let change = write.preview(&args).unwrap()?.remove(0);
+        assert!(change.before.is_none());
+        assert!(change.unified_diff().starts_with("--- /dev/null\n"));
+        assert!(!new_file.exists());
+
+        let replace = ReplaceTextTool { workspace: workspace(dir.path()) };
+        let args = serde_json::json!({ "path": path, "old_text": "missing", "new_text": "x" });
+        assert!(replace.preview(&args).unwrap().is_err());
+        assert_eq!(replace.editable_argument(), Some("new_text"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_apply_patch_tool() -> Result<()> {
+        let dir = tempdir()?;
+        let lib = dir.path().join("lib.rs");
+        std::fs::write(&lib, "fn one() -> u32 {\n    1\n}\n\nfn two() -> u32 {\n    2\n}\n")?;
+        let tool = ApplyPatchTool { workspace: workspace(dir.path()) };
+        let root = dir.path().display();
+
+        let patch = format!(
+            "--- a/{root}/lib.rs\n+++ b/{root}/lib.rs\n@@ -1,3 +1,3 @@\n fn one() -> u32 {{\n-    1\n+    10\n }}\n@@ -5,3 +5,3 @@\n fn two() -> u32 {{\n-    2\n+    20\n }}\n--- /dev/null\n+++ b/{root}/docs/notes.md\n@@ -0,0 +1 @@\n+# Notes\n"
+        );
+        let args = serde_json::json!({ "patch": patch });
+        let previews = tool.preview(&args).unwrap()?;
+        assert_eq!(previews.len(), 2);
+        assert!(previews[0].unified_diff().contains("-    2\n+    20\n"));
+
+        let output = tool.execute(args, &ToolContext::default()).await?;
+        assert!(output.contains("hunk 2 applied at line 5"), "{}", output);
+        assert_eq!(std::fs::read_to_string(&lib)?, "fn one() -> u32 {\n    10\n}\n\nfn two() -> u32 {\n    20\n}\n");
+        assert_eq!(std::fs::read_to_string(dir.path().join("docs/notes.md"))?, "# Notes\n");
+
+        // One failing hunk leaves every file untouched.
+        let patch = format!(
+            "--- a/{root}/lib.rs\n+++ b/{root}/lib.rs\n@@ -2 +2 @@\n-    10\n+    100\n@@ -6 +6 @@\n-    missing\n+    200\n"
+        );
+        let err = tool.execute(serde_json::json!({ "patch": patch }), &ToolContext::default()).await.unwrap_err().to_string();
+        assert!(err.contains("hunk 1 applied") && err.contains("hunk 2 FAILED"), "{}", err);
+        assert!(std::fs::read_to_string(&lib)?.contains("    10\n"));
+        Ok(())
+    }
 }
@@ -1,10 +1,14 @@
 use crate::ollama::{ToolDefinition, ToolFunction};
-use anyhow::Result;
+use anyhow::{bail, Result};
+use async_trait::async_trait;
 use directories::BaseDirs;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::path::{Path, PathBuf};
+use tokio_util::sync::CancellationToken;
 
 /// Trait defining a tool that can be invoked by the AI.
+#[async_trait]
 pub trait Tool: Send + Sync {
     /// Returns the unique name of the tool (e.g., "read_file").
     fn name(&self) -> &str;
@@ -30,12 +34,101 @@ pub trait Tool: Send + Sync {
     /// # Arguments
     ///
     /// * `args` - A JSON Value containing the arguments passed by the model.
-    fn execute(&self, args: Value) -> Result<String>;
+    /// * `ctx` - The call's cancellation token, progress sink and session.
+    async fn execute(&self, args: Value, ctx: &ToolContext) -> Result<String>;
 
     /// Whether this tool requires explicit user confirmation before execution.
     fn requires_confirmation(&self) -> bool {
         false
     }
+
+    /// The changes a call would make to files, for review before it runs.
+    /// Only tools that modify files return them.
+    fn preview(&self, _args: &Value) -> Option<Result<Vec<FileChange>>> {
+        None
+    }
+
+    /// The argument holding the text a call writes, which the user may edit
+    /// before approving it.
+    fn editable_argument(&self) -> Option<&str> {
+        None
+    }
+
+    /// Every file or directory a call would touch, resolved the way the
+    /// tool resolves them, for path rules in the permission policy. None
+    /// means the call's `path` argument is used as given.
+    fn target_paths(&self, _args: &Value) -> Option<Vec<PathBuf>> {
+        None
+    }
+}
+
+/// `target_paths` for tools taking one `path` argument, which defaults to
+/// `default` when given.
+pub(crate) fn path_argument(workspace: &Workspace, args: &Value, default: Option<&str>) -> Option<Vec<PathBuf>> {
+    let raw = args.get("path").and_then(|v| v.as_str()).or(default)?;
+    Some(vec![workspace.target(raw)])
+}
+
+/// What a tool call runs with besides its arguments.
+#[derive(Clone, Default)]
+pub struct ToolContext {
+    /// Cancelled when the user stops generation; tools should give up and
+    /// kill anything they spawned.
+    pub cancel: CancellationToken,
+    /// Receives incremental output (e.g. command output lines) while the
+    /// tool runs.
+    pub progress: Option<StatusSender>,
+    /// Name of the chat session the call was made in.
+    pub session: String,
+    /// Directory the agent works in.
+    pub workspace: PathBuf,
+    /// Where the call saves files before changing them, so the edit can be
+    /// undone.
+    pub checkpoints: Option<Checkpointer>,
+}
+
+impl ToolContext {
+    pub fn new(session: impl Into<String>, workspace: PathBuf) -> Self {
+        Self {
+            session: session.into(),
+            workspace,
+            ..Self::default()
+        }
+    }
+
+    /// Reports a line of incremental output.
+    pub fn progress(&self, line: impl Into<String>) {
+        if let Some(tx) = &self.progress {
+            let _ = tx.send(line.into());
+        }
+    }
+
+    /// Saves `path` before `tool` changes it. Does nothing for calls made
+    /// without a checkpoint store.
+    pub fn checkpoint(&self, tool: &str, path: &Path) -> Result<()> {
+        match &self.checkpoints {
+            Some(checkpoints) => checkpoints.save(tool, path),
+            None => Ok(()),
+        }
+    }
+
+    /// Waits for `cmd` to finish, killing it if the call is cancelled first.
+    pub async fn output(&self, cmd: &mut tokio::process::Command) -> Result<std::process::Output> {
+        cmd.kill_on_drop(true);
+        tokio::select! {
+            output = cmd.output() => Ok(output?),
+            _ = self.cancel.cancelled() => bail!("Cancelled by user"),
+        }
+    }
+
+    /// Runs blocking `work` on the blocking thread pool. A cancelled call
+    /// returns at once; the work itself finishes in the background.
+    pub async fn blocking<T: Send + 'static>(&self, work: impl FnOnce() -> Result<T> + Send + 'static) -> Result<T> {
+        tokio::select! {
+            result = tokio::task::spawn_blocking(work) => result?,
+            _ = self.cancel.cancelled() => bail!("Cancelled by user"),
+        }
+    }
 }
 
 /// Expands `~` at the start of a path to the user's home directory.
@@ -53,7 +146,7 @@ pub fn expand_path(path: &str) -> String {
 }
 
 /// Represents a chunk of text indexed for semantic search.
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub struct TextChunk {
     /// The path of the file containing this chunk.
     pub file_path: String,
@@ -63,11 +156,49 @@ pub struct TextChunk {
     pub start_line: usize,
     /// The ending line number in the source file.
     pub end_line: usize,
-    /// The vector embedding of the content.
+    /// The vector embedding of the content. Stored separately from the rest
+    /// of the chunk on disk, hence the default.
+    #[serde(default)]
     pub embedding: Vec<f64>,
     /// The collection name (e.g., "work", "default") this chunk belongs to.
     #[serde(default = "default_collection")]
     pub collection: String,
+    /// When the chunk was added, in seconds since the Unix epoch. Zero for
+    /// chunks indexed before this was recorded.
+    #[serde(default)]
+    pub indexed_at: u64,
+    /// Hash of `content`, used to skip re-adding a chunk that is already in
+    /// its collection. Zero until the chunk enters an index.
+    #[serde(default)]
+    pub content_hash: u64,
+    /// Where tool output came from; None for indexed files.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+}
+
+/// The tool call that produced an auto-ingested chunk. The time it was
+/// ingested is the chunk's `indexed_at`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Provenance {
+    /// Name of the tool (e.g. "read_url").
+    pub tool: String,
+    /// Arguments the tool was called with.
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub args: Value,
+    /// Chat session the call was made in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<String>,
+    /// Page the content was read from, for web tools.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+/// Current time in seconds since the Unix epoch.
+pub fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
 }
 
 fn default_collection() -> String {
@@ -81,6 +212,119 @@ pub struct VectorIndex {
     pub chunks: Vec<TextChunk>,
     /// Timestamp of the last index update.
     pub indexed_at: std::time::SystemTime,
+    /// Approximate-nearest-neighbour graph over `chunks`. Persisted
+    /// separately by `RagSystem`; kept in sync by `extend`/`sync_ann`.
+    #[serde(skip)]
+    pub ann: crate::rag::hnsw::HnswIndex,
+    /// Lexical index over `chunks`, rebuilt in memory on load.
+    #[serde(skip)]
+    pub bm25: crate::rag::bm25::Bm25Index,
+    /// Number of leading `chunks` already written to the segment store.
+    #[serde(skip)]
+    pub persisted: usize,
+    /// Number of leading chunks covered by the ANN graph saved on disk.
+    #[serde(skip)]
+    pub ann_persisted: usize,
+    /// Set when the on-disk store is no longer a prefix of `chunks` (chunks
+    /// were removed, or the index wasn't loaded from the store), so the next
+    /// save must rewrite it instead of appending.
+    #[serde(skip)]
+    pub rewrite_needed: bool,
+}
+
+/// Stamps chunks that don't have an `indexed_at` yet with the current time.
+fn stamp_indexed_at(chunks: &mut [TextChunk]) {
+    let now = unix_now();
+    for chunk in chunks.iter_mut().filter(|c| c.indexed_at == 0) {
+        chunk.indexed_at = now;
+    }
+}
+
+/// Fills in `content_hash` for chunks that don't have one yet.
+pub fn stamp_content_hash(chunks: &mut [TextChunk]) {
+    for chunk in chunks.iter_mut().filter(|c| c.content_hash == 0) {
+        chunk.content_hash = crate::rag::indexer::content_hash(chunk.content.as_bytes());
+    }
+}
+
+impl VectorIndex {
+    pub fn new(mut chunks: Vec<TextChunk>) -> Self {
+        stamp_indexed_at(&mut chunks);
+        stamp_content_hash(&mut chunks);
+        let mut index = Self {
+            chunks,
+            indexed_at: std::time::SystemTime::now(),
+            ann: Default::default(),
+            bm25: Default::default(),
+            persisted: 0,
+            ann_persisted: 0,
+            rewrite_needed: true,
+        };
+        index.sync_ann();
+        index.sync_bm25();
+        index
+    }
+
+    /// Wraps chunks that were just read from the store. The ANN graph is
+    /// left empty so a saved one can be attached before calling `sync_ann`.
+    pub fn from_store(mut chunks: Vec<TextChunk>) -> Self {
+        stamp_content_hash(&mut chunks);
+        Self {
+            persisted: chunks.len(),
+            chunks,
+            indexed_at: std::time::SystemTime::now(),
+            ann: Default::default(),
+            bm25: Default::default(),
+            ann_persisted: 0,
+            rewrite_needed: false,
+        }
+    }
+
+    /// Removes the chunks for which `keep` returns false and returns how many
+    /// were removed. The ANN graph is rebuilt and the next save compacts.
+    pub fn retain(&mut self, keep: impl FnMut(&TextChunk) -> bool) -> usize {
+        let before = self.chunks.len();
+        self.chunks.retain(keep);
+        let removed = before - self.chunks.len();
+        if removed > 0 {
+            self.indexed_at = std::time::SystemTime::now();
+            self.ann = Default::default();
+            self.sync_ann();
+            self.bm25 = Default::default();
+            self.sync_bm25();
+            self.persisted = 0;
+            self.rewrite_needed = true;
+        }
+        removed
+    }
+
+    /// Appends chunks and links them into the ANN graph and BM25 index.
+    pub fn extend(&mut self, mut chunks: Vec<TextChunk>) {
+        stamp_indexed_at(&mut chunks);
+        stamp_content_hash(&mut chunks);
+        self.chunks.extend(chunks);
+        self.indexed_at = std::time::SystemTime::now();
+        self.sync_ann();
+        self.sync_bm25();
+    }
+
+    /// Brings the ANN graph up to date with `chunks`: appended chunks are
+    /// inserted incrementally, anything else (removals) triggers a rebuild.
+    pub fn sync_ann(&mut self) {
+        if self.ann.len() > self.chunks.len() {
+            self.ann = Default::default();
+        }
+        self.ann.extend(self.chunks.as_slice());
+    }
+
+    /// Same as `sync_ann`, for the BM25 index.
+    pub fn sync_bm25(&mut self) {
+        if self.bm25.len() > self.chunks.len() {
+            self.bm25 = Default::default();
+        }
+        let start = self.bm25.len();
+        self.bm25.extend(self.chunks[start..].iter().map(|c| c.content.as_str()));
+    }
 }
 
 // Export modules
@@ -90,13 +334,20 @@ pub mod system;
 pub mod code_intelligence;
 pub mod rag;
 pub mod python;
+pub mod checkpoint;
+pub mod patch;
+pub mod preview;
+pub mod sandbox;
 
 // Re-export tools for easier access
-pub use filesystem::{ListDirectoryTool, GrepTool, CatTool, WriteFileTool, ReplaceTextTool, EditFileTool, DeleteFileTool};
+pub use filesystem::{ListDirectoryTool, GrepTool, CatTool, WriteFileTool, ReplaceTextTool, EditFileTool, DeleteFileTool, ApplyPatchTool};
 pub use web::{WebSearchTool, ReadUrlTool};
 pub use system::RunCommandTool;
-pub use rag::{SemanticSearchTool, MemoryTool};
+pub use rag::{SemanticSearchTool, MemoryTool, ForgetTool, ListMemoriesTool, UpdateMemoryTool, ManageMemoryTool};
 pub use code_intelligence::SymbolSearchTool;
 pub use python::RunPythonTool;
+pub use checkpoint::{CheckpointStore, Checkpointer, Turn};
+pub use preview::FileChange;
+pub use sandbox::{Access, Workspace, WorkspaceRoots};
 
 pub type StatusSender = tokio::sync::mpsc::UnboundedSender<String>;
@@ -0,0 +1,412 @@
+//! Unified diffs, as written by `diff -u` and `git diff`, applied with fuzzy
+//! context matching for the `apply_patch` tool.
+//!
+//! A hunk is looked for where its header says, shifted by however far the
+//! hunks before it moved, and otherwise at the nearest place in the file
+//! where its lines match. Lines are compared exactly, then ignoring trailing
+//! whitespace, then ignoring all surrounding whitespace. If that fails, up
+//! to two context lines are dropped from each end of the hunk, like the fuzz
+//! factor of `patch`. Such loose matches must stay within
+//! `MAX_LOOSE_OFFSET` lines of the expected place and keep at least
+//! `MIN_LOOSE_LINES` non-blank lines, so a few generic lines like `}` can't
+//! land somewhere unrelated. Hunk line counts are ignored, since models often
+//! get them wrong.
+
+use anyhow::{bail, Result};
+use regex::Regex;
+use std::fmt;
+use std::sync::LazyLock;
+
+/// Context lines `apply` may drop from each end of a hunk.
+const MAX_FUZZ: usize = 2;
+
+/// Furthest a fuzzed or whitespace-insensitive match may be from where the
+/// hunk was expected.
+const MAX_LOOSE_OFFSET: usize = 50;
+
+/// Non-blank lines a fuzzed or whitespace-insensitive match must compare.
+const MIN_LOOSE_LINES: usize = 2;
+
+/// Offsets beyond this are called out in the report.
+const FAR_OFFSET: usize = 20;
+
+static HUNK_HEADER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^@@ -(\d+)(?:,\d+)? \+\d+(?:,\d+)? @@").unwrap());
+
+/// The changes a patch makes to one file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilePatch {
+    /// Path before the change, or None when the patch creates the file.
+    pub old_path: Option<String>,
+    /// Path after the change, or None when the patch deletes the file.
+    pub new_path: Option<String>,
+    pub hunks: Vec<Hunk>,
+}
+
+/// One `@@` section of a file patch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hunk {
+    /// First old line the hunk covers (1-based), if the header gave one.
+    pub old_start: Option<usize>,
+    pub lines: Vec<HunkLine>,
+    /// Whether the new side ends without a final newline.
+    pub no_newline_at_end: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HunkLine {
+    Context(String),
+    Remove(String),
+    Add(String),
+}
+
+/// Where a hunk was applied.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Placement {
+    /// First line of the hunk in the patched file (1-based).
+    pub line: usize,
+    /// Lines between where the header expected the hunk and where it was.
+    pub offset: isize,
+    /// Context lines dropped from each end to find it.
+    pub fuzz: usize,
+    /// Whether whitespace had to be ignored to find it.
+    pub loose_whitespace: bool,
+}
+
+impl fmt::Display for Placement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "applied at line {}", self.line)?;
+        if self.offset.unsigned_abs() > FAR_OFFSET {
+            write!(f, " (offset {:+} lines: far from the hunk header, check the placement)", self.offset)?;
+        } else if self.offset != 0 {
+            write!(f, " (offset {:+} lines)", self.offset)?;
+        }
+        if self.fuzz > 0 {
+            write!(f, " (fuzz {})", self.fuzz)?;
+        }
+        if self.loose_whitespace {
+            write!(f, " (ignoring whitespace)")?;
+        }
+        Ok(())
+    }
+}
+
+/// How strictly lines are compared, from strictest to loosest.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Match {
+    Exact,
+    TrailingWhitespace,
+    Whitespace,
+}
+
+impl Match {
+    fn eq(self, a: &str, b: &str) -> bool {
+        match self {
+            Match::Exact => a == b,
+            Match::TrailingWhitespace => a.trim_end() == b.trim_end(),
+            Match::Whitespace => a.trim() == b.trim(),
+        }
+    }
+}
+
+/// Splits a unified diff into its file patches. Lines outside file patches
+/// (`diff --git`, `index`, commentary) are skipped.
+pub fn parse(patch: &str) -> Result<Vec<FilePatch>> {
+    let lines: Vec<&str> = patch.lines().collect();
+    let mut files: Vec<FilePatch> = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        if let (Some(old), Some(new)) = (line.strip_prefix("--- "), lines.get(i + 1).and_then(|l| l.strip_prefix("+++ "))) {
+            let (old_path, new_path) = header_paths(old, new);
+            files.push(FilePatch { old_path, new_path, hunks: Vec::new() });
+            i += 2;
+            continue;
+        }
+        if line.starts_with("@@") {
+            let Some(file) = files.last_mut() else {
+                bail!("Hunk before any '---'/'+++' file header: {}", line);
+            };
+            let old_start = HUNK_HEADER.captures(line).and_then(|c| c[1].parse().ok());
+            let mut hunk = Hunk { old_start, lines: Vec::new(), no_newline_at_end: false };
+            i += 1;
+            while let Some(line) = lines.get(i) {
+                if line.starts_with("@@") || (line.starts_with("--- ") && lines.get(i + 1).is_some_and(|l| l.starts_with("+++ "))) {
+                    break;
+                }
+                match line.chars().next() {
+                    Some(' ') => hunk.lines.push(HunkLine::Context(line[1..].to_string())),
+                    Some('-') => hunk.lines.push(HunkLine::Remove(line[1..].to_string())),
+                    Some('+') => hunk.lines.push(HunkLine::Add(line[1..].to_string())),
+                    Some('\\') => {
+                        if !matches!(hunk.lines.last(), Some(HunkLine::Remove(_))) {
+                            hunk.no_newline_at_end = true;
+                        }
+                    }
+                    // Editors and models often strip the space of blank context lines.
+                    None => hunk.lines.push(HunkLine::Context(String::new())),
+                    Some(_) => break,
+                }
+                i += 1;
+            }
+            // Blank lines after the last hunk are usually just the end of the message.
+            while hunk.lines.last() == Some(&HunkLine::Context(String::new())) {
+                hunk.lines.pop();
+            }
+            if hunk.lines.is_empty() {
+                bail!("Empty hunk in the patch for {}", file.display_path());
+            }
+            file.hunks.push(hunk);
+            continue;
+        }
+        i += 1;
+    }
+    files.retain(|f| !f.hunks.is_empty());
+    if files.is_empty() {
+        bail!("No hunks found; the patch must be a unified diff with '---'/'+++' headers and '@@' hunks");
+    }
+    Ok(files)
+}
+
+/// The paths of a file header, without timestamps, `/dev/null` or git's
+/// `a/` and `b/` prefixes.
+fn header_paths(old: &str, new: &str) -> (Option<String>, Option<String>) {
+    let path = |raw: &str| {
+        let raw = raw.split('\t').next().unwrap_or_default().trim();
+        (raw != "/dev/null").then(|| raw.to_string())
+    };
+    let (mut old, mut new) = (path(old), path(new));
+    let prefixed = old.as_deref().is_none_or(|p| p.starts_with("a/")) && new.as_deref().is_none_or(|p| p.starts_with("b/"));
+    if prefixed && (old.is_some() || new.is_some()) {
+        old = old.map(|p| p[2..].to_string());
+        new = new.map(|p| p[2..].to_string());
+    }
+    (old, new)
+}
+
+impl FilePatch {
+    /// The file the patch changes.
+    pub fn display_path(&self) -> &str {
+        self.new_path.as_deref().or(self.old_path.as_deref()).unwrap_or("(unnamed)")
+    }
+}
+
+/// Applies `hunks` to `content` in order. Returns the patched text and the
+/// placement of each hunk, or why it couldn't be placed; hunks that fail
+/// are skipped.
+pub fn apply(content: &str, hunks: &[Hunk]) -> (String, Vec<Result<Placement, String>>) {
+    let newline = if content.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut lines: Vec<String> = content.lines().map(String::from).collect();
+    let mut final_newline = content.is_empty() || content.ends_with('\n');
+    let mut results = Vec::new();
+    // How far hunks have moved from their headers, and the first line the
+    // next hunk may touch.
+    let mut delta: isize = 0;
+    let mut floor = 0;
+
+    for hunk in hunks {
+        let Some((pos, expected, trimmed, fuzz, level)) = locate(&lines, hunk, delta, floor) else {
+            let near = hunk.old_start.map(|l| format!(" near line {}", (l as isize + delta).max(1))).unwrap_or_default();
+            let old: Vec<&str> = old_lines(&hunk.lines).into_iter().take(5).collect();
+            results.push(Err(format!("could not find these lines{}:\n    {}", near, old.join("\n    "))));
+            continue;
+        };
+        let mut replacement = Vec::new();
+        let mut cursor = pos;
+        for line in trimmed {
+            match line {
+                HunkLine::Context(_) => {
+                    replacement.push(lines[cursor].clone());
+                    cursor += 1;
+                }
+                HunkLine::Remove(_) => cursor += 1,
+                HunkLine::Add(text) => replacement.push(text.clone()),
+            }
+        }
+        let (old_len, new_len) = (cursor - pos, replacement.len());
+        lines.splice(pos..cursor, replacement);
+        if hunk.no_newline_at_end {
+            final_newline = false;
+        }
+
+        results.push(Ok(Placement {
+            line: pos + 1,
+            offset: pos as isize - expected as isize,
+            fuzz,
+            loose_whitespace: level != Match::Exact,
+        }));
+        delta += (pos as isize - expected as isize) + new_len as isize - old_len as isize;
+        floor = pos + new_len;
+    }
+
+    let mut patched = lines.join(newline);
+    if final_newline && !lines.is_empty() {
+        patched.push_str(newline);
+    }
+    (patched, results)
+}
+
+/// Finds where `hunk` applies at or after line `floor`, loosening the match
+/// step by step. Returns the position, where the header expected it, the
+/// hunk lines that matched, the fuzz and the comparison used.
+fn locate<'h>(lines: &[String], hunk: &'h Hunk, delta: isize, floor: usize) -> Option<(usize, usize, &'h [HunkLine], usize, Match)> {
+    let full_old = old_lines(&hunk.lines).len();
+    let mut tried = None;
+    for fuzz in 0..=MAX_FUZZ {
+        let front = hunk.lines.iter().take(fuzz).take_while(|l| matches!(l, HunkLine::Context(_))).count();
+        let back = hunk.lines[front..].iter().rev().take(fuzz).take_while(|l| matches!(l, HunkLine::Context(_))).count();
+        // Nothing more to drop.
+        if tried == Some((front, back)) {
+            break;
+        }
+        tried = Some((front, back));
+        let fuzz = front.max(back);
+        let trimmed = &hunk.lines[front..hunk.lines.len() - back];
+        let old = old_lines(trimmed);
+        if old.is_empty() && full_old > 0 {
+            break;
+        }
+        let expected = match hunk.old_start {
+            // `@@ -0,0` inserts before the first line.
+            Some(start) => (start.saturating_sub(1) as isize + front as isize + delta).max(0) as usize,
+            None => floor,
+        };
+        if old.is_empty() {
+            return Some((expected.clamp(floor, lines.len()), expected, trimmed, fuzz, Match::Exact));
+        }
+        let distinctive = old.iter().filter(|l| !l.trim().is_empty()).count() >= MIN_LOOSE_LINES;
+        for level in [Match::Exact, Match::TrailingWhitespace, Match::Whitespace] {
+            let loose = fuzz > 0 || level != Match::Exact;
+            if loose && !distinctive {
+                continue;
+            }
+            let reach = if loose { MAX_LOOSE_OFFSET } else { usize::MAX };
+            if let Some(pos) = nearest_match(lines, &old, expected, floor, reach, level) {
+                return Some((pos, expected, trimmed, fuzz, level));
+            }
+        }
+    }
+    None
+}
+
+/// The position at or after `floor` closest to `expected`, and at most
+/// `reach` lines from it, where `old` matches.
+fn nearest_match(lines: &[String], old: &[&str], expected: usize, floor: usize, reach: usize, level: Match) -> Option<usize> {
+    if old.len() > lines.len() {
+        return None;
+    }
+    let last = lines.len() - old.len();
+    if floor > last {
+        return None;
+    }
+    let matches_at = |pos: usize| old.iter().zip(&lines[pos..]).all(|(a, b)| level.eq(a, b));
+    let start = expected.clamp(floor, last);
+    (0..=last.saturating_sub(floor))
+        .find_map(|distance| {
+            let after = start + distance;
+            let before = start.checked_sub(distance).filter(|p| *p >= floor);
+            [Some(after).filter(|p| *p <= last), before].into_iter().flatten().find(|p| matches_at(*p))
+        })
+        .filter(|pos| pos.abs_diff(expected) <= reach)
+}
+
+/// The lines a hunk expects to find: its context and removed lines.
+fn old_lines(lines: &[HunkLine]) -> Vec<&str> {
+    lines
+        .iter()
+        .filter_map(|l| match l {
+            HunkLine::Context(text) | HunkLine::Remove(text) => Some(text.as_str()),
+            HunkLine::Add(_) => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: &str = "fn main() {\n    let a = 1;\n    let b = 2;\n    println!(\"{}\", a + b);\n}\n\nfn helper() {\n    todo!()\n}\n";
+
+    fn apply_one(content: &str, patch: &str) -> (String, Vec<Result<Placement, String>>) {
+        let files = parse(patch).unwrap();
+        apply(content, &files[0].hunks)
+    }
+
+    #[test]
+    fn test_parse_git_diff_with_several_files() {
+        let patch = "diff --git a/src/main.rs b/src/main.rs\nindex 83db48f..bf269f4 100644\n--- a/src/main.rs\n+++ b/src/main.rs\n@@ -2,1 +2,1 @@\n-    let a = 1;\n+    let a = 10;\n@@ -8 +8 @@\n-    todo!()\n+    unimplemented!()\n--- /dev/null\n+++ b/notes.md\n@@ -0,0 +1,2 @@\n+# Notes\n+\n";
+        let files = parse(patch).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].old_path.as_deref(), Some("src/main.rs"));
+        assert_eq!(files[0].hunks.len(), 2);
+        assert_eq!(files[0].hunks[1].old_start, Some(8));
+        assert_eq!(files[1].old_path, None);
+        assert_eq!(files[1].new_path.as_deref(), Some("notes.md"));
+        assert_eq!(files[1].hunks[0].lines, vec![HunkLine::Add("# Notes".to_string()), HunkLine::Add(String::new())]);
+
+        assert!(parse("just some text").is_err());
+    }
+
+    #[test]
+    fn test_apply_with_offset_and_wrong_counts() {
+        // The header is two lines off and its counts are wrong.
+        let patch = "--- a.rs\n+++ a.rs\n@@ -5,9 +5,9 @@\n     let b = 2;\n-    println!(\"{}\", a + b);\n+    println!(\"sum: {}\", a + b);\n }\n@@ -9,3 +9,3 @@\n fn helper() {\n-    todo!()\n+    42\n }\n";
+        let (patched, results) = apply_one(FILE, patch);
+        assert!(patched.contains("println!(\"sum: {}\", a + b);") && patched.contains("    42\n}\n"));
+        let first = results[0].as_ref().unwrap();
+        assert_eq!((first.line, first.offset), (3, -2));
+        assert_eq!(results[1].as_ref().unwrap().line, 7);
+    }
+
+    #[test]
+    fn test_apply_ignores_whitespace_and_uses_fuzz() {
+        // Tabs instead of spaces, and a first context line that's wrong.
+        let patch = "--- a.rs\n+++ a.rs\n@@ -1,4 +1,4 @@\n fn start() {\n \tlet a = 1;\n-\tlet b = 2;\n+\tlet b = 3;\n \tprintln!(\"{}\", a + b);\n";
+        let (patched, results) = apply_one(FILE, patch);
+        let placement = results[0].as_ref().unwrap();
+        assert!(placement.loose_whitespace);
+        assert_eq!(placement.fuzz, 1);
+        // Context keeps the file's own lines; added lines come from the patch.
+        assert!(patched.starts_with("fn main() {\n    let a = 1;\n\tlet b = 3;\n    println!"));
+    }
+
+    #[test]
+    fn test_failed_hunks_are_reported_and_skipped() {
+        let patch = "--- a.rs\n+++ a.rs\n@@ -2 +2 @@\n-    let a = 1;\n+    let a = 5;\n@@ -20 +20 @@\n-    does_not_exist();\n+    x();\n";
+        let (patched, results) = apply_one(FILE, patch);
+        assert!(results[0].is_ok());
+        let err = results[1].as_ref().unwrap_err();
+        assert!(err.contains("near line 20") && err.contains("does_not_exist"), "{}", err);
+        assert!(patched.contains("let a = 5;") && !patched.contains("x();"));
+    }
+
+    #[test]
+    fn test_loose_matches_stay_near_the_header() {
+        // With its wrong context fuzzed away only a lone `}` is left, which
+        // must not be matched at the end of `main`.
+        let patch = "--- a.rs\n+++ a.rs\n@@ -1,5 +1,5 @@\n fn start() {\n     let x = 0;\n-}\n+};\n \n fn other() {\n";
+        let (patched, results) = apply_one(FILE, patch);
+        assert!(results[0].is_err());
+        assert_eq!(patched, FILE);
+
+        // Distinctive lines found loosely far from the header are refused too.
+        let far = format!("{}{}", "// filler\n".repeat(60), FILE);
+        let patch = "--- a.rs\n+++ a.rs\n@@ -1,3 +1,3 @@\n fn main() {\n \tlet a = 1;\n-\tlet b = 2;\n+\tlet b = 3;\n";
+        assert!(apply_one(&far, patch).1[0].is_err());
+
+        // An exact match there still applies, with the offset called out.
+        let patch = "--- a.rs\n+++ a.rs\n@@ -1,3 +1,3 @@\n fn main() {\n     let a = 1;\n-    let b = 2;\n+    let b = 3;\n";
+        let (patched, results) = apply_one(&far, patch);
+        assert!(patched.contains("let b = 3;"));
+        let report = results[0].as_ref().unwrap().to_string();
+        assert!(report.contains("offset +60 lines: far from the hunk header"), "{}", report);
+    }
+
+    #[test]
+    fn test_new_file_and_missing_final_newline() {
+        let patch = "--- /dev/null\n+++ b/new.txt\n@@ -0,0 +1,2 @@\n+first\n+second\n\\ No newline at end of file\n";
+        let (patched, results) = apply_one("", patch);
+        assert_eq!(patched, "first\nsecond");
+        assert_eq!(results[0].as_ref().unwrap().line, 1);
+    }
+}
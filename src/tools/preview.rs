@@ -0,0 +1,63 @@
+//! Previews of the changes file-editing tools are about to make, shown in
+//! the confirmation dialog before the user approves a call.
+
+use similar::TextDiff;
+use std::path::PathBuf;
+
+/// A file's content before and after a proposed tool call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileChange {
+    pub path: PathBuf,
+    /// Current content, or None when the call creates the file.
+    pub before: Option<String>,
+    pub after: String,
+    /// Shown instead of a diff when the current content is binary or too
+    /// large to diff.
+    pub summary: Option<String>,
+}
+
+impl FileChange {
+    /// The change as a unified diff with three lines of context. A new
+    /// file shows every line as added.
+    pub fn unified_diff(&self) -> String {
+        let path = self.path.display().to_string();
+        if let Some(summary) = &self.summary {
+            return format!("{}: {}\n", path, summary);
+        }
+        let old_header = if self.before.is_some() { path.as_str() } else { "/dev/null" };
+        let diff = TextDiff::from_lines(self.before.as_deref().unwrap_or(""), &self.after);
+        let body = diff.unified_diff().context_radius(3).header(old_header, &path).to_string();
+        if body.is_empty() {
+            format!("No changes to {}\n", path)
+        } else {
+            body
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified_diff_of_an_edit() {
+        let change = FileChange {
+            path: PathBuf::from("src/main.rs"),
+            before: Some("fn main() {\n    println!(\"Old\");\n}\n".to_string()),
+            after: "fn main() {\n    println!(\"New\");\n}\n".to_string(),
+            summary: None,
+        };
+        let diff = change.unified_diff();
+        assert!(diff.starts_with("--- src/main.rs\n+++ src/main.rs\n@@ -1,3 +1,3 @@\n"));
+        assert!(diff.contains("-    println!(\"Old\");\n+    println!(\"New\");\n"));
+    }
+
+    #[test]
+    fn test_new_file_and_unchanged_previews() {
+        let new_file = FileChange { path: PathBuf::from("notes.md"), before: None, after: "# Notes\nhello\n".to_string(), summary: None };
+        assert_eq!(new_file.unified_diff(), "--- /dev/null\n+++ notes.md\n@@ -0,0 +1,2 @@\n+# Notes\n+hello\n");
+
+        let unchanged = FileChange { path: PathBuf::from("a.txt"), before: Some("x\n".to_string()), after: "x\n".to_string(), summary: None };
+        assert_eq!(unchanged.unified_diff(), "No changes to a.txt\n");
+    }
+}
@@ -1,5 +1,6 @@
-use super::Tool;
+use super::{Tool, ToolContext};
 use anyhow::Result;
+use async_trait::async_trait;
 use serde_json::Value;
 use std::sync::Arc;
 use crate::python::PythonRuntime;
@@ -9,6 +10,7 @@ pub struct RunPythonTool {
     pub runtime: Arc<PythonRuntime>,
 }
 
+#[async_trait]
 impl Tool for RunPythonTool {
     fn name(&self) -> &str {
         "run_python"
@@ -40,54 +42,66 @@ Returns the STDOUT and STDERR of the script."
         })
     }
 
-    fn execute(&self, args: Value) -> Result<String> {
+    async fn execute(&self, args: Value, ctx: &ToolContext) -> Result<String> {
         let script = args
             .get("script")
             .and_then(|v| v.as_str())
-            .ok_or_else(|| anyhow::anyhow!("Missing 'script' argument"))?;
+            .ok_or_else(|| anyhow::anyhow!("Missing 'script' argument"))?
+            .to_string();
+        let dependencies: Vec<String> = args
+            .get("dependencies")
+            .and_then(|v| v.as_array())
+            .map(|deps| deps.iter().filter_map(|v| v.as_str()).map(String::from).collect())
+            .unwrap_or_default();
 
-        // Install explicit dependencies first
-        if let Some(deps) = args.get("dependencies").and_then(|v| v.as_array()) {
-             let packages: Vec<&str> = deps.iter().filter_map(|v| v.as_str()).collect();
-             if !packages.is_empty() {
-                 self.runtime.install_packages(&packages)?;
-             }
-        }
+        // Installs and scripts block, so they run off the async runtime.
+        let runtime = self.runtime.clone();
+        ctx.blocking(move || run_script(&runtime, &script, &dependencies)).await
+    }
+}
 
-        // Try running the script
-        match self.runtime.run_script(script) {
-            Ok(output) => {
-                 // Check if it failed with a ModuleNotFoundError in the captured stderr
-                 if output.contains("ModuleNotFoundError: No module named") {
-                     // Regex to match: ModuleNotFoundError: No module named 'requests'
-                     // or: ModuleNotFoundError: No module named 'PIL'
-                     let re = Regex::new(r"ModuleNotFoundError: No module named ['\x22](.*?)['\x22]").unwrap();
-                     
-                     if let Some(caps) = re.captures(&output) {
-                         if let Some(package_match) = caps.get(1) {
-                             let package = package_match.as_str();
-                             // Clean up package name if it has submodules (e.g., 'sklearn.model_selection' -> 'sklearn' - wait, usually we want to map this, but for now try direct install or guessing 'scikit-learn')
-                             // Mapping is hard without a database. Let's try installing exactly what it says first.
-                             
-                             let install_msg = format!("(Auto-installing missing package: '{}'...)\n", package);
-                             
-                             // Install
-                             if let Err(e) = self.runtime.install_packages(&[package]) {
-                                 return Ok(format!("{}Failed to auto-install package '{}': {}\n\nOriginal Output:\n{}", install_msg, package, e, output));
-                             }
-                             
-                             // Retry script
-                             match self.runtime.run_script(script) {
-                                 Ok(retry_output) => return Ok(format!("{}Package installed successfully.\n\n{}", install_msg, retry_output)),
-                                 Err(e) => return Err(e),
-                             }
-                        }
-                     }
-                 }
+/// Runs `script` after installing `dependencies`, retrying once after
+/// installing a module the script failed to import.
+fn run_script(runtime: &PythonRuntime, script: &str, dependencies: &[String]) -> Result<String> {
+    // Install explicit dependencies first
+    let packages: Vec<&str> = dependencies.iter().map(String::as_str).collect();
+    if !packages.is_empty() {
+        runtime.install_packages(&packages)?;
+    }
+
+    // Try running the script
+    match runtime.run_script(script) {
+        Ok(output) => {
+             // Check if it failed with a ModuleNotFoundError in the captured stderr
+             if output.contains("ModuleNotFoundError: No module named") {
+                 // Regex to match: ModuleNotFoundError: No module named 'requests'
+                 // or: ModuleNotFoundError: No module named 'PIL'
+                 let re = Regex::new(r"ModuleNotFoundError: No module named ['\x22](.*?)['\x22]").unwrap();
                  
-                 Ok(output)
-            },
-            Err(e) => Err(e),
-        }
+                 if let Some(caps) = re.captures(&output) {
+                     if let Some(package_match) = caps.get(1) {
+                         let package = package_match.as_str();
+                         // Clean up package name if it has submodules (e.g., 'sklearn.model_selection' -> 'sklearn' - wait, usually we want to map this, but for now try direct install or guessing 'scikit-learn')
+                         // Mapping is hard without a database. Let's try installing exactly what it says first.
+                         
+                         let install_msg = format!("(Auto-installing missing package: '{}'...)\n", package);
+                         
+                         // Install
+                         if let Err(e) = runtime.install_packages(&[package]) {
+                             return Ok(format!("{}Failed to auto-install package '{}': {}\n\nOriginal Output:\n{}", install_msg, package, e, output));
+                         }
+                         
+                         // Retry script
+                         match runtime.run_script(script) {
+                             Ok(retry_output) => return Ok(format!("{}Package installed successfully.\n\n{}", install_msg, retry_output)),
+                             Err(e) => return Err(e),
+                         }
+                    }
+                 }
+             }
+             
+             Ok(output)
+        },
+        Err(e) => Err(e),
     }
 }
@@ -1,24 +1,30 @@
-use super::{expand_path, Tool, TextChunk, VectorIndex, StatusSender};
+use super::{expand_path, Tool, ToolContext, StatusSender};
 use anyhow::Result;
+use async_trait::async_trait;
 use serde_json::Value;
-use std::sync::Arc;
-use std::collections::HashMap;
-use crate::rag::RagSystem;
+use std::sync::{Arc, Mutex};
+use std::collections::{BTreeSet, HashMap};
+use crate::rag::{RagSystem, SearchMode};
+use crate::rag::collections::{parse_age, ForgetFilter};
 
 pub struct SemanticSearchTool {
     pub rag: Arc<RagSystem>,
     pub ignored_patterns: Vec<String>,
     pub knowledge_bases: HashMap<String, String>,
     pub status_tx: Option<StatusSender>,
+    /// Collections the chat session has enabled (`None` for all); searched
+    /// when no `index_path` is given.
+    pub session_collections: Arc<Mutex<Option<BTreeSet<String>>>>,
 }
 
+#[async_trait]
 impl Tool for SemanticSearchTool {
     fn name(&self) -> &str {
         "semantic_search"
     }
 
     fn description(&self) -> &str {
-        "USE THIS to find code, notes, or web search results by CONCEPT. Auto-indexes workspace on first use. Can also index specific directories or named knowledge bases."
+        "USE THIS to find code, notes, or web search results by CONCEPT. Auto-indexes workspace on first use. Can also index specific directories or named knowledge bases. Without index_path, searches the collections enabled for this session. Each match is headed `path:start-end (score)` so it can be opened with read_file or edit_file."
     }
 
     fn parameters(&self) -> Value {
@@ -36,13 +42,18 @@ impl Tool for SemanticSearchTool {
                 "refresh": {
                     "type": "boolean",
                     "description": "Force re-indexing of the workspace (default false)."
+                },
+                "mode": {
+                    "type": "string",
+                    "enum": ["vector", "keyword", "hybrid"],
+                    "description": "Retrieval mode: 'vector' for concepts, 'keyword' for exact identifiers, error codes or file names, 'hybrid' for both (default)."
                 }
             },
             "required": ["query"]
         })
     }
 
-            fn execute(&self, args: Value) -> Result<String> {
+            async fn execute(&self, args: Value, _ctx: &ToolContext) -> Result<String> {
 
                 let query = args.get("query").and_then(|v| v.as_str())
 
@@ -52,6 +63,11 @@ impl Tool for SemanticSearchTool {
 
                 let refresh = args.get("refresh").and_then(|v| v.as_bool()).unwrap_or(false);
 
+                let mode: SearchMode = match args.get("mode").and_then(|v| v.as_str()) {
+                    Some(m) => m.parse()?,
+                    None => SearchMode::default(),
+                };
+
                 let index_path_raw = args.get("index_path").and_then(|v| v.as_str());
 
         
@@ -130,13 +146,13 @@ impl Tool for SemanticSearchTool {
 
                      tokio::spawn(async move {
 
-                         match SemanticSearchTool::index_directory_logic(rag_clone, &path_clone, &coll_clone, status_tx_clone.clone()).await {
+                         match rag_clone.index_directory(&path_clone, &coll_clone, status_tx_clone.clone()).await {
 
-                             Ok(count) => {
+                             Ok(report) => {
 
                                  if let Some(tx) = status_tx_clone {
 
-                                     let _ = tx.send(format!("Completed indexing '{}': {} chunks added.", path_clone, count));
+                                     let _ = tx.send(format!("Completed indexing '{}': {}.", path_clone, report));
 
                                  }
 
@@ -162,11 +178,13 @@ impl Tool for SemanticSearchTool {
 
                 // 3. Search (Immediate, on existing data)
 
-                let handle = tokio::runtime::Handle::current();
 
-                let filter = collection_name.as_deref();
+                let scope: Option<Vec<String>> = match collection_name {
+                    Some(name) => Some(vec![name]),
+                    None => self.session_collections.lock().unwrap().as_ref().map(|set| set.iter().cloned().collect()),
+                };
 
-                let results = handle.block_on(self.rag.search(query, 5, filter))?;
+                let results = self.rag.search_scoped(query, 5, scope.as_deref(), mode).await?;
 
         
 
@@ -184,11 +202,12 @@ impl Tool for SemanticSearchTool {
 
                 } else {
 
-                    let mut output = format!("Top conceptual matches for '{}' (Collection: {}):\n\n", query, filter.unwrap_or("ALL"));
+                    let collections = scope.map(|names| names.join(", ")).unwrap_or_else(|| "ALL".to_string());
+                    let mut output = format!("Top conceptual matches for '{}' (Collection: {}):\n\n", query, collections);
 
                     for (i, res) in results.into_iter().enumerate() {
 
-                        output.push_str(&format!("{}. {}\n\n", i + 1, res.trim()));
+                        output.push_str(&format!("{}. {}\n\n", i + 1, res));
 
                     }
 
@@ -208,274 +227,333 @@ impl Tool for SemanticSearchTool {
 
         
 
-        impl SemanticSearchTool {
-
-            async fn index_directory_logic(rag: Arc<RagSystem>, dir_path: &str, collection: &str, status_tx: Option<StatusSender>) -> Result<usize> {
-
-                let dir_path_owned = dir_path.to_string();
-
-                
-
-                let walker_files = tokio::task::spawn_blocking(move || {
-
-                    let mut files = Vec::new();
-
-                    let walker = ignore::WalkBuilder::new(&dir_path_owned).standard_filters(true).build();
-
-                    for result in walker {
-
-                        if let Ok(entry) = result {
-
-                            if entry.file_type().map(|ft| ft.is_file()).unwrap_or(false) {
-
-                                 files.push(entry.path().to_owned());
-
-                            }
-
-                        }
-
-                    }
-
-                    files
-
-                }).await?;
-
-        
-
-                let mut chunks = Vec::new();
-
-                let collection_owned = collection.to_string();
-
-                
-
-                for file_path in walker_files {
-
-                     if let Ok(content) = std::fs::read_to_string(&file_path) {
-
-                         let lines: Vec<&str> = content.lines().collect();
+    pub struct MemoryTool {
 
-                         let chunk_size = 30;
+        pub rag: Arc<RagSystem>,
 
-                         let overlap = 5;
+    }
 
-                         
+    
 
-                         let mut start = 0;
+    #[async_trait]
+    impl Tool for MemoryTool {
 
-                         while start < lines.len() {
+        fn name(&self) -> &str {
 
-                             let end = std::cmp::min(start + chunk_size, lines.len());
+            "remember"
 
-                             let chunk_text = lines[start..end].join("\n");
+        }
 
-                             
+    
 
-                             if chunk_text.len() > 50 {
+        fn description(&self) -> &str {
 
-                                 chunks.push(TextChunk {
+            "USE THIS to explicitly save an important fact, note, or piece of information to your long-term memory. This helps you remember things across conversations or after a restart."
 
-                                     file_path: file_path.to_string_lossy().to_string(),
+        }
 
-                                     content: chunk_text,
+    
 
-                                     start_line: start + 1,
+        fn parameters(&self) -> Value {
 
-                                     end_line: end,
+            serde_json::json!({
 
-                                     embedding: vec![],
+                "type": "object",
 
-                                     collection: collection_owned.clone(),
+                "properties": {
 
-                                 });
+                    "fact": {
 
-                             }
+                        "type": "string",
 
-                             if end == lines.len() { break; }
+                        "description": "The fact or information to remember (e.g. 'The user prefers dark mode', 'The project uses port 8081')."
 
-                             start += chunk_size - overlap;
+                    },
 
-                         }
+                    "tags": {
 
-                     }
+                        "type": "array",
 
-                }
+                        "items": { "type": "string" },
 
-                
+                        "description": "Optional tags for grouping memories (e.g. ['preferences'])."
 
-                if chunks.is_empty() {
+                    }
 
-                    return Ok(0);
+                },
 
-                }
+                "required": ["fact"]
 
-        
+            })
 
-                if let Some(tx) = &status_tx {
+        }
 
-                    let _ = tx.send(format!("Generating embeddings for {} chunks...", chunks.len()));
+    
 
-                }
+        async fn execute(&self, args: Value, ctx: &ToolContext) -> Result<String> {
 
-        
+            let fact = args.get("fact").and_then(|v| v.as_str())
 
-                // Process in batches
+                .ok_or_else(|| anyhow::anyhow!("Missing 'fact' argument"))?;
 
-                for batch in chunks.chunks_mut(10) {
+            let tags = string_list(&args, "tags").unwrap_or_default();
 
-                     for chunk in batch.iter_mut() {
+            let session = ctx.session.clone();
 
-                         if let Ok(emb) = rag.client.generate_embeddings(&rag.embedding_model, &chunk.content).await {
+    
 
-                             chunk.embedding = emb;
 
-                         }
+            let fact = self.rag.remember(fact, Some(session), tags).await?;
 
-                     }
+    
 
-                }
+            Ok(format!("Successfully remembered #{}: {}", fact.id, fact.text))
 
-                
+        }
 
-                chunks.retain(|c| !c.embedding.is_empty());
+    }
 
-                let count = chunks.len();
+    
 
-        
+    /// Reads a memory id given as `12` or `"#12"`.
+    fn fact_id(args: &Value) -> Result<u64> {
+        let id = args.get("id").ok_or_else(|| anyhow::anyhow!("Missing 'id' argument"))?;
+        id.as_u64()
+            .or_else(|| id.as_str().and_then(|s| s.trim().trim_start_matches('#').parse().ok()))
+            .ok_or_else(|| anyhow::anyhow!("Invalid memory id {}; use the number shown by list_memories", id))
+    }
 
-                let mut guard = rag.index.lock().unwrap();
+    /// Reads an optional list of strings, also accepting a comma-separated string.
+    fn string_list(args: &Value, key: &str) -> Option<Vec<String>> {
+        match args.get(key)? {
+            Value::Array(items) => Some(items.iter().filter_map(|v| v.as_str()).map(str::to_string).collect()),
+            Value::String(s) => Some(s.split(',').map(str::to_string).collect()),
+            _ => None,
+        }
+    }
 
-                if let Some(index) = &mut *guard {
+    /// Deletes a remembered fact.
+    pub struct ForgetTool {
+        pub rag: Arc<RagSystem>,
+    }
 
-                    index.chunks.extend(chunks);
+    #[async_trait]
+    impl Tool for ForgetTool {
+        fn name(&self) -> &str {
+            "forget"
+        }
 
-                    index.indexed_at = std::time::SystemTime::now();
+        fn description(&self) -> &str {
+            "USE THIS to delete a remembered fact that is wrong or no longer wanted. Find its id with list_memories first."
+        }
 
-                } else {
+        fn parameters(&self) -> Value {
+            serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "id": {
+                        "type": "integer",
+                        "description": "The id of the memory to delete, as shown by list_memories."
+                    }
+                },
+                "required": ["id"]
+            })
+        }
 
-                    *guard = Some(VectorIndex {
+        async fn execute(&self, args: Value, _ctx: &ToolContext) -> Result<String> {
+            let id = fact_id(&args)?;
+            let fact = self.rag.forget_fact(id).await?;
+            Ok(format!("Forgot #{}: {}", fact.id, fact.text))
+        }
+    }
 
-                        chunks,
+    /// Lists remembered facts with their ids.
+    pub struct ListMemoriesTool {
+        pub rag: Arc<RagSystem>,
+    }
 
-                        indexed_at: std::time::SystemTime::now(),
+    #[async_trait]
+    impl Tool for ListMemoriesTool {
+        fn name(&self) -> &str {
+            "list_memories"
+        }
 
-                    });
+        fn description(&self) -> &str {
+            "USE THIS to list the facts saved in long-term memory, with their ids, tags, dates and source sessions. Use the ids with update_memory or forget."
+        }
 
+        fn parameters(&self) -> Value {
+            serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "tag": {
+                        "type": "string",
+                        "description": "Optional: only list memories with this tag."
+                    }
                 }
+            })
+        }
 
-                drop(guard);
-
-                
-
-                rag.save()?;
-
-        
-
-                Ok(count)
-
+        async fn execute(&self, args: Value, _ctx: &ToolContext) -> Result<String> {
+            let tag = args.get("tag").and_then(|v| v.as_str());
+            let facts = self.rag.list_facts(tag)?;
+            if facts.is_empty() {
+                return Ok(match tag {
+                    Some(tag) => format!("No memories tagged '{}'.", tag),
+                    None => "No memories saved.".to_string(),
+                });
             }
-
-        
-
-            // Retaining dummy chunk_file for now as it's not used by execute anymore but might be by other methods? 
-
-            // Actually, index_directory_logic duplicates chunking logic because it's static/async. 
-
-            // We can remove `index_directory` and `chunk_file` instance methods if unused.
-
-            // But `chunk_file` is used by the old implementation. 
-
-            // We are REPLACING `execute` and `impl SemanticSearchTool`.
-
-            
-
-            // We need to be careful to remove the OLD `index_directory` and `chunk_file`.
-
-            // The previous `replace` failed, so `execute` is still the old one.
-
-            // The `old_string` I provide below must match the CURRENT file content EXACTLY.
-
-            
-
-            // I will target the `execute` method implementation block.
-
+            Ok(facts.iter().map(|f| format!("- {}", f.summary())).collect::<Vec<_>>().join("\n"))
         }
+    }
 
-        
-
-    
-
-    pub struct MemoryTool {
-
+    /// Corrects a remembered fact in place.
+    pub struct UpdateMemoryTool {
         pub rag: Arc<RagSystem>,
-
     }
 
-    
-
-    impl Tool for MemoryTool {
-
+    #[async_trait]
+    impl Tool for UpdateMemoryTool {
         fn name(&self) -> &str {
-
-            "remember"
-
+            "update_memory"
         }
 
-    
-
         fn description(&self) -> &str {
-
-            "USE THIS to explicitly save an important fact, note, or piece of information to your long-term memory. This helps you remember things across conversations or after a restart."
-
+            "USE THIS to correct or re-tag a remembered fact instead of saving a contradicting one. Find its id with list_memories first."
         }
 
-    
-
         fn parameters(&self) -> Value {
-
             serde_json::json!({
-
                 "type": "object",
-
                 "properties": {
-
+                    "id": {
+                        "type": "integer",
+                        "description": "The id of the memory to update, as shown by list_memories."
+                    },
                     "fact": {
-
                         "type": "string",
-
-                        "description": "The fact or information to remember (e.g. 'The user prefers dark mode', 'The project uses port 8081')."
-
+                        "description": "The corrected text. Omit to keep the current text."
+                    },
+                    "tags": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "Replacement tags. Omit to keep the current tags."
                     }
-
                 },
-
-                "required": ["fact"]
-
+                "required": ["id"]
             })
-
         }
 
-    
-
-        fn execute(&self, args: Value) -> Result<String> {
-
-            let fact = args.get("fact").and_then(|v| v.as_str())
-
-                .ok_or_else(|| anyhow::anyhow!("Missing 'fact' argument"))?;
-
-    
-
-            let handle = tokio::runtime::Handle::current();
+        async fn execute(&self, args: Value, _ctx: &ToolContext) -> Result<String> {
+            let id = fact_id(&args)?;
+            let text = args.get("fact").and_then(|v| v.as_str());
+            let tags = string_list(&args, "tags");
+            if text.is_none() && tags.is_none() {
+                return Err(anyhow::anyhow!("Provide 'fact' and/or 'tags' to update"));
+            }
+            let fact = self.rag.update_fact(id, text, tags).await?;
+            Ok(format!("Updated {}", fact.summary()))
+        }
+    }
 
-            // Use "memory" collection
+    /// Lists, inspects, prunes and re-embeds collections in the vector store.
+    pub struct ManageMemoryTool {
+        pub rag: Arc<RagSystem>,
+        /// Receives progress of background re-embedding.
+        pub status_tx: Option<StatusSender>,
+    }
 
-            handle.block_on(self.rag.add_text(fact, Some("memory".to_string())))?;
+    #[async_trait]
+    impl Tool for ManageMemoryTool {
+        fn name(&self) -> &str {
+            "manage_memory"
+        }
 
-    
+        fn description(&self) -> &str {
+            "USE THIS to list or inspect indexed collections (chunk counts, embedding model, last indexed time), to remove stale chunks by collection, file path prefix or age, or to re-embed a collection quarantined after the embedding model changed."
+        }
 
-            Ok(format!("Successfully remembered: {}", fact))
+        fn parameters(&self) -> Value {
+            serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "action": {
+                        "type": "string",
+                        "enum": ["list", "stats", "forget", "purge", "reembed"],
+                        "description": "'list' collections, show 'stats', 'forget' matching chunks, 'purge' everything, or 'reembed' a collection with the current embedding model."
+                    },
+                    "collection": {
+                        "type": "string",
+                        "description": "For stats/forget: only this collection; for reembed: the collection to migrate (e.g. 'work', 'web', 'memory')."
+                    },
+                    "path_prefix": {
+                        "type": "string",
+                        "description": "For forget: only chunks whose source path starts with this."
+                    },
+                    "older_than": {
+                        "type": "string",
+                        "description": "For forget: only chunks indexed longer ago than this, e.g. '30d', '12h'."
+                    }
+                },
+                "required": ["action"]
+            })
+        }
 
+        async fn execute(&self, args: Value, _ctx: &ToolContext) -> Result<String> {
+            let action = args.get("action").and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Missing 'action' argument"))?;
+            let collection = args.get("collection").and_then(|v| v.as_str()).map(str::to_string);
+
+            match action {
+                "list" | "stats" => {
+                    let stats: Vec<_> = self.rag.collection_stats()?
+                        .into_iter()
+                        .filter(|s| collection.as_ref().is_none_or(|c| s.name == *c))
+                        .collect();
+                    if stats.is_empty() {
+                        return Ok("No indexed collections.".to_string());
+                    }
+                    let lines: Vec<String> = if action == "list" {
+                        stats.iter().map(|s| format!("- {} ({} chunks)", s.name, s.chunks)).collect()
+                    } else {
+                        stats.iter().map(|s| format!("- {}", s.summary())).collect()
+                    };
+                    Ok(lines.join("\n"))
+                }
+                "forget" => {
+                    let filter = ForgetFilter {
+                        collection,
+                        path_prefix: args.get("path_prefix").and_then(|v| v.as_str()).map(expand_path),
+                        older_than: args.get("older_than").and_then(|v| v.as_str()).map(parse_age).transpose()?,
+                    };
+                    let removed = self.rag.forget(&filter).await?;
+                    Ok(format!("Forgot {} chunks.", removed))
+                }
+                "purge" => {
+                    let removed = self.rag.purge().await?;
+                    Ok(format!("Purged {} chunks from all collections.", removed))
+                }
+                "reembed" => {
+                    let collection = collection.ok_or_else(|| anyhow::anyhow!("'reembed' needs a 'collection'"))?;
+                    let rag = self.rag.clone();
+                    let status_tx = self.status_tx.clone();
+                    let name = collection.clone();
+                    tokio::spawn(async move {
+                        let message = match rag.reembed(&name, status_tx.clone()).await {
+                            Ok(n) => format!("Re-embedded {} chunks in '{}'.", n, name),
+                            Err(e) => format!("Failed to re-embed '{}': {}", name, e),
+                        };
+                        if let Some(tx) = status_tx {
+                            let _ = tx.send(message);
+                        }
+                    });
+                    Ok(format!("Started re-embedding '{}' in the background. Progress will be shown in the status bar.", collection))
+                }
+                other => Err(anyhow::anyhow!("Unknown action '{}'. Use list, stats, forget, purge or reembed.", other)),
+            }
         }
 
+        fn requires_confirmation(&self) -> bool {
+            true
+        }
     }
-
-    
\ No newline at end of file
@@ -0,0 +1,275 @@
+//! Where the filesystem tools may read and write.
+//!
+//! Paths are resolved to canonical form, following symlinks, before they
+//! are checked, so `..`, absolute paths and links pointing elsewhere can't
+//! reach outside the workspace roots. Paths that don't exist yet are
+//! resolved through their nearest existing parent. Ignored patterns are
+//! compared with whole path components below the root, so `node_modules`
+//! blocks `node_modules/x` but not `node_modules_backup`.
+
+use super::expand_path;
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use tracing::warn;
+
+/// Workspace roots (`[workspace]` in the config). Writing is limited to
+/// `write`; reading to `read` and `write`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorkspaceRoots {
+    pub read: Vec<String>,
+    pub write: Vec<String>,
+}
+
+impl Default for WorkspaceRoots {
+    fn default() -> Self {
+        Self {
+            read: vec!["~".to_string(), ".".to_string()],
+            write: vec![".".to_string()],
+        }
+    }
+}
+
+/// The kind of access a tool needs to a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Access::Read => write!(f, "read"),
+            Access::Write => write!(f, "write"),
+        }
+    }
+}
+
+/// Resolves tool paths and checks them against the workspace roots.
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    read_roots: Vec<PathBuf>,
+    write_roots: Vec<PathBuf>,
+    ignored_patterns: Vec<String>,
+}
+
+impl Workspace {
+    /// A workspace reading under `read` and `write` and writing under
+    /// `write`. Roots are expanded and canonicalised; missing ones are
+    /// skipped with a warning.
+    pub fn new<S: AsRef<str>>(read: &[S], write: &[S], ignored_patterns: Vec<String>) -> Self {
+        let roots = |paths: &[S]| -> Vec<PathBuf> {
+            paths
+                .iter()
+                .filter_map(|root| match absolute(root.as_ref()).and_then(|p| Ok(p.canonicalize()?)) {
+                    Ok(path) => Some(path),
+                    Err(e) => {
+                        warn!("Skipping workspace root '{}': {}", root.as_ref(), e);
+                        None
+                    }
+                })
+                .collect()
+        };
+        let write_roots = roots(write);
+        let mut read_roots = roots(read);
+        read_roots.extend(write_roots.iter().cloned());
+        Self { read_roots, write_roots, ignored_patterns }
+    }
+
+    /// Builds the workspace from the config's roots.
+    pub fn from_roots(roots: &WorkspaceRoots, ignored_patterns: Vec<String>) -> Self {
+        Self::new(&roots.read, &roots.write, ignored_patterns)
+    }
+
+    /// The canonical form of `raw` (which may start with `~` or be relative
+    /// to the working directory), if it lies in a root allowing `access`
+    /// and outside ignored directories.
+    pub fn resolve(&self, raw: &str, access: Access) -> Result<PathBuf> {
+        let path = canonicalize(&absolute(raw)?)
+            .map_err(|e| anyhow::anyhow!("Access denied: cannot resolve '{}': {}", raw, e))?;
+        let roots = match access {
+            Access::Read => &self.read_roots,
+            Access::Write => &self.write_roots,
+        };
+        let Some(root) = roots.iter().find(|root| path.starts_with(root)) else {
+            let allowed: Vec<String> = roots.iter().map(|r| r.display().to_string()).collect();
+            bail!(
+                "Access denied: '{}' is outside the workspace {} roots ({})",
+                raw,
+                access,
+                if allowed.is_empty() { "none configured".to_string() } else { allowed.join(", ") }
+            );
+        };
+        if let Some(ignored) = self.ignored_below(root, &path) {
+            bail!("Access denied: '{}' is inside ignored directory '{}'", raw, ignored);
+        }
+        Ok(path)
+    }
+
+    /// The path `resolve` would check for `raw`, without checking it. A
+    /// path that can't be canonicalised is only made absolute.
+    pub fn target(&self, raw: &str) -> PathBuf {
+        match absolute(raw) {
+            Ok(path) => canonicalize(&path).unwrap_or(path),
+            Err(_) => PathBuf::from(raw),
+        }
+    }
+
+    /// The ignored patterns, for tools that pass them on to `find` or `rg`.
+    pub fn ignored_patterns(&self) -> &[String] {
+        &self.ignored_patterns
+    }
+
+    /// Whether the resolved `path` lies in an ignored directory below the
+    /// innermost read root containing it.
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        let root = self.read_roots.iter().filter(|root| path.starts_with(root)).max_by_key(|root| root.components().count());
+        root.and_then(|root| self.ignored_below(root, path)).is_some()
+    }
+
+    /// The first component of `path` below `root` that is ignored.
+    fn ignored_below(&self, root: &Path, path: &Path) -> Option<&str> {
+        let below_root = path.strip_prefix(root).unwrap_or(path);
+        below_root
+            .components()
+            .find_map(|c| self.ignored_patterns.iter().find(|p| c.as_os_str() == p.as_str()))
+            .map(String::as_str)
+    }
+}
+
+/// `raw` with `~` expanded, joined to the working directory if relative.
+fn absolute(raw: &str) -> Result<PathBuf> {
+    let path = PathBuf::from(expand_path(raw));
+    if path.is_relative() {
+        Ok(std::env::current_dir()?.join(path))
+    } else {
+        Ok(path)
+    }
+}
+
+/// Canonicalises `path`, following symlinks. A path that doesn't exist yet
+/// is resolved through its nearest existing ancestor; the missing part must
+/// be plain names.
+fn canonicalize(path: &Path) -> Result<PathBuf> {
+    let mut missing: Vec<OsString> = Vec::new();
+    let mut existing = path;
+    loop {
+        match existing.canonicalize() {
+            Ok(resolved) => {
+                return Ok(missing.iter().rev().fold(resolved, |path, name| path.join(name)));
+            }
+            Err(e) => {
+                // A dangling link would be followed when the file is created.
+                if existing.symlink_metadata().is_ok() {
+                    bail!("{} is a broken symlink", existing.display());
+                }
+                match (existing.components().next_back(), existing.parent()) {
+                    (Some(Component::Normal(name)), Some(parent)) => {
+                        missing.push(name.to_os_string());
+                        existing = parent;
+                    }
+                    _ => return Err(e.into()),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    struct Fixture {
+        _dir: tempfile::TempDir,
+        root: PathBuf,
+        outside: PathBuf,
+        workspace: Workspace,
+    }
+
+    /// A writable `project` directory with a read-only `docs` sibling and an
+    /// `outside` directory that is neither.
+    fn fixture() -> Fixture {
+        let dir = tempdir().unwrap();
+        let base = dir.path().canonicalize().unwrap();
+        let root = base.join("project");
+        let docs = base.join("docs");
+        let outside = base.join("outside");
+        for d in [&root, &docs, &outside, &root.join("node_modules"), &root.join("node_modules_backup")] {
+            std::fs::create_dir_all(d).unwrap();
+        }
+        std::fs::write(root.join("main.rs"), "fn main() {}").unwrap();
+        std::fs::write(docs.join("guide.md"), "# Guide").unwrap();
+        std::fs::write(outside.join("secret.txt"), "secret").unwrap();
+        let workspace = Workspace::new(
+            &[docs.to_str().unwrap()],
+            &[root.to_str().unwrap()],
+            vec!["node_modules".to_string()],
+        );
+        Fixture { _dir: dir, root, outside, workspace }
+    }
+
+    fn denied(result: Result<PathBuf>) -> String {
+        result.unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_paths_inside_roots_resolve() {
+        let f = fixture();
+        let main = f.root.join("main.rs");
+        assert_eq!(f.workspace.resolve(main.to_str().unwrap(), Access::Write).unwrap(), main);
+        let new_file = f.root.join("src/lib.rs");
+        assert_eq!(f.workspace.resolve(new_file.to_str().unwrap(), Access::Write).unwrap(), new_file);
+        let guide = f.root.join("../docs/guide.md");
+        assert!(f.workspace.resolve(guide.to_str().unwrap(), Access::Read).is_ok());
+        assert!(denied(f.workspace.resolve(guide.to_str().unwrap(), Access::Write)).contains("outside the workspace write roots"));
+    }
+
+    #[test]
+    fn test_parent_and_absolute_escapes_are_denied() {
+        let f = fixture();
+        let up = f.root.join("../outside/secret.txt");
+        assert!(denied(f.workspace.resolve(up.to_str().unwrap(), Access::Read)).contains("outside the workspace read roots"));
+        let absolute = f.outside.join("secret.txt");
+        assert!(f.workspace.resolve(absolute.to_str().unwrap(), Access::Read).is_err());
+        // `..` after a directory that doesn't exist can't be resolved.
+        let through_missing = f.root.join("missing/../../outside/new.txt");
+        assert!(f.workspace.resolve(through_missing.to_str().unwrap(), Access::Write).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_escapes_are_denied() {
+        let f = fixture();
+        std::os::unix::fs::symlink(&f.outside, f.root.join("link")).unwrap();
+        let through_link = f.root.join("link/secret.txt");
+        assert!(f.workspace.resolve(through_link.to_str().unwrap(), Access::Read).is_err());
+        let new_through_link = f.root.join("link/new.txt");
+        assert!(f.workspace.resolve(new_through_link.to_str().unwrap(), Access::Write).is_err());
+
+        // A dangling link would create its target outside the workspace.
+        std::os::unix::fs::symlink(f.outside.join("planted.txt"), f.root.join("dangling")).unwrap();
+        let dangling = f.root.join("dangling");
+        assert!(denied(f.workspace.resolve(dangling.to_str().unwrap(), Access::Write)).contains("broken symlink"));
+
+        // Links that stay inside the workspace are fine.
+        std::os::unix::fs::symlink(f.root.join("main.rs"), f.root.join("alias.rs")).unwrap();
+        let alias = f.root.join("alias.rs");
+        assert_eq!(f.workspace.resolve(alias.to_str().unwrap(), Access::Read).unwrap(), f.root.join("main.rs"));
+    }
+
+    #[test]
+    fn test_ignored_patterns_match_whole_components() {
+        let f = fixture();
+        let ignored = f.root.join("node_modules/pkg/index.js");
+        assert!(denied(f.workspace.resolve(ignored.to_str().unwrap(), Access::Read)).contains("ignored directory 'node_modules'"));
+        let similar = f.root.join("node_modules_backup/index.js");
+        assert!(f.workspace.resolve(similar.to_str().unwrap(), Access::Write).is_ok());
+        assert!(f.workspace.is_ignored(&ignored));
+        assert!(!f.workspace.is_ignored(&similar));
+    }
+}
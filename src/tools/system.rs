@@ -1,8 +1,11 @@
-use super::Tool;
-use anyhow::Result;
+use super::{Tool, ToolContext};
+use anyhow::{bail, Result};
+use async_trait::async_trait;
 use serde_json::Value;
-use std::process::{Command, Stdio};
+use std::process::{ExitStatus, Stdio};
 use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::process::{Child, Command};
 use crate::process::ProcessTracker;
 
 pub struct RunCommandTool {
@@ -10,6 +13,7 @@ pub struct RunCommandTool {
     pub process_tracker: Arc<ProcessTracker>,
 }
 
+#[async_trait]
 impl Tool for RunCommandTool {
     fn name(&self) -> &str {
         "run_command"
@@ -39,7 +43,7 @@ impl Tool for RunCommandTool {
         })
     }
 
-    fn execute(&self, args: Value) -> Result<String> {
+    async fn execute(&self, args: Value, ctx: &ToolContext) -> Result<String> {
         let raw_command = args
             .get("command")
             .and_then(|v| v.as_str())
@@ -74,52 +78,53 @@ impl Tool for RunCommandTool {
             shell_operators.iter().any(|op| arg.contains(op))
         });
 
-        let output = if needs_shell {
+        let mut cmd = if needs_shell {
             let full_command = format!("{} {}", command_name, cmd_args.join(" "));
             
-            let child = if cfg!(target_os = "windows") {
-                Command::new("cmd")
-                    .arg("/C")
-                    .arg(&full_command)
-                    .stdin(Stdio::null())
-                    .stdout(Stdio::piped())
-                    .stderr(Stdio::piped())
-                    .spawn()? 
+            let mut cmd = if cfg!(target_os = "windows") {
+                let mut cmd = Command::new("cmd");
+                cmd.arg("/C");
+                cmd
             } else {
-                Command::new("sh")
-                    .arg("-c")
-                    .arg(&full_command)
-                    .stdin(Stdio::null())
-                    .stdout(Stdio::piped())
-                    .stderr(Stdio::piped())
-                    .spawn()?
+                let mut cmd = Command::new("sh");
+                cmd.arg("-c");
+                cmd
             };
-
-            let pid = child.id();
-            self.process_tracker.add_pid(pid);
-            let result = child.wait_with_output();
-            self.process_tracker.remove_pid(pid);
-            result?
+            cmd.arg(&full_command);
+            cmd
         } else {
             let mut cmd = Command::new(command_name);
-            cmd.stdin(Stdio::null()); 
-            cmd.stdout(Stdio::piped());
-            cmd.stderr(Stdio::piped());
             cmd.args(&cmd_args);
-            
-            let child = cmd.spawn()?;
-            let pid = child.id();
-            
-            self.process_tracker.add_pid(pid);
-            let result = child.wait_with_output();
-            self.process_tracker.remove_pid(pid);
-            result?
+            cmd
         };
+        cmd.stdin(Stdio::null()); 
+        cmd.stdout(Stdio::piped());
+        cmd.stderr(Stdio::piped());
+        cmd.kill_on_drop(true);
+        // Its own process group, so cancelling also stops what a shell started.
+        #[cfg(unix)]
+        cmd.process_group(0);
 
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let stderr = String::from_utf8_lossy(&output.stderr);
+        let mut child = cmd.spawn()?;
+        // Also kills the group if the caller drops this call, e.g. on a timeout.
+        let mut group = child.id().map(|pid| ProcessGroup::track(pid, &self.process_tracker));
+        let finished = tokio::select! {
+            output = wait_streaming(&mut child, ctx) => Some(output),
+            _ = ctx.cancel.cancelled() => None,
+        };
+        match finished {
+            Some(_) => group.iter_mut().for_each(|group| group.running = false),
+            None => {
+                drop(group);
+                let _ = child.kill().await;
+            }
+        }
+        let Some(output) = finished else {
+            bail!("Cancelled by user");
+        };
+        let (status, stdout, stderr) = output?;
 
-        let result = if output.status.success() {
+        let result = if status.success() {
             if stdout.trim().is_empty() {
                 "Command succeeded with no output.".to_string()
             } else {
@@ -141,12 +146,64 @@ impl Tool for RunCommandTool {
     }
 }
 
+/// A tracked command's process group. Dropping it while the command still
+/// runs kills the whole group, so what a shell started goes too.
+struct ProcessGroup<'a> {
+    pid: u32,
+    tracker: &'a ProcessTracker,
+    running: bool,
+}
+
+impl<'a> ProcessGroup<'a> {
+    fn track(pid: u32, tracker: &'a ProcessTracker) -> Self {
+        tracker.add_pid(pid);
+        Self { pid, tracker, running: true }
+    }
+}
+
+impl Drop for ProcessGroup<'_> {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if self.running {
+            let _ = std::process::Command::new("kill").args(["-KILL", "--", &format!("-{}", self.pid)]).output();
+        }
+        self.tracker.remove_pid(self.pid);
+    }
+}
+
+/// Waits for `child` to exit, reporting each line of stdout as progress.
+/// Returns the exit status, stdout and stderr.
+async fn wait_streaming(child: &mut Child, ctx: &ToolContext) -> Result<(ExitStatus, String, String)> {
+    let (Some(stdout), Some(mut stderr)) = (child.stdout.take(), child.stderr.take()) else {
+        bail!("Command output is not captured");
+    };
+    let read_stdout = async {
+        let mut reader = BufReader::new(stdout);
+        let mut line = Vec::new();
+        let mut text = String::new();
+        while reader.read_until(b'\n', &mut line).await? > 0 {
+            let decoded = String::from_utf8_lossy(&line);
+            ctx.progress(decoded.trim_end());
+            text.push_str(&decoded);
+            line.clear();
+        }
+        Ok::<_, std::io::Error>(text)
+    };
+    let read_stderr = async {
+        let mut bytes = Vec::new();
+        stderr.read_to_end(&mut bytes).await?;
+        Ok(String::from_utf8_lossy(&bytes).to_string())
+    };
+    let (stdout, stderr, status) = tokio::try_join!(read_stdout, read_stderr, child.wait())?;
+    Ok((status, stdout, stderr))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    #[test]
-    fn test_run_command_shell_piping() -> Result<()> {
+    #[tokio::test]
+    async fn test_run_command_shell_piping() -> Result<()> {
         let tracker = Arc::new(ProcessTracker::new());
         let tool = RunCommandTool {
             allowed_commands: vec!["echo".to_string(), "grep".to_string()],
@@ -157,13 +214,13 @@ mod tests {
             "args": ["hello world", "|", "grep", "hello"]
         });
 
-        let output = tool.execute(args)?;
+        let output = tool.execute(args, &ToolContext::default()).await?;
         assert!(output.contains("hello world"));
         Ok(())
     }
 
-    #[test]
-    fn test_run_command_shell_piping_fail_allowlist() -> Result<()> {
+    #[tokio::test]
+    async fn test_run_command_shell_piping_fail_allowlist() -> Result<()> {
         let tracker = Arc::new(ProcessTracker::new());
         let tool = RunCommandTool {
             allowed_commands: vec!["ls".to_string()],
@@ -174,8 +231,75 @@ mod tests {
             "args": ["hello"]
         });
 
-        let result = tool.execute(args);
+        let result = tool.execute(args, &ToolContext::default()).await;
         assert!(result.is_err());
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_run_command_streams_output_and_stops_when_cancelled() -> Result<()> {
+        let tool = RunCommandTool {
+            allowed_commands: vec!["echo".to_string()],
+            process_tracker: Arc::new(ProcessTracker::new()),
+        };
+        let (progress_tx, mut progress_rx) = tokio::sync::mpsc::unbounded_channel();
+        let ctx = ToolContext { progress: Some(progress_tx), ..ToolContext::default() };
+        let cancel = ctx.cancel.clone();
+        let args = serde_json::json!({
+            "command": "echo",
+            "args": ["started", ";", "sleep", "30"]
+        });
+
+        let run = tokio::spawn(async move { tool.execute(args, &ctx).await });
+        assert_eq!(progress_rx.recv().await.as_deref(), Some("started"));
+        cancel.cancel();
+
+        let result = tokio::time::timeout(std::time::Duration::from_secs(5), run).await??;
+        assert!(result.unwrap_err().to_string().contains("Cancelled"));
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_timed_out_commands_take_their_children_with_them() -> Result<()> {
+        let tracker = Arc::new(ProcessTracker::new());
+        let tool = RunCommandTool {
+            allowed_commands: vec!["sleep".to_string()],
+            process_tracker: tracker.clone(),
+        };
+        let (progress_tx, mut progress_rx) = tokio::sync::mpsc::unbounded_channel();
+        let ctx = ToolContext { progress: Some(progress_tx), ..ToolContext::default() };
+        // Runs as `sh -c 'sleep 60 & echo $!; wait'`.
+        let args = serde_json::json!({
+            "command": "sleep",
+            "args": ["60", "&", "echo", "$!;", "wait"]
+        });
+
+        let mut run = Box::pin(tool.execute(args, &ctx));
+        let sleep_pid = tokio::select! {
+            line = progress_rx.recv() => line.unwrap(),
+            _ = &mut run => panic!("the command finished early"),
+        };
+        assert!(is_running(&sleep_pid));
+        // Times out, and the call is dropped like `spawn_tool` does.
+        assert!(tokio::time::timeout(std::time::Duration::from_millis(100), &mut run).await.is_err());
+        drop(run);
+
+        for _ in 0..50 {
+            if !is_running(&sleep_pid) {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert!(!is_running(&sleep_pid), "sleep {} survived the timeout", sleep_pid);
+        assert!(tracker.is_empty());
+        Ok(())
+    }
+
+    /// Whether process `pid` exists and isn't a zombie.
+    #[cfg(target_os = "linux")]
+    fn is_running(pid: &str) -> bool {
+        std::fs::read_to_string(format!("/proc/{}/stat", pid))
+            .is_ok_and(|stat| stat.rsplit(')').next().is_some_and(|rest| !rest.trim_start().starts_with('Z')))
+    }
 }
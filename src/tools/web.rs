@@ -1,5 +1,6 @@
-use super::Tool;
+use super::{Provenance, Tool, ToolContext};
 use anyhow::Result;
+use async_trait::async_trait;
 use serde_json::Value;
 use std::sync::{Arc, OnceLock, Mutex};
 use crate::rag::RagSystem;
@@ -16,11 +17,12 @@ const USER_AGENTS: &[&str] = &[
 
 pub struct WebSearchTool {
     pub searxng_url: String,
-    pub client: OnceLock<reqwest::blocking::Client>,
+    pub client: OnceLock<reqwest::Client>,
     pub rag: Arc<RagSystem>,
     pub browser: Arc<BrowserClient>,
 }
 
+#[async_trait]
 impl Tool for WebSearchTool {
     fn name(&self) -> &str {
         "web_search"
@@ -59,14 +61,14 @@ IMPORTANT:
         })
     }
 
-    fn execute(&self, args: Value) -> Result<String> {
+    async fn execute(&self, args: Value, ctx: &ToolContext) -> Result<String> {
         let url_arg = args.get("url").and_then(|v| v.as_str());
 
         let client = self.client.get_or_init(|| {
-            reqwest::blocking::Client::builder()
+            reqwest::Client::builder()
                 .timeout(std::time::Duration::from_secs(10))
                 .build()
-                .unwrap_or_else(|_| reqwest::blocking::Client::new())
+                .unwrap_or_else(|_| reqwest::Client::new())
         });
         
         let user_agent = USER_AGENTS.choose(&mut rand::rng()).unwrap_or(&USER_AGENTS[0]);
@@ -75,11 +77,12 @@ IMPORTANT:
         if let Some(url) = url_arg.filter(|u| !u.is_empty()) {
              let response = client.get(url)
                  .header("User-Agent", *user_agent)
-                 .send()?;
+                 .send()
+                 .await?;
             
             if response.status().as_u16() == 403 || response.status().as_u16() == 429 {
                 // Fallback to browser for direct URL access
-                return self.browser.get_content(url).map(|text| {
+                return browse(&self.browser, url, ctx).await.map(|text| {
                      if text.len() > 20000 {
                         format!("{}\n... (truncated)", &text[..20000])
                     } else {
@@ -91,7 +94,7 @@ IMPORTANT:
                 return Err(anyhow::anyhow!("Failed to fetch URL: {}", response.status()));
             }
 
-            let html = response.text()?;
+            let html = response.text().await?;
             let width = 120; // Reasonable width for TUI reading
             let text = html2text::from_read(html.as_bytes(), width);
             
@@ -127,13 +130,14 @@ IMPORTANT:
                 ("language", "en-US"),
                 ("categories", category)
             ])
-            .send()?;
+            .send()
+            .await?;
 
         if response.status().as_u16() == 403 || response.status().as_u16() == 429 {
              // Fallback to browser for search
              // Construct the search URL manually for the browser
              let browser_search_url = format!("{}?q={}&categories={}", url, urlencoding::encode(query), category);
-             return self.browser.get_content(&browser_search_url).map(|text| {
+             return browse(&self.browser, &browser_search_url, ctx).await.map(|text| {
                  // Try to format the raw text a bit or just return it
                  // Since it's raw text from body, it might be messy but contains the results
                  format!("(Fallback: Search via Browser due to 403)\n\n{}", text)
@@ -144,7 +148,7 @@ IMPORTANT:
              return Err(anyhow::anyhow!("Search request failed: {}", response.status()));
         }
 
-        let json: Value = response.json()?;
+        let json: Value = response.json().await?;
         
         if let Some(results) = json.get("results").and_then(|v| v.as_array()) {
             if results.is_empty() {
@@ -163,8 +167,13 @@ IMPORTANT:
             }
 
             // Auto-ingest snippets into RAG
-            let handle = tokio::runtime::Handle::current();
-            let _ = handle.block_on(self.rag.add_text(&all_content, Some("web".to_string())));
+            let provenance = Provenance {
+                tool: self.name().to_string(),
+                args: args.clone(),
+                session: Some(ctx.session.clone()),
+                url: None,
+            };
+            let _ = self.rag.add_tool_output(&all_content, "web", provenance).await;
 
             Ok(output)
         } else {
@@ -209,12 +218,20 @@ impl BrowserClient {
     }
 }
 
+/// Renders `url` in the headless browser, off the async runtime.
+async fn browse(browser: &Arc<BrowserClient>, url: &str, ctx: &ToolContext) -> Result<String> {
+    let browser = browser.clone();
+    let url = url.to_string();
+    ctx.blocking(move || browser.get_content(&url)).await
+}
+
 pub struct ReadUrlTool {
-    pub client: OnceLock<reqwest::blocking::Client>,
+    pub client: OnceLock<reqwest::Client>,
     pub rag: Arc<RagSystem>,
     pub browser: Arc<BrowserClient>,
 }
 
+#[async_trait]
 impl Tool for ReadUrlTool {
     fn name(&self) -> &str {
         "read_url"
@@ -243,7 +260,7 @@ If NO query is provided, the tool returns the start of the page and INDEXES the
         })
     }
 
-    fn execute(&self, args: Value) -> Result<String> {
+    async fn execute(&self, args: Value, ctx: &ToolContext) -> Result<String> {
         let url = args
             .get("url")
             .and_then(|v| v.as_str())
@@ -252,10 +269,10 @@ If NO query is provided, the tool returns the start of the page and INDEXES the
         let query = args.get("query").and_then(|v| v.as_str());
 
         let client = self.client.get_or_init(|| {
-            reqwest::blocking::Client::builder()
+            reqwest::Client::builder()
                 .timeout(std::time::Duration::from_secs(10))
                 .build()
-                .unwrap_or_else(|_| reqwest::blocking::Client::new())
+                .unwrap_or_else(|_| reqwest::Client::new())
         });
         
         // Primary Strategy: HTTP Request (Fast)
@@ -266,11 +283,12 @@ If NO query is provided, the tool returns the start of the page and INDEXES the
 
         match client.get(url)
             .header("User-Agent", "Mozilla/5.0 (compatible; Intus/1.0; +https://github.com/harryw1/intus)")
-            .send() 
+            .send()
+            .await
         {
             Ok(response) => {
                 if response.status().is_success() {
-                    let html = response.text().unwrap_or_default();
+                    let html = response.text().await.unwrap_or_default();
                     let width = 120;
                     text = html2text::from_read(html.as_bytes(), width);
                     
@@ -288,7 +306,7 @@ If NO query is provided, the tool returns the start of the page and INDEXES the
         }
 
         if needs_browser {
-             match self.browser.get_content(url) {
+             match browse(&self.browser, url, ctx).await {
                  Ok(browser_text) => {
                      text = browser_text;
                  },
@@ -304,20 +322,25 @@ If NO query is provided, the tool returns the start of the page and INDEXES the
         }
         
         // Always ingest into RAG
-        let handle = tokio::runtime::Handle::current();
+        let provenance = Provenance {
+            tool: self.name().to_string(),
+            args: args.clone(),
+            session: Some(ctx.session.clone()),
+            url: Some(url.to_string()),
+        };
         
-        if let Err(e) = handle.block_on(self.rag.add_text(&text, Some("web".to_string()))) {
+        if let Err(e) = self.rag.add_tool_output(&text, "web", provenance).await {
              return Ok(format!("Fetched content but failed to index: {}\n\n{}", e, &text.chars().take(2000).collect::<String>()));
         }
 
         if let Some(q) = query {
             let clean_query = q.trim_matches('\'').trim_matches('"');
-            let results = handle.block_on(self.rag.search(clean_query, 5, Some("web")))?;
+            let results = self.rag.search(clean_query, 5, Some("web")).await?;
             
             if results.is_empty() {
                 Ok(format!("Page indexed, but no sections found matching query '{}'.\nHere is the beginning of the page:\n\n{}", clean_query, &text.chars().take(2000).collect::<String>()))
             } else {
-                Ok(format!("Found {} relevant sections for '{}' in {}:\n\n{}", results.len(), clean_query, url, results.join("\n\n---\n\n")))
+                Ok(format!("Found {} relevant sections for '{}' in {}:\n\n{}", results.len(), clean_query, url, results.iter().map(|hit| hit.content.as_str()).collect::<Vec<_>>().join("\n\n---\n\n")))
             }
         } else {
             if text.len() > 20000 {
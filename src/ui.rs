@@ -2,7 +2,7 @@ use crate::app::{App, Mode};
 use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
-    text::{Span, Text},
+    text::{Line, Span, Text},
     widgets::{Block, BorderType, Borders, Clear, List, ListItem, Paragraph, Row, Table, Wrap},
     Frame,
 };
@@ -190,12 +190,21 @@ pub fn ui(f: &mut Frame, app: &mut App) {
         Mode::SessionCreate => {
             render_session_create(f, app, size);
         }
+        Mode::CollectionSelect => {
+            render_collection_select(f, app, size);
+        }
         Mode::ModelPullInput => {
             render_model_pull_input(f, app, size);
         }
         Mode::ToolConfirmation => {
             render_tool_confirmation(f, app, size);
         }
+        Mode::ToolEdit => {
+            render_tool_edit(f, app, size);
+        }
+        Mode::CheckpointList => {
+            render_checkpoint_list(f, app, size);
+        }
     }
 }
 
@@ -233,8 +242,8 @@ fn render_status_bar(f: &mut Frame, app: &App, area: Rect) {
              format!(" {}", msg)
         } else {
              format!(
-                " {} | Session: {} | Tokens: {}/{} | F1: Help ",
-                mode_str, app.current_session, app.current_token_usage, app.context_token_limit
+                " {} | Session: {} | RAG: {} | Tokens: {}/{} | F1: Help ",
+                mode_str, app.current_session, app.collections_label(), app.current_token_usage, app.context_token_limit
             )
         }
     } else {
@@ -264,8 +273,8 @@ fn render_status_bar(f: &mut Frame, app: &App, area: Rect) {
             } else { String::new() };
 
             format!(
-                " {} | Session: {} | Tokens: {}/{}{} | F1: Help ",
-                mode_str, app.current_session, app.current_token_usage, limit, warning_text
+                " {} | Session: {} | RAG: {} | Tokens: {}/{}{} | F1: Help ",
+                mode_str, app.current_session, app.collections_label(), app.current_token_usage, limit, warning_text
             )
         }
     };
@@ -288,7 +297,7 @@ fn render_chat_history(f: &mut Frame, app: &mut App, area: Rect) {
     let max_available_width = (width as f32 * 0.90) as u16;
 
     if app.messages.is_empty() {
-        let empty_text = "Start a conversation.\nCtrl+o: Model | Ctrl+r: Sessions | Ctrl+s: System Prompt";
+        let empty_text = "Start a conversation.\nCtrl+o: Model | Ctrl+r: Sessions | Ctrl+g: Collections | Ctrl+s: System Prompt";
         let p = Paragraph::new(empty_text)
             .alignment(ratatui::layout::Alignment::Center)
             .style(Style::default().fg(app.theme.secondary_fg));
@@ -376,8 +385,16 @@ fn render_chat_history(f: &mut Frame, app: &mut App, area: Rect) {
     // Add margins (1 line between bubbles)
     if app.is_tool_executing {
         let height = 3;
-        let bubble_width = 22.min(max_available_width);
-        calculated_msgs.push((height, None, Some("Executing Tool...".to_string()), bubble_width));
+        // Show the tool's latest output line, if it streams any.
+        let label = match &app.tool_progress {
+            Some(line) => {
+                let line: String = line.trim().chars().take(60).collect();
+                format!("Executing Tool... {}", line)
+            }
+            None => "Executing Tool...".to_string(),
+        };
+        let bubble_width = (label.chars().count() as u16 + 5).min(max_available_width);
+        calculated_msgs.push((height, None, Some(label), bubble_width));
         total_height += height;
     }
 
@@ -655,6 +672,7 @@ fn render_help_popup(f: &mut Frame, app: &App, size: Rect) {
         Row::new(vec!["General", ""]),
         Row::new(vec![" Ctrl+o", "Model Select"]),
         Row::new(vec![" Ctrl+r", "Session Manager"]),
+        Row::new(vec![" Ctrl+g", "Session Collections"]),
         Row::new(vec![" Ctrl+s", "System Prompt"]),
         Row::new(vec![" Ctrl+l", "Clear History"]),
         Row::new(vec![" F1", "Toggle Help"]),
@@ -667,6 +685,8 @@ fn render_help_popup(f: &mut Frame, app: &App, size: Rect) {
         Row::new(vec!["Normal Mode", ""]),
         Row::new(vec![" j/k", "Scroll"]),
         Row::new(vec![" i", "Switch to Insert"]),
+        Row::new(vec![" u", "Undo Last Edit (or Since Selected)"]),
+        Row::new(vec![" c", "Checkpoints"]),
         Row::new(vec![" q", "Quit"]),
     ];
     
@@ -744,6 +764,74 @@ fn render_session_select(f: &mut Frame, app: &mut App, size: Rect) {
     f.render_stateful_widget(list, area, &mut app.session_list_state);
 }
 
+fn render_collection_select(f: &mut Frame, app: &mut App, size: Rect) {
+    let area = centered_rect(60, 50, size);
+    f.render_widget(Clear, area);
+
+    let block = Block::default()
+        .title(" Session Collections (Space: Toggle, Enter: Done) ")
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .border_style(Style::default().fg(app.theme.modal_border));
+
+    let enabled = app.retrieval_scope();
+    let row = |label: &str, on: bool| {
+        let style = if on {
+            Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().fg(app.theme.secondary_fg)
+        };
+        ListItem::new(Span::styled(format!("[{}] {}", if on { "x" } else { " " }, label), style))
+    };
+    let mut items = vec![row("All collections", enabled.is_none())];
+    items.extend(app.available_collections.iter().map(|name| {
+        row(name, enabled.as_ref().is_none_or(|names| names.contains(name)))
+    }));
+
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .highlight_symbol("> ");
+
+    f.render_stateful_widget(list, area, &mut app.collection_list_state);
+}
+
+fn render_checkpoint_list(f: &mut Frame, app: &mut App, size: Rect) {
+    let area = centered_rect(70, 60, size);
+    f.render_widget(Clear, area);
+
+    let block = Block::default()
+        .title(" Checkpoints (Enter: Undo Turn and Later Edits, Esc: Close) ")
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .border_style(Style::default().fg(app.theme.modal_border));
+
+    let items: Vec<ListItem> = app
+        .checkpoint_turns
+        .iter()
+        .map(|turn| {
+            let prompt = if turn.prompt.is_empty() { "(no prompt)" } else { turn.prompt.as_str() };
+            let edits = turn.edits.len();
+            let mut lines = vec![Line::from(vec![
+                Span::styled(prompt.to_string(), Style::default().add_modifier(Modifier::BOLD)),
+                Span::styled(
+                    format!("  {} edit{}", edits, if edits == 1 { "" } else { "s" }),
+                    Style::default().fg(app.theme.secondary_fg),
+                ),
+            ])];
+            lines.extend(turn.files().into_iter().map(|path| Line::from(format!("    {}", path.display()))));
+            ListItem::new(lines)
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .highlight_symbol("> ");
+
+    f.render_stateful_widget(list, area, &mut app.checkpoint_list_state);
+}
+
 fn render_system_prompt_edit(f: &mut Frame, app: &mut App, size: Rect) {
     let area = centered_rect(80, 30, size);
     f.render_widget(Clear, area);
@@ -786,17 +874,35 @@ fn render_model_pull_input(f: &mut Frame, app: &mut App, size: Rect) {
 }
 
 fn render_tool_confirmation(f: &mut Frame, app: &mut App, size: Rect) {
-    let area = centered_rect(60, 40, size);
+    let area = centered_rect(80, 70, size);
     f.render_widget(Clear, area);
 
     if let Some(tool_call) = &app.pending_tool_call {
         let tool_name = &tool_call.function.name;
-        let args_str = serde_json::to_string_pretty(&tool_call.function.arguments)
-            .unwrap_or_else(|_| "Invalid JSON".to_string());
+        let mut lines = vec![Line::from(format!("Tool: {}", tool_name)), Line::from("")];
+
+        match &app.tool_preview {
+            Some(Ok(diff)) => lines.extend(diff.lines().map(diff_line)),
+            preview => {
+                if let Some(Err(e)) = preview {
+                    lines.push(Line::styled(format!("No preview: {}", e), Style::default().fg(Color::Yellow)));
+                    lines.push(Line::from(""));
+                }
+                let args_str = serde_json::to_string_pretty(&tool_call.function.arguments)
+                    .unwrap_or_else(|_| "Invalid JSON".to_string());
+                lines.push(Line::from("Arguments:"));
+                lines.extend(args_str.lines().map(|l| Line::from(l.to_string())));
+            }
+        }
 
-        let text = format!("Tool: {}\n\nArguments:\n{}\n\nAllow execution? (y/n)", tool_name, args_str);
-        
-        let p = Paragraph::new(text)
+        let editable = app.tools.get(tool_name).is_some_and(|t| t.editable_argument().is_some());
+        lines.push(Line::from(""));
+        lines.push(Line::from(format!(
+            "Allow execution? (y)es / (n)o, or for this session (a)lways / (d)eny{}",
+            if editable { " | (e)dit" } else { "" }
+        )));
+
+        let p = Paragraph::new(lines)
             .block(Block::default()
                 .title(" Confirm Tool Execution (Scroll with Up/Down or j/k) ")
                 .borders(Borders::ALL)
@@ -808,6 +914,43 @@ fn render_tool_confirmation(f: &mut Frame, app: &mut App, size: Rect) {
     }
 }
 
+/// A line of a unified diff, coloured by kind.
+fn diff_line(line: &str) -> Line<'static> {
+    let style = if line.starts_with("+++") || line.starts_with("---") {
+        Style::default().add_modifier(Modifier::BOLD)
+    } else if line.starts_with('+') {
+        Style::default().fg(Color::Green)
+    } else if line.starts_with('-') {
+        Style::default().fg(Color::Red)
+    } else if line.starts_with("@@") {
+        Style::default().fg(Color::Cyan)
+    } else {
+        Style::default()
+    };
+    Line::styled(line.to_string(), style)
+}
+
+fn render_tool_edit(f: &mut Frame, app: &mut App, size: Rect) {
+    let area = centered_rect(80, 70, size);
+    f.render_widget(Clear, area);
+
+    let argument = app
+        .pending_tool_call
+        .as_ref()
+        .and_then(|call| app.tools.get(&call.function.name))
+        .and_then(|tool| tool.editable_argument())
+        .unwrap_or("content")
+        .to_string();
+    app.tool_edit_input.set_block(
+        Block::default()
+            .title(format!(" Edit {} (Esc to Cancel, Ctrl+s to Apply) ", argument))
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .border_style(Style::default().fg(Color::Red)),
+    );
+    f.render_widget(&app.tool_edit_input, area);
+}
+
 // Helpers
 fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
     let popup_layout = Layout::default()
@@ -0,0 +1,73 @@
+use intus::config::Config;
+use intus::headless::{self, AskOptions};
+use serde_json::json;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+#[tokio::test]
+async fn test_headless_ask_returns_answer() {
+    let mock_server = MockServer::start().await;
+
+    let chunk1 = json!({ "message": { "role": "assistant", "content": "The answer" }, "done": false });
+    let chunk2 = json!({ "message": { "role": "assistant", "content": " is 42." }, "done": true });
+    let body = format!("{}\n{}\n", chunk1, chunk2);
+
+    Mock::given(method("POST"))
+        .and(path("/api/chat"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(body))
+        .mount(&mock_server)
+        .await;
+
+    let mut config = Config::new_test_config();
+    config.ollama_url = mock_server.uri();
+
+    let options = AskOptions {
+        model: Some("llama3".to_string()),
+        ..Default::default()
+    };
+
+    let outcome = tokio::time::timeout(
+        std::time::Duration::from_secs(30),
+        headless::run_ask(config, "What is the answer?".to_string(), options),
+    )
+    .await
+    .expect("headless run timed out")
+    .expect("headless run failed");
+
+    assert_eq!(outcome.answer, "The answer is 42.");
+    assert_eq!(outcome.model, "llama3");
+    assert!(outcome.error.is_none());
+    assert!(outcome.tool_decisions.is_empty());
+    assert_eq!(outcome.exit_code(), headless::EXIT_OK);
+    assert!(outcome.messages.iter().any(|m| m.role == "user" && m.content == "What is the answer?"));
+}
+
+#[tokio::test]
+async fn test_headless_ask_reports_chat_failure() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/chat"))
+        .respond_with(ResponseTemplate::new(500).set_body_string("boom"))
+        .mount(&mock_server)
+        .await;
+
+    let mut config = Config::new_test_config();
+    config.ollama_url = mock_server.uri();
+
+    let options = AskOptions {
+        model: Some("llama3".to_string()),
+        ..Default::default()
+    };
+
+    let outcome = tokio::time::timeout(
+        std::time::Duration::from_secs(30),
+        headless::run_ask(config, "Hello".to_string(), options),
+    )
+    .await
+    .expect("headless run timed out")
+    .expect("headless run failed");
+
+    assert!(outcome.error.is_some());
+    assert_eq!(outcome.exit_code(), headless::EXIT_ERROR);
+}
@@ -1,5 +1,6 @@
 use intus::app::{Action, App};
 use intus::config::Config;
+use intus::ollama::ChatMessage;
 use std::fs;
 use tempfile::tempdir;
 use tokio::sync::mpsc;
@@ -141,3 +142,52 @@ async fn test_atomic_write_creates_backup() {
         "Backup should contain original data"
     );
 }
+
+#[tokio::test]
+async fn test_session_collections_are_saved_and_restored() {
+    let dir = tempdir().unwrap();
+    let file_path = dir.path().join("work.json");
+
+    let (tx, _rx) = mpsc::unbounded_channel();
+    let config = Config::new_test_config();
+
+    let mut app = App::init(tx.clone(), config.clone(), false, Some(file_path.clone())).await;
+    assert_eq!(app.retrieval_scope(), None);
+    assert_eq!(app.collections_label(), "all");
+
+    // Turning one collection off while all are on keeps the others.
+    app.available_collections = vec!["personal".to_string(), "work".to_string()];
+    app.update(Action::ToggleCollection(Some("personal".to_string()))).await;
+    assert_eq!(app.retrieval_scope(), Some(vec!["work".to_string()]));
+    assert_eq!(app.collections_label(), "work");
+
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    let app2 = App::init(tx.clone(), config.clone(), false, Some(file_path.clone())).await;
+    assert_eq!(app2.retrieval_scope(), Some(vec!["work".to_string()]));
+
+    app.update(Action::ToggleCollection(Some("work".to_string()))).await;
+    assert_eq!(app.collections_label(), "none");
+    app.update(Action::ToggleCollection(None)).await;
+    assert_eq!(app.retrieval_scope(), None);
+}
+
+#[tokio::test]
+async fn test_legacy_session_array_still_loads() {
+    let dir = tempdir().unwrap();
+    let file_path = dir.path().join("old.json");
+    let messages = vec![ChatMessage {
+        role: "user".to_string(),
+        content: "From before".to_string(),
+        images: None,
+        tool_calls: None,
+        tool_name: None,
+        tool_call_id: None,
+        thought: None,
+    }];
+    fs::write(&file_path, serde_json::to_string(&messages).unwrap()).unwrap();
+
+    let (tx, _rx) = mpsc::unbounded_channel();
+    let app = App::init(tx, Config::new_test_config(), false, Some(file_path)).await;
+    assert_eq!(app.messages, messages);
+    assert_eq!(app.retrieval_scope(), None);
+}
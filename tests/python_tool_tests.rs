@@ -1,10 +1,10 @@
 use intus::python::PythonRuntime;
-use intus::tools::{RunPythonTool, Tool};
+use intus::tools::{RunPythonTool, Tool, ToolContext};
 use std::sync::Arc;
 use serde_json::json;
 
-#[test]
-fn test_python_explicit_deps() {
+#[tokio::test]
+async fn test_python_explicit_deps() {
     let runtime = Arc::new(PythonRuntime::new().expect("Failed to init runtime"));
     let tool = RunPythonTool { runtime: runtime.clone() };
 
@@ -17,13 +17,13 @@ fn test_python_explicit_deps() {
         "dependencies": ["packaging"]
     });
 
-    let result = tool.execute(args).expect("Tool execution failed");
+    let result = tool.execute(args, &ToolContext::default()).await.expect("Tool execution failed");
     println!("Tool output: {}", result);
     assert!(result.contains("packaging installed"));
 }
 
-#[test]
-fn test_python_auto_install() {
+#[tokio::test]
+async fn test_python_auto_install() {
     let runtime = Arc::new(PythonRuntime::new().expect("Failed to init runtime"));
     let tool = RunPythonTool { runtime: runtime.clone() };
 
@@ -41,7 +41,7 @@ fn test_python_auto_install() {
     // To test auto-install logic specifically, we'd need a clean venv, but we can't easily guarantee that here without wiping usage.
     // Let's rely on the fact that if it fails, it returns an error, if it succeeds (after install), it returns output.
     
-    let result = tool.execute(args).expect("Tool execution failed");
+    let result = tool.execute(args, &ToolContext::default()).await.expect("Tool execution failed");
     println!("Tool output: {}", result);
     assert!(result.contains("colorama auto-installed"));
 }
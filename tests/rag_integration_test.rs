@@ -1,4 +1,4 @@
-use intus::tools::{Tool, ReadUrlTool};
+use intus::tools::{Tool, ToolContext, ReadUrlTool};
 use intus::rag::RagSystem;
 use intus::ollama::OllamaClient;
 use serde_json::json;
@@ -47,15 +47,12 @@ async fn test_rag_end_to_end() {
     };
 
     // 3. Execute Tool (Fetch + Index)
-    // We wrap in spawn_blocking because ReadUrlTool uses blocking reqwest
     let server_uri = mock_server.uri();
     let url = format!("{}/test-page", server_uri);
     
     let args = json!({ "url": url });
     
-    let execution_result = tokio::task::spawn_blocking(move || {
-        tool.execute(args)
-    }).await.expect("Task failed").expect("Tool execution failed");
+    let execution_result = tool.execute(args, &ToolContext::default()).await.expect("Tool execution failed");
 
     println!("Tool Output: {}", execution_result);
 
@@ -77,6 +74,7 @@ async fn test_rag_end_to_end() {
     let search_results = rag.search("content", 5, None).await.expect("Search failed");
     
     assert!(!search_results.is_empty(), "Search should return results");
-    let found = search_results.iter().any(|s| s.contains("Important content"));
+    let found = search_results.iter().any(|hit| hit.content.contains("Important content"));
     assert!(found, "Search result should match one of the chunks. Results: {:?}", search_results);
+    assert!(search_results.iter().all(|hit| hit.collection == "web"), "Read pages are indexed into the web collection");
 }
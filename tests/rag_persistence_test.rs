@@ -35,7 +35,7 @@ async fn test_rag_persistence() {
         );
 
         rag.add_text("Persistent Memory Test Content", None).await.expect("Add text failed");
-        assert!(storage_path.exists(), "File should have been created");
+        assert!(storage_path.with_extension("store").join("manifest.json").exists(), "Store should have been created");
     }
 
     // 2. Create a second system and load
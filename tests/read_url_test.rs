@@ -1,4 +1,4 @@
-use intus::tools::{Tool, ReadUrlTool};
+use intus::tools::{Tool, ToolContext, ReadUrlTool};
 use serde_json::json;
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, MockServer, ResponseTemplate};
@@ -24,27 +24,23 @@ async fn test_read_url_fetching() {
     let url = format!("{}/test-page", server_uri);
 
     // Initial dummy tool
-    // We wrap execution in spawn_blocking because ReadUrlTool uses reqwest::blocking
-    // and we are in an async runtime.
-    let result = tokio::task::spawn_blocking(move || {
-            let rag = Arc::new(RagSystem::new(
-                OllamaClient::new("http://localhost".to_string(), "ollama".to_string(), "".to_string()),
-                "dummy".to_string(),
-                Arc::new(Mutex::new(None)),
-                None,
-            ));
-        let tool = ReadUrlTool {
-            client: OnceLock::new(),
-            rag,
-            browser: Arc::new(intus::tools::web::BrowserClient::new()),
-        };
-
-        let args = json!({
-            "url": url
-        });
-
-        tool.execute(args)
-    }).await.expect("Task failed").expect("Tool execution failed");
+    let rag = Arc::new(RagSystem::new(
+        OllamaClient::new("http://localhost".to_string(), "ollama".to_string(), "".to_string()),
+        "dummy".to_string(),
+        Arc::new(Mutex::new(None)),
+        None,
+    ));
+    let tool = ReadUrlTool {
+        client: OnceLock::new(),
+        rag,
+        browser: Arc::new(intus::tools::web::BrowserClient::new()),
+    };
+
+    let args = json!({
+        "url": url
+    });
+
+    let result = tool.execute(args, &ToolContext::default()).await.expect("Tool execution failed");
 
     // html2text should convert h1 to # or similar bold text, depending on width
     println!("DEBUG: Tool Output: {}", result);
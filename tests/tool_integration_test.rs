@@ -1,19 +1,20 @@
-use intus::tools::{ReplaceTextTool, Tool, WriteFileTool};
+use intus::tools::{ReplaceTextTool, Tool, ToolContext, Workspace, WriteFileTool};
 use serde_json::json;
 use std::fs;
 use tempfile::tempdir;
 
-#[test]
-fn test_integration_workflow() {
+#[tokio::test]
+async fn test_integration_workflow() {
     // 1. Setup
     let dir = tempdir().expect("failed to create temp dir");
     let main_rs = dir.path().join("main.rs");
 
+    let root = dir.path().to_str().unwrap();
     let write_tool = WriteFileTool {
-        ignored_patterns: vec![],
+        workspace: Workspace::new(&[root], &[root], vec![]),
     };
     let replace_tool = ReplaceTextTool {
-        ignored_patterns: vec![],
+        workspace: Workspace::new(&[root], &[root], vec![]),
     };
 
     // 2. AI "Writes" a file
@@ -21,7 +22,7 @@ fn test_integration_workflow() {
         "path": main_rs.to_str().unwrap(),
         "content": "fn main() {\n    println!(\"Hello World\");\n}"
     });
-    let result = write_tool.execute(write_args).expect("Write failed");
+    let result = write_tool.execute(write_args, &ToolContext::default()).await.expect("Write failed");
     assert!(result.contains("Successfully wrote"));
 
     // Verify content
@@ -34,7 +35,7 @@ fn test_integration_workflow() {
         "old_text": "println!(\"Hello World\");",
         "new_text": "println!(\"Hello Integration\");"
     });
-    let result = replace_tool.execute(replace_args).expect("Replace failed");
+    let result = replace_tool.execute(replace_args, &ToolContext::default()).await.expect("Replace failed");
     assert!(result.contains("Successfully modified"));
 
     // Verify change